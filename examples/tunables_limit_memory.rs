@@ -5,8 +5,8 @@ use loupe::MemoryUsage;
 use wasmer::{
     imports,
     vm::{self, MemoryError, MemoryStyle, TableStyle, VMMemoryDefinition, VMTableDefinition},
-    wat2wasm, BaseTunables, Instance, Memory, MemoryType, Module, Pages, Store, TableType, Target,
-    Tunables,
+    wat2wasm, BaseTunables, Instance, Memory, MemoryType, Module, ModuleLimits, Pages,
+    ResourceLimiter, Store, TableType, Target, Tunables,
 };
 use wasmer_compiler_cranelift::Cranelift;
 use wasmer_engine_jit::JIT;
@@ -81,6 +81,20 @@ impl<T: Tunables> Tunables for LimitingTunables<T> {
         self.base.table_style(table)
     }
 
+    /// The limits the modules must satisfy, if any.
+    ///
+    /// Delegated to base.
+    fn module_limits(&self) -> Option<&ModuleLimits> {
+        self.base.module_limits()
+    }
+
+    /// The limiter consulted when creating instances, if any.
+    ///
+    /// Delegated to base.
+    fn resource_limiter(&self) -> Option<&Arc<dyn ResourceLimiter>> {
+        self.base.resource_limiter()
+    }
+
     /// Create a memory owned by the host given a [`MemoryType`] and a [`MemoryStyle`].
     ///
     /// The requested memory type is validated, adjusted to the limited and then passed to base.
@@ -0,0 +1,126 @@
+//! Core dumps of an [`Instance`] that trapped, see [`Instance::coredump`].
+//!
+//! [`Instance`]: crate::Instance
+//! [`Instance::coredump`]: crate::Instance::coredump
+
+use crate::encode::{
+    data_segments, write_custom_section, write_i64, write_name, write_section, write_u32,
+};
+use crate::snapshot::InstanceItems;
+use crate::types::{Val, ValType};
+use wasmer_engine::FrameInfo;
+use wasmer_types::{Mutability, WASM_PAGE_SIZE};
+
+/// Encodes the state of an instance and the stack of a trap in the Wasm
+/// core dump format: a module whose memories, globals and data hold the
+/// state of the instance, with `core` and `corestack` custom sections
+/// describing the process and its stack.
+pub(crate) fn coredump(
+    items: &InstanceItems,
+    trace: &[FrameInfo],
+    executable_name: &str,
+) -> Vec<u8> {
+    let mut output = b"\0asm\x01\0\0\0".to_vec();
+
+    let mut process_info = vec![0x00];
+    write_name(&mut process_info, executable_name);
+    write_custom_section(&mut output, "core", &process_info);
+
+    let mut section = Vec::new();
+    write_u32(&mut section, items.memories.len() as u32);
+    for memory in &items.memories {
+        let ty = memory.ty();
+        let pages = (memory.data_size() / WASM_PAGE_SIZE as u64) as u32;
+        match ty.maximum {
+            Some(maximum) => {
+                section.push(0x01);
+                write_u32(&mut section, pages);
+                write_u32(&mut section, maximum.0);
+            }
+            None => {
+                section.push(0x00);
+                write_u32(&mut section, pages);
+            }
+        }
+    }
+    write_section(&mut output, 5, &section);
+
+    let mut section = Vec::new();
+    write_u32(&mut section, items.globals.len() as u32);
+    for global in &items.globals {
+        let ty = global.ty();
+        section.push(value_type(ty.ty));
+        section.push(match ty.mutability {
+            Mutability::Const => 0x00,
+            Mutability::Var => 0x01,
+        });
+        match global.get() {
+            Val::I32(value) => {
+                section.push(0x41);
+                write_i64(&mut section, value as i64);
+            }
+            Val::I64(value) => {
+                section.push(0x42);
+                write_i64(&mut section, value);
+            }
+            Val::F32(value) => {
+                section.push(0x43);
+                section.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+            Val::F64(value) => {
+                section.push(0x44);
+                section.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+            Val::V128(value) => {
+                section.extend_from_slice(&[0xfd, 0x0c]);
+                section.extend_from_slice(&value.to_le_bytes());
+            }
+            // References don't mean anything outside of the process, the
+            // type of the global is kept but not its value.
+            reference => section.extend_from_slice(&[0xd0, value_type(reference.ty())]),
+        }
+        section.push(0x0b);
+    }
+    write_section(&mut output, 6, &section);
+
+    let mut segments = Vec::new();
+    for (index, memory) in items.memories.iter().enumerate() {
+        // Safety: there is no concurrent access to the memory while we copy it
+        let data = unsafe { memory.data_unchecked() };
+        segments.extend(data_segments(index as u32, data));
+    }
+    let mut section = Vec::new();
+    write_u32(&mut section, segments.len() as u32);
+    for segment in &segments {
+        section.extend_from_slice(segment);
+    }
+    write_section(&mut output, 11, &section);
+
+    let mut stack = vec![0x00];
+    write_name(&mut stack, "main");
+    write_u32(&mut stack, trace.len() as u32);
+    for frame in trace {
+        stack.push(0x00);
+        write_u32(&mut stack, frame.func_index());
+        write_u32(&mut stack, frame.func_offset() as u32);
+        // The values of the locals and of the operand stack are not
+        // available.
+        write_u32(&mut stack, 0);
+        write_u32(&mut stack, 0);
+    }
+    write_custom_section(&mut output, "corestack", &stack);
+
+    output
+}
+
+fn value_type(ty: ValType) -> u8 {
+    match ty {
+        ValType::I32 => 0x7f,
+        ValType::I64 => 0x7e,
+        ValType::F32 => 0x7d,
+        ValType::F64 => 0x7c,
+        ValType::V128 => 0x7b,
+        ValType::FuncRef => 0x70,
+        ValType::ExternRef => 0x6f,
+    }
+}
@@ -0,0 +1,134 @@
+//! Helpers to encode WebAssembly modules, shared by the pre-initializer
+//! and the core dumps.
+
+use std::ops::Range;
+
+/// Runs of zeroes shorter than this are kept inside a data segment rather
+/// than splitting it, as a new segment costs about as many bytes.
+const MIN_SEGMENT_GAP: usize = 8;
+
+pub(crate) fn write_u32(output: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_i64(output: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_name(output: &mut Vec<u8>, name: &str) {
+    write_u32(output, name.len() as u32);
+    output.extend_from_slice(name.as_bytes());
+}
+
+pub(crate) fn write_section(output: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    output.push(id);
+    write_u32(output, contents.len() as u32);
+    output.extend_from_slice(contents);
+}
+
+pub(crate) fn write_custom_section(output: &mut Vec<u8>, name: &str, contents: &[u8]) {
+    let mut section = Vec::new();
+    write_name(&mut section, name);
+    section.extend_from_slice(contents);
+    write_section(output, 0, &section);
+}
+
+/// Returns the active data segments initializing the memory `memory_index`
+/// with the contents `memory`, skipping the runs of zeroes.
+pub(crate) fn data_segments(memory_index: u32, memory: &[u8]) -> Vec<Vec<u8>> {
+    nonzero_runs(memory)
+        .into_iter()
+        .map(|run| {
+            let mut segment = Vec::new();
+            if memory_index == 0 {
+                segment.push(0x00);
+            } else {
+                segment.push(0x02);
+                write_u32(&mut segment, memory_index);
+            }
+            segment.push(0x41);
+            write_i64(&mut segment, run.start as i32 as i64);
+            segment.push(0x0b);
+            write_u32(&mut segment, run.len() as u32);
+            segment.extend_from_slice(&memory[run]);
+            segment
+        })
+        .collect()
+}
+
+/// Returns the ranges of `memory` holding non-zero bytes, merging the ones
+/// separated by short runs of zeroes.
+fn nonzero_runs(memory: &[u8]) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    let mut offset = 0;
+    while offset < memory.len() {
+        if memory[offset] == 0 {
+            offset += 1;
+            continue;
+        }
+        let start = offset;
+        while offset < memory.len() && memory[offset] != 0 {
+            offset += 1;
+        }
+        match runs.last_mut() {
+            Some(last) if start - last.end < MIN_SEGMENT_GAP => last.end = offset,
+            _ => runs.push(start..offset),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn leb128() {
+        let encode_u32 = |value| {
+            let mut output = Vec::new();
+            write_u32(&mut output, value);
+            output
+        };
+        let encode_i64 = |value| {
+            let mut output = Vec::new();
+            write_i64(&mut output, value);
+            output
+        };
+        assert_eq!(encode_u32(0), vec![0x00]);
+        assert_eq!(encode_u32(624_485), vec![0xe5, 0x8e, 0x26]);
+        assert_eq!(encode_u32(u32::MAX), vec![0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert_eq!(encode_i64(63), vec![0x3f]);
+        assert_eq!(encode_i64(64), vec![0xc0, 0x00]);
+        assert_eq!(encode_i64(-1), vec![0x7f]);
+        assert_eq!(encode_i64(-123_456), vec![0xc0, 0xbb, 0x78]);
+    }
+
+    #[test]
+    fn runs_of_nonzero_bytes() {
+        let mut memory = vec![0; 64];
+        memory[2] = 1;
+        memory[3] = 2;
+        // Close enough to the previous run to be merged.
+        memory[7] = 3;
+        memory[40] = 4;
+        memory[63] = 5;
+        assert_eq!(nonzero_runs(&memory), vec![2..8, 40..41, 63..64]);
+        assert_eq!(nonzero_runs(&[0; 16]), vec![]);
+    }
+}
@@ -0,0 +1,420 @@
+//! Callbacks notified of the lifecycle events of a [`Store`], such as
+//! instances being created or memories growing, to monitor what the
+//! WebAssembly programs running in the store do.
+//!
+//! The events are delivered by the runtime itself, so no middleware is
+//! required to observe them.
+
+use crate::{Instance, LinkError, RuntimeError, Store};
+use loupe::MemoryUsage;
+use std::fmt;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use wasmer_compiler::ModuleLimits;
+use wasmer_engine::Tunables;
+use wasmer_types::entity::PrimaryMap;
+use wasmer_types::{
+    GlobalType, LocalGlobalIndex, LocalMemoryIndex, LocalTableIndex, MemoryIndex, MemoryType,
+    Pages, TableIndex, TableType,
+};
+use wasmer_vm::{
+    with_current_trap_handler, Global, Memory, MemoryError, MemoryStyle, ModuleInfo,
+    ResourceLimiter, Table, TableElement, TableError, TableStyle, Trap, VMMemoryDefinition,
+    VMTableDefinition,
+};
+
+/// Callbacks registered on a [`Store`] with [`Store::set_events`], notified
+/// of the lifecycle events of the store.
+///
+/// All the methods do nothing by default, so only the events of interest
+/// need to be implemented.
+///
+/// The callbacks are called synchronously, possibly from inside
+/// WebAssembly code, so they should be quick and must not call back into
+/// the store.
+///
+/// ```
+/// # use std::sync::atomic::{AtomicU32, Ordering};
+/// # use std::sync::Arc;
+/// # use wasmer::{imports, Instance, Module, Pages, Store, StoreEvents};
+/// # fn main() -> anyhow::Result<()> {
+/// #[derive(Default)]
+/// struct PeakMemory(AtomicU32);
+///
+/// impl StoreEvents for PeakMemory {
+///     fn memory_grown(&self, _old: Pages, new: Pages) {
+///         self.0.fetch_max(new.0, Ordering::SeqCst);
+///     }
+/// }
+///
+/// let store = Store::default();
+/// let peak = Arc::new(PeakMemory::default());
+/// store.set_events(Some(peak.clone()));
+///
+/// let module = Module::new(&store, r#"
+///   (module
+///     (memory 1)
+///     (func (export "grow") (result i32)
+///       (memory.grow (i32.const 2))))
+/// "#)?;
+/// let instance = Instance::new(&module, &imports! {})?;
+/// instance.exports.get_native_function::<(), i32>("grow")?.call()?;
+/// assert_eq!(peak.0.load(Ordering::SeqCst), 3);
+/// # Ok(())
+/// # }
+/// ```
+pub trait StoreEvents: Send + Sync {
+    /// Called when an instance is created, once it's ready to be used.
+    fn instance_created(&self, instance: &Instance) {
+        let _ = instance;
+    }
+
+    /// Called when a created instance is dropped, along with everything
+    /// referencing it.
+    fn instance_dropped(&self) {}
+
+    /// Called when a memory has grown from `old` to `new` pages, either
+    /// from WebAssembly or from the host.
+    fn memory_grown(&self, old: Pages, new: Pages) {
+        let _ = (old, new);
+    }
+
+    /// Called when a table has grown from `old` to `new` elements, either
+    /// from WebAssembly or from the host.
+    fn table_grown(&self, old: u32, new: u32) {
+        let _ = (old, new);
+    }
+
+    /// Called when a call into WebAssembly, or the start function of an
+    /// instance, fails with `error`.
+    ///
+    /// Errors raised by host functions are reported too, once they reach
+    /// the host that called into WebAssembly.
+    fn trap_raised(&self, error: &RuntimeError) {
+        let _ = error;
+    }
+
+    /// Called when WebAssembly calls a host function, before it runs.
+    fn host_function_entered(&self) {}
+
+    /// Called when a host function called by WebAssembly returns, traps or
+    /// panics.
+    fn host_function_exited(&self) {}
+}
+
+/// Whether events have been set on any store, so that the calls to host
+/// functions don't look for the events of the calling store until then.
+static EVENTS_SET: AtomicBool = AtomicBool::new(false);
+
+/// The events registered on a store, shared by its clones and by the
+/// memories, tables and instances it creates.
+#[derive(Clone, Default)]
+pub(crate) struct SharedEvents(Arc<RwLock<Option<Arc<dyn StoreEvents>>>>);
+
+impl SharedEvents {
+    /// Replaces the events.
+    pub(crate) fn set(&self, events: Option<Arc<dyn StoreEvents>>) {
+        if events.is_some() {
+            EVENTS_SET.store(true, Ordering::SeqCst);
+        }
+        *self.0.write().unwrap() = events;
+    }
+
+    /// Returns the events, if any.
+    pub(crate) fn get(&self) -> Option<Arc<dyn StoreEvents>> {
+        self.0.read().unwrap().clone()
+    }
+}
+
+/// The [`Tunables`] of a store, notifying its events when the memories and
+/// tables created by the wrapped tunables grow.
+#[derive(MemoryUsage)]
+pub(crate) struct EventTunables<T: Tunables> {
+    tunables: T,
+    #[loupe(skip)]
+    events: SharedEvents,
+}
+
+impl<T: Tunables> EventTunables<T> {
+    /// Wraps `tunables` to notify `events`.
+    pub(crate) fn new(tunables: T, events: SharedEvents) -> Self {
+        Self { tunables, events }
+    }
+
+    fn observe_memory(&self, memory: Arc<dyn Memory>) -> Arc<dyn Memory> {
+        Arc::new(EventMemory {
+            memory,
+            events: self.events.clone(),
+        })
+    }
+
+    fn observe_table(&self, table: Arc<dyn Table>) -> Arc<dyn Table> {
+        Arc::new(EventTable {
+            table,
+            events: self.events.clone(),
+        })
+    }
+}
+
+impl<T: Tunables> Tunables for EventTunables<T> {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        self.tunables.memory_style(memory)
+    }
+
+    fn table_style(&self, table: &TableType) -> TableStyle {
+        self.tunables.table_style(table)
+    }
+
+    fn module_limits(&self) -> Option<&ModuleLimits> {
+        self.tunables.module_limits()
+    }
+
+    fn resource_limiter(&self) -> Option<&Arc<dyn ResourceLimiter>> {
+        self.tunables.resource_limiter()
+    }
+
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        let memory = self.tunables.create_host_memory(ty, style)?;
+        Ok(self.observe_memory(memory))
+    }
+
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        let memory = self
+            .tunables
+            .create_vm_memory(ty, style, vm_definition_location)?;
+        Ok(self.observe_memory(memory))
+    }
+
+    fn create_host_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+    ) -> Result<Arc<dyn Table>, String> {
+        let table = self.tunables.create_host_table(ty, style)?;
+        Ok(self.observe_table(table))
+    }
+
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<Arc<dyn Table>, String> {
+        let table = self
+            .tunables
+            .create_vm_table(ty, style, vm_definition_location)?;
+        Ok(self.observe_table(table))
+    }
+
+    fn create_global(&self, ty: GlobalType) -> Result<Arc<Global>, String> {
+        self.tunables.create_global(ty)
+    }
+
+    unsafe fn create_memories(
+        &self,
+        module: &ModuleInfo,
+        memory_styles: &PrimaryMap<MemoryIndex, MemoryStyle>,
+        memory_definition_locations: &[NonNull<VMMemoryDefinition>],
+    ) -> Result<PrimaryMap<LocalMemoryIndex, Arc<dyn Memory>>, LinkError> {
+        let mut memories =
+            self.tunables
+                .create_memories(module, memory_styles, memory_definition_locations)?;
+        for memory in memories.values_mut() {
+            *memory = self.observe_memory(memory.clone());
+        }
+        Ok(memories)
+    }
+
+    unsafe fn create_tables(
+        &self,
+        module: &ModuleInfo,
+        table_styles: &PrimaryMap<TableIndex, TableStyle>,
+        table_definition_locations: &[NonNull<VMTableDefinition>],
+    ) -> Result<PrimaryMap<LocalTableIndex, Arc<dyn Table>>, LinkError> {
+        let mut tables =
+            self.tunables
+                .create_tables(module, table_styles, table_definition_locations)?;
+        for table in tables.values_mut() {
+            *table = self.observe_table(table.clone());
+        }
+        Ok(tables)
+    }
+
+    fn create_globals(
+        &self,
+        module: &ModuleInfo,
+    ) -> Result<PrimaryMap<LocalGlobalIndex, Arc<Global>>, LinkError> {
+        self.tunables.create_globals(module)
+    }
+}
+
+/// A memory notifying the events of its store when it grows.
+#[derive(MemoryUsage)]
+struct EventMemory {
+    memory: Arc<dyn Memory>,
+    #[loupe(skip)]
+    events: SharedEvents,
+}
+
+impl fmt::Debug for EventMemory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.memory.fmt(f)
+    }
+}
+
+impl Memory for EventMemory {
+    fn ty(&self) -> MemoryType {
+        self.memory.ty()
+    }
+
+    fn style(&self) -> &MemoryStyle {
+        self.memory.style()
+    }
+
+    fn size(&self) -> Pages {
+        self.memory.size()
+    }
+
+    fn grow(&self, delta: Pages) -> Result<Pages, MemoryError> {
+        let old = self.memory.grow(delta)?;
+        if let Some(events) = self.events.get() {
+            events.memory_grown(old, old + delta);
+        }
+        Ok(old)
+    }
+
+    fn vmmemory(&self) -> NonNull<VMMemoryDefinition> {
+        self.memory.vmmemory()
+    }
+}
+
+/// A table notifying the events of its store when it grows.
+#[derive(MemoryUsage)]
+struct EventTable {
+    table: Arc<dyn Table>,
+    #[loupe(skip)]
+    events: SharedEvents,
+}
+
+impl fmt::Debug for EventTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.table.fmt(f)
+    }
+}
+
+impl Table for EventTable {
+    fn style(&self) -> &TableStyle {
+        self.table.style()
+    }
+
+    fn ty(&self) -> &TableType {
+        self.table.ty()
+    }
+
+    fn size(&self) -> u32 {
+        self.table.size()
+    }
+
+    fn grow(&self, delta: u32, init_value: TableElement) -> Result<u32, TableError> {
+        let old = self.table.grow(delta, init_value)?;
+        if let Some(events) = self.events.get() {
+            events.table_grown(old, old + delta);
+        }
+        Ok(old)
+    }
+
+    fn get(&self, index: u32) -> Option<TableElement> {
+        self.table.get(index)
+    }
+
+    fn set(&self, index: u32, reference: TableElement) -> Result<(), Trap> {
+        self.table.set(index, reference)
+    }
+
+    fn vmtable(&self) -> NonNull<VMTableDefinition> {
+        self.table.vmtable()
+    }
+
+    fn copy(
+        &self,
+        src_table: &dyn Table,
+        dst_index: u32,
+        src_index: u32,
+        len: u32,
+    ) -> Result<(), Trap> {
+        self.table.copy(src_table, dst_index, src_index, len)
+    }
+}
+
+/// Notifies the events of a store when one of its instances is dropped.
+///
+/// It's kept in the host state of the instance, so it's dropped along
+/// with it, and only notifies instances that were fully created.
+pub(crate) struct InstanceEvents {
+    store: Store,
+    created: AtomicBool,
+}
+
+impl InstanceEvents {
+    /// Creates the notifier of an instance of `store` being created.
+    pub(crate) fn new(store: &Store) -> Self {
+        Self {
+            store: store.clone(),
+            created: AtomicBool::new(false),
+        }
+    }
+
+    /// Notifies that `instance` is created.
+    pub(crate) fn created(&self, instance: &Instance) {
+        self.created.store(true, Ordering::SeqCst);
+        if let Some(events) = self.store.events() {
+            events.instance_created(instance);
+        }
+    }
+}
+
+impl Drop for InstanceEvents {
+    fn drop(&mut self) {
+        if !self.created.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Some(events) = self.store.events() {
+            events.instance_dropped();
+        }
+    }
+}
+
+/// A call from WebAssembly to a host function, notifying the events of
+/// the calling store when it's entered and, when dropped, exited.
+pub(crate) struct HostFunctionCall(Arc<dyn StoreEvents>);
+
+impl HostFunctionCall {
+    /// Enters a host function, if it's called from WebAssembly by a store
+    /// with events.
+    pub(crate) fn enter() -> Option<Self> {
+        if !EVENTS_SET.load(Ordering::SeqCst) {
+            return None;
+        }
+        let events = with_current_trap_handler(|handler| {
+            handler?.as_any().downcast_ref::<Store>()?.events()
+        })?;
+        events.host_function_entered();
+        Some(Self(events))
+    }
+}
+
+impl Drop for HostFunctionCall {
+    fn drop(&mut self) {
+        self.0.host_function_exited();
+    }
+}
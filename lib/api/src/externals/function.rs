@@ -1,3 +1,4 @@
+use crate::events::HostFunctionCall;
 use crate::exports::{ExportError, Exportable};
 use crate::externals::Extern;
 use crate::store::Store;
@@ -6,6 +7,7 @@ use crate::FunctionType;
 use crate::NativeFunc;
 use crate::RuntimeError;
 use crate::WasmerEnv;
+use crate::{HostEnvInitError, Instance, Module};
 pub use inner::{FromToNativeWasmType, HostFunction, WasmTypeList, WithEnv, WithoutEnv};
 #[cfg(feature = "deprecated")]
 pub use inner::{UnsafeMutableEnv, WithUnsafeMutableEnv};
@@ -16,6 +18,7 @@ use std::ffi::c_void;
 use std::fmt;
 use std::sync::Arc;
 use wasmer_engine::{Export, ExportFunction, ExportFunctionMetadata};
+use wasmer_types::FunctionIndex;
 use wasmer_vm::{
     raise_user_trap, resume_panic, wasmer_call_trampoline, ImportInitializerFuncPtr,
     VMCallerCheckedAnyfunc, VMDynamicFunctionContext, VMFuncRef, VMFunction, VMFunctionBody,
@@ -433,7 +436,9 @@ impl Function {
 
     fn call_wasm(
         &self,
+        vmctx: VMFunctionEnvironment,
         trampoline: VMTrampoline,
+        address: *const VMFunctionBody,
         params: &[Val],
         results: &mut [Val],
     ) -> Result<(), RuntimeError> {
@@ -481,13 +486,13 @@ impl Function {
         if let Err(error) = unsafe {
             wasmer_call_trampoline(
                 &self.store,
-                self.exported.vm_function.vmctx,
+                vmctx,
                 trampoline,
-                self.exported.vm_function.address,
+                address,
                 values_vec.as_mut_ptr() as *mut u8,
             )
         } {
-            return Err(RuntimeError::from_trap(error));
+            return Err(self.store.trap_raised(RuntimeError::from_trap(error)));
         }
 
         // Load the return values out of `values_vec`.
@@ -573,7 +578,13 @@ impl Function {
     pub fn call(&self, params: &[Val]) -> Result<Box<[Val]>, RuntimeError> {
         if let Some(trampoline) = self.exported.vm_function.call_trampoline {
             let mut results = vec![Val::null(); self.result_arity()];
-            self.call_wasm(trampoline, params, &mut results)?;
+            self.call_wasm(
+                self.exported.vm_function.vmctx,
+                trampoline,
+                self.exported.vm_function.address,
+                params,
+                &mut results,
+            )?;
             return Ok(results.into_boxed_slice());
         }
 
@@ -736,6 +747,98 @@ impl fmt::Debug for Function {
     }
 }
 
+/// A function imported by an `Instance`, prepared to be called from the
+/// host on behalf of the instance.
+///
+/// Host functions get their own copy of their host env, initialized with
+/// the instance like the engine does for the functions it imports.
+pub(crate) struct ImportedFunction {
+    function: Function,
+    trampoline: VMTrampoline,
+    address: *const VMFunctionBody,
+    /// The copy of the host env, or `None` for functions with no host env
+    /// like the functions exported by other instances.
+    host_env: Option<*mut c_void>,
+}
+
+// The host env is `Send` and `Sync` as required by `WasmerEnv`, and it's
+// only freed when the `ImportedFunction` is dropped.
+unsafe impl Send for ImportedFunction {}
+unsafe impl Sync for ImportedFunction {}
+
+impl ImportedFunction {
+    /// Prepares `function`, imported by `instance` as the function
+    /// `index` of `module`, to be called from the host.
+    pub(crate) fn new(
+        function: Function,
+        module: &Module,
+        index: FunctionIndex,
+        instance: &Instance,
+    ) -> Result<Self, HostEnvInitError> {
+        let artifact = module.artifact();
+        let signature = module.info().functions[index];
+        let trampoline = artifact.finished_function_call_trampolines()[signature];
+        let address = match function.exported.vm_function.kind {
+            VMFunctionKind::Dynamic => {
+                artifact.finished_dynamic_function_trampolines()[index].0 as *const VMFunctionBody
+            }
+            VMFunctionKind::Static => function.exported.vm_function.address,
+        };
+
+        let host_env = match &function.exported.metadata {
+            Some(metadata) => {
+                let host_env = metadata.clone_host_env();
+                let imported = Self {
+                    function: function.clone(),
+                    trampoline,
+                    address,
+                    host_env: Some(host_env),
+                };
+                // # Safety
+                // - `host_env` is a fresh clone and `instance` is a `wasmer::Instance`.
+                unsafe {
+                    metadata.initialize_host_env::<HostEnvInitError>(
+                        host_env,
+                        instance as *const _ as *const _,
+                    )?;
+                }
+                return Ok(imported);
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            function,
+            trampoline,
+            address,
+            host_env,
+        })
+    }
+
+    /// Calls the function with `params`.
+    pub(crate) fn call(&self, params: &[Val]) -> Result<Box<[Val]>, RuntimeError> {
+        let vmctx = match self.host_env {
+            Some(host_env) => VMFunctionEnvironment { host_env },
+            None => self.function.exported.vm_function.vmctx,
+        };
+        let mut results = vec![Val::null(); self.function.result_arity()];
+        self.function
+            .call_wasm(vmctx, self.trampoline, self.address, params, &mut results)?;
+        Ok(results.into_boxed_slice())
+    }
+}
+
+impl Drop for ImportedFunction {
+    fn drop(&mut self) {
+        if let (Some(host_env), Some(metadata)) = (self.host_env, &self.function.exported.metadata)
+        {
+            // # Safety
+            // - `host_env` was cloned from `metadata` and isn't used anymore.
+            unsafe { metadata.drop_host_env(host_env) }
+        }
+    }
+}
+
 /// This trait is one that all dynamic functions must fulfill.
 pub(crate) trait VMDynamicFunction: Send + Sync {
     fn call(&self, args: &[Val]) -> Result<Vec<Val>, RuntimeError>;
@@ -808,6 +911,7 @@ impl<T: VMDynamicFunction> VMDynamicFunctionCall<T> for VMDynamicFunctionContext
     ) {
         use std::panic::{self, AssertUnwindSafe};
         let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _call = HostFunctionCall::enter();
             let func_ty = self.ctx.function_type();
             let mut args = Vec::with_capacity(func_ty.params().len());
             let store = self.ctx.store();
@@ -845,6 +949,7 @@ impl<T: VMDynamicFunction> VMDynamicFunctionCall<T> for VMDynamicFunctionContext
 /// This private inner module contains the low-level implementation
 /// for `Function` and its siblings.
 mod inner {
+    use crate::events::HostFunctionCall;
     use std::array::TryFromSliceError;
     use std::convert::{Infallible, TryInto};
     use std::error::Error;
@@ -1334,6 +1439,7 @@ mod inner {
                     {
                         let func: &Func = unsafe { &*(&() as *const () as *const Func) };
                         let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                            let _call = HostFunctionCall::enter();
                             func( $( FromToNativeWasmType::from_native($x) ),* ).into_result()
                         }));
 
@@ -1378,6 +1484,7 @@ mod inner {
                         let func: &Func = unsafe { &*(&() as *const () as *const Func) };
 
                         let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                            let _call = HostFunctionCall::enter();
                             func(env, $( FromToNativeWasmType::from_native($x) ),* ).into_result()
                         }));
 
@@ -1424,6 +1531,7 @@ mod inner {
                         let func: &Func = unsafe { &*(&() as *const () as *const Func) };
 
                         let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                            let _call = HostFunctionCall::enter();
                             func(env, $( FromToNativeWasmType::from_native($x) ),* ).into_result()
                         }));
 
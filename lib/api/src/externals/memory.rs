@@ -4,12 +4,33 @@ use crate::store::Store;
 use crate::{MemoryType, MemoryView};
 use loupe::MemoryUsage;
 use std::convert::TryInto;
-use std::slice;
 use std::sync::Arc;
-use wasmer_engine::Export;
+use std::{mem, ptr, slice};
+use thiserror::Error;
+use wasmer_engine::{Export, RuntimeError};
 use wasmer_types::{Pages, ValueType};
 use wasmer_vm::{MemoryError, VMMemory};
 
+/// An error while reading or writing a [`Memory`].
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccessError {
+    /// The bytes accessed aren't all in the memory.
+    #[error("out of bounds memory access of {len} bytes at {offset:#x}")]
+    HeapOutOfBounds {
+        /// The offset of the first byte accessed.
+        offset: u64,
+
+        /// The number of bytes accessed.
+        len: u64,
+    },
+}
+
+impl From<MemoryAccessError> for RuntimeError {
+    fn from(error: MemoryAccessError) -> Self {
+        Self::new(error.to_string())
+    }
+}
+
 /// A WebAssembly `memory` instance.
 ///
 /// A memory instance is the runtime representation of a linear memory.
@@ -226,6 +247,103 @@ impl Memory {
         unsafe { MemoryView::new(base as _, length as u32) }
     }
 
+    /// Returns the address of the `len` bytes at `offset`, if they're all
+    /// in the memory.
+    fn checked_ptr(&self, offset: u64, len: usize) -> Result<*mut u8, MemoryAccessError> {
+        let error = MemoryAccessError::HeapOutOfBounds {
+            offset,
+            len: len as u64,
+        };
+        match offset.checked_add(len as u64) {
+            Some(end) if end <= self.data_size() => {
+                Ok(unsafe { self.data_ptr().add(offset as usize) })
+            }
+            _ => Err(error),
+        }
+    }
+
+    /// Reads the bytes at `offset` into `buf`.
+    ///
+    /// Nothing is read if some of the bytes aren't in the memory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::{Memory, MemoryAccessError, MemoryType, Store};
+    /// # let store = Store::default();
+    /// #
+    /// let m = Memory::new(&store, MemoryType::new(1, None, false)).unwrap();
+    /// m.write(0x10, b"hello").unwrap();
+    ///
+    /// let mut buf = [0; 5];
+    /// m.read(0x10, &mut buf).unwrap();
+    /// assert_eq!(&buf, b"hello");
+    ///
+    /// assert_eq!(
+    ///     m.read(0xfffe, &mut buf),
+    ///     Err(MemoryAccessError::HeapOutOfBounds { offset: 0xfffe, len: 5 }),
+    /// );
+    /// ```
+    pub fn read(&self, offset: u64, buf: &mut [u8]) -> Result<(), MemoryAccessError> {
+        let src = self.checked_ptr(offset, buf.len())?;
+        unsafe { ptr::copy(src, buf.as_mut_ptr(), buf.len()) };
+        Ok(())
+    }
+
+    /// Writes `data` at `offset`.
+    ///
+    /// Nothing is written if some of the bytes aren't in the memory.
+    pub fn write(&self, offset: u64, data: &[u8]) -> Result<(), MemoryAccessError> {
+        let dst = self.checked_ptr(offset, data.len())?;
+        unsafe { ptr::copy(data.as_ptr(), dst, data.len()) };
+        Ok(())
+    }
+
+    /// Reads the value of type `T` at `offset`, which doesn't need to be
+    /// aligned.
+    ///
+    /// The value is read with the little-endian layout of the WebAssembly
+    /// memories, see [`ValueType::convert_le`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use wasmer::{Memory, MemoryType, Store, ValueType};
+    /// # let store = Store::default();
+    /// #
+    /// #[derive(Debug, Clone, Copy, PartialEq, ValueType)]
+    /// #[repr(C)]
+    /// struct Point {
+    ///     x: i32,
+    ///     y: i32,
+    /// }
+    ///
+    /// let m = Memory::new(&store, MemoryType::new(1, None, false)).unwrap();
+    /// m.write_struct(0x11, &Point { x: 1, y: -1 }).unwrap();
+    ///
+    /// assert_eq!(m.read_struct::<Point>(0x11).unwrap(), Point { x: 1, y: -1 });
+    /// assert_eq!(m.read_struct::<u32>(0x11).unwrap(), 1);
+    /// ```
+    pub fn read_struct<T: ValueType>(&self, offset: u64) -> Result<T, MemoryAccessError> {
+        let src = self.checked_ptr(offset, mem::size_of::<T>())?;
+        let value = unsafe { ptr::read_unaligned(src as *const T) };
+        Ok(value.convert_le())
+    }
+
+    /// Writes `value` at `offset`, which doesn't need to be aligned.
+    ///
+    /// The value is written with the little-endian layout of the
+    /// WebAssembly memories, see [`ValueType::convert_le`].
+    pub fn write_struct<T: ValueType>(
+        &self,
+        offset: u64,
+        value: &T,
+    ) -> Result<(), MemoryAccessError> {
+        let dst = self.checked_ptr(offset, mem::size_of::<T>())?;
+        unsafe { ptr::write_unaligned(dst as *mut T, value.convert_le()) };
+        Ok(())
+    }
+
     pub(crate) fn from_vm_export(store: &Store, vm_memory: VMMemory) -> Self {
         Self {
             store: store.clone(),
@@ -7,7 +7,7 @@ use crate::TableType;
 use loupe::MemoryUsage;
 use std::sync::Arc;
 use wasmer_engine::Export;
-use wasmer_vm::{Table as RuntimeTable, TableElement, VMTable};
+use wasmer_vm::{Table as RuntimeTable, TableElement, TableError, VMTable};
 
 /// A WebAssembly `table` instance.
 ///
@@ -96,13 +96,14 @@ impl Table {
     ///
     /// # Errors
     ///
-    /// Returns an error if the `delta` is out of bounds for the table.
+    /// Returns an error if the `delta` is out of bounds for the table,
+    /// or if the resource limiter of the store denies the growth.
     pub fn grow(&self, delta: u32, init: Val) -> Result<u32, RuntimeError> {
         let item = init.into_table_reference(&self.store)?;
-        self.vm_table
-            .from
-            .grow(delta, item)
-            .ok_or_else(|| RuntimeError::new(format!("failed to grow table by `{}`", delta)))
+        self.vm_table.from.grow(delta, item).map_err(|e| match e {
+            TableError::ResourceLimit(e) => RuntimeError::user(Box::new(e)),
+            _ => RuntimeError::new(format!("failed to grow table by `{}`", delta)),
+        })
     }
 
     /// Copies the `len` elements of `src_table` starting at `src_index`
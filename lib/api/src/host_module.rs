@@ -0,0 +1,57 @@
+//! Host modules, see [`HostModule`].
+
+use crate::env::WasmerEnv;
+use crate::exports::Exports;
+use crate::externals::{Memory, MemoryAccessError};
+use crate::import_object::ImportObject;
+use crate::store::Store;
+use wasmer_engine::RuntimeError;
+
+/// A type whose methods are the host functions of an import namespace.
+///
+/// Implement `HostModule` with the [`host_module`](crate::host_module)
+/// attribute on an `impl` block: each method taking `&self` becomes a host
+/// function, with the value as its environment.
+pub trait HostModule: WasmerEnv + Clone + Send + Sync + 'static {
+    /// The namespace of the host functions.
+    const NAMESPACE: &'static str;
+
+    /// Returns the host functions, by name.
+    fn exports(&self, store: &Store) -> Exports;
+
+    /// Returns the host functions in the namespace [`HostModule::NAMESPACE`].
+    fn imports(&self, store: &Store) -> ImportObject {
+        let mut import_object = ImportObject::new();
+        import_object.register(Self::NAMESPACE, self.exports(store));
+        import_object
+    }
+}
+
+/// Reads the `len` bytes at `ptr` in `memory`.
+#[doc(hidden)]
+pub fn read_bytes(memory: &Memory, ptr: u32, len: u32) -> Result<Vec<u8>, RuntimeError> {
+    // Check the bounds before allocating.
+    if u64::from(ptr) + u64::from(len) > memory.data_size() {
+        return Err(MemoryAccessError::HeapOutOfBounds {
+            offset: ptr.into(),
+            len: len.into(),
+        }
+        .into());
+    }
+    let mut bytes = vec![0; len as usize];
+    memory.read(ptr.into(), &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reads the UTF-8 string of `len` bytes at `ptr` in `memory`.
+#[doc(hidden)]
+pub fn read_string(memory: &Memory, ptr: u32, len: u32) -> Result<String, RuntimeError> {
+    String::from_utf8(read_bytes(memory, ptr, len)?)
+        .map_err(|error| RuntimeError::new(format!("invalid string at {:#x}: {}", ptr, error)))
+}
+
+/// Writes `bytes` at `ptr` in `memory`.
+#[doc(hidden)]
+pub fn write_bytes(memory: &Memory, ptr: u32, bytes: &[u8]) -> Result<(), RuntimeError> {
+    Ok(memory.write(ptr.into(), bytes)?)
+}
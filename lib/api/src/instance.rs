@@ -1,6 +1,9 @@
+use crate::coredump;
+use crate::events::InstanceEvents;
 use crate::exports::Exports;
 use crate::externals::Extern;
 use crate::module::Module;
+use crate::snapshot::{InstanceItems, InstanceSnapshot, SnapshotError};
 use crate::store::Store;
 use crate::{HostEnvInitError, LinkError, RuntimeError};
 use loupe::MemoryUsage;
@@ -8,6 +11,7 @@ use std::fmt;
 use std::sync::{Arc, Mutex};
 use thiserror::Error;
 use wasmer_engine::Resolver;
+use wasmer_types::ExportIndex;
 use wasmer_vm::{InstanceHandle, VMContext};
 
 /// A WebAssembly Instance is a stateful, executable
@@ -114,7 +118,8 @@ impl Instance {
     ///  * Runtime errors that happen when running the module `start` function.
     pub fn new(module: &Module, resolver: &dyn Resolver) -> Result<Self, InstantiationError> {
         let store = module.store();
-        let handle = module.instantiate(resolver)?;
+        let events = Arc::new(InstanceEvents::new(store));
+        let handle = module.instantiate(resolver, events.clone())?;
         let exports = module
             .exports()
             .map(|export| {
@@ -147,6 +152,7 @@ impl Instance {
                 .initialize_host_envs::<HostEnvInitError>(&instance as *const _ as *const _)?;
         }
 
+        events.created(&instance);
         Ok(instance)
     }
 
@@ -164,6 +170,135 @@ impl Instance {
     pub fn vmctx_ptr(&self) -> *mut VMContext {
         self.handle.lock().unwrap().vmctx_ptr()
     }
+
+    /// Takes a snapshot of the memories, mutable globals and tables of the
+    /// instance.
+    ///
+    /// The snapshot can be restored with [`Instance::restore`], possibly on
+    /// another host.
+    ///
+    /// ```
+    /// # use wasmer::{imports, Instance, Module, Store, Value};
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let module = Module::new(&store, r#"
+    ///   (module
+    ///     (global $counter (export "counter") (mut i32) (i32.const 0))
+    ///     (func (export "increment")
+    ///       (global.set $counter (i32.add (global.get $counter) (i32.const 1)))))
+    /// "#)?;
+    /// let instance = Instance::new(&module, &imports! {})?;
+    /// instance.exports.get_function("increment")?.call(&[])?;
+    ///
+    /// let snapshot = instance.snapshot()?;
+    /// let restored = Instance::restore(&module, &imports! {}, &snapshot)?;
+    /// assert_eq!(restored.exports.get_global("counter")?.get(), Value::I32(1));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// ## Errors
+    ///
+    /// Taking a snapshot fails if a global or table contains an `externref`
+    /// or a reference to a function that isn't part of the instance.
+    ///
+    /// Function references to host functions created with
+    /// [`Function::new`](crate::Function::new) or
+    /// [`Function::new_with_env`](crate::Function::new_with_env) are not
+    /// recognized once stored in a table, as they go through a trampoline.
+    pub fn snapshot(&self) -> Result<InstanceSnapshot, SnapshotError> {
+        self.items().snapshot()
+    }
+
+    /// Creates a new `Instance` from `module` and `resolver`, like
+    /// [`Instance::new`], and restores `snapshot` in it.
+    ///
+    /// `snapshot` must have been taken from an instance of the same module.
+    /// Note that the start function of the module runs before the snapshot
+    /// is restored, and that memories and tables of the snapshot can only
+    /// be restored if they're at least as big as the ones of the new
+    /// instance, which is always the case for snapshots of the same module.
+    pub fn restore(
+        module: &Module,
+        resolver: &dyn Resolver,
+        snapshot: &InstanceSnapshot,
+    ) -> Result<Self, SnapshotError> {
+        let instance = Self::new(module, resolver)?;
+        instance.items().restore(snapshot)?;
+        Ok(instance)
+    }
+
+    /// Creates a core dump of the instance after the trap `error`, in the
+    /// [Wasm core dump format](https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md).
+    ///
+    /// The core dump holds the contents of the memories, the values of the
+    /// globals and, for every frame of the trace of `error`, the function
+    /// index and the offset of the instruction in the function.  The values
+    /// of the locals are not available.  Frames of other instances called
+    /// through imports are included with the function indices of their
+    /// own module.
+    ///
+    /// ```
+    /// # use wasmer::{imports, Instance, Module, Store};
+    /// # fn main() -> anyhow::Result<()> {
+    /// let store = Store::default();
+    /// let module = Module::new(&store, "(module (func (export \"run\") unreachable))")?;
+    /// let instance = Instance::new(&module, &imports! {})?;
+    ///
+    /// let error = instance.exports.get_function("run")?.call(&[]).unwrap_err();
+    /// let coredump = instance.coredump(&error, "example.wasm");
+    /// assert!(wasmer::is_wasm(&coredump));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn coredump(&self, error: &RuntimeError, executable_name: &str) -> Vec<u8> {
+        coredump::coredump(&self.items(), error.trace(), executable_name)
+    }
+
+    /// Looks up every memory, global, table and function of the instance,
+    /// including the ones that aren't exported.
+    fn items(&self) -> InstanceItems {
+        let store = self.store();
+        let handle = self.handle.lock().unwrap();
+        let info = handle.module_ref();
+        let lookup = |index: ExportIndex| {
+            Extern::from_vm_export(store, handle.lookup_by_declaration(&index).into())
+        };
+        InstanceItems {
+            memories: info
+                .memories
+                .keys()
+                .map(|index| match lookup(ExportIndex::Memory(index)) {
+                    Extern::Memory(memory) => memory,
+                    _ => unreachable!("memory index resolved to a non-memory"),
+                })
+                .collect(),
+            globals: info
+                .globals
+                .keys()
+                .map(|index| match lookup(ExportIndex::Global(index)) {
+                    Extern::Global(global) => global,
+                    _ => unreachable!("global index resolved to a non-global"),
+                })
+                .collect(),
+            tables: info
+                .tables
+                .keys()
+                .map(|index| match lookup(ExportIndex::Table(index)) {
+                    Extern::Table(table) => table,
+                    _ => unreachable!("table index resolved to a non-table"),
+                })
+                .collect(),
+            functions: info
+                .functions
+                .keys()
+                .map(|index| match lookup(ExportIndex::Function(index)) {
+                    Extern::Function(function) => function,
+                    _ => unreachable!("function index resolved to a non-function"),
+                })
+                .collect(),
+        }
+    }
 }
 
 impl fmt::Debug for Instance {
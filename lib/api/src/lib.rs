@@ -221,6 +221,8 @@
 //! - `llvm` - enable Wasmer's LLVM compiler. (See [wasmer-llvm][])
 //! - `singlepass` - enable Wasmer's Singlepass compiler. (See [wasmer-singlepass][])
 //! - `wat` - enable `wasmer` to parse the WebAssembly text format.
+//! - `async` - enable compiling modules from asynchronous byte streams
+//!   with `Module::from_stream`.
 //!
 //! The features that set defaults come in sets that are mutually exclusive.
 //!
@@ -253,14 +255,23 @@
 //! [wasmer-llvm]: https://docs.rs/wasmer-compiler-llvm/*/wasmer_compiler_llvm/
 //! [wasmer-wasi]: https://docs.rs/wasmer-wasi/*/wasmer_wasi/
 
+mod coredump;
+mod encode;
 mod env;
+mod events;
 mod exports;
 mod externals;
+mod host_module;
 mod import_object;
 mod instance;
+mod linker;
 mod module;
 mod native;
+#[cfg(feature = "compiler")]
+mod preinitialize;
 mod ptr;
+mod record;
+mod snapshot;
 mod store;
 mod tunables;
 mod types;
@@ -271,6 +282,59 @@ mod utils;
 /// See the [`WasmerEnv`] trait for more information.
 pub use wasmer_derive::WasmerEnv;
 
+/// Implement [`ValueType`] for your struct with `#[derive(ValueType)]`.
+///
+/// The struct must be `#[repr(C)]` or `#[repr(transparent)]`, and have no
+/// padding, which is checked at compile time, and all its fields must
+/// implement [`ValueType`].
+pub use wasmer_derive::ValueType;
+
+/// Implement [`HostModule`] for your type with `#[host_module]` on an `impl`
+/// block.
+///
+/// Each method taking `&self` becomes a host function named after it, with
+/// the value as its environment. Besides the types that can be passed to
+/// host functions, such as integers, floats and [`WasmPtr`]s, the arguments
+/// can be `&str`, `&[u8]` and `&mut [u8]`, which are passed as a pointer and
+/// a length in bytes into the memory of the environment, and written back
+/// for `&mut [u8]`. The methods can return a `Result`, whose errors become
+/// [`RuntimeError`] traps, which can be downcast back, and leave the
+/// `&mut [u8]` arguments untouched.
+///
+/// The attribute takes the `namespace` of the functions, `"env"` by
+/// default, and the name of the `LazyInit<Memory>` field of the environment
+/// used for the string and slice arguments, as `memory`.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use wasmer::{host_module, HostModule, LazyInit, Memory, WasmerEnv};
+///
+/// #[derive(Clone, Default, WasmerEnv)]
+/// struct Logger {
+///     #[wasmer(export)]
+///     memory: LazyInit<Memory>,
+///     lines: Arc<Mutex<Vec<String>>>,
+/// }
+///
+/// #[host_module(namespace = "logger", memory = "memory")]
+/// impl Logger {
+///     fn log(&self, line: &str) {
+///         self.lines.lock().unwrap().push(line.to_string());
+///     }
+///
+///     fn line_len(&self, index: u32) -> Result<u32, std::fmt::Error> {
+///         let lines = self.lines.lock().unwrap();
+///         let line = lines.get(index as usize).ok_or(std::fmt::Error)?;
+///         Ok(line.len() as u32)
+///     }
+/// }
+///
+/// assert_eq!(Logger::NAMESPACE, "logger");
+/// ```
+pub use wasmer_derive::host_module;
+
 #[doc(hidden)]
 pub mod internals {
     //! We use the internals module for exporting types that are only
@@ -281,18 +345,27 @@ pub mod internals {
     #[cfg(feature = "deprecated")]
     pub use crate::externals::{UnsafeMutableEnv, WithUnsafeMutableEnv};
     pub use crate::externals::{WithEnv, WithoutEnv};
+    pub use crate::host_module::{read_bytes, read_string, write_bytes};
 }
 
 pub use crate::env::{HostEnvInitError, LazyInit, WasmerEnv};
+pub use crate::events::StoreEvents;
 pub use crate::exports::{ExportError, Exportable, Exports, ExportsIterator};
 pub use crate::externals::{
-    Extern, FromToNativeWasmType, Function, Global, HostFunction, Memory, Table, WasmTypeList,
+    Extern, FromToNativeWasmType, Function, Global, HostFunction, Memory, MemoryAccessError, Table,
+    WasmTypeList,
 };
+pub use crate::host_module::HostModule;
 pub use crate::import_object::{ImportObject, ImportObjectIterator, LikeNamespace};
 pub use crate::instance::{Instance, InstantiationError};
-pub use crate::module::Module;
+pub use crate::linker::{Linker, LinkerError, Shadowing};
+pub use crate::module::{IoCompileError, Module};
 pub use crate::native::NativeFunc;
+#[cfg(feature = "compiler")]
+pub use crate::preinitialize::{PreinitializeError, Preinitializer};
 pub use crate::ptr::{Array, Item, WasmPtr};
+pub use crate::record::{HostCall, HostCallOutcome, MemoryWrite, Recorder, Replayer};
+pub use crate::snapshot::{InstanceSnapshot, SnapshotError, SnapshotValue};
 pub use crate::store::{Store, StoreObject};
 pub use crate::tunables::BaseTunables;
 pub use crate::types::{
@@ -304,15 +377,16 @@ pub use crate::utils::is_wasm;
 pub use target_lexicon::{Architecture, CallingConvention, OperatingSystem, Triple, HOST};
 #[cfg(feature = "compiler")]
 pub use wasmer_compiler::{
-    wasmparser, CompilerConfig, FunctionMiddleware, MiddlewareError, MiddlewareReaderState,
-    ModuleMiddleware,
+    wasmparser, CompilerConfig, FunctionAdditions, FunctionMiddleware, MiddlewareError,
+    MiddlewareReaderState, ModuleMiddleware,
 };
 pub use wasmer_compiler::{
-    CompileError, CpuFeature, Features, ParseCpuFeatureError, Target, WasmError, WasmResult,
+    CompileError, CpuFeature, Features, ModuleLimit, ModuleLimits, ParseCpuFeatureError, Target,
+    WasmError, WasmResult,
 };
 pub use wasmer_engine::{
-    ChainableNamedResolver, DeserializeError, Engine, Export, FrameInfo, LinkError, NamedResolver,
-    NamedResolverChain, Resolver, RuntimeError, SerializeError, Tunables,
+    ChainableNamedResolver, DeserializeError, Engine, Export, FrameInfo, ImportError, LinkError,
+    NamedResolver, NamedResolverChain, Resolver, RuntimeError, SerializeError, Tunables,
 };
 #[cfg(feature = "experimental-reference-types-extern-ref")]
 pub use wasmer_types::ExternRef;
@@ -322,13 +396,15 @@ pub use wasmer_types::{
 };
 
 // TODO: should those be moved into wasmer::vm as well?
-pub use wasmer_vm::{raise_user_trap, MemoryError};
+pub use wasmer_vm::{
+    raise_user_trap, MemoryError, ResourceBudget, ResourceLimitError, ResourceLimiter,
+};
 pub mod vm {
     //! The vm module re-exports wasmer-vm types.
 
     pub use wasmer_vm::{
-        Memory, MemoryError, MemoryStyle, Table, TableStyle, VMExtern, VMMemoryDefinition,
-        VMTableDefinition,
+        LinearMemory, LinearTable, Memory, MemoryError, MemoryStyle, ModuleInfo, Table, TableError,
+        TableStyle, VMExtern, VMMemoryDefinition, VMTableDefinition,
     };
 }
 
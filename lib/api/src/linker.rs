@@ -0,0 +1,285 @@
+//! Linking modules and host functions together by name, see [`Linker`].
+
+use crate::exports::Exportable;
+use crate::externals::Extern;
+use crate::instance::{Instance, InstantiationError};
+use crate::module::Module;
+use crate::store::{Store, StoreObject};
+use indexmap::IndexMap;
+use std::fmt;
+use thiserror::Error;
+use wasmer_engine::{Export, ImportError, LinkError, NamedResolver};
+
+/// How a [`Linker`] handles the definition of an item already defined
+/// under the same module and field names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shadowing {
+    /// The definition fails with [`LinkerError::AlreadyDefined`].
+    Deny,
+
+    /// The new item replaces the previous one. The instances already
+    /// linked keep the previous item.
+    Allow,
+}
+
+impl Default for Shadowing {
+    fn default() -> Self {
+        Self::Deny
+    }
+}
+
+/// An error while defining items in a [`Linker`] or instantiating a module
+/// with it.
+#[derive(Error, Debug)]
+pub enum LinkerError {
+    /// An item is already defined under these module and field names, and
+    /// shadowing isn't allowed.
+    #[error("`{0}`.`{1}` is already defined")]
+    AlreadyDefined(String, String),
+
+    /// The item doesn't belong to the store of the linker.
+    #[error("`{0}`.`{1}` doesn't belong to the store of the linker")]
+    WrongStore(String, String),
+
+    /// No module is registered under this name.
+    #[error("unknown module `{0}`")]
+    UnknownModule(String),
+
+    /// Some imports of the module are missing or have incompatible types.
+    #[error("{}", DisplayLinkErrors(.0))]
+    Imports(Vec<LinkError>),
+
+    /// The module could not be instantiated.
+    #[error(transparent)]
+    Instantiation(#[from] InstantiationError),
+}
+
+/// Displays a list of link errors, one per line.
+struct DisplayLinkErrors<'a>(&'a [LinkError]);
+
+impl fmt::Display for DisplayLinkErrors<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} imports couldn't be resolved:", self.0.len())?;
+        for error in self.0 {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Links modules and host functions together by name.
+///
+/// Host functions and other items are defined under a module name and a
+/// field name, like the imports of a module. Instances are registered under
+/// a module name, which defines all their exports under that name, so that
+/// the modules instantiated afterwards can import them. Modules can also be
+/// registered under a name and instantiated later, their instances being
+/// registered under the same name.
+///
+/// A `Linker` is a [`NamedResolver`], so it can be given to
+/// [`Instance::new`], but [`Linker::instantiate`] reports all the imports
+/// of the module that can't be resolved at once, instead of the first one.
+///
+/// # Example
+///
+/// ```
+/// # use wasmer::{wat2wasm, Function, Linker, Module, Store};
+/// # fn main() -> anyhow::Result<()> {
+/// let store = Store::default();
+/// let mut linker = Linker::new(&store);
+/// linker.define("env", "double", Function::new_native(&store, |x: i32| x * 2))?;
+///
+/// let math = Module::new(&store, wat2wasm(br#"
+///     (module
+///       (import "env" "double" (func $double (param i32) (result i32)))
+///       (func (export "quadruple") (param i32) (result i32)
+///         (call $double (call $double (local.get 0)))))
+/// "#)?)?;
+/// linker.register_module("math", math);
+/// linker.instantiate_module("math")?;
+///
+/// let app = Module::new(&store, wat2wasm(br#"
+///     (module
+///       (import "math" "quadruple" (func $quadruple (param i32) (result i32)))
+///       (func (export "run") (result i32)
+///         (call $quadruple (i32.const 3))))
+/// "#)?)?;
+/// let instance = linker.instantiate(&app)?;
+/// let run = instance.exports.get_native_function::<(), i32>("run")?;
+/// assert_eq!(run.call()?, 12);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Linker {
+    store: Store,
+    shadowing: Shadowing,
+
+    /// The items, by module and field names, in the order of their first
+    /// definition.
+    items: IndexMap<(String, String), Extern>,
+
+    /// The modules to instantiate by name.
+    modules: IndexMap<String, Module>,
+}
+
+impl Linker {
+    /// Creates an empty `Linker` for the items of `store`.
+    pub fn new(store: &Store) -> Self {
+        Self {
+            store: store.clone(),
+            shadowing: Shadowing::default(),
+            items: IndexMap::new(),
+            modules: IndexMap::new(),
+        }
+    }
+
+    /// Returns the store of the linker.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Sets how the definitions of items already defined are handled. By
+    /// default they fail.
+    pub fn shadowing(&mut self, shadowing: Shadowing) -> &mut Self {
+        self.shadowing = shadowing;
+        self
+    }
+
+    /// Defines `item` as the field `name` of the module `module`.
+    pub fn define<I>(&mut self, module: &str, name: &str, item: I) -> Result<&mut Self, LinkerError>
+    where
+        I: Into<Extern>,
+    {
+        let item = item.into();
+        if !item.comes_from_same_store(&self.store) {
+            return Err(LinkerError::WrongStore(
+                module.to_string(),
+                name.to_string(),
+            ));
+        }
+        let key = (module.to_string(), name.to_string());
+        if self.shadowing == Shadowing::Deny && self.items.contains_key(&key) {
+            return Err(LinkerError::AlreadyDefined(key.0, key.1));
+        }
+        self.items.insert(key, item);
+        Ok(self)
+    }
+
+    /// Defines all the exports of `instance` as the fields of the module
+    /// `module`.
+    ///
+    /// Nothing is defined if any of the exports can't be.
+    pub fn register_instance(
+        &mut self,
+        module: &str,
+        instance: &Instance,
+    ) -> Result<&mut Self, LinkerError> {
+        for (name, item) in instance.exports.iter() {
+            let key = (module.to_string(), name.to_string());
+            if !item.comes_from_same_store(&self.store) {
+                return Err(LinkerError::WrongStore(key.0, key.1));
+            }
+            if self.shadowing == Shadowing::Deny && self.items.contains_key(&key) {
+                return Err(LinkerError::AlreadyDefined(key.0, key.1));
+            }
+        }
+        for (name, item) in instance.exports.iter() {
+            self.items
+                .insert((module.to_string(), name.to_string()), item.clone());
+        }
+        Ok(self)
+    }
+
+    /// Registers `module` under the name `name`, to be instantiated with
+    /// [`Linker::instantiate_module`].
+    ///
+    /// A module registered under the same name is replaced.
+    pub fn register_module(&mut self, name: &str, module: Module) -> &mut Self {
+        self.modules.insert(name.to_string(), module);
+        self
+    }
+
+    /// Instantiates the module registered under the name `name`, and
+    /// registers the instance under the same name.
+    ///
+    /// The module isn't instantiated if its exports can't be registered.
+    pub fn instantiate_module(&mut self, name: &str) -> Result<Instance, LinkerError> {
+        let module = self
+            .modules
+            .get(name)
+            .ok_or_else(|| LinkerError::UnknownModule(name.to_string()))?
+            .clone();
+        // Instantiating may run the start function, so the exports are
+        // checked like in `register_instance` beforehand.
+        let same_store = Store::same(module.store(), &self.store);
+        for export in module.exports() {
+            let key = (name.to_string(), export.name().to_string());
+            if !same_store {
+                return Err(LinkerError::WrongStore(key.0, key.1));
+            }
+            if self.shadowing == Shadowing::Deny && self.items.contains_key(&key) {
+                return Err(LinkerError::AlreadyDefined(key.0, key.1));
+            }
+        }
+        let instance = self.instantiate(&module)?;
+        self.register_instance(name, &instance)?;
+        Ok(instance)
+    }
+
+    /// Instantiates `module`, resolving its imports with the items of the
+    /// linker.
+    ///
+    /// If some imports are missing or have incompatible types, the error
+    /// reports all of them.
+    pub fn instantiate(&self, module: &Module) -> Result<Instance, LinkerError> {
+        let errors = module
+            .imports()
+            .filter_map(|import| {
+                let key = (import.module().to_string(), import.name().to_string());
+                let error = match self.items.get(&key) {
+                    None => ImportError::UnknownImport(import.ty().clone()),
+                    Some(item) if !item.ty().is_compatible_with(import.ty()) => {
+                        ImportError::IncompatibleType(import.ty().clone(), item.ty())
+                    }
+                    Some(_) => return None,
+                };
+                Some(LinkError::Import(key.0, key.1, error))
+            })
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(LinkerError::Imports(errors));
+        }
+
+        Ok(Instance::new(module, self)?)
+    }
+
+    /// Returns the item defined as the field `name` of the module `module`.
+    pub fn get(&self, module: &str, name: &str) -> Option<&Extern> {
+        self.items.get(&(module.to_string(), name.to_string()))
+    }
+
+    /// Returns an iterator over the items of the linker, with their module
+    /// and field names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &Extern)> {
+        self.items
+            .iter()
+            .map(|((module, name), item)| (module.as_str(), name.as_str(), item))
+    }
+}
+
+impl NamedResolver for Linker {
+    fn resolve_by_name(&self, module: &str, name: &str) -> Option<Export> {
+        self.get(module, name).map(Exportable::to_export)
+    }
+}
+
+impl fmt::Debug for Linker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Linker")
+            .field("shadowing", &self.shadowing)
+            .field("items", &self.items)
+            .field("modules", &self.modules.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
@@ -1,11 +1,17 @@
+use crate::events::InstanceEvents;
 use crate::store::Store;
 use crate::types::{ExportType, ImportType};
 use crate::InstantiationError;
+#[cfg(feature = "async")]
+use futures::{channel::oneshot, Stream, StreamExt};
 use loupe::MemoryUsage;
 use std::fmt;
 use std::io;
+use std::iter;
 use std::path::Path;
 use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::{panic, sync::mpsc, thread};
 use thiserror::Error;
 use wasmer_compiler::CompileError;
 #[cfg(feature = "wat")]
@@ -13,6 +19,7 @@ use wasmer_compiler::WasmError;
 use wasmer_engine::{Artifact, DeserializeError, Resolver, SerializeError};
 use wasmer_vm::{ExportsIterator, ImportsIterator, InstanceHandle, ModuleInfo};
 
+/// An error while compiling a module read from a file or a reader.
 #[derive(Error, Debug)]
 pub enum IoCompileError {
     /// An IO error
@@ -23,6 +30,9 @@ pub enum IoCompileError {
     Compile(#[from] CompileError),
 }
 
+/// The size of the chunks read by [`Module::from_reader`].
+const STREAMING_CHUNK_SIZE: usize = 64 * 1024;
+
 /// A WebAssembly Module contains stateless WebAssembly
 /// code that has already been compiled and can be instantiated
 /// multiple times.
@@ -123,6 +133,94 @@ impl Module {
         Ok(module)
     }
 
+    /// Creates a new WebAssembly module from a binary read from `reader`,
+    /// such as a file or a socket.
+    ///
+    /// The module is validated and, if the engine supports it, its functions
+    /// are compiled while the rest of the binary is read. As opposed to
+    /// [`Module::new`], the WebAssembly text format isn't supported.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use wasmer::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let store = Store::default();
+    /// let bytes = wat::parse_str("(module (func (export \"run\")))")?;
+    /// let module = Module::from_reader(&store, &bytes[..])?;
+    /// assert_eq!(module.exports().count(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_reader(store: &Store, mut reader: impl io::Read) -> Result<Self, IoCompileError> {
+        let mut io_error = None;
+        let mut chunks = iter::from_fn(|| {
+            let mut chunk = vec![0; STREAMING_CHUNK_SIZE];
+            loop {
+                match reader.read(&mut chunk) {
+                    Ok(0) => return None,
+                    Ok(len) => {
+                        chunk.truncate(len);
+                        return Some(chunk);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        io_error = Some(e);
+                        return None;
+                    }
+                }
+            }
+        });
+        let artifact = store
+            .engine()
+            .compile_streaming(&mut chunks, store.tunables());
+        // The compilation fails when the binary is cut short by an IO error,
+        // which is the error to report then
+        if let Some(e) = io_error {
+            return Err(e.into());
+        }
+        Ok(Self::from_artifact(store, artifact?))
+    }
+
+    /// Creates a new WebAssembly module from a binary received from an
+    /// asynchronous `stream` of chunks, such as the body of an HTTP
+    /// response.
+    ///
+    /// The module is validated and, if the engine supports it, its functions
+    /// are compiled while the rest of the binary is received, on a thread of
+    /// its own so that the compilation doesn't block the executor.
+    #[cfg(feature = "async")]
+    pub async fn from_stream<S, B>(store: &Store, stream: S) -> Result<Self, IoCompileError>
+    where
+        S: Stream<Item = io::Result<B>>,
+        B: AsRef<[u8]>,
+    {
+        let (chunk_sender, chunk_receiver) = mpsc::channel::<Vec<u8>>();
+        let (result_sender, result_receiver) = oneshot::channel();
+        let compile_store = store.clone();
+        let compilation = thread::spawn(move || {
+            let result = compile_store
+                .engine()
+                .compile_streaming(&mut chunk_receiver.into_iter(), compile_store.tunables());
+            let _ = result_sender.send(result);
+        });
+
+        futures::pin_mut!(stream);
+        while let Some(chunk) = stream.next().await {
+            // Sending fails if the compilation already failed
+            if chunk_sender.send(chunk?.as_ref().to_vec()).is_err() {
+                break;
+            }
+        }
+        drop(chunk_sender);
+        let artifact = match result_receiver.await {
+            Ok(result) => result?,
+            // The result is only dropped if the compilation panicked
+            Err(_) => panic::resume_unwind(compilation.join().unwrap_err()),
+        };
+        Ok(Self::from_artifact(store, artifact))
+    }
+
     /// Creates a new WebAssembly module from a binary.
     ///
     /// Opposed to [`Module::new`], this function is not compatible with
@@ -154,7 +252,17 @@ impl Module {
     /// This validation is normally pretty fast and checks the enabled
     /// WebAssembly features in the Store Engine to assure deterministic
     /// validation of the Module.
+    ///
+    /// The Module is also checked against the [`ModuleLimits`] of the
+    /// Store [`Tunables`], if any.
+    ///
+    /// [`ModuleLimits`]: crate::ModuleLimits
+    /// [`Tunables`]: crate::Tunables
     pub fn validate(store: &Store, binary: &[u8]) -> Result<(), CompileError> {
+        #[cfg(feature = "compiler")]
+        if let Some(limits) = store.tunables().module_limits() {
+            limits.validate(binary)?;
+        }
         store.engine().validate(binary)
     }
 
@@ -262,12 +370,13 @@ impl Module {
     pub(crate) fn instantiate(
         &self,
         resolver: &dyn Resolver,
+        events: Arc<InstanceEvents>,
     ) -> Result<InstanceHandle, InstantiationError> {
         unsafe {
             let instance_handle = self.artifact.instantiate(
                 self.store.tunables(),
                 resolver,
-                Box::new((self.store.clone(), self.artifact.clone())),
+                Box::new((self.store.clone(), self.artifact.clone(), events)),
             )?;
 
             // After the instance handle is created, we need to initialize
@@ -276,7 +385,13 @@ impl Module {
             // as some of the Instance elements may have placed in other
             // instance tables.
             self.artifact
-                .finish_instantiation(&self.store, &instance_handle)?;
+                .finish_instantiation(&self.store, &instance_handle)
+                .map_err(|error| match InstantiationError::from(error) {
+                    InstantiationError::Start(error) => {
+                        InstantiationError::Start(self.store.trap_raised(error))
+                    }
+                    error => error,
+                })?;
 
             Ok(instance_handle)
         }
@@ -137,7 +137,7 @@ macro_rules! impl_native_traits {
                             self.address(),
                             args_rets.as_mut_ptr() as *mut u8,
                         )
-                    }?;
+                    }.map_err(|trap| self.store.trap_raised(RuntimeError::from_trap(trap)))?;
                     let num_rets = rets_list.len();
                     if !using_rets_array && num_rets > 0 {
                         let src_pointer = params_list.as_ptr();
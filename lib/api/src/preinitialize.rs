@@ -0,0 +1,357 @@
+//! Pre-initialization of WebAssembly modules, see [`Preinitializer`].
+
+use crate::encode::{data_segments, write_i64, write_section, write_u32};
+use crate::exports::ExportError;
+use crate::instance::{Instance, InstantiationError};
+use crate::module::Module;
+use crate::snapshot::{InstanceSnapshot, SnapshotError, SnapshotValue};
+use thiserror::Error;
+use wasmer_compiler::wasmparser::{
+    BinaryReaderError, DataKind, ExternalKind, MemoryType, Parser, Payload, Range as SectionRange,
+    SectionReader,
+};
+use wasmer_compiler::CompileError;
+use wasmer_engine::{Resolver, RuntimeError};
+use wasmer_types::WASM_PAGE_SIZE;
+
+/// The maximum number of data segments engines are required to accept.
+const MAX_DATA_SEGMENTS: usize = 100_000;
+
+/// An error while pre-initializing a module.
+#[derive(Error, Debug)]
+pub enum PreinitializeError {
+    /// The module could not be instantiated.
+    #[error(transparent)]
+    Instantiation(#[from] InstantiationError),
+
+    /// The init function isn't exported, or isn't a `[] -> []` function.
+    #[error(transparent)]
+    Export(#[from] ExportError),
+
+    /// The init function trapped.
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+
+    /// The state of the instance could not be captured.
+    #[error(transparent)]
+    Snapshot(#[from] SnapshotError),
+
+    /// The original module could not be parsed.
+    #[error("invalid module: {0}")]
+    Invalid(String),
+
+    /// The module or the state of the instance can't be captured in a new
+    /// module.
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+
+    /// The pre-initialized module failed to validate.
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+}
+
+impl From<BinaryReaderError> for PreinitializeError {
+    fn from(error: BinaryReaderError) -> Self {
+        Self::Invalid(error.to_string())
+    }
+}
+
+/// Pre-initializes WebAssembly modules, in the way of [Wizer].
+///
+/// The module is instantiated and its init function is called, then a new
+/// module is emitted whose data segments and global initializers hold the
+/// state of the instance after the call.  Instantiating the new module is
+/// equivalent to instantiating the original one and calling the init
+/// function, without paying for the call every time.
+///
+/// Only the state defined by the module is captured: imported memories,
+/// globals and tables belong to the host and are left alone.  Tables must
+/// not be modified by the init function.  Start functions are removed, as
+/// their effects are part of the captured state.
+///
+/// [Wizer]: https://github.com/bytecodealliance/wizer
+///
+/// # Example
+///
+/// ```
+/// # use wasmer::{imports, wat2wasm, Instance, Module, Preinitializer, Store, Value};
+/// # fn main() -> anyhow::Result<()> {
+/// let store = Store::default();
+/// let wasm = wat2wasm(br#"
+///     (module
+///       (global $value (mut i32) (i32.const 0))
+///       (func (export "init") (global.set $value (i32.const 42)))
+///       (func (export "value") (result i32) (global.get $value)))
+/// "#)?;
+/// let module = Module::new(&store, &wasm)?;
+///
+/// let preinitialized = Preinitializer::new("init").run(&module, &wasm, &imports! {})?;
+///
+/// let module = Module::new(&store, &preinitialized)?;
+/// let instance = Instance::new(&module, &imports! {})?;
+/// let value = instance.exports.get_function("value")?.call(&[])?;
+/// assert_eq!(value.to_vec(), vec![Value::I32(42)]);
+/// assert!(instance.exports.get_function("init").is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Preinitializer {
+    init_func: String,
+    keep_init_func: bool,
+}
+
+impl Preinitializer {
+    /// Create a `Preinitializer` calling the exported function `init_func`,
+    /// which must take no arguments and return nothing.
+    pub fn new(init_func: impl Into<String>) -> Self {
+        Self {
+            init_func: init_func.into(),
+            keep_init_func: false,
+        }
+    }
+
+    /// Keep the init function exported from the pre-initialized module.  It
+    /// is removed by default, as calling it again is usually a mistake.
+    pub fn keep_init_func(&mut self, keep: bool) -> &mut Self {
+        self.keep_init_func = keep;
+        self
+    }
+
+    /// Instantiate `module` with `resolver`, call the init function and
+    /// return the pre-initialized module.
+    ///
+    /// `wasm` must be the binary `module` was compiled from: it is the base
+    /// of the new module, which keeps its code and custom sections.
+    pub fn run(
+        &self,
+        module: &Module,
+        wasm: &[u8],
+        resolver: &dyn Resolver,
+    ) -> Result<Vec<u8>, PreinitializeError> {
+        let instance = Instance::new(module, resolver)?;
+        let before = instance.snapshot()?;
+        instance
+            .exports
+            .get_native_function::<(), ()>(&self.init_func)?
+            .call()?;
+        let after = instance.snapshot()?;
+        if before.tables != after.tables {
+            return Err(PreinitializeError::Unsupported(
+                "the init function modified a table".to_string(),
+            ));
+        }
+
+        let preinitialized = self.rewrite(module, wasm, &after)?;
+        Module::validate(module.store(), &preinitialized)?;
+        Ok(preinitialized)
+    }
+
+    /// Emit `wasm` with the memories and mutable globals it defines
+    /// initialized from `snapshot`.
+    fn rewrite(
+        &self,
+        module: &Module,
+        wasm: &[u8],
+        snapshot: &InstanceSnapshot,
+    ) -> Result<Vec<u8>, PreinitializeError> {
+        let info = module.info();
+        let memories = &snapshot.memories[info.num_imported_memories..];
+        let globals = &snapshot.globals[info.num_imported_globals..];
+
+        // Segments initializing imported memories are kept as they are, the
+        // ones of defined memories are replaced by their contents.
+        let mut segments = imported_memory_segments(wasm, info.num_imported_memories as u32)?;
+        let mut data = Vec::new();
+        for (index, memory) in memories.iter().enumerate() {
+            let memory_index = (info.num_imported_memories + index) as u32;
+            data.extend(data_segments(memory_index, memory));
+        }
+        segments.extend(data);
+        if segments.len() > MAX_DATA_SEGMENTS {
+            return Err(PreinitializeError::Unsupported(format!(
+                "{} data segments are needed, at most {} are allowed",
+                segments.len(),
+                MAX_DATA_SEGMENTS
+            )));
+        }
+        let mut data_section = Vec::new();
+        write_u32(&mut data_section, segments.len() as u32);
+        for segment in &segments {
+            data_section.extend_from_slice(segment);
+        }
+
+        let mut output = Vec::with_capacity(wasm.len() + data_section.len());
+        let mut data_written = false;
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::Version { .. } => output.extend_from_slice(&wasm[..8]),
+                Payload::TypeSection(reader) => write_raw(&mut output, 1, wasm, reader.range()),
+                Payload::ImportSection(reader) => write_raw(&mut output, 2, wasm, reader.range()),
+                Payload::FunctionSection(reader) => write_raw(&mut output, 3, wasm, reader.range()),
+                Payload::TableSection(reader) => write_raw(&mut output, 4, wasm, reader.range()),
+                Payload::MemorySection(mut reader) => {
+                    let mut section = Vec::new();
+                    write_u32(&mut section, reader.get_count());
+                    for memory in memories.iter().take(reader.get_count() as usize) {
+                        let (maximum, shared) = match reader.read()? {
+                            MemoryType::M32 { limits, shared } => (limits.maximum, shared),
+                            MemoryType::M64 { .. } => {
+                                return Err(PreinitializeError::Unsupported(
+                                    "64-bit memories".to_string(),
+                                ))
+                            }
+                        };
+                        let flags = maximum.map_or(0, |_| 0x01) | if shared { 0x02 } else { 0 };
+                        section.push(flags);
+                        write_u32(&mut section, (memory.len() / WASM_PAGE_SIZE) as u32);
+                        if let Some(maximum) = maximum {
+                            write_u32(&mut section, maximum);
+                        }
+                    }
+                    write_section(&mut output, 5, &section);
+                }
+                Payload::GlobalSection(mut reader) => {
+                    let mut section = Vec::new();
+                    write_u32(&mut section, reader.get_count());
+                    for value in globals.iter().take(reader.get_count() as usize) {
+                        let start = reader.original_position();
+                        reader.read()?;
+                        let global = &wasm[start..reader.original_position()];
+                        match value {
+                            // The value type and mutability take the first two
+                            // bytes, the init expression follows.
+                            Some(value) => {
+                                section.extend_from_slice(&global[..2]);
+                                write_const(&mut section, *value);
+                            }
+                            None => section.extend_from_slice(global),
+                        }
+                    }
+                    write_section(&mut output, 6, &section);
+                }
+                Payload::ExportSection(mut reader) => {
+                    let mut count = 0;
+                    let mut exports = Vec::new();
+                    for _ in 0..reader.get_count() {
+                        let start = reader.original_position();
+                        let export = reader.read()?;
+                        if !self.keep_init_func
+                            && matches!(export.kind, ExternalKind::Function)
+                            && export.field == self.init_func
+                        {
+                            continue;
+                        }
+                        exports.extend_from_slice(&wasm[start..reader.original_position()]);
+                        count += 1;
+                    }
+                    let mut section = Vec::new();
+                    write_u32(&mut section, count);
+                    section.extend_from_slice(&exports);
+                    write_section(&mut output, 7, &section);
+                }
+                // The start function already ran, its effects are in the snapshot.
+                Payload::StartSection { .. } => {}
+                Payload::ElementSection(reader) => write_raw(&mut output, 9, wasm, reader.range()),
+                Payload::DataCountSection { .. } => {
+                    let mut section = Vec::new();
+                    write_u32(&mut section, segments.len() as u32);
+                    write_section(&mut output, 12, &section);
+                }
+                Payload::CodeSectionStart { range, .. } => write_raw(&mut output, 10, wasm, range),
+                Payload::CodeSectionEntry(_) => {}
+                Payload::DataSection(_) => {
+                    write_section(&mut output, 11, &data_section);
+                    data_written = true;
+                }
+                Payload::EventSection(reader) => write_raw(&mut output, 13, wasm, reader.range()),
+                Payload::CustomSection { range, .. } => write_raw(&mut output, 0, wasm, range),
+                Payload::UnknownSection { id, range, .. } => {
+                    write_raw(&mut output, id, wasm, range)
+                }
+                Payload::AliasSection(_)
+                | Payload::InstanceSection(_)
+                | Payload::ModuleSectionStart { .. }
+                | Payload::ModuleSectionEntry { .. } => {
+                    return Err(PreinitializeError::Unsupported(
+                        "the module linking proposal".to_string(),
+                    ))
+                }
+                Payload::End => {
+                    // The data section goes after the code section, which is
+                    // the last known section.
+                    if !data_written && !segments.is_empty() {
+                        write_section(&mut output, 11, &data_section);
+                    }
+                }
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+/// Returns the raw data segments of `wasm` initializing imported memories.
+fn imported_memory_segments(
+    wasm: &[u8],
+    num_imported_memories: u32,
+) -> Result<Vec<Vec<u8>>, PreinitializeError> {
+    let mut segments = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        let mut reader = match payload? {
+            Payload::DataSection(reader) => reader,
+            _ => continue,
+        };
+        for _ in 0..reader.get_count() {
+            let start = reader.original_position();
+            match reader.read()?.kind {
+                DataKind::Passive => {
+                    return Err(PreinitializeError::Unsupported(
+                        "passive data segments".to_string(),
+                    ))
+                }
+                DataKind::Active { memory_index, .. } if memory_index < num_imported_memories => {
+                    segments.push(wasm[start..reader.original_position()].to_vec())
+                }
+                DataKind::Active { .. } => {}
+            }
+        }
+    }
+    Ok(segments)
+}
+
+/// Writes a constant expression evaluating to `value`.
+fn write_const(output: &mut Vec<u8>, value: SnapshotValue) {
+    match value {
+        SnapshotValue::I32(value) => {
+            output.push(0x41);
+            write_i64(output, value as i64);
+        }
+        SnapshotValue::I64(value) => {
+            output.push(0x42);
+            write_i64(output, value);
+        }
+        SnapshotValue::F32(bits) => {
+            output.push(0x43);
+            output.extend_from_slice(&bits.to_le_bytes());
+        }
+        SnapshotValue::F64(bits) => {
+            output.push(0x44);
+            output.extend_from_slice(&bits.to_le_bytes());
+        }
+        SnapshotValue::V128(value) => {
+            output.extend_from_slice(&[0xfd, 0x0c]);
+            output.extend_from_slice(&value.to_le_bytes());
+        }
+        SnapshotValue::FuncRef(None) => output.extend_from_slice(&[0xd0, 0x70]),
+        SnapshotValue::FuncRef(Some(index)) => {
+            output.push(0xd2);
+            write_u32(output, index);
+        }
+    }
+    output.push(0x0b);
+}
+
+fn write_raw(output: &mut Vec<u8>, id: u8, wasm: &[u8], range: SectionRange) {
+    write_section(output, id, &wasm[range.start..range.end]);
+}
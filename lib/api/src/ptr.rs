@@ -6,7 +6,7 @@
 //! Therefore, you should use this abstraction whenever possible to avoid memory
 //! related bugs when implementing an ABI.
 
-use crate::{externals::Memory, FromToNativeWasmType};
+use crate::{externals::Memory, FromToNativeWasmType, MemoryAccessError};
 use std::{cell::Cell, fmt, marker::PhantomData, mem};
 use wasmer_types::ValueType;
 
@@ -33,32 +33,27 @@ pub struct Item;
 /// }
 /// ```
 ///
-/// This type can also be used with primitive-filled structs, but be careful of
-/// guarantees required by `ValueType`.
+/// This type can also be used with primitive-filled structs, implementing
+/// `ValueType` with `#[derive(ValueType)]`.
 /// ```
 /// # use wasmer::Memory;
 /// # use wasmer::WasmPtr;
 /// # use wasmer::ValueType;
 ///
-/// #[derive(Copy, Clone, Debug)]
+/// #[derive(Copy, Clone, Debug, ValueType)]
 /// #[repr(C)]
 /// struct V3 {
 ///     x: f32,
 ///     y: f32,
 ///     z: f32
 /// }
-/// // This is safe as the 12 bytes represented by this struct
-/// // are valid for all bit combinations.
-/// unsafe impl ValueType for V3 {
-/// }
 ///
 /// fn update_vector_3(memory: Memory, ptr: WasmPtr<V3>) {
-///     let derefed_ptr = ptr.deref(&memory).expect("pointer in bounds");
-///     let mut inner_val: V3 = derefed_ptr.get();
+///     let mut inner_val: V3 = ptr.read(&memory).expect("pointer in bounds");
 ///     println!("Got {:?} from Wasm memory address 0x{:X}", inner_val, ptr.offset());
 ///     // update the value being pointed to
 ///     inner_val.x = 10.4;
-///     derefed_ptr.set(inner_val);
+///     ptr.write(&memory, inner_val).expect("pointer in bounds");
 /// }
 /// ```
 #[repr(transparent)]
@@ -96,6 +91,23 @@ fn align_pointer(ptr: usize, align: usize) -> usize {
 /// that implement [`ValueType`], meaning that they're valid for all possible
 /// bit patterns.
 impl<T: Copy + ValueType> WasmPtr<T, Item> {
+    /// Reads the value pointed to, which doesn't need to be aligned.
+    ///
+    /// See [`Memory::read_struct`].
+    #[inline]
+    pub fn read(self, memory: &Memory) -> Result<T, MemoryAccessError> {
+        memory.read_struct(self.offset.into())
+    }
+
+    /// Writes `value` where the `WasmPtr` points, which doesn't need to be
+    /// aligned.
+    ///
+    /// See [`Memory::write_struct`].
+    #[inline]
+    pub fn write(self, memory: &Memory, value: T) -> Result<(), MemoryAccessError> {
+        memory.write_struct(self.offset.into(), &value)
+    }
+
     /// Dereference the `WasmPtr` getting access to a `&Cell<T>` allowing for
     /// reading and mutating of the inner value.
     ///
@@ -306,7 +318,12 @@ unsafe impl<T: Copy, Ty> FromToNativeWasmType for WasmPtr<T, Ty> {
     }
 }
 
-unsafe impl<T: Copy, Ty> ValueType for WasmPtr<T, Ty> {}
+unsafe impl<T: Copy, Ty> ValueType for WasmPtr<T, Ty> {
+    #[inline]
+    fn convert_le(self) -> Self {
+        Self::new(self.offset.convert_le())
+    }
+}
 
 impl<T: Copy, Ty> Clone for WasmPtr<T, Ty> {
     fn clone(&self) -> Self {
@@ -423,4 +440,39 @@ mod test {
             assert!(unsafe { oob_end_array_ptr.deref_mut(&memory, 1, 0).is_none() });
         }
     }
+
+    /// Ensure that `read` and `write` work at unaligned offsets, with the
+    /// little-endian layout, and catch out of bounds accesses.
+    #[test]
+    fn wasm_ptr_read_write() {
+        let store = Store::default();
+        let memory = Memory::new(&store, MemoryType::new(1, Some(1), false)).unwrap();
+
+        let ptr: WasmPtr<[u32; 2]> = WasmPtr::new(3);
+        ptr.write(&memory, [0x0403_0201, 0x0807_0605]).unwrap();
+        assert_eq!(ptr.read(&memory).unwrap(), [0x0403_0201, 0x0807_0605]);
+        let mut bytes = [0; 8];
+        memory.read(3, &mut bytes).unwrap();
+        assert_eq!(bytes, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let last_valid_address_for_u32 = (memory.size().bytes().0 - 4) as u32;
+        let end_wasm_ptr: WasmPtr<u32> = WasmPtr::new(last_valid_address_for_u32);
+        end_wasm_ptr.write(&memory, 42).unwrap();
+        assert_eq!(end_wasm_ptr.read(&memory).unwrap(), 42);
+
+        let oob_wasm_ptr: WasmPtr<u32> = WasmPtr::new(last_valid_address_for_u32 + 1);
+        let error = MemoryAccessError::HeapOutOfBounds {
+            offset: (last_valid_address_for_u32 + 1).into(),
+            len: 4,
+        };
+        assert_eq!(oob_wasm_ptr.read(&memory), Err(error));
+        assert_eq!(oob_wasm_ptr.write(&memory, 42), Err(error));
+        assert_eq!(
+            memory.read(u64::MAX, &mut bytes),
+            Err(MemoryAccessError::HeapOutOfBounds {
+                offset: u64::MAX,
+                len: 8,
+            })
+        );
+    }
 }
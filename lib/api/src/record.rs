@@ -0,0 +1,440 @@
+//! Record and replay of the calls an [`Instance`] makes to its imported
+//! functions, see [`Recorder`] and [`Replayer`].
+//!
+//! [`Instance`]: crate::Instance
+
+use crate::env::{HostEnvInitError, LazyInit, WasmerEnv};
+use crate::exports::Exports;
+use crate::externals::function::ImportedFunction;
+use crate::externals::{Extern, Function, Memory};
+use crate::import_object::ImportObject;
+use crate::instance::Instance;
+use crate::module::Module;
+use crate::snapshot::SnapshotValue;
+use crate::types::Val;
+use crate::FunctionType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use wasmer_engine::{Export, Resolver, RuntimeError};
+use wasmer_types::{FunctionIndex, ImportIndex};
+
+/// Runs of unchanged bytes shorter than this are kept inside a
+/// [`MemoryWrite`] rather than splitting it.
+const MIN_WRITE_GAP: usize = 8;
+
+/// The size of the blocks of memory compared at once when looking for
+/// the bytes changed by a call.
+const BLOCK_SIZE: usize = 4096;
+
+/// A call to an imported function, as logged by a [`Recorder`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostCall {
+    /// The module name of the import.
+    pub module: String,
+    /// The field name of the import.
+    pub name: String,
+    /// The arguments of the call.
+    pub params: Vec<SnapshotValue>,
+    /// What the call returned.
+    pub outcome: HostCallOutcome,
+    /// The size of the memory after the call, in pages, if the call changed
+    /// it.
+    pub memory_pages: Option<u32>,
+    /// The bytes of the memory written by the call.
+    pub memory_writes: Vec<MemoryWrite>,
+}
+
+/// What a [`HostCall`] returned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HostCallOutcome {
+    /// The call returned these results.
+    Returned(Vec<SnapshotValue>),
+    /// The call trapped with this message.
+    Trapped(String),
+}
+
+/// Bytes of the memory written by a [`HostCall`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryWrite {
+    /// The address of the first byte written.
+    pub offset: u64,
+    /// The bytes written.
+    pub bytes: Vec<u8>,
+}
+
+/// Logs every call an instance makes to its imported functions, to replay
+/// them later with a [`Replayer`].
+///
+/// The arguments, results and the bytes of the memory exported as `memory`
+/// written by each call are logged as a [`HostCall`], encoded with
+/// `bincode`, as soon as the call returns.  Finding the bytes written
+/// means comparing the memory before and after each call, so recording
+/// is meant for debugging rather than production use.
+///
+/// # Example
+///
+/// ```
+/// # use wasmer::{imports, wat2wasm, Function, Instance, Module, Recorder, Store};
+/// # fn main() -> anyhow::Result<()> {
+/// let store = Store::default();
+/// let module = Module::new(&store, wat2wasm(br#"
+///     (module
+///       (import "env" "now" (func $now (result i64)))
+///       (func (export "run") (result i64) (call $now)))
+/// "#)?)?;
+/// let import_object = imports! {
+///     "env" => { "now" => Function::new_native(&store, || 42i64) },
+/// };
+///
+/// let recorder = Recorder::new(std::io::sink());
+/// let instance = Instance::new(&module, &recorder.wrap_imports(&module, &import_object))?;
+/// instance.exports.get_function("run")?.call(&[])?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Recorder {
+    log: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Recorder {
+    /// Creates a recorder writing the log to `log`.
+    pub fn new(log: impl Write + Send + 'static) -> Self {
+        Self {
+            log: Arc::new(Mutex::new(Box::new(log))),
+        }
+    }
+
+    /// Resolves the imports of `module` with `resolver`, wrapping the
+    /// functions so that their calls are logged.
+    pub fn wrap_imports(&self, module: &Module, resolver: &dyn Resolver) -> ImportObject {
+        wrap_imports(module, resolver, |import, ty, function| {
+            let env = RecordEnv {
+                log: self.log.clone(),
+                module: module.clone(),
+                import,
+                function: function?,
+                memory: LazyInit::new(),
+                imported: None,
+            };
+            Some(Function::new_with_env(module.store(), ty, env, record_call))
+        })
+    }
+}
+
+/// Runs an instance against the log of a [`Recorder`] rather than against
+/// the host: the calls to its imported functions return the recorded
+/// results and apply the recorded writes to the memory, without calling
+/// the host.
+///
+/// The instance must make the same calls, with the same arguments, as the
+/// recorded one did, or the calls trap.  Recorded traps are replayed as
+/// traps with the same message.
+#[derive(Clone)]
+pub struct Replayer {
+    log: Arc<Mutex<Box<dyn Read + Send>>>,
+}
+
+impl Replayer {
+    /// Creates a replayer reading the log from `log`.
+    pub fn new(log: impl Read + Send + 'static) -> Self {
+        Self {
+            log: Arc::new(Mutex::new(Box::new(log))),
+        }
+    }
+
+    /// Creates the imports of `module`: the functions are replayed from the
+    /// log, the other imports are resolved with `resolver`.
+    pub fn wrap_imports(&self, module: &Module, resolver: &dyn Resolver) -> ImportObject {
+        wrap_imports(module, resolver, |import, ty, _function| {
+            let env = ReplayEnv {
+                log: self.log.clone(),
+                import,
+                memory: LazyInit::new(),
+            };
+            Some(Function::new_with_env(module.store(), ty, env, replay_call))
+        })
+    }
+}
+
+/// An imported function.
+#[derive(Debug, Clone)]
+struct Import {
+    module: String,
+    name: String,
+    index: FunctionIndex,
+}
+
+/// Resolves the imports of `module` with `resolver`, replacing the
+/// functions by the ones created by `wrap`.
+fn wrap_imports(
+    module: &Module,
+    resolver: &dyn Resolver,
+    mut wrap: impl FnMut(Import, &FunctionType, Option<Function>) -> Option<Function>,
+) -> ImportObject {
+    let store = module.store();
+    let info = module.info();
+    let mut namespaces: HashMap<String, Exports> = HashMap::new();
+    for ((module_name, name, index), import_index) in info.imports.iter() {
+        let export = resolver.resolve(*index, module_name, name);
+        let import = match import_index {
+            ImportIndex::Function(function_index) => {
+                let ty = &info.signatures[info.functions[*function_index]];
+                let function = match export {
+                    Some(Export::Function(function)) => {
+                        Some(Function::from_vm_export(store, function))
+                    }
+                    _ => None,
+                };
+                let import = Import {
+                    module: module_name.clone(),
+                    name: name.clone(),
+                    index: *function_index,
+                };
+                wrap(import, ty, function).map(Extern::Function)
+            }
+            _ => export.map(|export| Extern::from_vm_export(store, export)),
+        };
+        if let Some(import) = import {
+            namespaces
+                .entry(module_name.clone())
+                .or_insert_with(Exports::new)
+                .insert(name.clone(), import);
+        }
+    }
+
+    let mut import_object = ImportObject::new();
+    for (name, namespace) in namespaces {
+        import_object.register(name, namespace);
+    }
+    import_object
+}
+
+#[derive(Clone)]
+struct RecordEnv {
+    log: Arc<Mutex<Box<dyn Write + Send>>>,
+    module: Module,
+    import: Import,
+    function: Function,
+    memory: LazyInit<Memory>,
+    /// The wrapped function, prepared to be called by the instance.
+    imported: Option<Arc<ImportedFunction>>,
+}
+
+impl WasmerEnv for RecordEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        if let Ok(memory) = instance.exports.get_memory("memory") {
+            self.memory.initialize(memory.clone());
+        }
+        self.imported = Some(Arc::new(ImportedFunction::new(
+            self.function.clone(),
+            &self.module,
+            self.import.index,
+            instance,
+        )?));
+        Ok(())
+    }
+}
+
+fn record_call(env: &RecordEnv, params: &[Val]) -> Result<Vec<Val>, RuntimeError> {
+    let imported = env
+        .imported
+        .as_ref()
+        .expect("the recorded function should be imported by an instance");
+    let memory = env.memory.get_ref();
+    let before = memory.map(|memory| unsafe { memory.data_unchecked() }.to_vec());
+
+    let result = imported.call(params);
+
+    let mut call = HostCall {
+        module: env.import.module.clone(),
+        name: env.import.name.clone(),
+        params: params.iter().map(record_value).collect::<Result<_, _>>()?,
+        outcome: match &result {
+            Ok(results) => HostCallOutcome::Returned(
+                results.iter().map(record_value).collect::<Result<_, _>>()?,
+            ),
+            Err(error) => HostCallOutcome::Trapped(error.message()),
+        },
+        memory_pages: None,
+        memory_writes: Vec::new(),
+    };
+    if let (Some(memory), Some(before)) = (memory, before) {
+        let after = unsafe { memory.data_unchecked() };
+        if after.len() != before.len() {
+            call.memory_pages = Some(memory.size().0);
+        }
+        call.memory_writes = memory_writes(&before, after);
+    }
+
+    let mut log = env.log.lock().unwrap();
+    bincode::serialize_into(&mut *log, &call)
+        .and_then(|()| Ok(log.flush()?))
+        .map_err(|error| {
+            RuntimeError::new(format!(
+                "failed to record the call to `{}.{}`: {}",
+                call.module, call.name, error
+            ))
+        })?;
+
+    result.map(Vec::from)
+}
+
+#[derive(Clone)]
+struct ReplayEnv {
+    log: Arc<Mutex<Box<dyn Read + Send>>>,
+    import: Import,
+    memory: LazyInit<Memory>,
+}
+
+impl WasmerEnv for ReplayEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        if let Ok(memory) = instance.exports.get_memory("memory") {
+            self.memory.initialize(memory.clone());
+        }
+        Ok(())
+    }
+}
+
+fn replay_call(env: &ReplayEnv, params: &[Val]) -> Result<Vec<Val>, RuntimeError> {
+    let call: HostCall = {
+        let mut log = env.log.lock().unwrap();
+        bincode::deserialize_from(&mut *log).map_err(|error| {
+            RuntimeError::new(format!(
+                "no recorded call to replay for `{}.{}`: {}",
+                env.import.module, env.import.name, error
+            ))
+        })?
+    };
+
+    let params = params
+        .iter()
+        .map(record_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    if call.module != env.import.module || call.name != env.import.name || call.params != params {
+        return Err(RuntimeError::new(format!(
+            "replay diverged: `{}.{}` was called with {:?}, but the recording has a call to `{}.{}` with {:?}",
+            env.import.module, env.import.name, params, call.module, call.name, call.params
+        )));
+    }
+
+    if let Some(memory) = env.memory.get_ref() {
+        if let Some(pages) = call.memory_pages {
+            let current = memory.size().0;
+            if pages > current {
+                memory
+                    .grow(pages - current)
+                    .map_err(|error| RuntimeError::new(error.to_string()))?;
+            }
+        }
+        let data = unsafe { memory.data_unchecked_mut() };
+        for write in &call.memory_writes {
+            let start = write.offset as usize;
+            data.get_mut(start..start + write.bytes.len())
+                .ok_or_else(|| RuntimeError::new("recorded memory write out of bounds"))?
+                .copy_from_slice(&write.bytes);
+        }
+    }
+
+    match call.outcome {
+        HostCallOutcome::Returned(results) => Ok(results.into_iter().map(replay_value).collect()),
+        HostCallOutcome::Trapped(message) => Err(RuntimeError::new(message)),
+    }
+}
+
+fn record_value(value: &Val) -> Result<SnapshotValue, RuntimeError> {
+    Ok(match value {
+        Val::I32(value) => SnapshotValue::I32(*value),
+        Val::I64(value) => SnapshotValue::I64(*value),
+        Val::F32(value) => SnapshotValue::F32(value.to_bits()),
+        Val::F64(value) => SnapshotValue::F64(value.to_bits()),
+        Val::V128(value) => SnapshotValue::V128(*value),
+        Val::FuncRef(None) => SnapshotValue::FuncRef(None),
+        _ => return Err(RuntimeError::new("reference values can't be recorded")),
+    })
+}
+
+fn replay_value(value: SnapshotValue) -> Val {
+    match value {
+        SnapshotValue::I32(value) => Val::I32(value),
+        SnapshotValue::I64(value) => Val::I64(value),
+        SnapshotValue::F32(bits) => Val::F32(f32::from_bits(bits)),
+        SnapshotValue::F64(bits) => Val::F64(f64::from_bits(bits)),
+        SnapshotValue::V128(value) => Val::V128(value),
+        SnapshotValue::FuncRef(_) => Val::FuncRef(None),
+    }
+}
+
+/// Returns the ranges of `after` that differ from `before`, where bytes
+/// past the end of `before` are compared to zero.
+fn memory_writes(before: &[u8], after: &[u8]) -> Vec<MemoryWrite> {
+    let old = |offset: usize| before.get(offset).copied().unwrap_or(0);
+    let mut writes = Vec::new();
+    let mut offset = 0;
+    while offset < after.len() {
+        if offset % BLOCK_SIZE == 0 {
+            let end = (offset + BLOCK_SIZE).min(after.len());
+            if end <= before.len() && before[offset..end] == after[offset..end] {
+                offset = end;
+                continue;
+            }
+        }
+        if old(offset) == after[offset] {
+            offset += 1;
+            continue;
+        }
+
+        let start = offset;
+        let mut end = offset + 1;
+        offset += 1;
+        while offset < after.len() && offset - end < MIN_WRITE_GAP {
+            if old(offset) != after[offset] {
+                end = offset + 1;
+            }
+            offset += 1;
+        }
+        writes.push(MemoryWrite {
+            offset: start as u64,
+            bytes: after[start..end].to_vec(),
+        });
+        offset = end;
+    }
+    writes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changed_memory_ranges() {
+        let before = vec![0; 2 * BLOCK_SIZE];
+        let mut after = before.clone();
+        after[3] = 1;
+        after[5] = 2;
+        after[20] = 3;
+        after.extend_from_slice(&[0, 0, 4]);
+
+        let writes = memory_writes(&before, &after);
+        assert_eq!(
+            writes,
+            vec![
+                MemoryWrite {
+                    offset: 3,
+                    bytes: vec![1, 0, 2]
+                },
+                MemoryWrite {
+                    offset: 20,
+                    bytes: vec![3]
+                },
+                MemoryWrite {
+                    offset: 2 * BLOCK_SIZE as u64 + 2,
+                    bytes: vec![4]
+                },
+            ]
+        );
+        assert!(memory_writes(&after, &after).is_empty());
+    }
+}
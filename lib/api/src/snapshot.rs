@@ -0,0 +1,238 @@
+//! Snapshots of the state of an [`Instance`], see [`Instance::snapshot`].
+//!
+//! [`Instance`]: crate::Instance
+//! [`Instance::snapshot`]: crate::Instance::snapshot
+
+use crate::externals::{Function, Global, Memory, Table};
+use crate::instance::InstantiationError;
+use crate::types::Val;
+use crate::RuntimeError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+use wasmer_types::{Mutability, Pages, WASM_PAGE_SIZE};
+use wasmer_vm::MemoryError;
+
+/// A serializable image of the state of an [`Instance`](crate::Instance): the contents of
+/// its memories, the values of its mutable globals and the elements of its
+/// tables.
+///
+/// Every item of the instance is included, whether it's exported, imported
+/// or internal to the instance.  Function references are encoded by the
+/// [`FunctionIndex`](wasmer_types::FunctionIndex) of the function in the
+/// module, so a snapshot can only be restored with the module it was taken
+/// from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceSnapshot {
+    /// The contents of the memories, by memory index.
+    pub memories: Vec<Vec<u8>>,
+    /// The values of the globals, by global index.  Immutable globals are
+    /// `None` as they can't change after instantiation.
+    pub globals: Vec<Option<SnapshotValue>>,
+    /// The elements of the tables, by table index.  Each element is the
+    /// function index of the function it references, or `None` for a null
+    /// reference.
+    pub tables: Vec<Vec<Option<u32>>>,
+}
+
+/// The value of a global in an [`InstanceSnapshot`].
+///
+/// Floats are stored as their bit patterns so that NaNs are preserved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SnapshotValue {
+    /// A 32-bit integer.
+    I32(i32),
+    /// A 64-bit integer.
+    I64(i64),
+    /// The bits of a 32-bit float.
+    F32(u32),
+    /// The bits of a 64-bit float.
+    F64(u64),
+    /// A 128-bit number.
+    V128(u128),
+    /// A function reference, by function index.
+    FuncRef(Option<u32>),
+}
+
+/// An error while taking or restoring an [`InstanceSnapshot`].
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    /// An `externref` was found in a global or a table.  The host data they
+    /// point to can't be part of a snapshot.
+    #[error("externref values can't be snapshotted")]
+    ExternRef,
+
+    /// A table or global references a function which isn't a function of
+    /// the instance, so it can't be encoded by index.
+    #[error("reference to a function that isn't part of the instance")]
+    UnknownFunction,
+
+    /// The snapshot doesn't match the module it's being restored with.
+    #[error("snapshot doesn't match the module: {0}")]
+    Incompatible(String),
+
+    /// The instance to restore the snapshot in could not be created.
+    #[error(transparent)]
+    Instantiation(#[from] InstantiationError),
+
+    /// A memory of the instance couldn't be grown to the size in the snapshot.
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+
+    /// A global or table of the instance couldn't be set.
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+}
+
+/// The memories, globals, tables and functions of an instance, exported or
+/// not, in index order.
+pub(crate) struct InstanceItems {
+    pub(crate) memories: Vec<Memory>,
+    pub(crate) globals: Vec<Global>,
+    pub(crate) tables: Vec<Table>,
+    pub(crate) functions: Vec<Function>,
+}
+
+impl InstanceItems {
+    /// Maps the address and environment of every function to its index.
+    fn function_indices(&self) -> HashMap<(usize, usize), u32> {
+        self.functions
+            .iter()
+            .enumerate()
+            .map(|(index, function)| (function_key(function), index as u32))
+            .collect()
+    }
+
+    fn function(&self, index: u32) -> Result<&Function, SnapshotError> {
+        self.functions.get(index as usize).ok_or_else(|| {
+            SnapshotError::Incompatible(format!("function index {} out of bounds", index))
+        })
+    }
+
+    pub(crate) fn snapshot(&self) -> Result<InstanceSnapshot, SnapshotError> {
+        let function_indices = self.function_indices();
+        let function_index = |val: Val| -> Result<Option<u32>, SnapshotError> {
+            match val {
+                Val::FuncRef(None) => Ok(None),
+                Val::FuncRef(Some(function)) => function_indices
+                    .get(&function_key(&function))
+                    .copied()
+                    .map(Some)
+                    .ok_or(SnapshotError::UnknownFunction),
+                _ => Err(SnapshotError::ExternRef),
+            }
+        };
+
+        let memories = self
+            .memories
+            .iter()
+            // Safety: there is no concurrent access to the memory while we copy it
+            .map(|memory| unsafe { memory.data_unchecked().to_vec() })
+            .collect();
+        let globals = self
+            .globals
+            .iter()
+            .map(|global| {
+                if global.ty().mutability == Mutability::Const {
+                    return Ok(None);
+                }
+                Ok(Some(match global.get() {
+                    Val::I32(value) => SnapshotValue::I32(value),
+                    Val::I64(value) => SnapshotValue::I64(value),
+                    Val::F32(value) => SnapshotValue::F32(value.to_bits()),
+                    Val::F64(value) => SnapshotValue::F64(value.to_bits()),
+                    Val::V128(value) => SnapshotValue::V128(value),
+                    reference => SnapshotValue::FuncRef(function_index(reference)?),
+                }))
+            })
+            .collect::<Result<_, SnapshotError>>()?;
+        let tables = self
+            .tables
+            .iter()
+            .map(|table| {
+                (0..table.size())
+                    .map(|index| function_index(table.get(index).expect("index in bounds")))
+                    .collect()
+            })
+            .collect::<Result<_, SnapshotError>>()?;
+
+        Ok(InstanceSnapshot {
+            memories,
+            globals,
+            tables,
+        })
+    }
+
+    pub(crate) fn restore(&self, snapshot: &InstanceSnapshot) -> Result<(), SnapshotError> {
+        if snapshot.memories.len() != self.memories.len()
+            || snapshot.globals.len() != self.globals.len()
+            || snapshot.tables.len() != self.tables.len()
+        {
+            return Err(SnapshotError::Incompatible(
+                "the number of memories, globals or tables differs".to_string(),
+            ));
+        }
+        let function_ref = |index: Option<u32>| -> Result<Val, SnapshotError> {
+            Ok(Val::FuncRef(match index {
+                Some(index) => Some(self.function(index)?.clone()),
+                None => None,
+            }))
+        };
+
+        for (memory, data) in self.memories.iter().zip(snapshot.memories.iter()) {
+            let current_size = memory.data_size() as usize;
+            if data.len() < current_size || data.len() % WASM_PAGE_SIZE != 0 {
+                return Err(SnapshotError::Incompatible(format!(
+                    "memory of {} bytes can't be restored in a memory of {} bytes",
+                    data.len(),
+                    current_size
+                )));
+            }
+            memory.grow(Pages(((data.len() - current_size) / WASM_PAGE_SIZE) as u32))?;
+            // Safety: there is no concurrent access to the memory while we copy into it
+            unsafe { memory.data_unchecked_mut() }.copy_from_slice(data);
+        }
+
+        for (global, value) in self.globals.iter().zip(snapshot.globals.iter()) {
+            let value = match value {
+                Some(value) => *value,
+                None => continue,
+            };
+            global.set(match value {
+                SnapshotValue::I32(value) => Val::I32(value),
+                SnapshotValue::I64(value) => Val::I64(value),
+                SnapshotValue::F32(bits) => Val::F32(f32::from_bits(bits)),
+                SnapshotValue::F64(bits) => Val::F64(f64::from_bits(bits)),
+                SnapshotValue::V128(value) => Val::V128(value),
+                SnapshotValue::FuncRef(index) => function_ref(index)?,
+            })?;
+        }
+
+        for (table, elements) in self.tables.iter().zip(snapshot.tables.iter()) {
+            let size = table.size() as usize;
+            if elements.len() > size {
+                table.grow((elements.len() - size) as u32, Val::FuncRef(None))?;
+            } else if elements.len() < size {
+                return Err(SnapshotError::Incompatible(format!(
+                    "table of {} elements can't be restored in a table of {} elements",
+                    elements.len(),
+                    size
+                )));
+            }
+            for (index, element) in elements.iter().enumerate() {
+                table.set(index as u32, function_ref(*element)?)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Identifies a function by its address and environment, as function
+/// references read from tables don't carry any other information.
+fn function_key(function: &Function) -> (usize, usize) {
+    let vm_function = &function.exported.vm_function;
+    (vm_function.address as usize, unsafe {
+        vm_function.vmctx.host_env as usize
+    })
+}
@@ -1,4 +1,6 @@
+use crate::events::{EventTunables, SharedEvents, StoreEvents};
 use crate::tunables::BaseTunables;
+use crate::RuntimeError;
 use loupe::MemoryUsage;
 use std::any::Any;
 use std::fmt;
@@ -24,6 +26,8 @@ pub struct Store {
     tunables: Arc<dyn Tunables + Send + Sync>,
     #[loupe(skip)]
     trap_handler: Arc<RwLock<Option<Box<TrapHandlerFn>>>>,
+    #[loupe(skip)]
+    events: SharedEvents,
 }
 
 impl Store {
@@ -41,6 +45,29 @@ impl Store {
         *m = handler;
     }
 
+    /// Set the [`StoreEvents`] notified of the lifecycle events of this
+    /// store, replacing the previous ones.
+    ///
+    /// The events are shared by all the clones of the store, and apply to
+    /// the instances, memories and tables already created in it.
+    pub fn set_events(&self, events: Option<Arc<dyn StoreEvents>>) {
+        self.events.set(events);
+    }
+
+    /// Returns the [`StoreEvents`] of this store, if any.
+    pub(crate) fn events(&self) -> Option<Arc<dyn StoreEvents>> {
+        self.events.get()
+    }
+
+    /// Notifies the events of this store that a call into WebAssembly
+    /// failed with `error`, and returns it.
+    pub(crate) fn trap_raised(&self, error: RuntimeError) -> RuntimeError {
+        if let Some(events) = self.events() {
+            events.trap_raised(&error);
+        }
+        error
+    }
+
     /// Creates a new `Store` with a specific [`Engine`] and [`Tunables`].
     pub fn new_with_tunables<E>(engine: &E, tunables: impl Tunables + Send + Sync + 'static) -> Self
     where
@@ -50,10 +77,12 @@ impl Store {
         // This is required for handling traps.
         init_traps(is_wasm_pc);
 
+        let events = SharedEvents::default();
         Self {
             engine: engine.cloned(),
-            tunables: Arc::new(tunables),
+            tunables: Arc::new(EventTunables::new(tunables, events.clone())),
             trap_handler: Arc::new(RwLock::new(None)),
+            events,
         }
     }
 
@@ -4,12 +4,12 @@ use std::cmp::min;
 use std::ptr::NonNull;
 use std::sync::Arc;
 use target_lexicon::{OperatingSystem, PointerWidth};
-use wasmer_compiler::Target;
+use wasmer_compiler::{ModuleLimits, Target};
 use wasmer_engine::Tunables;
 use wasmer_vm::MemoryError;
 use wasmer_vm::{
-    LinearMemory, LinearTable, Memory, MemoryStyle, Table, TableStyle, VMMemoryDefinition,
-    VMTableDefinition,
+    LinearMemory, LinearTable, Memory, MemoryStyle, ResourceLimiter, Table, TableStyle,
+    VMMemoryDefinition, VMTableDefinition,
 };
 
 /// Tunable parameters for WebAssembly compilation.
@@ -30,6 +30,14 @@ pub struct BaseTunables {
 
     /// The size in bytes of the offset guard for dynamic heaps.
     pub dynamic_memory_offset_guard_size: u64,
+
+    /// The limits the modules must satisfy to be validated and compiled.
+    pub module_limits: Option<ModuleLimits>,
+
+    /// The limiter consulted when creating instances, and when creating
+    /// and growing memories and tables.
+    #[loupe(skip)]
+    pub resource_limiter: Option<Arc<dyn ResourceLimiter>>,
 }
 
 impl BaseTunables {
@@ -65,6 +73,8 @@ impl BaseTunables {
             static_memory_bound,
             static_memory_offset_guard_size,
             dynamic_memory_offset_guard_size,
+            module_limits: None,
+            resource_limiter: None,
         }
     }
 }
@@ -95,13 +105,26 @@ impl Tunables for BaseTunables {
         TableStyle::CallerChecksSignature
     }
 
+    /// Get the [`ModuleLimits`], if any.
+    fn module_limits(&self) -> Option<&ModuleLimits> {
+        self.module_limits.as_ref()
+    }
+
+    /// Get the [`ResourceLimiter`], if any.
+    fn resource_limiter(&self) -> Option<&Arc<dyn ResourceLimiter>> {
+        self.resource_limiter.as_ref()
+    }
+
     /// Create a memory owned by the host given a [`MemoryType`] and a [`MemoryStyle`].
     fn create_host_memory(
         &self,
         ty: &MemoryType,
         style: &MemoryStyle,
     ) -> Result<Arc<dyn Memory>, MemoryError> {
-        Ok(Arc::new(LinearMemory::new(&ty, &style)?))
+        Ok(Arc::new(match &self.resource_limiter {
+            Some(limiter) => LinearMemory::new_with_limiter(&ty, &style, limiter.clone())?,
+            None => LinearMemory::new(&ty, &style)?,
+        }))
     }
 
     /// Create a memory owned by the VM given a [`MemoryType`] and a [`MemoryStyle`].
@@ -115,11 +138,15 @@ impl Tunables for BaseTunables {
         style: &MemoryStyle,
         vm_definition_location: NonNull<VMMemoryDefinition>,
     ) -> Result<Arc<dyn Memory>, MemoryError> {
-        Ok(Arc::new(LinearMemory::from_definition(
-            &ty,
-            &style,
-            vm_definition_location,
-        )?))
+        Ok(Arc::new(match &self.resource_limiter {
+            Some(limiter) => LinearMemory::from_definition_with_limiter(
+                &ty,
+                &style,
+                vm_definition_location,
+                limiter.clone(),
+            )?,
+            None => LinearMemory::from_definition(&ty, &style, vm_definition_location)?,
+        }))
     }
 
     /// Create a table owned by the host given a [`TableType`] and a [`TableStyle`].
@@ -128,7 +155,11 @@ impl Tunables for BaseTunables {
         ty: &TableType,
         style: &TableStyle,
     ) -> Result<Arc<dyn Table>, String> {
-        Ok(Arc::new(LinearTable::new(&ty, &style)?))
+        Ok(Arc::new(match &self.resource_limiter {
+            Some(limiter) => LinearTable::new_with_limiter(&ty, &style, limiter.clone())
+                .map_err(|e| e.to_string())?,
+            None => LinearTable::new(&ty, &style)?,
+        }))
     }
 
     /// Create a table owned by the VM given a [`TableType`] and a [`TableStyle`].
@@ -142,11 +173,16 @@ impl Tunables for BaseTunables {
         style: &TableStyle,
         vm_definition_location: NonNull<VMTableDefinition>,
     ) -> Result<Arc<dyn Table>, String> {
-        Ok(Arc::new(LinearTable::from_definition(
-            &ty,
-            &style,
-            vm_definition_location,
-        )?))
+        Ok(Arc::new(match &self.resource_limiter {
+            Some(limiter) => LinearTable::from_definition_with_limiter(
+                &ty,
+                &style,
+                vm_definition_location,
+                limiter.clone(),
+            )
+            .map_err(|e| e.to_string())?,
+            None => LinearTable::from_definition(&ty, &style, vm_definition_location)?,
+        }))
     }
 }
 
@@ -160,6 +196,8 @@ mod tests {
             static_memory_bound: Pages(2048),
             static_memory_offset_guard_size: 128,
             dynamic_memory_offset_guard_size: 256,
+            module_limits: None,
+            resource_limiter: None,
         };
 
         // No maximum
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use wasmer::*;
+
+#[derive(Debug, PartialEq)]
+struct NotFound(u32);
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no line {}", self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+#[derive(Clone, Default, WasmerEnv)]
+struct Logger {
+    #[wasmer(export)]
+    memory: LazyInit<Memory>,
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+#[host_module(namespace = "logger", memory = "memory")]
+impl Logger {
+    fn log(&self, line: &str) {
+        self.lines.lock().unwrap().push(line.to_string());
+    }
+
+    fn line_len(&self, index: u32) -> Result<u32, NotFound> {
+        let lines = self.lines.lock().unwrap();
+        let line = lines.get(index as usize).ok_or(NotFound(index))?;
+        Ok(line.len() as u32)
+    }
+
+    fn upper(&self, bytes: &mut [u8]) {
+        bytes.make_ascii_uppercase();
+    }
+
+    fn upper_line(&self, index: u32, bytes: &mut [u8]) -> Result<(), NotFound> {
+        bytes.make_ascii_uppercase();
+        if index as usize >= self.lines.lock().unwrap().len() {
+            return Err(NotFound(index));
+        }
+        Ok(())
+    }
+
+    fn first_byte(&self, ptr: WasmPtr<u8>) -> u32 {
+        let memory = self.memory.get_ref().unwrap();
+        ptr.deref(memory).unwrap().get() as u32
+    }
+
+    // Not a host function.
+    #[allow(dead_code)]
+    fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[test]
+fn host_module_imports() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"
+    (module
+      (import "logger" "log" (func $log (param i32 i32)))
+      (import "logger" "line_len" (func $line_len (param i32) (result i32)))
+      (import "logger" "upper" (func $upper (param i32 i32)))
+      (import "logger" "first_byte" (func $first_byte (param i32) (result i32)))
+      (import "logger" "upper_line" (func $upper_line (param i32 i32 i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 16) "hello")
+      (data (i32.const 32) "world")
+      (func (export "run") (result i32)
+        (call $log (i32.const 16) (i32.const 5))
+        (call $upper (i32.const 16) (i32.const 5))
+        (call $log (i32.const 16) (i32.const 5))
+        (i32.add (call $line_len (i32.const 1)) (call $first_byte (i32.const 16))))
+      (func (export "missing") (result i32)
+        (call $line_len (i32.const 7)))
+      (func (export "upper_missing")
+        (call $upper_line (i32.const 7) (i32.const 32) (i32.const 5)))
+      (func (export "out_of_bounds")
+        (call $log (i32.const 65534) (i32.const 5))))
+"#,
+    )?;
+    let logger = Logger::default();
+    assert_eq!(Logger::NAMESPACE, "logger");
+    let instance = Instance::new(&module, &logger.imports(&store))?;
+
+    let run = instance.exports.get_native_function::<(), i32>("run")?;
+    assert_eq!(run.call()?, 5 + b'H' as i32);
+    assert_eq!(*logger.lines.lock().unwrap(), ["hello", "HELLO"]);
+
+    let missing = instance.exports.get_native_function::<(), i32>("missing")?;
+    let error = missing.call().unwrap_err();
+    assert_eq!(error.downcast::<NotFound>().unwrap(), NotFound(7));
+
+    // the buffer isn't written back when the call fails
+    let upper_missing = instance
+        .exports
+        .get_native_function::<(), ()>("upper_missing")?;
+    assert!(upper_missing.call().is_err());
+    let memory = instance.exports.get_memory("memory")?;
+    let world: Vec<u8> = memory.view::<u8>()[32..37]
+        .iter()
+        .map(|cell| cell.get())
+        .collect();
+    assert_eq!(world, b"world");
+
+    let out_of_bounds = instance
+        .exports
+        .get_native_function::<(), ()>("out_of_bounds")?;
+    assert!(out_of_bounds.call().is_err());
+    assert_eq!(logger.lines.lock().unwrap().len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn host_module_default_namespace() -> Result<()> {
+    #[derive(Clone, Default, WasmerEnv)]
+    struct Math;
+
+    #[host_module]
+    impl Math {
+        fn double(&self, x: i32) -> i32 {
+            x * 2
+        }
+    }
+
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"
+    (module
+      (import "env" "double" (func $double (param i32) (result i32)))
+      (func (export "run") (result i32)
+        (call $double (i32.const 21))))
+"#,
+    )?;
+    let instance = Instance::new(&module, &Math.imports(&store))?;
+    let run = instance.exports.get_native_function::<(), i32>("run")?;
+    assert_eq!(run.call()?, 42);
+
+    Ok(())
+}
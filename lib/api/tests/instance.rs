@@ -37,3 +37,179 @@ fn exports_work_after_multiple_instances_have_been_freed() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn snapshot_and_restore() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        "
+    (module
+      (type $ret_t (func (result i32)))
+      (memory 1)
+      (global $counter (mut i32) (i32.const 0))
+      (table (export \"table\") 2 funcref)
+      (elem (i32.const 0) $ten $twenty)
+      (func $ten (type $ret_t) i32.const 10)
+      (func $twenty (type $ret_t) i32.const 20)
+      (func (export \"mutate\")
+        (i32.store (i32.const 65540) (i32.const 42))
+        (global.set $counter (i32.add (global.get $counter) (i32.const 1))))
+      (func (export \"grow\") (drop (memory.grow (i32.const 1))))
+      (func (export \"load\") (result i32) (i32.load (i32.const 65540)))
+      (func (export \"counter\") (result i32) (global.get $counter))
+      (func (export \"call_first\") (result i32) (call_indirect (type $ret_t) (i32.const 0))))
+",
+    )?;
+
+    let import_object = ImportObject::new();
+    let instance = Instance::new(&module, &import_object)?;
+    instance.exports.get_function("grow")?.call(&[])?;
+    instance.exports.get_function("mutate")?.call(&[])?;
+    let table = instance.exports.get_table("table")?;
+    table.set(0, table.get(1).unwrap())?;
+
+    let snapshot = instance.snapshot()?;
+    assert_eq!(snapshot.memories[0].len(), 2 * WASM_PAGE_SIZE);
+    assert_eq!(snapshot.globals, vec![Some(SnapshotValue::I32(1))]);
+    assert_eq!(snapshot.tables, vec![vec![Some(1), Some(1)]]);
+
+    let restored = Instance::restore(&module, &import_object, &snapshot)?;
+    let call = |name: &str| -> Result<Box<[Value]>> {
+        Ok(restored.exports.get_function(name)?.call(&[])?)
+    };
+    assert_eq!(call("load")?.into_vec(), vec![Value::I32(42)]);
+    assert_eq!(call("counter")?.into_vec(), vec![Value::I32(1)]);
+    assert_eq!(call("call_first")?.into_vec(), vec![Value::I32(20)]);
+    assert_eq!(restored.snapshot()?, snapshot);
+
+    Ok(())
+}
+
+#[test]
+fn preinitialize() -> Result<()> {
+    let store = Store::default();
+    let wasm = wat2wasm(
+        br#"
+    (module
+      (memory 1)
+      (global $counter (mut i32) (i32.const 0))
+      (global $ten i64 (i64.const 10))
+      (data (i32.const 0) "unchanged")
+      (func $start (global.set $counter (i32.const 1)))
+      (start $start)
+      (func (export "init")
+        (drop (memory.grow (i32.const 1)))
+        (i32.store (i32.const 65540) (i32.const 42))
+        (global.set $counter (i32.add (global.get $counter) (i32.const 1))))
+      (func (export "load") (result i32) (i32.load (i32.const 65540)))
+      (func (export "counter") (result i32) (global.get $counter))
+      (func (export "ten") (result i64) (global.get $ten)))
+"#,
+    )?;
+    let module = Module::new(&store, &wasm)?;
+
+    let import_object = ImportObject::new();
+    let preinitialized = Preinitializer::new("init").run(&module, &wasm, &import_object)?;
+
+    let module = Module::new(&store, &preinitialized)?;
+    let instance = Instance::new(&module, &import_object)?;
+    let call = |name: &str| -> Result<Box<[Value]>> {
+        Ok(instance.exports.get_function(name)?.call(&[])?)
+    };
+    assert!(instance.exports.get_function("init").is_err());
+    assert_eq!(call("load")?.into_vec(), vec![Value::I32(42)]);
+    assert_eq!(call("counter")?.into_vec(), vec![Value::I32(2)]);
+    assert_eq!(call("ten")?.into_vec(), vec![Value::I64(10)]);
+    let snapshot = instance.snapshot()?;
+    assert_eq!(snapshot.memories[0].len(), 2 * WASM_PAGE_SIZE);
+    assert_eq!(&snapshot.memories[0][..9], b"unchanged");
+
+    Ok(())
+}
+
+#[test]
+fn record_and_replay() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        wat2wasm(
+            br#"
+    (module
+      (import "env" "fill" (func $fill (param i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "run") (result i32)
+        (i32.add
+          (call $fill (i32.const 16) (i32.const 4))
+          (i32.load (i32.const 16)))))
+"#,
+        )?,
+    )?;
+
+    #[derive(WasmerEnv, Clone)]
+    struct Env {
+        #[wasmer(export)]
+        memory: LazyInit<Memory>,
+    }
+
+    fn fill(env: &Env, offset: u32, len: u32) -> u32 {
+        let view = env.memory_ref().unwrap().view::<u8>();
+        for cell in &view[offset as usize..(offset + len) as usize] {
+            cell.set(7);
+        }
+        len
+    }
+
+    let log = tempfile::NamedTempFile::new()?;
+    let import_object = imports! {
+        "env" => {
+            "fill" => Function::new_native_with_env(&store, Env { memory: LazyInit::new() }, fill),
+        },
+    };
+    let recorder = Recorder::new(log.reopen()?);
+    let instance = Instance::new(&module, &recorder.wrap_imports(&module, &import_object))?;
+    let run = instance.exports.get_function("run")?;
+    assert_eq!(run.call(&[])?.into_vec(), vec![Value::I32(4 + 0x0707_0707)]);
+
+    // the host isn't needed anymore
+    let replayer = Replayer::new(log.reopen()?);
+    let instance = Instance::new(&module, &replayer.wrap_imports(&module, &imports! {}))?;
+    let run = instance.exports.get_function("run")?;
+    assert_eq!(run.call(&[])?.into_vec(), vec![Value::I32(4 + 0x0707_0707)]);
+    assert!(run.call(&[]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn coredump() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"
+    (module
+      (memory 1)
+      (global $counter (mut i32) (i32.const 0))
+      (func $fail
+        (global.set $counter (i32.const 42))
+        (i32.store (i32.const 16) (i32.const 0x01020304))
+        unreachable)
+      (func (export "run")
+        call $fail))
+"#,
+    )?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let error = instance.exports.get_function("run")?.call(&[]).unwrap_err();
+    let coredump = instance.coredump(&error, "test.wasm");
+
+    // the core dump is a module holding the state of the instance
+    Module::validate(&store, &coredump)?;
+    let contains = |bytes: &[u8]| coredump.windows(bytes.len()).any(|window| window == bytes);
+    assert!(contains(b"\x09corestack"));
+    assert!(contains(b"\x09test.wasm"));
+    assert!(contains(&[0x04, 0x03, 0x02, 0x01]));
+    // i32.const 42
+    assert!(contains(&[0x41, 0x2a, 0x0b]));
+
+    Ok(())
+}
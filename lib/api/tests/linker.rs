@@ -0,0 +1,146 @@
+use anyhow::Result;
+use wasmer::*;
+
+#[test]
+fn links_instances_by_name() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    linker.define("env", "offset", Global::new(&store, Value::I32(10)))?;
+
+    let counter = Module::new(
+        &store,
+        "
+    (module
+      (import \"env\" \"offset\" (global $offset i32))
+      (global $count (mut i32) (i32.const 0))
+      (func (export \"increment\") (result i32)
+        (global.set $count (i32.add (global.get $count) (i32.const 1)))
+        (i32.add (global.get $offset) (global.get $count))))
+",
+    )?;
+    linker.register_module("counter", counter);
+    linker.instantiate_module("counter")?;
+
+    // Both instances share the `counter` instance.
+    let client = Module::new(
+        &store,
+        "
+    (module
+      (import \"counter\" \"increment\" (func $increment (result i32)))
+      (func (export \"run\") (result i32)
+        (call $increment)))
+",
+    )?;
+    let first = linker.instantiate(&client)?;
+    let second = linker.instantiate(&client)?;
+    let run = first.exports.get_native_function::<(), i32>("run")?;
+    assert_eq!(run.call()?, 11);
+    let run = second.exports.get_native_function::<(), i32>("run")?;
+    assert_eq!(run.call()?, 12);
+
+    assert!(matches!(
+        linker.instantiate_module("missing"),
+        Err(LinkerError::UnknownModule(name)) if name == "missing"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn shadowing_policies() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    linker.define("env", "x", Global::new(&store, Value::I32(1)))?;
+
+    assert!(matches!(
+        linker.define("env", "x", Global::new(&store, Value::I32(2))),
+        Err(LinkerError::AlreadyDefined(module, name)) if module == "env" && name == "x"
+    ));
+    assert_eq!(
+        linker.get("env", "x").unwrap().ty(),
+        ExternType::Global(GlobalType::new(Type::I32, Mutability::Const))
+    );
+
+    linker.shadowing(Shadowing::Allow);
+    linker.define("env", "x", Global::new(&store, Value::I64(2)))?;
+    assert_eq!(
+        linker.get("env", "x").unwrap().ty(),
+        ExternType::Global(GlobalType::new(Type::I64, Mutability::Const))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn checks_exports_before_instantiating_modules() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    linker.define("module", "x", Global::new(&store, Value::I32(1)))?;
+
+    // The start function would trap if the module was instantiated.
+    let module = Module::new(
+        &store,
+        "
+    (module
+      (global (export \"x\") i32 (i32.const 2))
+      (func $start unreachable)
+      (start $start))
+",
+    )?;
+    linker.register_module("module", module.clone());
+    assert!(matches!(
+        linker.instantiate_module("module"),
+        Err(LinkerError::AlreadyDefined(module, name)) if module == "module" && name == "x"
+    ));
+
+    let other_store = Store::default();
+    let mut other_linker = Linker::new(&other_store);
+    other_linker.register_module("module", module);
+    assert!(matches!(
+        other_linker.instantiate_module("module"),
+        Err(LinkerError::WrongStore(module, name)) if module == "module" && name == "x"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn reports_all_unresolved_imports() -> Result<()> {
+    let store = Store::default();
+    let mut linker = Linker::new(&store);
+    linker.define(
+        "env",
+        "memory",
+        Memory::new(&store, MemoryType::new(1, None, false))?,
+    )?;
+    linker.define("env", "global", Global::new(&store, Value::I64(0)))?;
+    linker.define("env", "ok", Function::new_native(&store, || {}))?;
+
+    let module = Module::new(
+        &store,
+        "
+    (module
+      (import \"env\" \"ok\" (func))
+      (import \"env\" \"missing\" (func))
+      (import \"env\" \"global\" (global i32))
+      (import \"env\" \"memory\" (memory 1)))
+",
+    )?;
+    let errors = match linker.instantiate(&module) {
+        Err(LinkerError::Imports(errors)) => errors,
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    };
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(
+        &errors[0],
+        LinkError::Import(module, name, ImportError::UnknownImport(_))
+            if module == "env" && name == "missing"
+    ));
+    assert!(matches!(
+        &errors[1],
+        LinkError::Import(module, name, ImportError::IncompatibleType(..))
+            if module == "env" && name == "global"
+    ));
+
+    Ok(())
+}
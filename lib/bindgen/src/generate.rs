@@ -0,0 +1,706 @@
+//! Generating the host bindings of interfaces.
+
+use crate::parse::{Direction, Function, Interface, Type, TypeDef};
+use std::fmt::Write;
+
+const RT: &str = "::wasmer_bindgen::rt";
+const RESULT: &str = "::std::result::Result";
+const RUNTIME_ERROR: &str = "::wasmer::RuntimeError";
+
+/// The Rust keywords, which can't be used as names.
+const KEYWORDS: [&str; 51] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield", "union",
+];
+
+/// The names of the locals of the generated functions.
+const LOCALS: [&str; 3] = ["cx", "env", "result"];
+
+/// Returns the Rust name of a function, a field or a parameter.
+fn snake(name: &str) -> String {
+    let snake = name.replace('-', "_");
+    let is_local = LOCALS.contains(&snake.as_str())
+        || (snake.starts_with("arg") && snake[3..].chars().all(|c| c.is_ascii_digit()));
+    if KEYWORDS.contains(&snake.as_str()) || is_local {
+        snake + "_"
+    } else {
+        snake
+    }
+}
+
+/// Returns the Rust name of a type or a case.
+fn camel(name: &str) -> String {
+    let camel = name
+        .split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect::<String>();
+    if camel == "Self" {
+        camel + "_"
+    } else {
+        camel
+    }
+}
+
+/// Returns the Rust type of the values of `ty`.
+fn owned(ty: &Type) -> String {
+    match ty {
+        Type::Bool => "bool".to_string(),
+        Type::U8 => "u8".to_string(),
+        Type::S8 => "i8".to_string(),
+        Type::U16 => "u16".to_string(),
+        Type::S16 => "i16".to_string(),
+        Type::U32 => "u32".to_string(),
+        Type::S32 => "i32".to_string(),
+        Type::U64 => "u64".to_string(),
+        Type::S64 => "i64".to_string(),
+        Type::F32 => "f32".to_string(),
+        Type::F64 => "f64".to_string(),
+        Type::Char => "char".to_string(),
+        Type::String => "::std::string::String".to_string(),
+        Type::List(element) => format!("::std::vec::Vec<{}>", owned(element)),
+        Type::Named(name) => camel(name),
+    }
+}
+
+/// Returns the Rust type of the arguments of type `ty` passed to the guest.
+fn borrowed(ty: &Type) -> String {
+    match ty {
+        Type::String => "&str".to_string(),
+        Type::List(element) => format!("&[{}]", owned(element)),
+        Type::Named(name) => format!("&{}", camel(name)),
+        ty => owned(ty),
+    }
+}
+
+/// Returns whether `ty` is passed as a Wasm value of the same Rust type.
+fn is_number(ty: &Type) -> bool {
+    !matches!(
+        ty,
+        Type::Bool | Type::Char | Type::String | Type::List(_) | Type::Named(_)
+    )
+}
+
+/// Returns the Rust types of the Wasm values of `ty`.
+fn flat(ty: &Type) -> Vec<String> {
+    match ty {
+        Type::String | Type::List(_) => vec!["u32".to_string(), "u32".to_string()],
+        Type::Bool | Type::Char | Type::Named(_) => vec!["u32".to_string()],
+        ty => vec![owned(ty)],
+    }
+}
+
+/// Returns the Rust type of the Wasm results of `result`.
+fn flat_result(result: &Option<Type>) -> String {
+    match result {
+        None => "()".to_string(),
+        Some(ty) if is_number(ty) => owned(ty),
+        Some(_) => "u32".to_string(),
+    }
+}
+
+/// Returns the Rust type of the Wasm parameters of `function`.
+fn flat_params(function: &Function) -> String {
+    let params = function
+        .params
+        .iter()
+        .flat_map(|(_, ty)| flat(ty))
+        .collect::<Vec<_>>();
+    if params.len() == 1 {
+        params[0].clone()
+    } else {
+        format!("({})", params.join(", "))
+    }
+}
+
+/// Returns whether `ty` is passed through the memory of the guest.
+fn is_indirect(ty: &Type) -> bool {
+    matches!(ty, Type::String | Type::List(_) | Type::Named(_))
+}
+
+/// Returns the annotation of the Rust functions with `params` parameters.
+fn allow_many_params(params: usize) -> &'static str {
+    if params > 7 {
+        "    #[allow(clippy::too_many_arguments)]\n"
+    } else {
+        ""
+    }
+}
+
+fn canonical(ty: &str) -> String {
+    format!("<{} as {}::CanonicalType>", ty, RT)
+}
+
+/// What [`generate_layout`] lists of the types.
+#[derive(Clone, Copy)]
+enum Layout {
+    /// Their sizes and alignments.
+    Both,
+
+    /// Their alignments.
+    Align,
+}
+
+/// Generates the constant `name` of a `CanonicalType` impl, calling the
+/// function of `call` with the layout of `types`.
+fn generate_layout(out: &mut String, name: &str, call: &str, types: &[String], layout: Layout) {
+    if types.is_empty() {
+        writeln!(out, "    const {}: u32 = {}::{}&[]);", name, RT, call).unwrap();
+        return;
+    }
+    writeln!(out, "    const {}: u32 = {}::{}&[", name, RT, call).unwrap();
+    for ty in types {
+        let ty = canonical(ty);
+        match layout {
+            Layout::Both => writeln!(out, "        ({}::SIZE, {}::ALIGN),", ty, ty),
+            Layout::Align => writeln!(out, "        {}::ALIGN,", ty),
+        }
+        .unwrap();
+    }
+    writeln!(out, "    ]);").unwrap();
+}
+
+impl Interface {
+    /// Generates the host bindings of the interface, as Rust code.
+    ///
+    /// The code defines:
+    ///
+    /// - the records as structs and the variants as enums, implementing
+    ///   [`CanonicalType`](crate::rt::CanonicalType);
+    /// - if the guest imports functions, a `Host` trait with a method per
+    ///   function, and an `add_to_imports` function registering the
+    ///   methods of a `Host` as the imports, in the namespace named after
+    ///   the interface;
+    /// - if the guest exports functions, a `Guest` struct created from an
+    ///   [`Instance`](wasmer::Instance), with a method calling each
+    ///   function.
+    ///
+    /// The code uses the `wasmer` and `wasmer_bindgen` crates, and is meant
+    /// to be included in a module of its own.
+    pub fn generate(&self) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "// Generated by wasmer-bindgen from the `{}` interface. Do not edit.",
+            self.name
+        )
+        .unwrap();
+        for ty in &self.types {
+            out.push('\n');
+            match ty {
+                TypeDef::Record(name, fields) => self.generate_record(&mut out, name, fields),
+                TypeDef::Variant(name, cases) => self.generate_variant(&mut out, name, cases),
+            }
+        }
+
+        let imports = self
+            .functions
+            .iter()
+            .filter(|function| function.direction == Direction::Import)
+            .collect::<Vec<_>>();
+        if !imports.is_empty() {
+            out.push('\n');
+            self.generate_host(&mut out, &imports);
+        }
+
+        let exports = self
+            .functions
+            .iter()
+            .filter(|function| function.direction == Direction::Export)
+            .collect::<Vec<_>>();
+        if !exports.is_empty() {
+            out.push('\n');
+            self.generate_guest(&mut out, &exports);
+        }
+        out
+    }
+
+    fn generate_record(&self, out: &mut String, name: &str, fields: &[(String, Type)]) {
+        let ty = camel(name);
+        writeln!(out, "/// The `{}` record.", name).unwrap();
+        writeln!(out, "#[derive(Debug, Clone, PartialEq)]").unwrap();
+        writeln!(out, "pub struct {} {{", ty).unwrap();
+        for (field, field_ty) in fields {
+            writeln!(out, "    pub {}: {},", snake(field), owned(field_ty)).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+
+        let field_types = fields.iter().map(|(_, ty)| owned(ty)).collect::<Vec<_>>();
+        writeln!(out, "impl {}::CanonicalType for {} {{", RT, ty).unwrap();
+        generate_layout(out, "SIZE", "fields_size(", &field_types, Layout::Both);
+        generate_layout(out, "ALIGN", "fields_align(", &field_types, Layout::Align);
+        writeln!(out).unwrap();
+
+        writeln!(
+            out,
+            "    fn store(&self, cx: &{}::Context, ptr: u32) -> {}<(), {}> {{",
+            RT, RESULT, RUNTIME_ERROR
+        )
+        .unwrap();
+        if fields.is_empty() {
+            writeln!(out, "        let _ = (cx, ptr);").unwrap();
+        } else {
+            writeln!(out, "        let mut fields = {}::Fields::new(ptr);", RT).unwrap();
+        }
+        for (field, field_ty) in fields {
+            writeln!(
+                out,
+                "        {}::store(&self.{}, cx, fields.next::<{}>())?;",
+                canonical(&owned(field_ty)),
+                snake(field),
+                owned(field_ty)
+            )
+            .unwrap();
+        }
+        writeln!(out, "        Ok(())").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out).unwrap();
+
+        writeln!(
+            out,
+            "    fn load(cx: &{}::Context, ptr: u32) -> {}<Self, {}> {{",
+            RT, RESULT, RUNTIME_ERROR
+        )
+        .unwrap();
+        if fields.is_empty() {
+            writeln!(out, "        let _ = (cx, ptr);").unwrap();
+            writeln!(out, "        Ok(Self {{}})").unwrap();
+        } else {
+            writeln!(out, "        let mut fields = {}::Fields::new(ptr);", RT).unwrap();
+            writeln!(out, "        Ok(Self {{").unwrap();
+            for (field, field_ty) in fields {
+                writeln!(
+                    out,
+                    "            {}: {}::load(cx, fields.next::<{}>())?,",
+                    snake(field),
+                    canonical(&owned(field_ty)),
+                    owned(field_ty)
+                )
+                .unwrap();
+            }
+            writeln!(out, "        }})").unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+
+    fn generate_variant(&self, out: &mut String, name: &str, cases: &[(String, Option<Type>)]) {
+        let ty = camel(name);
+        let discriminant = match cases.len() {
+            0..=0x100 => "u8",
+            0x101..=0x10000 => "u16",
+            _ => "u32",
+        };
+        let payloads = cases
+            .iter()
+            .filter_map(|(_, payload)| payload.as_ref())
+            .collect::<Vec<_>>();
+
+        writeln!(out, "/// The `{}` variant.", name).unwrap();
+        writeln!(out, "#[derive(Debug, Clone, PartialEq)]").unwrap();
+        writeln!(out, "pub enum {} {{", ty).unwrap();
+        for (case, payload) in cases {
+            match payload {
+                Some(payload) => writeln!(out, "    {}({}),", camel(case), owned(payload)),
+                None => writeln!(out, "    {},", camel(case)),
+            }
+            .unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+
+        let payload_types = payloads.iter().map(|ty| owned(ty)).collect::<Vec<_>>();
+        let align_types = std::iter::once(discriminant.to_string())
+            .chain(payload_types.iter().cloned())
+            .collect::<Vec<_>>();
+        writeln!(out, "impl {}::CanonicalType for {} {{", RT, ty).unwrap();
+        generate_layout(
+            out,
+            "SIZE",
+            &format!("cases_size({}::SIZE, ", canonical(discriminant)),
+            &payload_types,
+            Layout::Both,
+        );
+        generate_layout(out, "ALIGN", "fields_align(", &align_types, Layout::Align);
+        writeln!(out).unwrap();
+
+        let payload_ptr = format!(
+            "        let payload = ptr.wrapping_add({}::align_to({}::SIZE, Self::ALIGN));",
+            RT,
+            canonical(discriminant)
+        );
+        writeln!(
+            out,
+            "    fn store(&self, cx: &{}::Context, ptr: u32) -> {}<(), {}> {{",
+            RT, RESULT, RUNTIME_ERROR
+        )
+        .unwrap();
+        if !payloads.is_empty() {
+            writeln!(out, "{}", payload_ptr).unwrap();
+        }
+        writeln!(out, "        match self {{").unwrap();
+        for (index, (case, payload)) in cases.iter().enumerate() {
+            let store_discriminant = format!(
+                "{}::store(&{}{}, cx, ptr)",
+                canonical(discriminant),
+                index,
+                discriminant
+            );
+            match payload {
+                Some(payload) => {
+                    writeln!(out, "            Self::{}(value) => {{", camel(case)).unwrap();
+                    writeln!(out, "                {}?;", store_discriminant).unwrap();
+                    writeln!(
+                        out,
+                        "                {}::store(value, cx, payload)",
+                        canonical(&owned(payload))
+                    )
+                    .unwrap();
+                    writeln!(out, "            }}").unwrap();
+                }
+                None => {
+                    writeln!(
+                        out,
+                        "            Self::{} => {},",
+                        camel(case),
+                        store_discriminant
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out).unwrap();
+
+        writeln!(
+            out,
+            "    fn load(cx: &{}::Context, ptr: u32) -> {}<Self, {}> {{",
+            RT, RESULT, RUNTIME_ERROR
+        )
+        .unwrap();
+        if !payloads.is_empty() {
+            writeln!(out, "{}", payload_ptr).unwrap();
+        }
+        writeln!(
+            out,
+            "        match {}::load(cx, ptr)? {{",
+            canonical(discriminant)
+        )
+        .unwrap();
+        for (index, (case, payload)) in cases.iter().enumerate() {
+            match payload {
+                Some(payload) => writeln!(
+                    out,
+                    "            {} => Ok(Self::{}({}::load(cx, payload)?)),",
+                    index,
+                    camel(case),
+                    canonical(&owned(payload))
+                ),
+                None => writeln!(out, "            {} => Ok(Self::{}),", index, camel(case)),
+            }
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "            case => Err({}::new(format!(\"invalid case {{}} of `{}`\", case))),",
+            RUNTIME_ERROR, name
+        )
+        .unwrap();
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+
+    fn generate_host(&self, out: &mut String, imports: &[&Function]) {
+        writeln!(
+            out,
+            "/// The functions of the `{}` interface implemented by the host.",
+            self.name
+        )
+        .unwrap();
+        writeln!(out, "pub trait Host: Send + 'static {{").unwrap();
+        for (index, function) in imports.iter().enumerate() {
+            if index > 0 {
+                writeln!(out).unwrap();
+            }
+            let params = function
+                .params
+                .iter()
+                .map(|(name, ty)| format!(", {}: {}", snake(name), owned(ty)))
+                .collect::<String>();
+            let result = match &function.result {
+                Some(ty) => format!(" -> {}", owned(ty)),
+                None => String::new(),
+            };
+            writeln!(
+                out,
+                "    /// Implements the `{}` function imported by the guest.",
+                function.name
+            )
+            .unwrap();
+            out.push_str(allow_many_params(function.params.len() + 1));
+            writeln!(
+                out,
+                "    fn {}(&mut self{}){};",
+                snake(&function.name),
+                params,
+                result
+            )
+            .unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+
+        writeln!(
+            out,
+            "/// Registers the functions implemented by `host` in the namespace `{}` of `imports`.",
+            self.name
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "pub fn add_to_imports<T: Host>(store: &::wasmer::Store, imports: &mut ::wasmer::ImportObject, host: T) {{"
+        )
+        .unwrap();
+        for function in imports {
+            self.generate_import(out, function);
+            writeln!(out).unwrap();
+        }
+        writeln!(out, "    let env = {}::HostEnv::new(host);", RT).unwrap();
+        writeln!(out, "    let mut namespace = ::wasmer::Exports::new();").unwrap();
+        for function in imports {
+            writeln!(
+                out,
+                "    namespace.insert(\"{}\", ::wasmer::Function::new_native_with_env(store, env.clone(), {}::<T>));",
+                function.name,
+                snake(&function.name)
+            )
+            .unwrap();
+        }
+        writeln!(out, "    imports.register(\"{}\", namespace);", self.name).unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+
+    /// Generates the host function calling the `Host` method of `function`.
+    fn generate_import(&self, out: &mut String, function: &Function) {
+        let mut params = vec![];
+        let mut lifts = vec![];
+        for (name, ty) in &function.params {
+            let name = snake(name);
+            if is_number(ty) {
+                params.push(format!("{}: {}", name, owned(ty)));
+                continue;
+            }
+            let arg = format!("arg{}", params.len());
+            params.push(format!("{}: u32", arg));
+            let lift = match ty {
+                Type::Bool => format!("{} != 0", arg),
+                Type::Char => format!("{}::char_from_u32({})?", RT, arg),
+                Type::String | Type::List(_) => {
+                    let len = format!("arg{}", params.len());
+                    params.push(format!("{}: u32", len));
+                    let load = if *ty == Type::String {
+                        "load_string"
+                    } else {
+                        "load_list"
+                    };
+                    format!("cx.{}({}, {})?", load, arg, len)
+                }
+                _ => format!("cx.load_value({})?", arg),
+            };
+            lifts.push(format!("let {} = {};", name, lift));
+        }
+        let uses_cx = function.params.iter().any(|(_, ty)| is_indirect(ty))
+            || matches!(&function.result, Some(ty) if is_indirect(ty));
+
+        let call = format!(
+            "env.host().{}({})",
+            snake(&function.name),
+            function
+                .params
+                .iter()
+                .map(|(name, _)| snake(name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        out.push_str(allow_many_params(params.len() + 1));
+        writeln!(
+            out,
+            "    fn {}<T: Host>(env: &{}::HostEnv<T>{}) -> {}<{}, {}> {{",
+            snake(&function.name),
+            RT,
+            params
+                .iter()
+                .map(|param| format!(", {}", param))
+                .collect::<String>(),
+            RESULT,
+            flat_result(&function.result),
+            RUNTIME_ERROR
+        )
+        .unwrap();
+        if uses_cx {
+            writeln!(out, "        let cx = env.context()?;").unwrap();
+        }
+        for lift in &lifts {
+            writeln!(out, "        {}", lift).unwrap();
+        }
+        match &function.result {
+            None => {
+                writeln!(out, "        {};", call).unwrap();
+                writeln!(out, "        Ok(())").unwrap();
+            }
+            Some(ty) if is_number(ty) => writeln!(out, "        Ok({})", call).unwrap(),
+            Some(Type::Bool) | Some(Type::Char) => {
+                writeln!(out, "        Ok({} as u32)", call).unwrap()
+            }
+            Some(_) => {
+                writeln!(out, "        let result = {};", call).unwrap();
+                writeln!(out, "        cx.store_value(&result)").unwrap();
+            }
+        }
+        writeln!(out, "    }}").unwrap();
+    }
+
+    fn generate_guest(&self, out: &mut String, exports: &[&Function]) {
+        writeln!(
+            out,
+            "/// The functions of the `{}` interface implemented by the guest.",
+            self.name
+        )
+        .unwrap();
+        writeln!(out, "#[derive(Clone)]").unwrap();
+        writeln!(out, "pub struct Guest {{").unwrap();
+        writeln!(out, "    context: {}::Context,", RT).unwrap();
+        for function in exports {
+            writeln!(
+                out,
+                "    {}_func: ::wasmer::NativeFunc<{}, {}>,",
+                snake(&function.name),
+                flat_params(function),
+                flat_result(&function.result)
+            )
+            .unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+
+        writeln!(out, "impl Guest {{").unwrap();
+        writeln!(out, "    /// Returns the functions exported by `instance`.").unwrap();
+        writeln!(
+            out,
+            "    pub fn new(instance: &::wasmer::Instance) -> {}<Self, ::wasmer::ExportError> {{",
+            RESULT
+        )
+        .unwrap();
+        writeln!(out, "        Ok(Self {{").unwrap();
+        writeln!(
+            out,
+            "            context: {}::Context::new(&instance.exports)?,",
+            RT
+        )
+        .unwrap();
+        for function in exports {
+            writeln!(
+                out,
+                "            {}_func: instance.exports.get_native_function(\"{}\")?,",
+                snake(&function.name),
+                function.name
+            )
+            .unwrap();
+        }
+        writeln!(out, "        }})").unwrap();
+        writeln!(out, "    }}").unwrap();
+        for function in exports {
+            writeln!(out).unwrap();
+            self.generate_export(out, function);
+        }
+        writeln!(out, "}}").unwrap();
+    }
+
+    /// Generates the method calling the guest function `function`.
+    fn generate_export(&self, out: &mut String, function: &Function) {
+        let mut args = vec![];
+        let mut lowers = vec![];
+        for (name, ty) in &function.params {
+            let name = snake(name);
+            match ty {
+                Type::Bool | Type::Char => args.push(format!("{} as u32", name)),
+                Type::String | Type::List(_) => {
+                    let (ptr, len) = (
+                        format!("arg{}", args.len()),
+                        format!("arg{}", args.len() + 1),
+                    );
+                    let store = if *ty == Type::String {
+                        "store_string"
+                    } else {
+                        "store_list"
+                    };
+                    lowers.push(format!("let ({}, {}) = cx.{}({})?;", ptr, len, store, name));
+                    args.push(ptr);
+                    args.push(len);
+                }
+                Type::Named(_) => {
+                    let ptr = format!("arg{}", args.len());
+                    lowers.push(format!("let {} = cx.store_value({})?;", ptr, name));
+                    args.push(ptr);
+                }
+                _ => args.push(name),
+            }
+        }
+        let lifts_result = matches!(&function.result, Some(ty) if is_indirect(ty));
+
+        let params = function
+            .params
+            .iter()
+            .map(|(name, ty)| format!(", {}: {}", snake(name), borrowed(ty)))
+            .collect::<String>();
+        let result = match &function.result {
+            Some(ty) => owned(ty),
+            None => "()".to_string(),
+        };
+        writeln!(
+            out,
+            "    /// Calls the `{}` function exported by the guest.",
+            function.name
+        )
+        .unwrap();
+        out.push_str(allow_many_params(function.params.len() + 1));
+        writeln!(
+            out,
+            "    pub fn {}(&self{}) -> {}<{}, {}> {{",
+            snake(&function.name),
+            params,
+            RESULT,
+            result,
+            RUNTIME_ERROR
+        )
+        .unwrap();
+        if !lowers.is_empty() || lifts_result {
+            writeln!(out, "        let cx = &self.context;").unwrap();
+        }
+        for lower in &lowers {
+            writeln!(out, "        {}", lower).unwrap();
+        }
+        let call = format!(
+            "self.{}_func.call({})",
+            snake(&function.name),
+            args.join(", ")
+        );
+        match &function.result {
+            Some(Type::Bool) => writeln!(out, "        Ok({}? != 0)", call),
+            Some(Type::Char) => writeln!(out, "        {}::char_from_u32({}?)", RT, call),
+            Some(_) if lifts_result => writeln!(out, "        cx.load_value({}?)", call),
+            _ => writeln!(out, "        {}", call),
+        }
+        .unwrap();
+        writeln!(out, "    }}").unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+//! Host bindings generated from interface definitions.
+//!
+//! An interface definition lists the records, variants and functions
+//! shared by a host and a guest, see [`Interface::parse`]. The generated
+//! Rust code, see [`Interface::generate`], implements the functions
+//! imported by the guest with a `Host` trait, and calls the functions
+//! exported by the guest with a `Guest` struct, passing the strings, lists,
+//! records and variants through the memory of the guest with the canonical
+//! ABI described in [`rt`].
+//!
+//! The code is typically generated by a build script:
+//!
+//! ```no_run
+//! // build.rs
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let source = std::fs::read_to_string("shapes.wit")?;
+//! let code = wasmer_bindgen::generate("shapes", &source)?;
+//! let out_dir = std::env::var("OUT_DIR")?;
+//! std::fs::write(format!("{}/shapes.rs", out_dir), code)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! and included in a module of its own:
+//!
+//! ```ignore
+//! mod shapes {
+//!     include!(concat!(env!("OUT_DIR"), "/shapes.rs"));
+//! }
+//! ```
+
+#![deny(missing_docs, unused_extern_crates)]
+#![warn(unused_import_braces)]
+
+mod generate;
+mod parse;
+pub mod rt;
+
+pub use crate::parse::{Interface, ParseError};
+
+/// Parses the definition of the interface `name` and generates its host
+/// bindings.
+pub fn generate(name: &str, source: &str) -> Result<String, ParseError> {
+    Ok(Interface::parse(name, source)?.generate())
+}
@@ -0,0 +1,594 @@
+//! Parsing interface definitions.
+
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// An error in an interface definition.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{line}:{column}: {message}")]
+pub struct ParseError {
+    /// The line of the error, from 1.
+    pub line: usize,
+
+    /// The column of the error, from 1.
+    pub column: usize,
+
+    /// The description of the error.
+    pub message: String,
+}
+
+/// A position in an interface definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn error(self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+/// A type of an interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Type {
+    Bool,
+    U8,
+    S8,
+    U16,
+    S16,
+    U32,
+    S32,
+    U64,
+    S64,
+    F32,
+    F64,
+    Char,
+    String,
+    List(Box<Type>),
+
+    /// A record or a variant, by name.
+    Named(String),
+}
+
+/// A record or a variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TypeDef {
+    /// A record, with its fields.
+    Record(String, Vec<(String, Type)>),
+
+    /// A variant, with its cases and their optional payloads.
+    Variant(String, Vec<(String, Option<Type>)>),
+}
+
+impl TypeDef {
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Self::Record(name, _) | Self::Variant(name, _) => name,
+        }
+    }
+}
+
+/// Whether a function is implemented by the host or by the guest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    /// The guest imports the function from the host.
+    Import,
+
+    /// The guest exports the function to the host.
+    Export,
+}
+
+/// A function of an interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Function {
+    pub(crate) direction: Direction,
+    pub(crate) name: String,
+    pub(crate) params: Vec<(String, Type)>,
+    pub(crate) result: Option<Type>,
+}
+
+/// The types and functions of an interface, parsed from its definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interface {
+    pub(crate) name: String,
+    pub(crate) types: Vec<TypeDef>,
+    pub(crate) functions: Vec<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Name(String),
+    Punct(&'static str),
+    End,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, Position)>, ParseError> {
+    const PUNCTS: [&str; 9] = ["->", "{", "}", "(", ")", "<", ">", ":", ","];
+
+    let mut tokens = vec![];
+    let mut position = Position { line: 1, column: 1 };
+    let mut end = position;
+    for line in source.lines() {
+        let mut rest = line;
+        position.column = 1;
+        loop {
+            let trimmed = rest.trim_start();
+            position.column += rest.len() - trimmed.len();
+            rest = trimmed;
+            if rest.is_empty() || rest.starts_with("//") {
+                break;
+            }
+
+            let len = if let Some(punct) = PUNCTS.iter().find(|punct| rest.starts_with(*punct)) {
+                tokens.push((Token::Punct(punct), position));
+                punct.len()
+            } else {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    let c = rest.chars().next().unwrap();
+                    return Err(position.error(format!("unexpected character `{}`", c)));
+                }
+                tokens.push((Token::Name(rest[..len].to_string()), position));
+                len
+            };
+            position.column += len;
+            rest = &rest[len..];
+        }
+        end = position;
+        position.line += 1;
+    }
+    tokens.push((Token::End, end));
+    Ok(tokens)
+}
+
+/// The names of the types that aren't defined by interfaces.
+const BUILTIN_TYPES: [&str; 14] = [
+    "bool", "u8", "s8", "u16", "s16", "u32", "s32", "u64", "s64", "f32", "f64", "char", "string",
+    "list",
+];
+
+/// Returns whether `name` is a valid name: lowercase words of letters and
+/// digits, separated by hyphens, the first word starting with a letter.
+fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.split('-').all(|word| {
+            !word.is_empty()
+                && word
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    next: usize,
+
+    /// The names of the types used, with their positions.
+    references: Vec<(String, Position)>,
+}
+
+impl Parser {
+    fn peek(&self) -> &(Token, Position) {
+        &self.tokens[self.next]
+    }
+
+    fn bump(&mut self) -> (Token, Position) {
+        let token = self.tokens[self.next].clone();
+        if token.0 != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        match self.peek().0 {
+            Token::Punct(p) if p == punct => {
+                self.next += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), ParseError> {
+        if self.eat(punct) {
+            return Ok(());
+        }
+        let (token, position) = self.peek();
+        Err(position.error(format!("expected `{}`, found {}", punct, describe(token))))
+    }
+
+    fn name(&mut self) -> Result<(String, Position), ParseError> {
+        match self.bump() {
+            (Token::Name(name), position) if is_valid_name(&name) => Ok((name, position)),
+            (Token::Name(name), position) => Err(position.error(format!(
+                "invalid name `{}`: expected lowercase words separated by hyphens",
+                name
+            ))),
+            (token, position) => {
+                Err(position.error(format!("expected a name, found {}", describe(&token))))
+            }
+        }
+    }
+
+    /// Parses a comma-separated list until `close`, allowing a trailing
+    /// comma.
+    fn list<T>(
+        &mut self,
+        close: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        let mut items = vec![];
+        while !self.eat(close) {
+            items.push(item(self)?);
+            if !self.eat(",") {
+                self.expect(close)?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn ty(&mut self) -> Result<Type, ParseError> {
+        let (name, position) = self.name()?;
+        Ok(match name.as_str() {
+            "bool" => Type::Bool,
+            "u8" => Type::U8,
+            "s8" => Type::S8,
+            "u16" => Type::U16,
+            "s16" => Type::S16,
+            "u32" => Type::U32,
+            "s32" => Type::S32,
+            "u64" => Type::U64,
+            "s64" => Type::S64,
+            "f32" => Type::F32,
+            "f64" => Type::F64,
+            "char" => Type::Char,
+            "string" => Type::String,
+            "list" => {
+                self.expect("<")?;
+                let element = self.ty()?;
+                self.expect(">")?;
+                Type::List(Box::new(element))
+            }
+            _ => {
+                self.references.push((name.clone(), position));
+                Type::Named(name)
+            }
+        })
+    }
+
+    fn field(&mut self) -> Result<(String, Type), ParseError> {
+        let (name, _) = self.name()?;
+        self.expect(":")?;
+        Ok((name, self.ty()?))
+    }
+
+    fn case(&mut self) -> Result<(String, Option<Type>), ParseError> {
+        let (name, _) = self.name()?;
+        if !self.eat("(") {
+            return Ok((name, None));
+        }
+        let payload = self.ty()?;
+        self.expect(")")?;
+        Ok((name, Some(payload)))
+    }
+
+    fn function(&mut self, direction: Direction) -> Result<Function, ParseError> {
+        let (name, _) = self.name()?;
+        self.expect(":")?;
+        match self.bump() {
+            (Token::Name(keyword), _) if keyword == "func" => {}
+            (token, position) => {
+                return Err(position.error(format!("expected `func`, found {}", describe(&token))))
+            }
+        }
+        self.expect("(")?;
+        let params = self.list(")", Self::field)?;
+        let result = if self.eat("->") {
+            Some(self.ty()?)
+        } else {
+            None
+        };
+        Ok(Function {
+            direction,
+            name,
+            params,
+            result,
+        })
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Name(name) => format!("`{}`", name),
+        Token::Punct(punct) => format!("`{}`", punct),
+        Token::End => "the end of the file".to_string(),
+    }
+}
+
+/// Checks that the fields of a record, the cases of a variant or the
+/// parameters of a function have distinct names.
+fn check_distinct<'a>(
+    names: impl Iterator<Item = &'a String>,
+    kind: &str,
+    position: Position,
+) -> Result<(), ParseError> {
+    let mut seen = vec![];
+    for name in names {
+        if seen.contains(&name) {
+            return Err(position.error(format!("duplicate {} `{}`", kind, name)));
+        }
+        seen.push(name);
+    }
+    Ok(())
+}
+
+impl Interface {
+    /// Parses the definition of the interface `name`.
+    ///
+    /// The definition is a list of records, variants, and functions
+    /// imported or exported by the guest:
+    ///
+    /// ```text
+    /// // A comment.
+    /// record point { x: s32, y: s32 }
+    /// variant shape { circle(f32), rectangle(point), empty }
+    ///
+    /// import log: func(message: string)
+    /// export area: func(shape: shape) -> f32
+    /// ```
+    ///
+    /// The types are `bool`, `u8`, `s8`, `u16`, `s16`, `u32`, `s32`,
+    /// `u64`, `s64`, `f32`, `f64`, `char`, `string`, `list<T>`, and the
+    /// records and variants of the interface, which can be used before
+    /// their definition.
+    pub fn parse(name: &str, source: &str) -> Result<Self, ParseError> {
+        if !is_valid_name(name) {
+            return Err(Position { line: 1, column: 1 }.error(format!(
+                "invalid interface name `{}`: expected lowercase words separated by hyphens",
+                name
+            )));
+        }
+
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            next: 0,
+            references: vec![],
+        };
+        let mut types = vec![];
+        let mut functions: Vec<Function> = vec![];
+        let mut positions = HashMap::new();
+        loop {
+            let (keyword, position) = match parser.bump() {
+                (Token::End, _) => break,
+                (Token::Name(keyword), position) => (keyword, position),
+                (token, position) => {
+                    return Err(position.error(format!(
+                        "expected `record`, `variant`, `import` or `export`, found {}",
+                        describe(&token)
+                    )))
+                }
+            };
+            match keyword.as_str() {
+                "record" | "variant" => {
+                    let (name, position) = parser.name()?;
+                    if BUILTIN_TYPES.contains(&name.as_str()) {
+                        return Err(position.error(format!("`{}` is a built-in type", name)));
+                    }
+                    parser.expect("{")?;
+                    let ty = if keyword == "record" {
+                        let fields = parser.list("}", Parser::field)?;
+                        check_distinct(fields.iter().map(|(name, _)| name), "field", position)?;
+                        TypeDef::Record(name.clone(), fields)
+                    } else {
+                        let cases = parser.list("}", Parser::case)?;
+                        if cases.is_empty() {
+                            return Err(position.error(format!("variant `{}` has no cases", name)));
+                        }
+                        check_distinct(cases.iter().map(|(name, _)| name), "case", position)?;
+                        TypeDef::Variant(name.clone(), cases)
+                    };
+                    if positions.insert(name.clone(), position).is_some() {
+                        return Err(position.error(format!("type `{}` is already defined", name)));
+                    }
+                    types.push(ty);
+                }
+                "import" | "export" => {
+                    let direction = if keyword == "import" {
+                        Direction::Import
+                    } else {
+                        Direction::Export
+                    };
+                    let function = parser.function(direction)?;
+                    check_distinct(
+                        function.params.iter().map(|(name, _)| name),
+                        "parameter",
+                        position,
+                    )?;
+                    if functions
+                        .iter()
+                        .any(|f| f.direction == direction && f.name == function.name)
+                    {
+                        return Err(position
+                            .error(format!("function `{}` is already defined", function.name)));
+                    }
+                    functions.push(function);
+                }
+                _ => {
+                    return Err(position.error(format!(
+                        "expected `record`, `variant`, `import` or `export`, found `{}`",
+                        keyword
+                    )))
+                }
+            }
+        }
+
+        for (name, position) in &parser.references {
+            if !positions.contains_key(name) {
+                return Err(position.error(format!("unknown type `{}`", name)));
+            }
+        }
+        let interface = Self {
+            name: name.to_string(),
+            types,
+            functions,
+        };
+        for ty in &interface.types {
+            if interface.contains(ty, ty.name(), &mut vec![]) {
+                return Err(positions[ty.name()].error(format!(
+                    "type `{}` contains itself, outside of a list",
+                    ty.name()
+                )));
+            }
+        }
+        Ok(interface)
+    }
+
+    /// Returns the name of the interface.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn type_def(&self, name: &str) -> &TypeDef {
+        self.types
+            .iter()
+            .find(|ty| ty.name() == name)
+            .expect("the types are checked while parsing")
+    }
+
+    /// Returns whether `ty` contains the type `name` inline, which would
+    /// make its size infinite.
+    fn contains<'a>(&'a self, ty: &'a TypeDef, name: &str, visited: &mut Vec<&'a str>) -> bool {
+        if visited.contains(&ty.name()) {
+            return false;
+        }
+        visited.push(ty.name());
+        let inline: Vec<&Type> = match ty {
+            TypeDef::Record(_, fields) => fields.iter().map(|(_, ty)| ty).collect(),
+            TypeDef::Variant(_, cases) => cases.iter().filter_map(|(_, ty)| ty.as_ref()).collect(),
+        };
+        inline.into_iter().any(|ty| match ty {
+            Type::Named(inner) => {
+                inner == name || self.contains(self.type_def(inner), name, visited)
+            }
+            _ => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        Interface::parse("test", source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parse_interface() {
+        let interface = Interface::parse(
+            "shapes",
+            "
+            // Shapes.
+            variant shape { circle(f32), rectangle(point), empty, }
+            record point { x: s32, y: s32 }
+
+            import log: func(message: string)
+            export areas: func(shapes: list<shape>) -> list<f64>
+            ",
+        )
+        .unwrap();
+        assert_eq!(interface.name(), "shapes");
+        assert_eq!(
+            interface.types,
+            [
+                TypeDef::Variant(
+                    "shape".to_string(),
+                    vec![
+                        ("circle".to_string(), Some(Type::F32)),
+                        (
+                            "rectangle".to_string(),
+                            Some(Type::Named("point".to_string()))
+                        ),
+                        ("empty".to_string(), None),
+                    ]
+                ),
+                TypeDef::Record(
+                    "point".to_string(),
+                    vec![("x".to_string(), Type::S32), ("y".to_string(), Type::S32)]
+                ),
+            ]
+        );
+        assert_eq!(
+            interface.functions,
+            [
+                Function {
+                    direction: Direction::Import,
+                    name: "log".to_string(),
+                    params: vec![("message".to_string(), Type::String)],
+                    result: None,
+                },
+                Function {
+                    direction: Direction::Export,
+                    name: "areas".to_string(),
+                    params: vec![(
+                        "shapes".to_string(),
+                        Type::List(Box::new(Type::Named("shape".to_string())))
+                    )],
+                    result: Some(Type::List(Box::new(Type::F64))),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            error("record a { x: s32 "),
+            "1:19: expected `}`, found the end of the file"
+        );
+        assert_eq!(
+            error("\n  export f: func(x: point)"),
+            "2:21: unknown type `point`"
+        );
+        assert_eq!(
+            error("record a { x: s32, x: u8 }"),
+            "1:8: duplicate field `x`"
+        );
+        assert_eq!(error("variant a {}"), "1:9: variant `a` has no cases");
+        assert_eq!(
+            error("record a {}\nrecord a {}"),
+            "2:8: type `a` is already defined"
+        );
+        assert_eq!(
+            error("import f: func()\nimport f: func()"),
+            "2:1: function `f` is already defined"
+        );
+        assert_eq!(
+            error("record Point {}"),
+            "1:8: invalid name `Point`: expected lowercase words separated by hyphens"
+        );
+        assert_eq!(
+            error("record a { x: s32; }"),
+            "1:18: unexpected character `;`"
+        );
+        assert_eq!(
+            error("record a { b: b }\nrecord b { a: a }"),
+            "1:8: type `a` contains itself, outside of a list"
+        );
+        assert_eq!(
+            error("variant string { a }"),
+            "1:9: `string` is a built-in type"
+        );
+        assert!(Interface::parse("test", "record a { b: list<a> }").is_ok());
+    }
+}
@@ -0,0 +1,349 @@
+//! The runtime support of the generated bindings.
+//!
+//! The generated code passes values between the host and the guest with
+//! this canonical ABI:
+//!
+//! - The integers, floats, `bool`s and `char`s are passed as Wasm values,
+//!   `bool`s and `char`s as `i32`s.
+//! - The strings and lists are passed as two `i32`s: a pointer to their
+//!   elements in the memory of the guest, and their number of elements, or
+//!   of bytes for strings, which are encoded in UTF-8.
+//! - The records and variants are passed as a pointer to their value in the
+//!   memory of the guest, with the layout of [`CanonicalType`].
+//! - The strings, lists, records and variants are returned as a pointer to
+//!   their value in the memory of the guest, with the layout of
+//!   [`CanonicalType`].
+//!
+//! The guest exports its memory as `memory`, and a function
+//! `canonical_abi_realloc(old_ptr: i32, old_size: i32, align: i32,
+//! new_size: i32) -> i32` that the host calls with an `old_ptr` and an
+//! `old_size` of 0 to allocate the values it passes to the guest. The
+//! memory of the values passed to a function belongs to the callee, and the
+//! memory of the values returned to the caller.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use wasmer::internals::{read_bytes, read_string, write_bytes};
+use wasmer::{
+    ExportError, Exports, HostEnvInitError, Instance, LazyInit, Memory, NativeFunc, RuntimeError,
+    WasmerEnv,
+};
+
+/// Returns `offset` rounded up to a multiple of `align`, a power of two.
+pub const fn align_to(offset: u32, align: u32) -> u32 {
+    (offset + align - 1) & !(align - 1)
+}
+
+/// Returns the size of a record with fields of the sizes and alignments
+/// `fields`.
+pub const fn fields_size(fields: &[(u32, u32)]) -> u32 {
+    let mut size = 0;
+    let mut align = 1;
+    let mut index = 0;
+    while index < fields.len() {
+        size = align_to(size, fields[index].1) + fields[index].0;
+        if fields[index].1 > align {
+            align = fields[index].1;
+        }
+        index += 1;
+    }
+    align_to(size, align)
+}
+
+/// Returns the alignment of a record with fields of the alignments
+/// `aligns`, or of a variant with a discriminant and payloads of the
+/// alignments `aligns`.
+pub const fn fields_align(aligns: &[u32]) -> u32 {
+    let mut align = 1;
+    let mut index = 0;
+    while index < aligns.len() {
+        if aligns[index] > align {
+            align = aligns[index];
+        }
+        index += 1;
+    }
+    align
+}
+
+/// Returns the size of a variant with a discriminant of `discriminant`
+/// bytes and payloads of the sizes and alignments `payloads`.
+pub const fn cases_size(discriminant: u32, payloads: &[(u32, u32)]) -> u32 {
+    let mut align = discriminant;
+    let mut size = 0;
+    let mut index = 0;
+    while index < payloads.len() {
+        if payloads[index].1 > align {
+            align = payloads[index].1;
+        }
+        if payloads[index].0 > size {
+            size = payloads[index].0;
+        }
+        index += 1;
+    }
+    align_to(align_to(discriminant, align) + size, align)
+}
+
+/// Returns the `char` of the code point `value`.
+pub fn char_from_u32(value: u32) -> Result<char, RuntimeError> {
+    std::char::from_u32(value)
+        .ok_or_else(|| RuntimeError::new(format!("invalid char {:#x}", value)))
+}
+
+/// A type with a layout in the memory of the guest.
+///
+/// The integers, floats, `bool`s and `char`s are stored in little-endian
+/// order, aligned to their size, with `bool`s as one byte and `char`s as
+/// four. The strings and lists are stored as a pointer to their elements
+/// and their number of elements, two `u32`s. The fields of the records are
+/// stored in order, each aligned to its type. The variants are stored as
+/// the index of their case, in a `u8`, a `u16` or a `u32` depending on
+/// their number of cases, followed by the payload of the case, aligned to
+/// the largest alignment of the payloads.
+pub trait CanonicalType: Sized {
+    /// The size of the values, a multiple of [`CanonicalType::ALIGN`].
+    const SIZE: u32;
+
+    /// The alignment of the values.
+    const ALIGN: u32;
+
+    /// Stores `self` at `ptr` in the memory of the guest, allocating the
+    /// memory of its strings and lists.
+    fn store(&self, cx: &Context, ptr: u32) -> Result<(), RuntimeError>;
+
+    /// Loads the value at `ptr` in the memory of the guest.
+    fn load(cx: &Context, ptr: u32) -> Result<Self, RuntimeError>;
+}
+
+macro_rules! impl_canonical_type_for_number {
+    ( $( $type:ty ),* ) => {
+        $(
+            impl CanonicalType for $type {
+                const SIZE: u32 = std::mem::size_of::<$type>() as u32;
+                const ALIGN: u32 = std::mem::size_of::<$type>() as u32;
+
+                fn store(&self, cx: &Context, ptr: u32) -> Result<(), RuntimeError> {
+                    cx.write(ptr, &self.to_le_bytes())
+                }
+
+                fn load(cx: &Context, ptr: u32) -> Result<Self, RuntimeError> {
+                    let mut bytes = [0; std::mem::size_of::<$type>()];
+                    bytes.copy_from_slice(&cx.read(ptr, Self::SIZE)?);
+                    Ok(Self::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_canonical_type_for_number!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+impl CanonicalType for bool {
+    const SIZE: u32 = 1;
+    const ALIGN: u32 = 1;
+
+    fn store(&self, cx: &Context, ptr: u32) -> Result<(), RuntimeError> {
+        (*self as u8).store(cx, ptr)
+    }
+
+    fn load(cx: &Context, ptr: u32) -> Result<Self, RuntimeError> {
+        Ok(u8::load(cx, ptr)? != 0)
+    }
+}
+
+impl CanonicalType for char {
+    const SIZE: u32 = 4;
+    const ALIGN: u32 = 4;
+
+    fn store(&self, cx: &Context, ptr: u32) -> Result<(), RuntimeError> {
+        (*self as u32).store(cx, ptr)
+    }
+
+    fn load(cx: &Context, ptr: u32) -> Result<Self, RuntimeError> {
+        char_from_u32(u32::load(cx, ptr)?)
+    }
+}
+
+impl CanonicalType for String {
+    const SIZE: u32 = 8;
+    const ALIGN: u32 = 4;
+
+    fn store(&self, cx: &Context, ptr: u32) -> Result<(), RuntimeError> {
+        let (elements, len) = cx.store_string(self)?;
+        elements.store(cx, ptr)?;
+        len.store(cx, ptr.wrapping_add(4))
+    }
+
+    fn load(cx: &Context, ptr: u32) -> Result<Self, RuntimeError> {
+        cx.load_string(u32::load(cx, ptr)?, u32::load(cx, ptr.wrapping_add(4))?)
+    }
+}
+
+impl<T: CanonicalType> CanonicalType for Vec<T> {
+    const SIZE: u32 = 8;
+    const ALIGN: u32 = 4;
+
+    fn store(&self, cx: &Context, ptr: u32) -> Result<(), RuntimeError> {
+        let (elements, len) = cx.store_list(self)?;
+        elements.store(cx, ptr)?;
+        len.store(cx, ptr.wrapping_add(4))
+    }
+
+    fn load(cx: &Context, ptr: u32) -> Result<Self, RuntimeError> {
+        cx.load_list(u32::load(cx, ptr)?, u32::load(cx, ptr.wrapping_add(4))?)
+    }
+}
+
+/// The pointers to the fields of a record, in order.
+pub struct Fields {
+    ptr: u32,
+    offset: u32,
+}
+
+impl Fields {
+    /// Starts at the first field of the record at `ptr`.
+    pub fn new(ptr: u32) -> Self {
+        Self { ptr, offset: 0 }
+    }
+
+    /// Returns the pointer to the next field, of type `T`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next<T: CanonicalType>(&mut self) -> u32 {
+        let offset = align_to(self.offset, T::ALIGN);
+        self.offset = offset + T::SIZE;
+        self.ptr.wrapping_add(offset)
+    }
+}
+
+/// The memory and the allocator of a guest.
+#[derive(Clone)]
+pub struct Context {
+    memory: Memory,
+    realloc: Option<NativeFunc<(u32, u32, u32, u32), u32>>,
+}
+
+impl Context {
+    /// Returns the context of the guest exporting `exports`.
+    ///
+    /// The allocator is optional, for the guests to which the host doesn't
+    /// pass strings, lists, records or variants.
+    pub fn new(exports: &Exports) -> Result<Self, ExportError> {
+        Ok(Self {
+            memory: exports.get_memory("memory")?.clone(),
+            realloc: exports.get_native_function("canonical_abi_realloc").ok(),
+        })
+    }
+
+    /// Returns the memory of the guest.
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Reads the `len` bytes at `ptr`.
+    pub fn read(&self, ptr: u32, len: u32) -> Result<Vec<u8>, RuntimeError> {
+        read_bytes(&self.memory, ptr, len)
+    }
+
+    /// Writes `bytes` at `ptr`.
+    pub fn write(&self, ptr: u32, bytes: &[u8]) -> Result<(), RuntimeError> {
+        write_bytes(&self.memory, ptr, bytes)
+    }
+
+    /// Allocates `size` bytes aligned to `align` in the memory of the guest.
+    pub fn alloc(&self, size: u32, align: u32) -> Result<u32, RuntimeError> {
+        let realloc = self
+            .realloc
+            .as_ref()
+            .ok_or_else(|| RuntimeError::new("the guest doesn't export `canonical_abi_realloc`"))?;
+        realloc.call(0, 0, align, size)
+    }
+
+    /// Allocates and stores `value`, and returns its pointer.
+    pub fn store_value<T: CanonicalType>(&self, value: &T) -> Result<u32, RuntimeError> {
+        let ptr = self.alloc(T::SIZE, T::ALIGN)?;
+        value.store(self, ptr)?;
+        Ok(ptr)
+    }
+
+    /// Loads the value at `ptr`.
+    pub fn load_value<T: CanonicalType>(&self, ptr: u32) -> Result<T, RuntimeError> {
+        T::load(self, ptr)
+    }
+
+    /// Allocates and stores `string`, and returns its pointer and its length.
+    pub fn store_string(&self, string: &str) -> Result<(u32, u32), RuntimeError> {
+        let len = string.len() as u32;
+        let ptr = self.alloc(len, 1)?;
+        self.write(ptr, string.as_bytes())?;
+        Ok((ptr, len))
+    }
+
+    /// Loads the string of `len` bytes at `ptr`.
+    pub fn load_string(&self, ptr: u32, len: u32) -> Result<String, RuntimeError> {
+        read_string(&self.memory, ptr, len)
+    }
+
+    /// Allocates and stores the elements of `list`, and returns their
+    /// pointer and their number.
+    pub fn store_list<T: CanonicalType>(&self, list: &[T]) -> Result<(u32, u32), RuntimeError> {
+        let len = list.len() as u32;
+        let size = len
+            .checked_mul(T::SIZE)
+            .ok_or_else(|| RuntimeError::new("list too large"))?;
+        let ptr = self.alloc(size, T::ALIGN)?;
+        for (index, element) in list.iter().enumerate() {
+            element.store(self, ptr.wrapping_add(index as u32 * T::SIZE))?;
+        }
+        Ok((ptr, len))
+    }
+
+    /// Loads the `len` elements at `ptr`.
+    pub fn load_list<T: CanonicalType>(&self, ptr: u32, len: u32) -> Result<Vec<T>, RuntimeError> {
+        (0..len)
+            .map(|index| T::load(self, ptr.wrapping_add(index.wrapping_mul(T::SIZE))))
+            .collect()
+    }
+}
+
+/// The environment of the host functions implementing the imports of a
+/// guest with a host value of type `T`.
+pub struct HostEnv<T> {
+    host: Arc<Mutex<T>>,
+    context: LazyInit<Context>,
+}
+
+impl<T> HostEnv<T> {
+    /// Creates the environment of the host value `host`.
+    pub fn new(host: T) -> Self {
+        Self {
+            host: Arc::new(Mutex::new(host)),
+            context: LazyInit::new(),
+        }
+    }
+
+    /// Locks and returns the host value.
+    pub fn host(&self) -> MutexGuard<'_, T> {
+        self.host.lock().unwrap()
+    }
+
+    /// Returns the context of the guest.
+    pub fn context(&self) -> Result<&Context, RuntimeError> {
+        self.context
+            .get_ref()
+            .ok_or_else(|| RuntimeError::new("the guest isn't instantiated yet"))
+    }
+}
+
+impl<T> Clone for HostEnv<T> {
+    fn clone(&self) -> Self {
+        Self {
+            host: self.host.clone(),
+            context: self.context.clone(),
+        }
+    }
+}
+
+impl<T: Send> WasmerEnv for HostEnv<T> {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        self.context.initialize(Context::new(&instance.exports)?);
+        Ok(())
+    }
+}
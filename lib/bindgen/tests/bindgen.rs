@@ -0,0 +1,109 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use wasmer::{ImportObject, Instance, Module, Store};
+
+mod shapes {
+    include!("fixtures/shapes.rs");
+}
+
+use shapes::{Point, Shape};
+
+#[test]
+fn generated_code_is_up_to_date() {
+    let code = wasmer_bindgen::generate("shapes", include_str!("fixtures/shapes.wit")).unwrap();
+    assert_eq!(code, include_str!("fixtures/shapes.rs"));
+}
+
+#[derive(Default)]
+struct Host {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl shapes::Host for Host {
+    fn log(&mut self, message: String) {
+        self.lines.lock().unwrap().push(message);
+    }
+
+    fn make_point(&mut self, x: i32, y: i32) -> Point {
+        Point { x: x + 1, y: y + 2 }
+    }
+}
+
+#[test]
+fn generated_bindings() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"
+    (module
+      (import "shapes" "log" (func $log (param i32 i32)))
+      (import "shapes" "make-point" (func $make_point (param i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (global $next (mut i32) (i32.const 1024))
+      (func $realloc (export "canonical_abi_realloc")
+        (param $old_ptr i32) (param $old_size i32) (param $align i32) (param $new_size i32)
+        (result i32)
+        (local $ptr i32)
+        (local.set $ptr
+          (i32.and
+            (i32.add (global.get $next) (i32.sub (local.get $align) (i32.const 1)))
+            (i32.sub (i32.const 0) (local.get $align))))
+        (global.set $next (i32.add (local.get $ptr) (local.get $new_size)))
+        (local.get $ptr))
+      (func (export "echo") (param $ptr i32) (param $len i32) (result i32)
+        (local $ret i32)
+        (call $log (local.get $ptr) (local.get $len))
+        (local.set $ret (call $realloc (i32.const 0) (i32.const 0) (i32.const 4) (i32.const 8)))
+        (i32.store (local.get $ret) (local.get $ptr))
+        (i32.store offset=4 (local.get $ret) (local.get $len))
+        (local.get $ret))
+      (func (export "area") (param $shape i32) (result f32)
+        (block $empty
+          (block $rectangle
+            (block $circle
+              (br_table $circle $rectangle $empty (i32.load8_u (local.get $shape))))
+            (return
+              (f32.mul
+                (f32.const 3)
+                (f32.mul
+                  (f32.load offset=4 (local.get $shape))
+                  (f32.load offset=4 (local.get $shape))))))
+          (return
+            (f32.convert_i32_s
+              (i32.mul
+                (i32.load offset=4 (local.get $shape))
+                (i32.load offset=8 (local.get $shape))))))
+        (f32.const 0))
+      (func (export "sum") (param $ptr i32) (param $len i32) (result i32)
+        (local $sum i32)
+        (block $done
+          (loop $next
+            (br_if $done (i32.eqz (local.get $len)))
+            (local.set $sum (i32.add (local.get $sum) (i32.load (local.get $ptr))))
+            (local.set $ptr (i32.add (local.get $ptr) (i32.const 4)))
+            (local.set $len (i32.sub (local.get $len) (i32.const 1)))
+            (br $next)))
+        (local.get $sum))
+      (func (export "origin") (result i32)
+        (call $make_point (i32.const 0) (i32.const 0))))
+"#,
+    )?;
+    let host = Host::default();
+    let lines = host.lines.clone();
+    let mut imports = ImportObject::new();
+    shapes::add_to_imports(&store, &mut imports, host);
+    let instance = Instance::new(&module, &imports)?;
+    let guest = shapes::Guest::new(&instance)?;
+
+    assert_eq!(guest.echo("hello")?, "hello");
+    assert_eq!(*lines.lock().unwrap(), ["hello"]);
+
+    assert_eq!(guest.area(&Shape::Circle(2.0))?, 12.0);
+    assert_eq!(guest.area(&Shape::Rectangle(Point { x: 3, y: 4 }))?, 12.0);
+    assert_eq!(guest.area(&Shape::Empty)?, 0.0);
+
+    assert_eq!(guest.sum(&[1, 2, 3, 4])?, 10);
+    assert_eq!(guest.origin()?, Point { x: 1, y: 2 });
+
+    Ok(())
+}
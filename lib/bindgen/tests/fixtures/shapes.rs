@@ -0,0 +1,160 @@
+// Generated by wasmer-bindgen from the `shapes` interface. Do not edit.
+
+/// The `point` record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl ::wasmer_bindgen::rt::CanonicalType for Point {
+    const SIZE: u32 = ::wasmer_bindgen::rt::fields_size(&[
+        (<i32 as ::wasmer_bindgen::rt::CanonicalType>::SIZE, <i32 as ::wasmer_bindgen::rt::CanonicalType>::ALIGN),
+        (<i32 as ::wasmer_bindgen::rt::CanonicalType>::SIZE, <i32 as ::wasmer_bindgen::rt::CanonicalType>::ALIGN),
+    ]);
+    const ALIGN: u32 = ::wasmer_bindgen::rt::fields_align(&[
+        <i32 as ::wasmer_bindgen::rt::CanonicalType>::ALIGN,
+        <i32 as ::wasmer_bindgen::rt::CanonicalType>::ALIGN,
+    ]);
+
+    fn store(&self, cx: &::wasmer_bindgen::rt::Context, ptr: u32) -> ::std::result::Result<(), ::wasmer::RuntimeError> {
+        let mut fields = ::wasmer_bindgen::rt::Fields::new(ptr);
+        <i32 as ::wasmer_bindgen::rt::CanonicalType>::store(&self.x, cx, fields.next::<i32>())?;
+        <i32 as ::wasmer_bindgen::rt::CanonicalType>::store(&self.y, cx, fields.next::<i32>())?;
+        Ok(())
+    }
+
+    fn load(cx: &::wasmer_bindgen::rt::Context, ptr: u32) -> ::std::result::Result<Self, ::wasmer::RuntimeError> {
+        let mut fields = ::wasmer_bindgen::rt::Fields::new(ptr);
+        Ok(Self {
+            x: <i32 as ::wasmer_bindgen::rt::CanonicalType>::load(cx, fields.next::<i32>())?,
+            y: <i32 as ::wasmer_bindgen::rt::CanonicalType>::load(cx, fields.next::<i32>())?,
+        })
+    }
+}
+
+/// The `shape` variant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Circle(f32),
+    Rectangle(Point),
+    Empty,
+}
+
+impl ::wasmer_bindgen::rt::CanonicalType for Shape {
+    const SIZE: u32 = ::wasmer_bindgen::rt::cases_size(<u8 as ::wasmer_bindgen::rt::CanonicalType>::SIZE, &[
+        (<f32 as ::wasmer_bindgen::rt::CanonicalType>::SIZE, <f32 as ::wasmer_bindgen::rt::CanonicalType>::ALIGN),
+        (<Point as ::wasmer_bindgen::rt::CanonicalType>::SIZE, <Point as ::wasmer_bindgen::rt::CanonicalType>::ALIGN),
+    ]);
+    const ALIGN: u32 = ::wasmer_bindgen::rt::fields_align(&[
+        <u8 as ::wasmer_bindgen::rt::CanonicalType>::ALIGN,
+        <f32 as ::wasmer_bindgen::rt::CanonicalType>::ALIGN,
+        <Point as ::wasmer_bindgen::rt::CanonicalType>::ALIGN,
+    ]);
+
+    fn store(&self, cx: &::wasmer_bindgen::rt::Context, ptr: u32) -> ::std::result::Result<(), ::wasmer::RuntimeError> {
+        let payload = ptr.wrapping_add(::wasmer_bindgen::rt::align_to(<u8 as ::wasmer_bindgen::rt::CanonicalType>::SIZE, Self::ALIGN));
+        match self {
+            Self::Circle(value) => {
+                <u8 as ::wasmer_bindgen::rt::CanonicalType>::store(&0u8, cx, ptr)?;
+                <f32 as ::wasmer_bindgen::rt::CanonicalType>::store(value, cx, payload)
+            }
+            Self::Rectangle(value) => {
+                <u8 as ::wasmer_bindgen::rt::CanonicalType>::store(&1u8, cx, ptr)?;
+                <Point as ::wasmer_bindgen::rt::CanonicalType>::store(value, cx, payload)
+            }
+            Self::Empty => <u8 as ::wasmer_bindgen::rt::CanonicalType>::store(&2u8, cx, ptr),
+        }
+    }
+
+    fn load(cx: &::wasmer_bindgen::rt::Context, ptr: u32) -> ::std::result::Result<Self, ::wasmer::RuntimeError> {
+        let payload = ptr.wrapping_add(::wasmer_bindgen::rt::align_to(<u8 as ::wasmer_bindgen::rt::CanonicalType>::SIZE, Self::ALIGN));
+        match <u8 as ::wasmer_bindgen::rt::CanonicalType>::load(cx, ptr)? {
+            0 => Ok(Self::Circle(<f32 as ::wasmer_bindgen::rt::CanonicalType>::load(cx, payload)?)),
+            1 => Ok(Self::Rectangle(<Point as ::wasmer_bindgen::rt::CanonicalType>::load(cx, payload)?)),
+            2 => Ok(Self::Empty),
+            case => Err(::wasmer::RuntimeError::new(format!("invalid case {} of `shape`", case))),
+        }
+    }
+}
+
+/// The functions of the `shapes` interface implemented by the host.
+pub trait Host: Send + 'static {
+    /// Implements the `log` function imported by the guest.
+    fn log(&mut self, message: ::std::string::String);
+
+    /// Implements the `make-point` function imported by the guest.
+    fn make_point(&mut self, x: i32, y: i32) -> Point;
+}
+
+/// Registers the functions implemented by `host` in the namespace `shapes` of `imports`.
+pub fn add_to_imports<T: Host>(store: &::wasmer::Store, imports: &mut ::wasmer::ImportObject, host: T) {
+    fn log<T: Host>(env: &::wasmer_bindgen::rt::HostEnv<T>, arg0: u32, arg1: u32) -> ::std::result::Result<(), ::wasmer::RuntimeError> {
+        let cx = env.context()?;
+        let message = cx.load_string(arg0, arg1)?;
+        env.host().log(message);
+        Ok(())
+    }
+
+    fn make_point<T: Host>(env: &::wasmer_bindgen::rt::HostEnv<T>, x: i32, y: i32) -> ::std::result::Result<u32, ::wasmer::RuntimeError> {
+        let cx = env.context()?;
+        let result = env.host().make_point(x, y);
+        cx.store_value(&result)
+    }
+
+    let env = ::wasmer_bindgen::rt::HostEnv::new(host);
+    let mut namespace = ::wasmer::Exports::new();
+    namespace.insert("log", ::wasmer::Function::new_native_with_env(store, env.clone(), log::<T>));
+    namespace.insert("make-point", ::wasmer::Function::new_native_with_env(store, env.clone(), make_point::<T>));
+    imports.register("shapes", namespace);
+}
+
+/// The functions of the `shapes` interface implemented by the guest.
+#[derive(Clone)]
+pub struct Guest {
+    context: ::wasmer_bindgen::rt::Context,
+    echo_func: ::wasmer::NativeFunc<(u32, u32), u32>,
+    area_func: ::wasmer::NativeFunc<u32, f32>,
+    sum_func: ::wasmer::NativeFunc<(u32, u32), i32>,
+    origin_func: ::wasmer::NativeFunc<(), u32>,
+}
+
+impl Guest {
+    /// Returns the functions exported by `instance`.
+    pub fn new(instance: &::wasmer::Instance) -> ::std::result::Result<Self, ::wasmer::ExportError> {
+        Ok(Self {
+            context: ::wasmer_bindgen::rt::Context::new(&instance.exports)?,
+            echo_func: instance.exports.get_native_function("echo")?,
+            area_func: instance.exports.get_native_function("area")?,
+            sum_func: instance.exports.get_native_function("sum")?,
+            origin_func: instance.exports.get_native_function("origin")?,
+        })
+    }
+
+    /// Calls the `echo` function exported by the guest.
+    pub fn echo(&self, message: &str) -> ::std::result::Result<::std::string::String, ::wasmer::RuntimeError> {
+        let cx = &self.context;
+        let (arg0, arg1) = cx.store_string(message)?;
+        cx.load_value(self.echo_func.call(arg0, arg1)?)
+    }
+
+    /// Calls the `area` function exported by the guest.
+    pub fn area(&self, shape: &Shape) -> ::std::result::Result<f32, ::wasmer::RuntimeError> {
+        let cx = &self.context;
+        let arg0 = cx.store_value(shape)?;
+        self.area_func.call(arg0)
+    }
+
+    /// Calls the `sum` function exported by the guest.
+    pub fn sum(&self, values: &[i32]) -> ::std::result::Result<i32, ::wasmer::RuntimeError> {
+        let cx = &self.context;
+        let (arg0, arg1) = cx.store_list(values)?;
+        self.sum_func.call(arg0, arg1)
+    }
+
+    /// Calls the `origin` function exported by the guest.
+    pub fn origin(&self) -> ::std::result::Result<Point, ::wasmer::RuntimeError> {
+        let cx = &self.context;
+        cx.load_value(self.origin_func.call()?)
+    }
+}
@@ -1,12 +1,12 @@
 //! The logic for the Wasmer CLI tool.
 
-#[cfg(feature = "compiler")]
-use crate::commands::Compile;
 #[cfg(all(feature = "object-file", feature = "compiler"))]
 use crate::commands::CreateExe;
 #[cfg(feature = "wast")]
 use crate::commands::Wast;
 use crate::commands::{Cache, Config, Inspect, Run, SelfUpdate, Validate};
+#[cfg(feature = "compiler")]
+use crate::commands::{Compile, Snapshot};
 use crate::error::PrettyError;
 use anyhow::Result;
 
@@ -62,6 +62,11 @@ enum WasmerCLIOptions {
     #[clap(name = "inspect")]
     Inspect(Inspect),
 
+    /// Pre-initialize a module and snapshot its state into a new module
+    #[cfg(feature = "compiler")]
+    #[clap(name = "snapshot")]
+    Snapshot(Snapshot),
+
     /// Run spec testsuite
     #[cfg(feature = "wast")]
     #[clap(name = "wast")]
@@ -81,6 +86,8 @@ impl WasmerCLIOptions {
             Self::CreateExe(create_exe) => create_exe.execute(),
             Self::Config(config) => config.execute(),
             Self::Inspect(inspect) => inspect.execute(),
+            #[cfg(feature = "compiler")]
+            Self::Snapshot(snapshot) => snapshot.execute(),
             #[cfg(feature = "wast")]
             Self::Wast(wast) => wast.execute(),
         }
@@ -101,7 +108,7 @@ pub fn wasmer_main() {
     let command = args.get(1);
     let options = match command.unwrap_or(&"".to_string()).as_ref() {
         "cache" | "compile" | "config" | "create-exe" | "help" | "inspect" | "run"
-        | "self-update" | "validate" | "wast" => WasmerCLIOptions::parse(),
+        | "self-update" | "snapshot" | "validate" | "wast" => WasmerCLIOptions::parse(),
         _ => {
             WasmerCLIOptions::try_parse_from(args.iter()).unwrap_or_else(|e| {
                 match e.kind {
@@ -5,11 +5,17 @@ use crate::store::{CompilerType, EngineType, StoreOptions};
 use crate::suggestions::suggest_function_exports;
 use crate::warning;
 use anyhow::{anyhow, Context, Result};
-use std::path::PathBuf;
+#[cfg(feature = "compiler")]
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+#[cfg(feature = "compiler")]
+use std::sync::{Arc, Mutex};
 use wasmer::*;
 #[cfg(feature = "cache")]
 use wasmer_cache::{Cache, FileSystemCache, Hash};
+#[cfg(feature = "compiler")]
+use wasmer_middlewares::debugger::{self, gdb::GdbStub, Debugger};
 
 use clap::Clap;
 
@@ -17,7 +23,7 @@ use clap::Clap;
 mod wasi;
 
 #[cfg(feature = "wasi")]
-use wasi::Wasi;
+pub use wasi::Wasi;
 
 #[derive(Debug, Clap, Clone)]
 /// The options for the `wasmer run` subcommand
@@ -46,6 +52,16 @@ pub struct Run {
     #[clap(long = "cache-key", hidden = true)]
     cache_key: Option<String>,
 
+    /// Write a core dump of the instance to this path if it traps
+    #[clap(long = "coredump-on-trap", parse(from_os_str))]
+    coredump_on_trap: Option<PathBuf>,
+
+    /// Wait for a debugger to connect to this port with the GDB remote
+    /// protocol, and debug the module
+    #[cfg(feature = "compiler")]
+    #[clap(long = "gdb-port")]
+    gdb_port: Option<u16>,
+
     #[clap(flatten)]
     store: StoreOptions,
 
@@ -76,7 +92,7 @@ impl Run {
         if self.debug {
             logging::set_up_logging().unwrap();
         }
-        self.inner_execute().with_context(|| {
+        let exit_code = self.inner_execute().with_context(|| {
             format!(
                 "failed to run `{}`{}",
                 self.path.display(),
@@ -86,14 +102,37 @@ impl Run {
                     ""
                 }
             )
-        })
+        })?;
+        if exit_code != 0 {
+            // We should exit with the provided exit code
+            std::process::exit(exit_code as _);
+        }
+        Ok(())
     }
 
-    fn inner_execute(&self) -> Result<()> {
+    /// Runs the module, and returns the exit code of the program.
+    fn inner_execute(&self) -> Result<u32> {
         let module = self.get_module()?;
+        #[cfg(feature = "compiler")]
+        if let Some(port) = self.gdb_port {
+            let stub = self.wait_for_debugger(port, &module)?;
+            let imports = debugger::imports(module.store(), stub.clone());
+            let result = self.execute_module(module, imports);
+            // The debugger may be gone already
+            let _ = stub.lock().unwrap().exited(match &result {
+                Ok(exit_code) => *exit_code as u8,
+                Err(_) => 1,
+            });
+            return result;
+        }
+        self.execute_module(module, imports! {})
+    }
+
+    /// Executes the module, with `imports` in addition to the imports of
+    /// the ABI of the module, and returns the exit code of the program.
+    fn execute_module(&self, module: Module, imports: ImportObject) -> Result<u32> {
         // Do we want to invoke a function?
         if let Some(ref invoke) = self.invoke {
-            let imports = imports! {};
             let instance = Instance::new(&module, &imports)?;
             let result = self.invoke_function(&instance, &invoke, &self.args)?;
             println!(
@@ -104,7 +143,7 @@ impl Run {
                     .collect::<Vec<String>>()
                     .join(" ")
             );
-            return Ok(());
+            return Ok(0);
         }
         #[cfg(feature = "emscripten")]
         {
@@ -118,7 +157,8 @@ impl Run {
                     .map_err(|e| anyhow!("{}", e))?;
                 let mut em_env = EmEnv::new(&emscripten_globals.data, Default::default());
                 let import_object =
-                    generate_emscripten_env(module.store(), &mut emscripten_globals, &mut em_env);
+                    generate_emscripten_env(module.store(), &mut emscripten_globals, &mut em_env)
+                        .chain_back(imports);
                 let mut instance = match Instance::new(&module, &import_object) {
                     Ok(instance) => instance,
                     Err(e) => {
@@ -145,7 +185,7 @@ impl Run {
                     self.args.iter().map(|arg| arg.as_str()).collect(),
                     None, //run.em_entrypoint.clone(),
                 )?;
-                return Ok(());
+                return Ok(0);
             }
         }
 
@@ -186,7 +226,13 @@ impl Run {
                         .unwrap_or_default();
                     return self
                         .wasi
-                        .execute(module, program_name, self.args.clone())
+                        .execute(
+                            module,
+                            program_name,
+                            self.args.clone(),
+                            imports,
+                            self.coredump_on_trap.as_deref(),
+                        )
                         .with_context(|| "WASI execution failed");
                 }
                 // not WASI
@@ -194,17 +240,59 @@ impl Run {
             }
         }
 
-        // Try to instantiate the wasm file, with no provided imports
-        let imports = imports! {};
+        // Try to instantiate the wasm file, with no other imports
         let instance = Instance::new(&module, &imports)?;
         let start: Function = self.try_find_function(&instance, "_start", &[])?;
-        start.call(&[])?;
+        start
+            .call(&[])
+            .map_err(|err| self.maybe_write_coredump(&instance, err))?;
 
-        Ok(())
+        Ok(0)
+    }
+
+    fn maybe_write_coredump(&self, instance: &Instance, error: RuntimeError) -> RuntimeError {
+        if let Some(path) = &self.coredump_on_trap {
+            let executable_name = self
+                .path
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default();
+            write_coredump(path, instance, &error, &executable_name);
+        }
+        error
+    }
+
+    #[cfg(feature = "compiler")]
+    fn wait_for_debugger(&self, port: u16, module: &Module) -> Result<Arc<Mutex<GdbStub>>> {
+        // The debugger reads the code from the binary of the module
+        let contents = std::fs::read(&self.path)?;
+        #[cfg(feature = "wat")]
+        let contents = wat2wasm(&contents)?.to_vec();
+
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .with_context(|| format!("failed to listen on port {}", port))?;
+        eprintln!("Waiting for a debugger to connect to port {}", port);
+        let (stream, _) = listener.accept()?;
+        Ok(Arc::new(Mutex::new(GdbStub::new(
+            stream,
+            contents,
+            module.name().unwrap_or_default(),
+        ))))
     }
 
     fn get_module(&self) -> Result<Module> {
         let contents = std::fs::read(self.path.clone())?;
+        #[cfg(feature = "compiler")]
+        if self.gdb_port.is_some() {
+            // The module must be compiled with the hooks of the debugger,
+            // it can't be a precompiled artifact or come from the cache.
+            let (store, _, _) = self.store.get_store_with_middlewares(vec![
+                Arc::new(Debugger::new()) as Arc<dyn ModuleMiddleware>,
+            ])?;
+            let mut module = Module::new(&store, &contents)?;
+            module.set_name(&self.path.file_name().unwrap_or_default().to_string_lossy());
+            return Ok(module);
+        }
         #[cfg(feature = "native")]
         {
             if wasmer_engine_native::NativeArtifact::is_deserializable(&contents) {
@@ -410,6 +498,25 @@ impl Run {
                 )),
             })
             .collect::<Result<Vec<_>>>()?;
-        Ok(func.call(&invoke_args)?)
+        Ok(func
+            .call(&invoke_args)
+            .map_err(|err| self.maybe_write_coredump(instance, err))?)
+    }
+}
+
+/// Writes the core dump of `instance` after the trap `error` to `path`.
+pub(crate) fn write_coredump(
+    path: &Path,
+    instance: &Instance,
+    error: &RuntimeError,
+    executable_name: &str,
+) {
+    match std::fs::write(path, instance.coredump(error, executable_name)) {
+        Ok(()) => eprintln!("Core dump written to `{}`", path.display()),
+        Err(err) => warning!(
+            "failed to write the core dump to `{}`: {}",
+            path.display(),
+            err
+        ),
     }
 }
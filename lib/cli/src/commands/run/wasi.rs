@@ -1,9 +1,9 @@
 use crate::utils::{parse_envvar, parse_mapdir};
 use anyhow::{Context, Result};
 use std::collections::BTreeSet;
-use std::path::PathBuf;
-use wasmer::{Instance, Module};
-use wasmer_wasi::{get_wasi_versions, WasiError, WasiState, WasiVersion};
+use std::path::{Path, PathBuf};
+use wasmer::{ChainableNamedResolver, ImportObject, Instance, Module};
+use wasmer_wasi::{get_wasi_versions, WasiEnv, WasiError, WasiState, WasiVersion};
 
 use clap::Clap;
 
@@ -34,6 +34,10 @@ pub struct Wasi {
     /// Require WASI modules to only import 1 version of WASI.
     #[clap(long = "deny-multiple-wasi-versions")]
     pub deny_multiple_wasi_versions: bool,
+
+    /// Trace the WASI syscalls to stderr, as JSON lines
+    #[clap(long = "strace")]
+    strace: bool,
 }
 
 #[allow(dead_code)]
@@ -52,8 +56,9 @@ impl Wasi {
         get_wasi_versions(&module, false).is_some()
     }
 
-    /// Helper function for executing Wasi from the `Run` command.
-    pub fn execute(&self, module: Module, program_name: String, args: Vec<String>) -> Result<()> {
+    /// Creates the `WasiEnv` described by the options, for a program named
+    /// `program_name` called with `args`.
+    pub fn get_env(&self, program_name: String, args: Vec<String>) -> Result<WasiEnv> {
         let args = args.iter().cloned().map(|arg| arg.into_bytes());
 
         let mut wasi_state_builder = WasiState::new(program_name);
@@ -72,22 +77,47 @@ impl Wasi {
         }
 
         let mut wasi_env = wasi_state_builder.finalize()?;
-        let resolver = wasi_env.import_object_for_all_wasi_versions(&module)?;
+        if self.strace {
+            wasi_env.trace_to_writer(std::io::stderr());
+        }
+        Ok(wasi_env)
+    }
+
+    /// Helper function for executing Wasi from the `Run` command.
+    ///
+    /// Returns the exit code of the program.
+    pub fn execute(
+        &self,
+        module: Module,
+        program_name: String,
+        args: Vec<String>,
+        imports: ImportObject,
+        coredump_on_trap: Option<&Path>,
+    ) -> Result<u32> {
+        let mut wasi_env = self.get_env(program_name.clone(), args)?;
+        let resolver = wasi_env
+            .import_object_for_all_wasi_versions(&module)?
+            .chain_back(imports);
         let instance = Instance::new(&module, &resolver)?;
 
         let start = instance.exports.get_function("_start")?;
         let result = start.call(&[]);
 
         match result {
-            Ok(_) => Ok(()),
+            Ok(_) => Ok(0),
             Err(err) => {
                 let err: anyhow::Error = match err.downcast::<WasiError>() {
                     Ok(WasiError::Exit(exit_code)) => {
-                        // We should exit with the provided exit code
-                        std::process::exit(exit_code as _);
+                        // The caller exits with the provided exit code
+                        return Ok(exit_code);
                     }
                     Ok(err) => err.into(),
-                    Err(err) => err.into(),
+                    Err(err) => {
+                        if let Some(path) = coredump_on_trap {
+                            super::write_coredump(path, &instance, &err, &program_name);
+                        }
+                        err.into()
+                    }
                 };
                 Err(err)
             }
@@ -0,0 +1,82 @@
+#[cfg(feature = "wasi")]
+use crate::commands::Wasi;
+use crate::store::StoreOptions;
+use anyhow::{Context, Result};
+use clap::Clap;
+use std::path::PathBuf;
+use wasmer::*;
+
+#[derive(Debug, Clap)]
+/// The options for the `wasmer snapshot` subcommand
+pub struct Snapshot {
+    /// Input file
+    #[clap(name = "FILE", parse(from_os_str))]
+    path: PathBuf,
+
+    /// Output file
+    #[clap(name = "OUTPUT PATH", short = 'o', parse(from_os_str))]
+    output: PathBuf,
+
+    /// The exported function initializing the module, called before taking
+    /// the snapshot
+    #[clap(long = "init-func", default_value = "wizer.initialize")]
+    init_func: String,
+
+    /// Keep the init function exported from the pre-initialized module
+    #[clap(long = "keep-init-func")]
+    keep_init_func: bool,
+
+    #[clap(flatten)]
+    store: StoreOptions,
+
+    #[cfg(feature = "wasi")]
+    #[clap(flatten)]
+    wasi: Wasi,
+
+    /// Application arguments, for WASI modules
+    #[cfg(feature = "wasi")]
+    #[clap(name = "--", multiple = true)]
+    args: Vec<String>,
+}
+
+impl Snapshot {
+    /// Runs logic for the `snapshot` subcommand
+    pub fn execute(&self) -> Result<()> {
+        self.inner_execute()
+            .context(format!("failed to snapshot `{}`", self.path.display()))
+    }
+
+    fn inner_execute(&self) -> Result<()> {
+        let (store, _engine_type, _compiler_type) = self.store.get_store()?;
+        let wasm = std::fs::read(&self.path)?;
+        #[cfg(feature = "wat")]
+        let wasm = wat2wasm(&wasm)?.into_owned();
+        let module = Module::new(&store, &wasm)?;
+
+        let mut preinitializer = Preinitializer::new(&self.init_func);
+        preinitializer.keep_init_func(self.keep_init_func);
+
+        #[cfg(feature = "wasi")]
+        let preinitialized = if Wasi::has_wasi_imports(&module) {
+            let program_name = self
+                .path
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let mut wasi_env = self.wasi.get_env(program_name, self.args.clone())?;
+            let resolver = wasi_env.import_object_for_all_wasi_versions(&module)?;
+            preinitializer.run(&module, &wasm, &resolver)?
+        } else {
+            preinitializer.run(&module, &wasm, &imports! {})?
+        };
+        #[cfg(not(feature = "wasi"))]
+        let preinitialized = preinitializer.run(&module, &wasm, &imports! {})?;
+
+        std::fs::write(&self.output, preinitialized)?;
+        eprintln!(
+            "✔ Pre-initialized module written to `{}`.",
+            self.output.display(),
+        );
+        Ok(())
+    }
+}
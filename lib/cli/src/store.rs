@@ -397,6 +397,22 @@ impl StoreOptions {
         Ok((store, engine_type, compiler_type))
     }
 
+    /// Gets the store for the host target, with the given middlewares
+    /// applied by the compiler
+    pub fn get_store_with_middlewares(
+        &self,
+        middlewares: Vec<Arc<dyn ModuleMiddleware>>,
+    ) -> Result<(Store, EngineType, CompilerType)> {
+        let (mut compiler_config, compiler_type) = self.compiler.get_compiler_config()?;
+        for middleware in middlewares {
+            compiler_config.push_middleware(middleware);
+        }
+        let (engine, engine_type) =
+            self.get_engine_with_compiler(Target::default(), compiler_config)?;
+        let store = Store::new(&*engine);
+        Ok((store, engine_type, compiler_type))
+    }
+
     fn get_engine_with_compiler(
         &self,
         target: Target,
@@ -489,4 +505,13 @@ impl StoreOptions {
     ) -> Result<(Store, EngineType, CompilerType)> {
         bail!("No engines are enabled");
     }
+
+    /// Gets the store for the host target, with the given middlewares
+    #[cfg(feature = "compiler")]
+    pub fn get_store_with_middlewares(
+        &self,
+        _middlewares: Vec<Arc<dyn ModuleMiddleware>>,
+    ) -> Result<(Store, EngineType, CompilerType)> {
+        bail!("No engines are enabled");
+    }
 }
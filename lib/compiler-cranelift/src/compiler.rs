@@ -14,20 +14,21 @@ use crate::translator::{
     CraneliftUnwindInfo, FuncTranslator,
 };
 use cranelift_codegen::ir;
+use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::{binemit, Context};
 #[cfg(feature = "unwind")]
-use gimli::write::{Address, EhFrame, FrameTable};
+use gimli::write::{Address, CieId, EhFrame, FrameTable};
 use loupe::MemoryUsage;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use wasmer_compiler::CompileError;
 use wasmer_compiler::{CallingConvention, ModuleTranslationState, Target};
 use wasmer_compiler::{
     Compilation, CompileModuleInfo, CompiledFunction, CompiledFunctionFrameInfo,
     CompiledFunctionUnwindInfo, Compiler, Dwarf, FunctionBinaryReader, FunctionBody,
     FunctionBodyData, MiddlewareBinaryReader, ModuleMiddleware, ModuleMiddlewareChain,
-    SectionIndex,
+    SectionIndex, StreamingCompilation,
 };
 use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::{FunctionIndex, LocalFunctionIndex, SignatureIndex};
@@ -66,10 +67,62 @@ impl Compiler for CraneliftCompiler {
         module_translation_state: &ModuleTranslationState,
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
     ) -> Result<Compilation, CompileError> {
-        let isa = self.config().isa(target);
+        let compilation =
+            CraneliftCompilation::new(self, target, compile_info, module_translation_state);
+        let functions = function_body_inputs
+            .iter()
+            .collect::<Vec<(LocalFunctionIndex, &FunctionBodyData<'_>)>>()
+            .par_iter()
+            .map_init(FuncTranslator::new, |func_translator, (i, input)| {
+                compilation.compile_function_body(func_translator, *i, input)
+            })
+            .collect::<Result<Vec<_>, CompileError>>()?
+            .into_iter()
+            .collect::<PrimaryMap<LocalFunctionIndex, _>>();
+        compilation.finish_with(functions)
+    }
+
+    fn start_streaming_compilation<'a>(
+        &'a self,
+        target: &'a Target,
+        compile_info: &'a CompileModuleInfo,
+        module_translation_state: &'a ModuleTranslationState,
+    ) -> Option<Box<dyn StreamingCompilation + 'a>> {
+        Some(Box::new(CraneliftCompilation::new(
+            self,
+            target,
+            compile_info,
+            module_translation_state,
+        )))
+    }
+}
+
+/// The compilation of a module with Cranelift, whose functions can be
+/// compiled concurrently.
+struct CraneliftCompilation<'a> {
+    compiler: &'a CraneliftCompiler,
+    #[cfg(feature = "unwind")]
+    target: &'a Target,
+    isa: Box<dyn TargetIsa>,
+    compile_info: &'a CompileModuleInfo,
+    module_translation_state: &'a ModuleTranslationState,
+    signatures: PrimaryMap<SignatureIndex, ir::Signature>,
+    #[cfg(feature = "unwind")]
+    dwarf_frametable: Option<(Mutex<FrameTable>, CieId)>,
+
+    /// The functions compiled by `StreamingCompilation::compile_function`.
+    functions: Mutex<Vec<(LocalFunctionIndex, CompiledFunction)>>,
+}
+
+impl<'a> CraneliftCompilation<'a> {
+    fn new(
+        compiler: &'a CraneliftCompiler,
+        target: &'a Target,
+        compile_info: &'a CompileModuleInfo,
+        module_translation_state: &'a ModuleTranslationState,
+    ) -> Self {
+        let isa = compiler.config().isa(target);
         let frontend_config = isa.frontend_config();
-        let memory_styles = &compile_info.memory_styles;
-        let table_styles = &compile_info.table_styles;
         let module = &compile_info.module;
         let signatures = module
             .signatures
@@ -79,20 +132,19 @@ impl Compiler for CraneliftCompiler {
 
         // Generate the frametable
         #[cfg(feature = "unwind")]
-        let dwarf_frametable = if function_body_inputs.is_empty() {
-            // If we have no function body inputs, we don't need to
+        let dwarf_frametable = if module.functions.len() == module.num_imported_functions {
+            // If we have no local functions, we don't need to
             // construct the `FrameTable`. Constructing it, with empty
             // FDEs will cause some issues in Linux.
             None
         } else {
-            use std::sync::Mutex;
             match target.triple().default_calling_convention() {
                 Ok(CallingConvention::SystemV) => {
                     match isa.create_systemv_cie() {
                         Some(cie) => {
                             let mut dwarf_frametable = FrameTable::default();
                             let cie_id = dwarf_frametable.add_cie(cie);
-                            Some((Arc::new(Mutex::new(dwarf_frametable)), cie_id))
+                            Some((Mutex::new(dwarf_frametable), cie_id))
                         }
                         // Even though we are in a SystemV system, Cranelift doesn't support it
                         None => None,
@@ -102,114 +154,140 @@ impl Compiler for CraneliftCompiler {
             }
         };
 
-        let functions = function_body_inputs
-            .iter()
-            .collect::<Vec<(LocalFunctionIndex, &FunctionBodyData<'_>)>>()
-            .par_iter()
-            .map_init(FuncTranslator::new, |func_translator, (i, input)| {
-                let func_index = module.func_index(*i);
-                let mut context = Context::new();
-                let mut func_env = FuncEnvironment::new(
-                    isa.frontend_config(),
-                    module,
-                    &signatures,
-                    &memory_styles,
-                    &table_styles,
-                );
-                context.func.name = get_function_name(func_index);
-                context.func.signature = signatures[module.functions[func_index]].clone();
-                // if generate_debug_info {
-                //     context.func.collect_debug_info();
-                // }
-                let mut reader =
-                    MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
-                reader.set_middleware_chain(
-                    self.config
-                        .middlewares
-                        .generate_function_middleware_chain(*i),
-                );
+        Self {
+            compiler,
+            #[cfg(feature = "unwind")]
+            target,
+            isa,
+            compile_info,
+            module_translation_state,
+            signatures,
+            #[cfg(feature = "unwind")]
+            dwarf_frametable,
+            functions: Mutex::new(Vec::new()),
+        }
+    }
 
-                func_translator.translate(
-                    module_translation_state,
-                    &mut reader,
-                    &mut context.func,
-                    &mut func_env,
-                    *i,
-                )?;
+    fn compile_function_body(
+        &self,
+        func_translator: &mut FuncTranslator,
+        i: LocalFunctionIndex,
+        input: &FunctionBodyData<'_>,
+    ) -> Result<CompiledFunction, CompileError> {
+        let isa = &self.isa;
+        let module = &self.compile_info.module;
+        let func_index = module.func_index(i);
+        let mut context = Context::new();
+        let mut func_env = FuncEnvironment::new(
+            isa.frontend_config(),
+            module,
+            &self.signatures,
+            &self.compile_info.memory_styles,
+            &self.compile_info.table_styles,
+        );
+        context.func.name = get_function_name(func_index);
+        context.func.signature = self.signatures[module.functions[func_index]].clone();
+        // if generate_debug_info {
+        //     context.func.collect_debug_info();
+        // }
+        let mut reader = MiddlewareBinaryReader::new_with_offset(&input.data, input.module_offset);
+        if !input.from_middleware {
+            reader.set_middleware_chain(
+                self.compiler
+                    .config
+                    .middlewares
+                    .generate_function_middleware_chain(i),
+            );
+        }
 
-                let mut code_buf: Vec<u8> = Vec::new();
-                let mut reloc_sink = RelocSink::new(&module, func_index);
-                let mut trap_sink = TrapSink::new();
-                let mut stackmap_sink = binemit::NullStackMapSink {};
-                context
-                    .compile_and_emit(
-                        &*isa,
-                        &mut code_buf,
-                        &mut reloc_sink,
-                        &mut trap_sink,
-                        &mut stackmap_sink,
-                    )
-                    .map_err(|error| {
-                        CompileError::Codegen(pretty_error(&context.func, Some(&*isa), error))
-                    })?;
+        func_translator.translate(
+            self.module_translation_state,
+            &mut reader,
+            &mut context.func,
+            &mut func_env,
+            i,
+        )?;
 
-                let unwind_info = match compiled_function_unwind_info(&*isa, &context)? {
-                    #[cfg(feature = "unwind")]
-                    CraneliftUnwindInfo::FDE(fde) => {
-                        if let Some((dwarf_frametable, cie_id)) = &dwarf_frametable {
-                            dwarf_frametable
-                                .lock()
-                                .expect("Can't write into DWARF frametable")
-                                .add_fde(
-                                    *cie_id,
-                                    fde.to_fde(Address::Symbol {
-                                        // The symbol is the kind of relocation.
-                                        // "0" is used for functions
-                                        symbol: WriterRelocate::FUNCTION_SYMBOL,
-                                        // We use the addend as a way to specify the
-                                        // function index
-                                        addend: i.index() as _,
-                                    }),
-                                );
-                            // The unwind information is inserted into the dwarf section
-                            Some(CompiledFunctionUnwindInfo::Dwarf)
-                        } else {
-                            None
-                        }
-                    }
-                    other => other.maybe_into_to_windows_unwind(),
-                };
+        let mut code_buf: Vec<u8> = Vec::new();
+        let mut reloc_sink = RelocSink::new(&module, func_index);
+        let mut trap_sink = TrapSink::new();
+        let mut stackmap_sink = binemit::NullStackMapSink {};
+        context
+            .compile_and_emit(
+                &**isa,
+                &mut code_buf,
+                &mut reloc_sink,
+                &mut trap_sink,
+                &mut stackmap_sink,
+            )
+            .map_err(|error| {
+                CompileError::Codegen(pretty_error(&context.func, Some(&**isa), error))
+            })?;
 
-                let range = reader.range();
-                let address_map = get_function_address_map(&context, range, code_buf.len(), &*isa);
+        let unwind_info = match compiled_function_unwind_info(&**isa, &context)? {
+            #[cfg(feature = "unwind")]
+            CraneliftUnwindInfo::FDE(fde) => {
+                if let Some((dwarf_frametable, cie_id)) = &self.dwarf_frametable {
+                    dwarf_frametable
+                        .lock()
+                        .expect("Can't write into DWARF frametable")
+                        .add_fde(
+                            *cie_id,
+                            fde.to_fde(Address::Symbol {
+                                // The symbol is the kind of relocation.
+                                // "0" is used for functions
+                                symbol: WriterRelocate::FUNCTION_SYMBOL,
+                                // We use the addend as a way to specify the
+                                // function index
+                                addend: i.index() as _,
+                            }),
+                        );
+                    // The unwind information is inserted into the dwarf section
+                    Some(CompiledFunctionUnwindInfo::Dwarf)
+                } else {
+                    None
+                }
+            }
+            other => other.maybe_into_to_windows_unwind(),
+        };
 
-                // We transform the Cranelift JumpTable's into compiler JumpTables
-                let func_jt_offsets = transform_jump_table(context.func.jt_offsets);
+        let range = reader.range();
+        let address_map = get_function_address_map(&context, range, code_buf.len(), &**isa);
 
-                Ok(CompiledFunction {
-                    body: FunctionBody {
-                        body: code_buf,
-                        unwind_info,
-                    },
-                    jt_offsets: func_jt_offsets,
-                    relocations: reloc_sink.func_relocs,
-                    frame_info: CompiledFunctionFrameInfo {
-                        address_map,
-                        traps: trap_sink.traps,
-                    },
-                })
-            })
-            .collect::<Result<Vec<_>, CompileError>>()?
-            .into_iter()
-            .collect::<PrimaryMap<LocalFunctionIndex, _>>();
+        // We transform the Cranelift JumpTable's into compiler JumpTables
+        let func_jt_offsets = transform_jump_table(context.func.jt_offsets);
+
+        Ok(CompiledFunction {
+            body: FunctionBody {
+                body: code_buf,
+                unwind_info,
+            },
+            jt_offsets: func_jt_offsets,
+            relocations: reloc_sink.func_relocs,
+            frame_info: CompiledFunctionFrameInfo {
+                address_map,
+                traps: trap_sink.traps,
+            },
+        })
+    }
+
+    /// Finishes the compilation of the module, whose local functions are
+    /// `functions`.
+    fn finish_with(
+        self,
+        functions: PrimaryMap<LocalFunctionIndex, CompiledFunction>,
+    ) -> Result<Compilation, CompileError> {
+        let isa = &self.isa;
+        let module = &self.compile_info.module;
 
         #[cfg(feature = "unwind")]
         let (custom_sections, dwarf) = {
             let mut custom_sections = PrimaryMap::new();
-            let dwarf = if let Some((dwarf_frametable, _cie_id)) = dwarf_frametable {
-                let mut eh_frame = EhFrame(WriterRelocate::new(target.triple().endianness().ok()));
+            let dwarf = if let Some((dwarf_frametable, _cie_id)) = self.dwarf_frametable {
+                let mut eh_frame =
+                    EhFrame(WriterRelocate::new(self.target.triple().endianness().ok()));
                 dwarf_frametable
-                    .lock()
+                    .into_inner()
                     .unwrap()
                     .write_eh_frame(&mut eh_frame)
                     .unwrap();
@@ -232,21 +310,21 @@ impl Compiler for CraneliftCompiler {
             .collect::<Vec<_>>()
             .par_iter()
             .map_init(FunctionBuilderContext::new, |mut cx, sig| {
-                make_trampoline_function_call(&*isa, &mut cx, sig)
+                make_trampoline_function_call(&**isa, &mut cx, sig)
             })
             .collect::<Result<Vec<FunctionBody>, CompileError>>()?
             .into_iter()
             .collect::<PrimaryMap<SignatureIndex, FunctionBody>>();
 
         use wasmer_vm::VMOffsets;
-        let offsets = VMOffsets::new_for_trampolines(frontend_config.pointer_bytes());
+        let offsets = VMOffsets::new_for_trampolines(isa.frontend_config().pointer_bytes());
         // dynamic function trampolines (only for imported functions)
         let dynamic_function_trampolines = module
             .imported_function_types()
             .collect::<Vec<_>>()
             .par_iter()
             .map_init(FunctionBuilderContext::new, |mut cx, func_type| {
-                make_trampoline_dynamic_function(&*isa, &offsets, &mut cx, &func_type)
+                make_trampoline_dynamic_function(&**isa, &offsets, &mut cx, &func_type)
             })
             .collect::<Result<Vec<_>, CompileError>>()?
             .into_iter()
@@ -261,3 +339,31 @@ impl Compiler for CraneliftCompiler {
         ))
     }
 }
+
+impl StreamingCompilation for CraneliftCompilation<'_> {
+    fn compile_function(
+        &self,
+        index: LocalFunctionIndex,
+        body: &FunctionBodyData<'_>,
+    ) -> Result<(), CompileError> {
+        let function = self.compile_function_body(&mut FuncTranslator::new(), index, body)?;
+        self.functions.lock().unwrap().push((index, function));
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<Compilation, CompileError> {
+        let mut functions = std::mem::take(&mut *self.functions.lock().unwrap());
+        functions.sort_by_key(|(index, _)| *index);
+        let module = &self.compile_info.module;
+        if functions.len() != module.functions.len() - module.num_imported_functions {
+            return Err(CompileError::Codegen(
+                "The bodies of some functions weren't compiled".to_string(),
+            ));
+        }
+        let functions = functions
+            .into_iter()
+            .map(|(_, function)| function)
+            .collect::<PrimaryMap<LocalFunctionIndex, _>>();
+        self.finish_with(functions)
+    }
+}
@@ -131,14 +131,16 @@ impl FuncTranslator {
         builder.position_at_end(start_of_code);
 
         let mut reader = MiddlewareBinaryReader::new_with_offset(
-            function_body.data,
+            &function_body.data,
             function_body.module_offset,
         );
-        reader.set_middleware_chain(
-            config
-                .middlewares
-                .generate_function_middleware_chain(*local_func_index),
-        );
+        if !function_body.from_middleware {
+            reader.set_middleware_chain(
+                config
+                    .middlewares
+                    .generate_function_middleware_chain(*local_func_index),
+            );
+        }
 
         let mut params = vec![];
         let first_param =
@@ -86,13 +86,15 @@ impl Compiler for SinglepassCompiler {
             .collect::<Vec<(LocalFunctionIndex, &FunctionBodyData<'_>)>>()
             .into_par_iter_if_rayon()
             .map(|(i, input)| {
-                let middleware_chain = self
-                    .config
-                    .middlewares
-                    .generate_function_middleware_chain(i);
                 let mut reader =
-                    MiddlewareBinaryReader::new_with_offset(input.data, input.module_offset);
-                reader.set_middleware_chain(middleware_chain);
+                    MiddlewareBinaryReader::new_with_offset(&input.data, input.module_offset);
+                if !input.from_middleware {
+                    let middleware_chain = self
+                        .config
+                        .middlewares
+                        .generate_function_middleware_chain(i);
+                    reader.set_middleware_chain(middleware_chain);
+                }
 
                 // This local list excludes arguments.
                 let mut locals = vec![];
@@ -59,7 +59,10 @@ where
 }
 
 /// An implementation of a Compiler from parsed WebAssembly module to Compiled native code.
-pub trait Compiler: Send + MemoryUsage {
+///
+/// A compiler is shared by the modules compiled concurrently by an engine,
+/// so it must be `Sync`.
+pub trait Compiler: Send + Sync + MemoryUsage {
     /// Validates a module.
     ///
     /// It returns the a succesful Result in case is valid, `CompileError` in case is not.
@@ -68,21 +71,7 @@ pub trait Compiler: Send + MemoryUsage {
         features: &Features,
         data: &'data [u8],
     ) -> Result<(), CompileError> {
-        let mut validator = Validator::new();
-        let wasm_features = WasmFeatures {
-            bulk_memory: features.bulk_memory,
-            threads: features.threads,
-            reference_types: features.reference_types,
-            multi_value: features.multi_value,
-            simd: features.simd,
-            tail_call: features.tail_call,
-            module_linking: features.module_linking,
-            multi_memory: features.multi_memory,
-            memory64: features.memory64,
-            exceptions: features.exceptions,
-            deterministic_only: false,
-        };
-        validator.wasm_features(wasm_features);
+        let mut validator = validator(features);
         validator
             .validate_all(data)
             .map_err(|e| CompileError::Validate(format!("{}", e)))?;
@@ -101,6 +90,21 @@ pub trait Compiler: Send + MemoryUsage {
         function_body_inputs: PrimaryMap<LocalFunctionIndex, FunctionBodyData<'data>>,
     ) -> Result<Compilation, CompileError>;
 
+    /// Starts compiling a module whose function bodies are given one at a
+    /// time, for example as they are received by a
+    /// [`ModuleStreamTranslator`](crate::ModuleStreamTranslator).
+    ///
+    /// It returns `None` if the compiler can only compile all the function
+    /// bodies at once, with [`Compiler::compile_module`].
+    fn start_streaming_compilation<'a>(
+        &'a self,
+        _target: &'a Target,
+        _module: &'a CompileModuleInfo,
+        _module_translation: &'a ModuleTranslationState,
+    ) -> Option<Box<dyn StreamingCompilation + 'a>> {
+        None
+    }
+
     /// Compiles a module into a native object file.
     ///
     /// It returns the bytes as a `&[u8]` or a [`CompileError`].
@@ -122,6 +126,42 @@ pub trait Compiler: Send + MemoryUsage {
     fn get_middlewares(&self) -> &[Arc<dyn ModuleMiddleware>];
 }
 
+/// The compilation of a module whose function bodies are given one at a
+/// time, started by [`Compiler::start_streaming_compilation`].
+pub trait StreamingCompilation: Send + Sync {
+    /// Compiles the body of a local function.
+    ///
+    /// The bodies may be compiled concurrently, in any order.
+    fn compile_function(
+        &self,
+        index: LocalFunctionIndex,
+        body: &FunctionBodyData<'_>,
+    ) -> Result<(), CompileError>;
+
+    /// Finishes the compilation once all the function bodies are compiled.
+    fn finish(self: Box<Self>) -> Result<Compilation, CompileError>;
+}
+
+/// Creates a validator for the WebAssembly `features`.
+pub(crate) fn validator(features: &Features) -> Validator {
+    let mut validator = Validator::new();
+    let wasm_features = WasmFeatures {
+        bulk_memory: features.bulk_memory,
+        threads: features.threads,
+        reference_types: features.reference_types,
+        multi_value: features.multi_value,
+        simd: features.simd,
+        tail_call: features.tail_call,
+        module_linking: features.module_linking,
+        multi_memory: features.multi_memory,
+        memory64: features.memory64,
+        exceptions: features.exceptions,
+        deterministic_only: false,
+    };
+    validator.wasm_features(wasm_features);
+    validator
+}
+
 /// The kinds of wasmer_types objects that might be found in a native object file.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Symbol {
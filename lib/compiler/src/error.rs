@@ -1,4 +1,5 @@
 use crate::lib::std::string::String;
+use crate::limits::ModuleLimit;
 #[cfg(feature = "std")]
 use thiserror::Error;
 
@@ -40,6 +41,20 @@ pub enum CompileError {
     /// Insufficient resources available for execution.
     #[cfg_attr(feature = "std", error("Insufficient resources: {0}"))]
     Resource(String),
+
+    /// The module exceeds one of its [`ModuleLimits`](crate::ModuleLimits).
+    #[cfg_attr(
+        feature = "std",
+        error("Module limit exceeded: the {limit} is {actual}, over the limit of {maximum}")
+    )]
+    LimitExceeded {
+        /// The limit exceeded.
+        limit: ModuleLimit,
+        /// The value exceeding the limit.
+        actual: u64,
+        /// The maximum value allowed by the limit.
+        maximum: u64,
+    },
 }
 
 impl From<WasmError> for CompileError {
@@ -55,6 +55,7 @@ mod compiler;
 mod error;
 mod function;
 mod jump_table;
+mod limits;
 mod module;
 mod relocation;
 mod target;
@@ -68,7 +69,7 @@ mod sourceloc;
 
 pub use crate::address_map::{FunctionAddressMap, InstructionAddressMap};
 #[cfg(feature = "translator")]
-pub use crate::compiler::{Compiler, CompilerConfig, Symbol, SymbolRegistry};
+pub use crate::compiler::{Compiler, CompilerConfig, StreamingCompilation, Symbol, SymbolRegistry};
 pub use crate::error::{
     CompileError, MiddlewareError, ParseCpuFeatureError, WasmError, WasmResult,
 };
@@ -77,6 +78,7 @@ pub use crate::function::{
     Functions,
 };
 pub use crate::jump_table::{JumpTable, JumpTableOffsets};
+pub use crate::limits::{ModuleLimit, ModuleLimits};
 pub use crate::module::CompileModuleInfo;
 pub use crate::relocation::{Relocation, RelocationKind, RelocationTarget, Relocations};
 pub use crate::section::{CustomSection, CustomSectionProtection, SectionBody, SectionIndex};
@@ -87,9 +89,10 @@ pub use crate::target::{
 };
 #[cfg(feature = "translator")]
 pub use crate::translator::{
-    translate_module, wptype_to_type, FunctionBinaryReader, FunctionBodyData, FunctionMiddleware,
-    MiddlewareBinaryReader, MiddlewareReaderState, ModuleEnvironment, ModuleInfoTranslation,
-    ModuleMiddleware, ModuleMiddlewareChain, ModuleTranslationState,
+    translate_module, wptype_to_type, FunctionAdditions, FunctionBinaryReader, FunctionBodyData,
+    FunctionMiddleware, MiddlewareBinaryReader, MiddlewareReaderState, ModuleEnvironment,
+    ModuleInfoTranslation, ModuleMiddleware, ModuleMiddlewareChain, ModuleStreamTranslation,
+    ModuleStreamTranslator, ModuleTranslationState, StreamedFunctionBody,
 };
 pub use crate::trap::TrapInformation;
 pub use crate::unwind::CompiledFunctionUnwindInfo;
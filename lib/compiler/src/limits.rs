@@ -0,0 +1,284 @@
+//! Limits a WebAssembly module must satisfy to be compiled.
+use crate::lib::std::fmt;
+#[cfg(feature = "translator")]
+use crate::lib::std::string::ToString;
+#[cfg(feature = "translator")]
+use crate::CompileError;
+use loupe::MemoryUsage;
+use wasmer_types::Pages;
+#[cfg(feature = "translator")]
+use wasmparser::{MemoryType, Parser, Payload};
+
+/// The limits a WebAssembly module must satisfy to be compiled, so that
+/// modules from untrusted sources can't exhaust the resources of the
+/// compiler.
+///
+/// A limit set to `None` isn't enforced, which is the default for all of
+/// them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, MemoryUsage)]
+pub struct ModuleLimits {
+    /// The maximum number of functions defined by the module.
+    pub max_functions: Option<u32>,
+
+    /// The maximum number of imports of the module.
+    pub max_imports: Option<u32>,
+
+    /// The maximum number of exports of the module.
+    pub max_exports: Option<u32>,
+
+    /// The maximum number of globals defined by the module.
+    pub max_globals: Option<u32>,
+
+    /// The maximum number of data segments of the module.
+    pub max_data_segments: Option<u32>,
+
+    /// The maximum minimum size, in elements, of the tables defined by
+    /// the module.
+    pub max_table_elements: Option<u32>,
+
+    /// The maximum minimum size of the memories defined by the module.
+    pub max_memory_pages: Option<Pages>,
+
+    /// The maximum number of locals declared by a function, excluding its
+    /// parameters.
+    pub max_locals_per_function: Option<u32>,
+
+    /// The maximum size in bytes of the body of a function.
+    pub max_function_body_size: Option<u32>,
+}
+
+/// A limit of the [`ModuleLimits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleLimit {
+    /// [`ModuleLimits::max_functions`].
+    Functions,
+    /// [`ModuleLimits::max_imports`].
+    Imports,
+    /// [`ModuleLimits::max_exports`].
+    Exports,
+    /// [`ModuleLimits::max_globals`].
+    Globals,
+    /// [`ModuleLimits::max_data_segments`].
+    DataSegments,
+    /// [`ModuleLimits::max_table_elements`].
+    TableElements,
+    /// [`ModuleLimits::max_memory_pages`].
+    MemoryPages,
+    /// [`ModuleLimits::max_locals_per_function`].
+    LocalsPerFunction,
+    /// [`ModuleLimits::max_function_body_size`].
+    FunctionBodySize,
+}
+
+impl fmt::Display for ModuleLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Functions => "number of functions",
+            Self::Imports => "number of imports",
+            Self::Exports => "number of exports",
+            Self::Globals => "number of globals",
+            Self::DataSegments => "number of data segments",
+            Self::TableElements => "minimum size of a table",
+            Self::MemoryPages => "minimum number of pages of a memory",
+            Self::LocalsPerFunction => "number of locals of a function",
+            Self::FunctionBodySize => "size of a function body",
+        })
+    }
+}
+
+#[cfg(feature = "translator")]
+impl ModuleLimits {
+    /// Checks that the WebAssembly `binary` doesn't exceed the limits.
+    ///
+    /// Only the sections and function bodies the limits apply to are
+    /// decoded, so this is meant to be done before validating the binary.
+    pub fn validate(&self, binary: &[u8]) -> Result<(), CompileError> {
+        for payload in Parser::new(0).parse_all(binary) {
+            let payload = payload.map_err(|e| CompileError::Validate(e.to_string()))?;
+            self.check_payload(&payload)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that a section or function body doesn't exceed the limits.
+    pub(crate) fn check_payload(&self, payload: &Payload) -> Result<(), CompileError> {
+        match payload {
+            Payload::FunctionSection(functions) => check(
+                ModuleLimit::Functions,
+                functions.get_count(),
+                self.max_functions,
+            ),
+            Payload::ImportSection(imports) => {
+                check(ModuleLimit::Imports, imports.get_count(), self.max_imports)
+            }
+            Payload::ExportSection(exports) => {
+                check(ModuleLimit::Exports, exports.get_count(), self.max_exports)
+            }
+            Payload::GlobalSection(globals) => {
+                check(ModuleLimit::Globals, globals.get_count(), self.max_globals)
+            }
+            Payload::DataSection(data) => check(
+                ModuleLimit::DataSegments,
+                data.get_count(),
+                self.max_data_segments,
+            ),
+            Payload::TableSection(tables) if self.max_table_elements.is_some() => {
+                for table in tables.clone() {
+                    let table = table.map_err(|e| CompileError::Validate(e.to_string()))?;
+                    check(
+                        ModuleLimit::TableElements,
+                        table.limits.initial,
+                        self.max_table_elements,
+                    )?;
+                }
+                Ok(())
+            }
+            Payload::MemorySection(memories) if self.max_memory_pages.is_some() => {
+                let maximum = self.max_memory_pages.map(|pages| pages.0);
+                for memory in memories.clone() {
+                    let initial = match memory.map_err(|e| CompileError::Validate(e.to_string()))? {
+                        MemoryType::M32 { limits, .. } => u64::from(limits.initial),
+                        MemoryType::M64 { limits, .. } => limits.initial,
+                    };
+                    check(ModuleLimit::MemoryPages, initial, maximum)?;
+                }
+                Ok(())
+            }
+            Payload::CodeSectionEntry(body) => {
+                let range = body.range();
+                check(
+                    ModuleLimit::FunctionBodySize,
+                    (range.end - range.start) as u64,
+                    self.max_function_body_size,
+                )?;
+                if self.max_locals_per_function.is_some() {
+                    let mut locals = body
+                        .get_locals_reader()
+                        .map_err(|e| CompileError::Validate(e.to_string()))?;
+                    let mut count = 0;
+                    for _ in 0..locals.get_count() {
+                        let (locals_count, _) = locals
+                            .read()
+                            .map_err(|e| CompileError::Validate(e.to_string()))?;
+                        count += u64::from(locals_count);
+                    }
+                    check(
+                        ModuleLimit::LocalsPerFunction,
+                        count,
+                        self.max_locals_per_function,
+                    )?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks that `actual` doesn't exceed the `maximum` of `limit`, if any.
+#[cfg(feature = "translator")]
+fn check(
+    limit: ModuleLimit,
+    actual: impl Into<u64>,
+    maximum: Option<u32>,
+) -> Result<(), CompileError> {
+    let actual = actual.into();
+    match maximum {
+        Some(maximum) if actual > u64::from(maximum) => Err(CompileError::LimitExceeded {
+            limit,
+            actual,
+            maximum: maximum.into(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(all(test, feature = "translator"))]
+mod tests {
+    use super::*;
+
+    /// The module
+    /// ```wat
+    /// (module
+    ///   (memory 2)
+    ///   (func (export "f") (local i32 i32 i64)))
+    /// ```
+    const MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // types
+        0x03, 0x02, 0x01, 0x00, // functions
+        0x05, 0x03, 0x01, 0x00, 0x02, // memories
+        0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x00, // exports
+        0x0a, 0x08, 0x01, 0x06, 0x02, 0x02, 0x7f, 0x01, 0x7e, 0x0b, // code
+    ];
+
+    fn exceeded(limits: ModuleLimits) -> Option<(ModuleLimit, u64, u64)> {
+        match limits.validate(MODULE) {
+            Ok(()) => None,
+            Err(CompileError::LimitExceeded {
+                limit,
+                actual,
+                maximum,
+            }) => Some((limit, actual, maximum)),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn accepts_modules_within_limits() {
+        assert_eq!(exceeded(ModuleLimits::default()), None);
+        assert_eq!(
+            exceeded(ModuleLimits {
+                max_functions: Some(1),
+                max_imports: Some(0),
+                max_exports: Some(1),
+                max_globals: Some(0),
+                max_data_segments: Some(0),
+                max_table_elements: Some(0),
+                max_memory_pages: Some(Pages(2)),
+                max_locals_per_function: Some(3),
+                max_function_body_size: Some(6),
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn names_the_limit_exceeded() {
+        assert_eq!(
+            exceeded(ModuleLimits {
+                max_functions: Some(0),
+                ..Default::default()
+            }),
+            Some((ModuleLimit::Functions, 1, 0))
+        );
+        assert_eq!(
+            exceeded(ModuleLimits {
+                max_exports: Some(0),
+                ..Default::default()
+            }),
+            Some((ModuleLimit::Exports, 1, 0))
+        );
+        assert_eq!(
+            exceeded(ModuleLimits {
+                max_memory_pages: Some(Pages(1)),
+                ..Default::default()
+            }),
+            Some((ModuleLimit::MemoryPages, 2, 1))
+        );
+        assert_eq!(
+            exceeded(ModuleLimits {
+                max_locals_per_function: Some(2),
+                ..Default::default()
+            }),
+            Some((ModuleLimit::LocalsPerFunction, 3, 2))
+        );
+        assert_eq!(
+            exceeded(ModuleLimits {
+                max_function_body_size: Some(5),
+                ..Default::default()
+            }),
+            Some((ModuleLimit::FunctionBodySize, 6, 5))
+        );
+    }
+}
@@ -3,12 +3,13 @@
 
 use super::module::translate_module;
 use super::state::ModuleTranslationState;
-use crate::lib::std::borrow::ToOwned;
+use crate::lib::std::borrow::{Cow, ToOwned};
 use crate::lib::std::string::ToString;
 use crate::lib::std::{boxed::Box, string::String, vec::Vec};
 use crate::wasmparser::{Operator, Range, Type};
 use crate::{WasmError, WasmResult};
 use std::convert::{TryFrom, TryInto};
+use std::mem;
 use std::sync::Arc;
 use wasmer_types::entity::PrimaryMap;
 use wasmer_types::FunctionType;
@@ -24,10 +25,14 @@ use wasmer_vm::ModuleInfo;
 #[derive(Hash)]
 pub struct FunctionBodyData<'a> {
     /// Function body bytecode.
-    pub data: &'a [u8],
+    pub data: Cow<'a, [u8]>,
 
     /// Body offset relative to the module file.
     pub module_offset: usize,
+
+    /// Whether the function was added by a middleware, in which case its
+    /// body isn't transformed by the middlewares.
+    pub from_middleware: bool,
 }
 
 /// Trait for iterating over the operators of a Wasm Function
@@ -105,6 +110,13 @@ impl<'data> ModuleEnvironment<'data> {
         Ok(self.result)
     }
 
+    /// Swaps the module being translated with the one of `other`, which
+    /// may borrow data for a different lifetime.
+    pub(crate) fn swap_module(&mut self, other: &mut ModuleEnvironment<'_>) {
+        mem::swap(&mut self.result.module, &mut other.result.module);
+        mem::swap(&mut self.imports, &mut other.imports);
+    }
+
     pub(crate) fn declare_export(&mut self, export: ExportIndex, name: &str) -> WasmResult<()> {
         self.result
             .module
@@ -403,8 +415,9 @@ impl<'data> ModuleEnvironment<'data> {
         body_offset: usize,
     ) -> WasmResult<()> {
         self.result.function_body_inputs.push(FunctionBodyData {
-            data: body_bytes,
+            data: Cow::Borrowed(body_bytes),
             module_offset: body_offset,
+            from_middleware: false,
         });
         Ok(())
     }
@@ -3,15 +3,19 @@
 
 use loupe::MemoryUsage;
 use smallvec::SmallVec;
+use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::ops::Deref;
-use wasmer_types::LocalFunctionIndex;
+use wasmer_types::entity::{EntityRef, PrimaryMap};
+use wasmer_types::{
+    ExportIndex, FunctionIndex, FunctionType, GlobalInit, ImportIndex, LocalFunctionIndex,
+};
 use wasmer_vm::ModuleInfo;
 use wasmparser::{BinaryReader, Operator, Range, Type};
 
-use crate::error::{MiddlewareError, WasmResult};
-use crate::translator::environ::FunctionBinaryReader;
+use crate::error::{CompileError, MiddlewareError, WasmResult};
+use crate::translator::environ::{FunctionBinaryReader, FunctionBodyData};
 
 /// A shared builder for function middlewares.
 pub trait ModuleMiddleware: Debug + Send + Sync + MemoryUsage {
@@ -26,7 +30,20 @@ pub trait ModuleMiddleware: Debug + Send + Sync + MemoryUsage {
     ) -> Box<dyn FunctionMiddleware>;
 
     /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    ///
+    /// Functions can be added to the module with [`FunctionAdditions`].
     fn transform_module_info(&self, _: &mut ModuleInfo) {}
+
+    /// Returns the bodies of the local functions added by `transform_module_info`.
+    ///
+    /// This is called once the whole chain has been applied on the `ModuleInfo`, so the bodies
+    /// must use the final indexes of the functions, as found in `module_info`. A body is encoded
+    /// like in the code section, without its size: the declarations of its locals followed by
+    /// its instructions. The bodies are not transformed by the middlewares, and their blocks
+    /// can't use the signatures added by the middlewares as block types.
+    fn function_bodies(&self, _module_info: &ModuleInfo) -> Vec<(LocalFunctionIndex, Vec<u8>)> {
+        Vec::new()
+    }
 }
 
 /// A function middleware specialized for a single function.
@@ -60,6 +77,12 @@ pub struct MiddlewareReaderState<'a> {
 
     /// The pending operations added by the middleware.
     pending_operations: VecDeque<Operator<'a>>,
+
+    /// The declarations of the locals of the function.
+    local_decls: Vec<(u32, Type)>,
+
+    /// A raw binary reader positioned at the operator being processed.
+    operator_reader: BinaryReader<'a>,
 }
 
 /// Trait for generating middleware chains from "prototype" (generator) chains.
@@ -72,6 +95,16 @@ pub trait ModuleMiddlewareChain {
 
     /// Applies the chain on a `ModuleInfo` struct.
     fn apply_on_module_info(&self, module_info: &mut ModuleInfo);
+
+    /// Appends the bodies of the functions added by the chain to `function_body_inputs`.
+    ///
+    /// This is called after `apply_on_module_info`. Fails if the bodies given by the
+    /// middlewares don't match the local functions of `module_info`.
+    fn add_function_bodies(
+        &self,
+        module_info: &ModuleInfo,
+        function_body_inputs: &mut PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
+    ) -> Result<(), CompileError>;
 }
 
 impl<T: Deref<Target = dyn ModuleMiddleware>> ModuleMiddlewareChain for [T] {
@@ -91,6 +124,182 @@ impl<T: Deref<Target = dyn ModuleMiddleware>> ModuleMiddlewareChain for [T] {
             item.transform_module_info(module_info);
         }
     }
+
+    /// Appends the bodies of the functions added by the chain to `function_body_inputs`.
+    fn add_function_bodies(
+        &self,
+        module_info: &ModuleInfo,
+        function_body_inputs: &mut PrimaryMap<LocalFunctionIndex, FunctionBodyData<'_>>,
+    ) -> Result<(), CompileError> {
+        let mut bodies = self
+            .iter()
+            .flat_map(|x| x.function_bodies(module_info))
+            .collect::<Vec<_>>();
+        bodies.sort_by_key(|(local_function_index, _)| *local_function_index);
+        for (local_function_index, body) in bodies {
+            let index = function_body_inputs.push(FunctionBodyData {
+                data: Cow::Owned(body),
+                module_offset: 0,
+                from_middleware: true,
+            });
+            if index != local_function_index {
+                return Err(CompileError::Codegen(format!(
+                    "the body of the local function {} is missing or duplicated",
+                    index.index()
+                )));
+            }
+        }
+        if function_body_inputs.len()
+            != module_info.functions.len() - module_info.num_imported_functions
+        {
+            return Err(CompileError::Codegen(
+                "the bodies of some functions added by the middlewares are missing".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The functions added to a module by a middleware, in
+/// [`ModuleMiddleware::transform_module_info`].
+///
+/// The imported functions are added after the other imported functions of
+/// the module, and the local functions after the other local functions. The
+/// indexes of the functions in the `ModuleInfo` (exports, start function,
+/// element segments, global initializers and names) are shifted past the
+/// added imported functions, and the indexes of the functions in the
+/// operators fed to the function middlewares must be shifted with
+/// [`FunctionAdditions::remap`].
+///
+/// The added local functions are exported by inserting their index in
+/// `module_info.exports`, and their bodies are given by
+/// [`ModuleMiddleware::function_bodies`].
+#[derive(Debug, Clone)]
+pub struct FunctionAdditions {
+    /// The number of imported functions of the module before the additions.
+    num_imported_functions: u32,
+
+    /// The number of added imported functions.
+    num_added_imports: u32,
+}
+
+impl FunctionAdditions {
+    /// Creates a `FunctionAdditions` for the functions to add to a module.
+    pub fn new(module_info: &ModuleInfo) -> Self {
+        Self {
+            num_imported_functions: module_info.num_imported_functions as u32,
+            num_added_imports: 0,
+        }
+    }
+
+    /// Adds the function `field`, imported from `module`, to the module and
+    /// returns its index.
+    ///
+    /// The index is final: the functions imported by the following
+    /// middlewares are added after it.
+    pub fn add_import(
+        &mut self,
+        module_info: &mut ModuleInfo,
+        module: &str,
+        field: &str,
+        ty: FunctionType,
+    ) -> FunctionIndex {
+        let function_index = FunctionIndex::new(module_info.num_imported_functions);
+        let shift = |index: FunctionIndex| {
+            if index < function_index {
+                index
+            } else {
+                FunctionIndex::new(index.index() + 1)
+            }
+        };
+
+        let signature_index = module_info.signatures.push(ty);
+        let mut functions = PrimaryMap::with_capacity(module_info.functions.len() + 1);
+        for signature in module_info.functions.values().take(function_index.index()) {
+            functions.push(*signature);
+        }
+        functions.push(signature_index);
+        for signature in module_info.functions.values().skip(function_index.index()) {
+            functions.push(*signature);
+        }
+        module_info.functions = functions;
+        module_info.num_imported_functions += 1;
+
+        let import_index = module_info.imports.len() as u32;
+        module_info.imports.insert(
+            (module.to_string(), field.to_string(), import_index),
+            ImportIndex::Function(function_index),
+        );
+
+        for export in module_info.exports.values_mut() {
+            if let ExportIndex::Function(index) = export {
+                *index = shift(*index);
+            }
+        }
+        module_info.start_function = module_info.start_function.map(shift);
+        for initializer in &mut module_info.table_initializers {
+            for element in initializer.elements.iter_mut() {
+                *element = shift(*element);
+            }
+        }
+        for elements in module_info.passive_elements.values_mut() {
+            for element in elements.iter_mut() {
+                *element = shift(*element);
+            }
+        }
+        for initializer in module_info.global_initializers.values_mut() {
+            if let GlobalInit::RefFunc(index) = initializer {
+                *index = shift(*index);
+            }
+        }
+        module_info.function_names = module_info
+            .function_names
+            .drain()
+            .map(|(index, name)| (shift(index), name))
+            .collect();
+
+        self.num_added_imports += 1;
+        function_index
+    }
+
+    /// Adds a local function of type `ty` to the module and returns its
+    /// local index.
+    ///
+    /// Its function index is shifted by the functions imported afterwards,
+    /// so it should be computed with [`ModuleInfo::func_index`] when needed.
+    pub fn add_function(
+        &mut self,
+        module_info: &mut ModuleInfo,
+        ty: FunctionType,
+    ) -> LocalFunctionIndex {
+        let signature_index = module_info.signatures.push(ty);
+        let function_index = module_info.functions.push(signature_index);
+        module_info.local_func_index(function_index).unwrap()
+    }
+
+    /// Shifts the function index of an operator of the module, or added by
+    /// the previous middlewares, past the added imported functions.
+    pub fn remap<'a>(&self, operator: Operator<'a>) -> Operator<'a> {
+        let shift = |function_index: u32| {
+            if function_index < self.num_imported_functions {
+                function_index
+            } else {
+                function_index + self.num_added_imports
+            }
+        };
+        match operator {
+            Operator::Call { function_index } => Operator::Call {
+                function_index: shift(function_index),
+            },
+            Operator::ReturnCall { function_index } => Operator::ReturnCall {
+                function_index: shift(function_index),
+            },
+            Operator::RefFunc { function_index } => Operator::RefFunc {
+                function_index: shift(function_index),
+            },
+            operator => operator,
+        }
+    }
 }
 
 impl<'a> MiddlewareReaderState<'a> {
@@ -98,6 +307,27 @@ impl<'a> MiddlewareReaderState<'a> {
     pub fn push_operator(&mut self, operator: Operator<'a>) {
         self.pending_operations.push_back(operator);
     }
+
+    /// Returns the declarations of the locals of the function, as pairs of
+    /// a count and a type. The parameters are not included.
+    pub fn local_decls(&self) -> &[(u32, Type)] {
+        &self.local_decls
+    }
+
+    /// Returns the offset in the module of the operator being processed.
+    pub fn operator_offset(&self) -> usize {
+        self.operator_reader.original_position()
+    }
+
+    /// Returns a reader of the operators of the original function body,
+    /// starting at the operator being processed.
+    ///
+    /// This lets a middleware look ahead, for instance to analyze the whole
+    /// function before instrumenting its first operator. The operators added
+    /// by the middlewares are not included.
+    pub fn operator_reader(&self) -> BinaryReader<'a> {
+        self.operator_reader.clone()
+    }
 }
 
 impl<'a> Extend<Operator<'a>> for MiddlewareReaderState<'a> {
@@ -118,8 +348,10 @@ impl<'a> MiddlewareBinaryReader<'a> {
         let inner = BinaryReader::new_with_offset(data, original_offset);
         Self {
             state: MiddlewareReaderState {
+                operator_reader: inner.clone(),
                 inner,
                 pending_operations: VecDeque::new(),
+                local_decls: vec![],
             },
             chain: vec![],
         }
@@ -139,6 +371,7 @@ impl<'a> FunctionBinaryReader<'a> for MiddlewareBinaryReader<'a> {
     fn read_local_decl(&mut self) -> WasmResult<(u32, Type)> {
         let count = self.state.inner.read_var_u32()?;
         let ty = self.state.inner.read_type()?;
+        self.state.local_decls.push((count, ty));
         Ok((count, ty))
     }
 
@@ -150,6 +383,7 @@ impl<'a> FunctionBinaryReader<'a> for MiddlewareBinaryReader<'a> {
 
         // Try to fill the `self.pending_operations` buffer, until it is non-empty.
         while self.state.pending_operations.is_empty() {
+            self.state.operator_reader = self.state.inner.clone();
             let raw_op = self.state.inner.read_operator()?;
 
             // Fill the initial raw operator into pending buffer.
@@ -9,6 +9,7 @@ mod environ;
 mod middleware;
 mod module;
 mod state;
+mod stream;
 #[macro_use]
 mod error;
 mod sections;
@@ -17,9 +18,10 @@ pub use self::environ::{
     FunctionBinaryReader, FunctionBodyData, ModuleEnvironment, ModuleInfoTranslation,
 };
 pub use self::middleware::{
-    FunctionMiddleware, MiddlewareBinaryReader, MiddlewareReaderState, ModuleMiddleware,
-    ModuleMiddlewareChain,
+    FunctionAdditions, FunctionMiddleware, MiddlewareBinaryReader, MiddlewareReaderState,
+    ModuleMiddleware, ModuleMiddlewareChain,
 };
 pub use self::module::translate_module;
 pub use self::sections::wptype_to_type;
 pub use self::state::ModuleTranslationState;
+pub use self::stream::{ModuleStreamTranslation, ModuleStreamTranslator, StreamedFunctionBody};
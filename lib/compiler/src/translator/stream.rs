@@ -0,0 +1,325 @@
+//! Translation of a WebAssembly module whose bytes are received in chunks,
+//! such as from a reader or a network stream.
+
+use super::environ::{FunctionBodyData, ModuleEnvironment};
+use super::module::translate_payload;
+use super::state::ModuleTranslationState;
+use crate::compiler::validator;
+use crate::lib::std::borrow::Cow;
+use crate::lib::std::string::ToString;
+use crate::lib::std::vec::Vec;
+use crate::{CompileError, ModuleLimits};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::{Features, LocalFunctionIndex, OwnedDataInitializer};
+use wasmer_vm::ModuleInfo;
+use wasmparser::{
+    Chunk, FuncValidator, FunctionBody, Parser, Payload, ValidPayload, Validator,
+    ValidatorResources,
+};
+
+/// The body of a local function received by a [`ModuleStreamTranslator`].
+///
+/// The body isn't validated by the translator, so that the bodies can be
+/// validated in parallel with [`StreamedFunctionBody::validate`].
+pub struct StreamedFunctionBody {
+    /// The index of the function.
+    pub index: LocalFunctionIndex,
+
+    /// The body of the function.
+    pub body: FunctionBodyData<'static>,
+
+    validator: FuncValidator<ValidatorResources>,
+}
+
+impl StreamedFunctionBody {
+    /// Validates the body of the function.
+    pub fn validate(&mut self) -> Result<(), CompileError> {
+        let body = FunctionBody::new(self.body.module_offset, &self.body.data);
+        self.validator
+            .validate(&body)
+            .map_err(|e| CompileError::Validate(format!("{}", e)))
+    }
+}
+
+/// The result of a [`ModuleStreamTranslator`].
+pub struct ModuleStreamTranslation {
+    /// ModuleInfo information.
+    pub module: ModuleInfo,
+
+    /// The bodies of the functions that weren't taken with
+    /// [`ModuleStreamTranslator::take_function_bodies`].
+    pub function_bodies: Vec<StreamedFunctionBody>,
+
+    /// The data initializers.
+    pub data_initializers: Vec<OwnedDataInitializer>,
+
+    /// The decoded Wasm types for the module.
+    pub module_translation_state: ModuleTranslationState,
+}
+
+/// Validates and translates a WebAssembly module as its bytes are received.
+///
+/// The sections are translated with [`translate_module`]'s structure as
+/// soon as they're complete, and the body of each function as soon as it's
+/// received, so that the functions can be compiled while the rest of the
+/// module is received.
+///
+/// [`translate_module`]: crate::translate_module
+pub struct ModuleStreamTranslator {
+    parser: Parser,
+    validator: Validator,
+    limits: Option<ModuleLimits>,
+
+    /// The bytes received but not parsed yet.
+    buffer: Vec<u8>,
+
+    /// The module being translated. The function bodies and data
+    /// initializers are moved out of it as they're received.
+    environ: ModuleEnvironment<'static>,
+    module_translation_state: ModuleTranslationState,
+    function_bodies: Vec<StreamedFunctionBody>,
+    num_function_bodies: usize,
+    data_initializers: Vec<OwnedDataInitializer>,
+    declarations_complete: bool,
+}
+
+impl ModuleStreamTranslator {
+    /// Creates a translator validating the module with `features`.
+    pub fn new(features: &Features) -> Self {
+        Self {
+            parser: Parser::new(0),
+            validator: validator(features),
+            limits: None,
+            buffer: Vec::new(),
+            environ: ModuleEnvironment::new(),
+            module_translation_state: ModuleTranslationState::new(),
+            function_bodies: Vec::new(),
+            num_function_bodies: 0,
+            data_initializers: Vec::new(),
+            declarations_complete: false,
+        }
+    }
+
+    /// Sets the limits the module must satisfy.
+    pub fn set_limits(&mut self, limits: ModuleLimits) {
+        self.limits = Some(limits);
+    }
+
+    /// Receives the next bytes of the module, and translates the sections
+    /// and function bodies they complete.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<(), CompileError> {
+        self.buffer.extend_from_slice(bytes);
+        self.parse(false).map(|_| ())
+    }
+
+    /// Returns whether the sections preceding the function bodies are
+    /// translated, in which case [`Self::module_info`] declares all the
+    /// functions, tables, memories and globals of the module.
+    pub fn declarations_complete(&self) -> bool {
+        self.declarations_complete
+    }
+
+    /// Returns the module translated so far.
+    pub fn module_info(&self) -> &ModuleInfo {
+        &self.environ.result.module
+    }
+
+    /// Returns the decoded Wasm types translated so far.
+    pub fn module_translation_state(&self) -> &ModuleTranslationState {
+        &self.module_translation_state
+    }
+
+    /// Takes the function bodies received since the last call.
+    pub fn take_function_bodies(&mut self) -> Vec<StreamedFunctionBody> {
+        self.function_bodies.drain(..).collect()
+    }
+
+    /// Finishes the translation once all the bytes are received.
+    ///
+    /// It fails if the module is incomplete.
+    pub fn finish(mut self) -> Result<ModuleStreamTranslation, CompileError> {
+        if !self.parse(true)? {
+            return Err(CompileError::Validate(
+                "the module ended before its last section".to_string(),
+            ));
+        }
+        Ok(ModuleStreamTranslation {
+            module: self.environ.result.module,
+            function_bodies: self.function_bodies,
+            data_initializers: self.data_initializers,
+            module_translation_state: self.module_translation_state,
+        })
+    }
+
+    /// Translates the complete payloads of the buffer, and returns whether
+    /// the end of the module was reached.
+    fn parse(&mut self, eof: bool) -> Result<bool, CompileError> {
+        let mut position = 0;
+        let mut end = false;
+        while !end {
+            let (consumed, payload) = match self.parser.parse(&self.buffer[position..], eof)? {
+                Chunk::NeedMoreData(_) => break,
+                Chunk::Parsed { consumed, payload } => (consumed, payload),
+            };
+            position += consumed;
+            end = matches!(payload, Payload::End);
+            if let Some(limits) = &self.limits {
+                limits.check_payload(&payload)?;
+            }
+            let validator = match self
+                .validator
+                .payload(&payload)
+                .map_err(|e| CompileError::Validate(format!("{}", e)))?
+            {
+                ValidPayload::Func(validator, _) => Some(validator),
+                _ => None,
+            };
+            if let Payload::CodeSectionStart { .. } | Payload::End = payload {
+                self.declarations_complete = true;
+            }
+
+            // The payload borrows the buffer, so it's translated in an
+            // environment of its own, moving the function bodies and data
+            // initializers it translates out of it.
+            let mut environ = ModuleEnvironment::new();
+            self.environ.swap_module(&mut environ);
+            let result =
+                translate_payload(payload, &mut self.module_translation_state, &mut environ);
+            self.environ.swap_module(&mut environ);
+            result?;
+
+            let translation = environ.result;
+            for (body, validator) in translation.function_body_inputs.values().zip(validator) {
+                self.function_bodies.push(StreamedFunctionBody {
+                    index: LocalFunctionIndex::new(self.num_function_bodies),
+                    body: FunctionBodyData {
+                        data: Cow::Owned(body.data.to_vec()),
+                        module_offset: body.module_offset,
+                        from_middleware: false,
+                    },
+                    validator,
+                });
+                self.num_function_bodies += 1;
+            }
+            self.data_initializers.extend(
+                translation
+                    .data_initializers
+                    .iter()
+                    .map(OwnedDataInitializer::new),
+            );
+        }
+        self.buffer.drain(..position);
+        Ok(end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmer_types::{ExportIndex, FunctionIndex};
+
+    /// The module
+    /// ```wat
+    /// (module $demo
+    ///   (memory 1)
+    ///   (data (i32.const 8) "hi")
+    ///   (func (export "f") (result i32)
+    ///     i32.const 42))
+    /// ```
+    const MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // types
+        0x03, 0x02, 0x01, 0x00, // functions
+        0x05, 0x03, 0x01, 0x00, 0x01, // memories
+        0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x00, // exports
+        0x0a, 0x06, 0x01, 0x04, 0x00, 0x41, 0x2a, 0x0b, // code
+        0x0b, 0x08, 0x01, 0x00, 0x41, 0x08, 0x0b, 0x02, 0x68, 0x69, // data
+        0x00, 0x0c, 0x04, 0x6e, 0x61, 0x6d, 0x65, 0x00, 0x05, 0x04, 0x64, 0x65, 0x6d,
+        0x6f, // names
+    ];
+
+    /// The offset of the code section in `MODULE`.
+    const CODE_SECTION: usize = 31;
+
+    #[test]
+    fn translates_module_fed_byte_by_byte() {
+        let mut translator = ModuleStreamTranslator::new(&Features::default());
+        for (offset, byte) in MODULE.iter().enumerate() {
+            assert_eq!(
+                translator.declarations_complete(),
+                offset > CODE_SECTION + 2
+            );
+            translator.feed(&[*byte]).unwrap();
+        }
+        assert_eq!(translator.module_info().functions.len(), 1);
+
+        let mut function_bodies = translator.take_function_bodies();
+        assert_eq!(function_bodies.len(), 1);
+        assert_eq!(function_bodies[0].index, LocalFunctionIndex::new(0));
+        assert_eq!(&*function_bodies[0].body.data, &[0x00, 0x41, 0x2a, 0x0b]);
+        assert_eq!(function_bodies[0].body.module_offset, CODE_SECTION + 4);
+        function_bodies[0].validate().unwrap();
+
+        let translation = translator.finish().unwrap();
+        assert!(translation.function_bodies.is_empty());
+        assert_eq!(translation.module.name.as_deref(), Some("demo"));
+        assert_eq!(
+            translation.module.exports.get("f"),
+            Some(&ExportIndex::Function(FunctionIndex::from_u32(0)))
+        );
+        assert_eq!(translation.data_initializers.len(), 1);
+        assert_eq!(translation.data_initializers[0].location.offset, 8);
+        assert_eq!(&*translation.data_initializers[0].data, b"hi");
+    }
+
+    #[test]
+    fn keeps_function_bodies_until_taken() {
+        let mut translator = ModuleStreamTranslator::new(&Features::default());
+        translator.feed(MODULE).unwrap();
+        let translation = translator.finish().unwrap();
+        assert_eq!(translation.function_bodies.len(), 1);
+    }
+
+    #[test]
+    fn validates_function_bodies_separately() {
+        // The function returns an `i64` instead
+        let mut module = MODULE.to_vec();
+        module[14] = 0x7e;
+        let mut translator = ModuleStreamTranslator::new(&Features::default());
+        translator.feed(&module).unwrap();
+        let mut translation = translator.finish().unwrap();
+        assert!(matches!(
+            translation.function_bodies[0].validate(),
+            Err(CompileError::Validate(_))
+        ));
+    }
+
+    #[test]
+    fn fails_on_modules_exceeding_limits() {
+        let mut translator = ModuleStreamTranslator::new(&Features::default());
+        translator.set_limits(ModuleLimits {
+            max_data_segments: Some(0),
+            ..Default::default()
+        });
+        assert!(matches!(
+            translator.feed(MODULE),
+            Err(CompileError::LimitExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn fails_on_invalid_or_truncated_modules() {
+        // The function exported doesn't exist
+        let mut module = MODULE.to_vec();
+        module[30] = 0x01;
+        let mut translator = ModuleStreamTranslator::new(&Features::default());
+        assert!(matches!(
+            translator.feed(&module),
+            Err(CompileError::Validate(_))
+        ));
+
+        let mut translator = ModuleStreamTranslator::new(&Features::default());
+        translator.feed(&MODULE[..MODULE.len() - 1]).unwrap();
+        assert!(translator.finish().is_err());
+    }
+}
@@ -0,0 +1,269 @@
+//! The `host_module` attribute, implementing `HostModule` for the type of an
+//! `impl` block.
+
+use proc_macro2::TokenStream;
+use proc_macro_error::abort;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{spanned::Spanned, *};
+
+/// The arguments of the `host_module` attribute.
+struct HostModuleArgs {
+    /// The namespace of the host functions.
+    namespace: LitStr,
+
+    /// The `LazyInit<Memory>` field of the environment, for the string and
+    /// slice arguments.
+    memory: Option<Ident>,
+}
+
+impl HostModuleArgs {
+    fn parse(args: AttributeArgs) -> Self {
+        let mut namespace = None;
+        let mut memory = None;
+        for arg in args {
+            match arg {
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    path,
+                    lit: Lit::Str(value),
+                    ..
+                })) if path.is_ident("namespace") => namespace = Some(value),
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    path,
+                    lit: Lit::Str(value),
+                    ..
+                })) if path.is_ident("memory") => match value.parse() {
+                    Ok(field) => memory = Some(field),
+                    Err(_) => abort!(value, "Expected the name of a field"),
+                },
+                arg => abort!(
+                    arg,
+                    "Unrecognized argument: expected `namespace = \"string\"` or `memory = \"field\"`"
+                ),
+            }
+        }
+        Self {
+            namespace: namespace
+                .unwrap_or_else(|| LitStr::new("env", proc_macro2::Span::call_site())),
+            memory,
+        }
+    }
+}
+
+/// How an argument of a method is passed to the host function.
+enum Argument {
+    /// A value of a type that host functions take.
+    Value,
+
+    /// A `&str`, as a pointer and a length.
+    Str,
+
+    /// A `&[u8]`, as a pointer and a length.
+    Bytes,
+
+    /// A `&mut [u8]`, as a pointer and a length, written back after the
+    /// call unless it returns an `Err`.
+    BytesMut,
+}
+
+impl Argument {
+    fn of(ty: &Type) -> Self {
+        let reference = match ty {
+            Type::Reference(reference) => reference,
+            _ => return Self::Value,
+        };
+        match (&*reference.elem, reference.mutability.is_some()) {
+            (Type::Path(path), false) if path.path.is_ident("str") => Self::Str,
+            (Type::Slice(slice), mutable) if is_u8(&slice.elem) => {
+                if mutable {
+                    Self::BytesMut
+                } else {
+                    Self::Bytes
+                }
+            }
+            _ => abort!(
+                ty,
+                "Unsupported reference argument: expected `&str`, `&[u8]` or `&mut [u8]`"
+            ),
+        }
+    }
+}
+
+fn is_u8(ty: &Type) -> bool {
+    matches!(ty, Type::Path(path) if path.path.is_ident("u8"))
+}
+
+/// Returns the `Ok` type of a `Result` return type, if any.
+fn result_ok_type(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(TypePath { path, .. }) => path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Result" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) => match arguments.args.first() {
+            Some(GenericArgument::Type(ty)) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub fn impl_host_module(args: AttributeArgs, item: ItemImpl) -> TokenStream {
+    let args = HostModuleArgs::parse(args);
+    if !item.generics.params.is_empty() {
+        abort!(item.generics, "`host_module` doesn't support generic impls");
+    }
+    if let Some((_, path, _)) = &item.trait_ {
+        abort!(path, "`host_module` expects an inherent impl");
+    }
+
+    let self_ty = &item.self_ty;
+    let namespace = &args.namespace;
+    let functions = item
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            ImplItem::Method(method) if method.sig.receiver().is_some() => Some(method),
+            _ => None,
+        })
+        .map(|method| host_function(&args, self_ty, method));
+
+    quote! {
+        #item
+
+        impl ::wasmer::HostModule for #self_ty {
+            const NAMESPACE: &'static str = #namespace;
+
+            fn exports(&self, store: &::wasmer::Store) -> ::wasmer::Exports {
+                let mut exports = ::wasmer::Exports::new();
+                #(#functions)*
+                exports
+            }
+        }
+    }
+}
+
+/// Generates the host function of `method` and inserts it in `exports`.
+fn host_function(args: &HostModuleArgs, self_ty: &Type, method: &ImplItemMethod) -> TokenStream {
+    let sig = &method.sig;
+    let name = &sig.ident;
+    let name_str = LitStr::new(&name.to_string(), name.span());
+    match sig.receiver() {
+        Some(FnArg::Receiver(Receiver {
+            reference: Some(_),
+            mutability: None,
+            ..
+        })) => {}
+        receiver => abort!(receiver, "Expected `&self`"),
+    }
+
+    let mut params = vec![];
+    let mut reads = vec![];
+    let mut call_args = vec![];
+    let mut writes = vec![];
+    for input in sig.inputs.iter().skip(1) {
+        let pat_type = match input {
+            FnArg::Typed(pat_type) => pat_type,
+            FnArg::Receiver(receiver) => abort!(receiver, "Unexpected receiver"),
+        };
+        let arg = match &*pat_type.pat {
+            Pat::Ident(pat_ident) => &pat_ident.ident,
+            pat => abort!(pat, "Expected an identifier"),
+        };
+        let ty = &pat_type.ty;
+        let argument = Argument::of(ty);
+        if let Argument::Value = argument {
+            params.push(quote_spanned! {ty.span()=> #arg: #ty });
+            call_args.push(quote! { #arg });
+            continue;
+        }
+
+        let memory = match &args.memory {
+            Some(memory) => memory,
+            None => abort!(
+                ty,
+                "Passing `{}` requires the `memory` argument of `host_module`",
+                quote!(#ty)
+            ),
+        };
+        let ptr = format_ident!("{}_ptr", arg);
+        let len = format_ident!("{}_len", arg);
+        params.push(quote! { #ptr: u32 });
+        params.push(quote! { #len: u32 });
+        let memory_error = format!(
+            "the memory of the environment of `{}` is not initialized",
+            name
+        );
+        let memory = quote! {
+            env.#memory
+                .get_ref()
+                .ok_or_else(|| ::wasmer::RuntimeError::new(#memory_error))?
+        };
+        match argument {
+            Argument::Str => {
+                reads.push(quote! {
+                    let #arg = ::wasmer::internals::read_string(#memory, #ptr, #len)?;
+                });
+                call_args.push(quote! { &#arg });
+            }
+            Argument::Bytes => {
+                reads.push(quote! {
+                    let #arg = ::wasmer::internals::read_bytes(#memory, #ptr, #len)?;
+                });
+                call_args.push(quote! { &#arg });
+            }
+            Argument::BytesMut => {
+                reads.push(quote! {
+                    let mut #arg = ::wasmer::internals::read_bytes(#memory, #ptr, #len)?;
+                });
+                call_args.push(quote! { &mut #arg });
+                writes.push(quote! {
+                    ::wasmer::internals::write_bytes(#memory, #ptr, &#arg)?;
+                });
+            }
+            Argument::Value => unreachable!(),
+        }
+    }
+
+    let (rets, result, fallible) = match &sig.output {
+        ReturnType::Default => (quote! { () }, quote! { Ok(result) }, false),
+        ReturnType::Type(_, ty) => match result_ok_type(ty) {
+            Some(ok) => (
+                quote! { #ok },
+                quote! {
+                    result.map_err(|error| ::wasmer::RuntimeError::user(Box::new(error)))
+                },
+                true,
+            ),
+            None => (quote! { #ty }, quote! { Ok(result) }, false),
+        },
+    };
+    // the buffers are left untouched when the call fails
+    let writes = if fallible && !writes.is_empty() {
+        quote! {
+            if result.is_ok() {
+                #(#writes)*
+            }
+        }
+    } else {
+        quote! { #(#writes)* }
+    };
+
+    quote_spanned! {method.span()=>
+        {
+            #[allow(clippy::too_many_arguments, clippy::unit_arg)]
+            fn #name(env: &#self_ty, #(#params),*) -> ::std::result::Result<#rets, ::wasmer::RuntimeError> {
+                #(#reads)*
+                let result = env.#name(#(#call_args),*);
+                #writes
+                #result
+            }
+            exports.insert(
+                #name_str,
+                ::wasmer::Function::new_native_with_env(store, ::std::clone::Clone::clone(self), #name),
+            );
+        }
+    }
+}
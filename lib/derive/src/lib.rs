@@ -5,7 +5,9 @@ use proc_macro_error::{abort, proc_macro_error, set_dummy};
 use quote::{quote, quote_spanned, ToTokens};
 use syn::{spanned::Spanned, *};
 
+mod host_module;
 mod parse;
+mod value_type;
 
 use crate::parse::WasmerAttr;
 
@@ -17,6 +19,26 @@ pub fn derive_wasmer_env(input: proc_macro::TokenStream) -> proc_macro::TokenStr
     gen.into()
 }
 
+#[proc_macro_error]
+#[proc_macro_derive(ValueType)]
+pub fn derive_value_type(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input: DeriveInput = syn::parse(input).unwrap();
+    let gen = value_type::impl_value_type(&input);
+    gen.into()
+}
+
+#[proc_macro_error]
+#[proc_macro_attribute]
+pub fn host_module(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(attr as AttributeArgs);
+    let item = parse_macro_input!(item as ItemImpl);
+    let gen = host_module::impl_host_module(args, item);
+    gen.into()
+}
+
 fn impl_wasmer_env_for_struct(
     name: &Ident,
     data: &DataStruct,
@@ -0,0 +1,87 @@
+//! The `ValueType` derive, checking that a struct has no padding.
+
+use proc_macro2::TokenStream;
+use proc_macro_error::abort;
+use quote::{quote, quote_spanned};
+use syn::{spanned::Spanned, *};
+
+/// Checks that the struct is `#[repr(C)]` or `#[repr(transparent)]`, and
+/// not `#[repr(packed)]`, whose fields may be unaligned.
+fn check_repr(input: &DeriveInput) {
+    let mut is_c = false;
+    for attr in input.attrs.iter().filter(|attr| attr.path.is_ident("repr")) {
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list,
+            _ => continue,
+        };
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path))
+                    if path.is_ident("C") || path.is_ident("transparent") =>
+                {
+                    is_c = true
+                }
+                NestedMeta::Meta(meta) if meta.path().is_ident("packed") => abort!(
+                    nested,
+                    "`ValueType` can't be derived for packed structs, whose fields may be unaligned"
+                ),
+                _ => {}
+            }
+        }
+    }
+    if !is_c {
+        abort!(
+            input.ident,
+            "`ValueType` can only be derived for `#[repr(C)]` or `#[repr(transparent)]` structs"
+        );
+    }
+}
+
+pub fn impl_value_type(input: &DeriveInput) -> TokenStream {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => abort!(input.ident, "`ValueType` can only be derived for structs"),
+    };
+    if !input.generics.params.is_empty() {
+        abort!(
+            input.generics,
+            "`ValueType` can't be derived for generic structs"
+        );
+    }
+    check_repr(input);
+
+    let members = fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index {
+                index: index as u32,
+                span: field.span(),
+            }),
+        })
+        .collect::<Vec<_>>();
+    let types = fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+    let conversions = members.iter().zip(&types).map(|(member, ty)| {
+        quote_spanned! {ty.span()=>
+            #member: <#ty as ::wasmer::ValueType>::convert_le(self.#member)
+        }
+    });
+
+    quote! {
+        unsafe impl ::wasmer::ValueType for #name {
+            #[inline]
+            fn convert_le(self) -> Self {
+                Self { #(#conversions),* }
+            }
+        }
+
+        // Fails to compile if the struct has padding, with a mismatch
+        // between an array of 0 elements and an array of 1.
+        const _: [(); 0] = [
+            ();
+            (::std::mem::size_of::<#name>() != 0 #(+ ::std::mem::size_of::<#types>())*) as usize
+        ];
+    }
+}
@@ -1,6 +1,9 @@
 #![allow(dead_code)]
 
-use wasmer::{Function, Global, LazyInit, Memory, NativeFunc, Table, WasmerEnv};
+use wasmer::{
+    Function, Global, LazyInit, Memory, MemoryType, NativeFunc, Store, Table, ValueType, WasmPtr,
+    WasmerEnv,
+};
 
 #[derive(WasmerEnv, Clone)]
 struct MyEnv {
@@ -117,3 +120,45 @@ struct StructWithAliases {
 fn test_derive_with_aliases() {
     assert!(impls_wasmer_env::<StructWithAliases>());
 }
+
+#[derive(ValueType, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+struct MyValue {
+    id: u32,
+    size: u16,
+    flags: [u8; 2],
+    offset: WasmPtr<u8>,
+    scale: f32,
+}
+
+#[derive(ValueType, Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+struct MyTupleValue(i64);
+
+fn impls_value_type<T: ValueType>() -> bool {
+    true
+}
+
+#[test]
+fn test_derive_value_type() {
+    assert!(impls_value_type::<MyValue>());
+    assert!(impls_value_type::<MyTupleValue>());
+
+    let store = Store::default();
+    let memory = Memory::new(&store, MemoryType::new(1, None, false)).unwrap();
+    let value = MyValue {
+        id: 0x0403_0201,
+        size: 0x0605,
+        flags: [7, 8],
+        offset: WasmPtr::new(0x0c0b_0a09),
+        scale: 1.5,
+    };
+    memory.write_struct(1, &value).unwrap();
+    assert_eq!(memory.read_struct::<MyValue>(1).unwrap(), value);
+    let mut bytes = [0; 12];
+    memory.read(1, &mut bytes).unwrap();
+    assert_eq!(bytes, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+
+    memory.write_struct(3, &MyTupleValue(-2)).unwrap();
+    assert_eq!(memory.read_struct::<i64>(3).unwrap(), -2);
+}
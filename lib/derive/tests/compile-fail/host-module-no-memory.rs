@@ -0,0 +1,13 @@
+extern crate wasmer;
+
+use wasmer::{host_module, WasmerEnv};
+
+#[derive(Clone, WasmerEnv)]
+struct NoMemory;
+
+#[host_module]
+impl NoMemory {
+    fn log(&self, line: &str) {} //~ Passing `& str` requires the `memory` argument of `host_module`
+}
+
+fn main() {}
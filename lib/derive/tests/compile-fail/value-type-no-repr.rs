@@ -0,0 +1,10 @@
+extern crate wasmer;
+
+use wasmer::ValueType;
+
+#[derive(ValueType, Clone, Copy)]
+struct NoRepr { //~ `ValueType` can only be derived for `#[repr(C)]` or `#[repr(transparent)]` structs
+    value: u32,
+}
+
+fn main() {}
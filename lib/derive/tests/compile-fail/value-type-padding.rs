@@ -0,0 +1,12 @@
+extern crate wasmer;
+
+use wasmer::ValueType;
+
+#[derive(ValueType, Clone, Copy)] //~ ERROR mismatched types
+#[repr(C)]
+struct Padded {
+    tag: u8,
+    value: u32,
+}
+
+fn main() {}
@@ -7,10 +7,15 @@ use crate::link::link_module;
 use crate::serialize::SerializableCompilation;
 use crate::serialize::SerializableModule;
 use loupe::MemoryUsage;
+#[cfg(feature = "compiler")]
+use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
 use std::sync::{Arc, Mutex};
-use wasmer_compiler::{CompileError, Features, Triple};
 #[cfg(feature = "compiler")]
-use wasmer_compiler::{CompileModuleInfo, ModuleEnvironment, ModuleMiddlewareChain};
+use wasmer_compiler::{
+    Compilation, CompileModuleInfo, ModuleEnvironment, ModuleMiddlewareChain,
+    ModuleStreamTranslator,
+};
+use wasmer_compiler::{CompileError, Features, Triple};
 use wasmer_engine::{
     register_frame_info, Artifact, DeserializeError, FunctionExtent, GlobalFrameInfoRegistration,
     SerializeError,
@@ -59,11 +64,14 @@ impl JITArtifact {
         data: &[u8],
         tunables: &dyn Tunables,
     ) -> Result<Self, CompileError> {
+        if let Some(limits) = tunables.module_limits() {
+            limits.validate(data)?;
+        }
         let environ = ModuleEnvironment::new();
         let mut inner_jit = jit.inner_mut();
         let features = inner_jit.features();
 
-        let translation = environ.translate(data).map_err(CompileError::Wasm)?;
+        let mut translation = environ.translate(data).map_err(CompileError::Wasm)?;
 
         let compiler = inner_jit.compiler()?;
 
@@ -71,7 +79,155 @@ impl JITArtifact {
         let mut module = translation.module;
         let middlewares = compiler.get_middlewares();
         middlewares.apply_on_module_info(&mut module);
+        middlewares.add_function_bodies(&module, &mut translation.function_body_inputs)?;
+
+        let compile_info = Self::compile_info(module, features, tunables);
+
+        // Compile the Module
+        let compilation = compiler.compile_module(
+            &jit.target(),
+            &compile_info,
+            // SAFETY: Calling `unwrap` is correct since
+            // `environ.translate()` above will write some data into
+            // `module_translation_state`.
+            translation.module_translation_state.as_ref().unwrap(),
+            translation.function_body_inputs,
+        )?;
+
+        let data_initializers = translation
+            .data_initializers
+            .iter()
+            .map(OwnedDataInitializer::new)
+            .collect::<Vec<_>>();
+
+        Self::from_compilation(&mut inner_jit, compile_info, compilation, data_initializers)
+    }
+
+    /// Compile a WebAssembly binary received in chunks into a `JITArtifact`,
+    /// validating it and compiling its functions as they're received.
+    ///
+    /// The functions are compiled once the whole binary is received if the
+    /// compiler has middlewares, since they may depend on the sections
+    /// following the function bodies, or if the compiler can only compile
+    /// all the functions at once.
+    #[cfg(feature = "compiler")]
+    pub fn new_streaming(
+        jit: &JITEngine,
+        chunks: &mut dyn Iterator<Item = Vec<u8>>,
+        tunables: &dyn Tunables,
+    ) -> Result<Self, CompileError> {
+        // The engine isn't kept locked while receiving the binary, which may
+        // take a while, so that it can be used in the meantime
+        let (compiler, features) = {
+            let inner_jit = jit.inner();
+            (inner_jit.shared_compiler()?, inner_jit.features().clone())
+        };
+        let features = &features;
+        let target = jit.target();
+        let mut translator = ModuleStreamTranslator::new(features);
+        if let Some(limits) = tunables.module_limits() {
+            translator.set_limits(limits.clone());
+        }
+
+        // We translate the sections declaring the functions first
+        while !translator.declarations_complete() {
+            match chunks.next() {
+                Some(chunk) => translator.feed(&chunk)?,
+                None => break,
+            }
+        }
+        let streaming_info = if compiler.get_middlewares().is_empty() {
+            Some((
+                Self::compile_info(translator.module_info().clone(), features, tunables),
+                translator.module_translation_state().clone(),
+            ))
+        } else {
+            None
+        };
+        let streaming = streaming_info.as_ref().and_then(|(compile_info, state)| {
+            compiler.start_streaming_compilation(target, compile_info, state)
+        });
+
+        // Then we compile the function bodies while receiving the rest of
+        // the module
+        let error = Mutex::new(None);
+        let translation = rayon::in_place_scope(|scope| loop {
+            if let Some(compilation) = &streaming {
+                for mut function in translator.take_function_bodies() {
+                    let compilation = &**compilation;
+                    let error = &error;
+                    scope.spawn(move |_| {
+                        let result = function.validate().and_then(|()| {
+                            compilation.compile_function(function.index, &function.body)
+                        });
+                        if let Err(e) = result {
+                            error.lock().unwrap().get_or_insert(e);
+                        }
+                    });
+                }
+            }
+            match chunks.next() {
+                Some(chunk) => translator.feed(&chunk)?,
+                None => break translator.finish(),
+            }
+        })?;
+        if let Some(e) = error.into_inner().unwrap() {
+            return Err(e);
+        }
+
+        let mut module = translation.module;
+        let mut function_bodies = translation.function_bodies;
+        function_bodies
+            .par_iter_mut()
+            .try_for_each(|function| function.validate())?;
+        let (compile_info, compilation) = match streaming {
+            Some(compilation) => {
+                for function in &function_bodies {
+                    compilation.compile_function(function.index, &function.body)?;
+                }
+                let compilation = compilation.finish()?;
+                // The module is complete now, with the sections following
+                // the function bodies
+                let (mut compile_info, _) = streaming_info.unwrap();
+                compile_info.module = Arc::new(module);
+                (compile_info, compilation)
+            }
+            None => {
+                let mut function_body_inputs = function_bodies
+                    .into_iter()
+                    .map(|function| function.body)
+                    .collect::<PrimaryMap<LocalFunctionIndex, _>>();
+                let middlewares = compiler.get_middlewares();
+                middlewares.apply_on_module_info(&mut module);
+                middlewares.add_function_bodies(&module, &mut function_body_inputs)?;
+
+                let compile_info = Self::compile_info(module, features, tunables);
+                let compilation = compiler.compile_module(
+                    target,
+                    &compile_info,
+                    &translation.module_translation_state,
+                    function_body_inputs,
+                )?;
+                (compile_info, compilation)
+            }
+        };
 
+        Self::from_compilation(
+            &mut jit.inner_mut(),
+            compile_info,
+            compilation,
+            translation.data_initializers,
+        )
+    }
+
+    /// Gets the styles of the memories and tables of `module` from the
+    /// tunables, to compile it.
+    #[cfg(feature = "compiler")]
+    fn compile_info(
+        module: ModuleInfo,
+        features: &Features,
+        tunables: &dyn Tunables,
+    ) -> CompileModuleInfo {
         let memory_styles: PrimaryMap<MemoryIndex, MemoryStyle> = module
             .memories
             .values()
@@ -83,33 +239,25 @@ impl JITArtifact {
             .map(|table_type| tunables.table_style(table_type))
             .collect();
 
-        let compile_info = CompileModuleInfo {
+        CompileModuleInfo {
             module: Arc::new(module),
             features: features.clone(),
             memory_styles,
             table_styles,
-        };
+        }
+    }
 
-        // Compile the Module
-        let compilation = compiler.compile_module(
-            &jit.target(),
-            &compile_info,
-            // SAFETY: Calling `unwrap` is correct since
-            // `environ.translate()` above will write some data into
-            // `module_translation_state`.
-            translation.module_translation_state.as_ref().unwrap(),
-            translation.function_body_inputs,
-        )?;
+    /// Construct a `JITArtifact` from the compilation of a module.
+    #[cfg(feature = "compiler")]
+    fn from_compilation(
+        inner_jit: &mut JITEngineInner,
+        compile_info: CompileModuleInfo,
+        compilation: Compilation,
+        data_initializers: Vec<OwnedDataInitializer>,
+    ) -> Result<Self, CompileError> {
         let function_call_trampolines = compilation.get_function_call_trampolines();
         let dynamic_function_trampolines = compilation.get_dynamic_function_trampolines();
 
-        let data_initializers = translation
-            .data_initializers
-            .iter()
-            .map(OwnedDataInitializer::new)
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
-
         let frame_infos = compilation.get_frame_info();
 
         let serializable_compilation = SerializableCompilation {
@@ -126,9 +274,9 @@ impl JITArtifact {
         let serializable = SerializableModule {
             compilation: serializable_compilation,
             compile_info,
-            data_initializers,
+            data_initializers: data_initializers.into_boxed_slice(),
         };
-        Self::from_parts(&mut inner_jit, serializable)
+        Self::from_parts(inner_jit, serializable)
     }
 
     /// Compile a data buffer into a `JITArtifact`, which may then be instantiated.
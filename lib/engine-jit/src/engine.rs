@@ -32,7 +32,7 @@ impl JITEngine {
     pub fn new(compiler: Box<dyn Compiler>, target: Target, features: Features) -> Self {
         Self {
             inner: Arc::new(Mutex::new(JITEngineInner {
-                compiler: Some(compiler),
+                compiler: Some(compiler.into()),
                 code_memory: vec![],
                 signatures: SignatureRegistry::new(),
                 func_data: Arc::new(FuncDataRegistry::new()),
@@ -118,6 +118,18 @@ impl Engine for JITEngine {
         Ok(Arc::new(JITArtifact::new(&self, binary, tunables)?))
     }
 
+    /// Validates and compiles a WebAssembly binary received in chunks
+    #[cfg(feature = "compiler")]
+    fn compile_streaming(
+        &self,
+        chunks: &mut dyn Iterator<Item = Vec<u8>>,
+        tunables: &dyn Tunables,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        Ok(Arc::new(JITArtifact::new_streaming(
+            &self, chunks, tunables,
+        )?))
+    }
+
     /// Compile a WebAssembly binary
     #[cfg(not(feature = "compiler"))]
     fn compile(
@@ -150,7 +162,7 @@ impl Engine for JITEngine {
 pub struct JITEngineInner {
     /// The compiler
     #[cfg(feature = "compiler")]
-    compiler: Option<Box<dyn Compiler>>,
+    compiler: Option<Arc<dyn Compiler>>,
     /// The features to compile the Wasm module with
     features: Features,
     /// The code memory is responsible of publishing the compiled
@@ -175,6 +187,14 @@ impl JITEngineInner {
         Ok(&**self.compiler.as_ref().unwrap())
     }
 
+    /// Gets the compiler associated to this engine, to compile without
+    /// keeping the engine locked.
+    #[cfg(feature = "compiler")]
+    pub(crate) fn shared_compiler(&self) -> Result<Arc<dyn Compiler>, CompileError> {
+        self.compiler()?;
+        Ok(self.compiler.clone().unwrap())
+    }
+
     /// Validate the module
     #[cfg(feature = "compiler")]
     pub fn validate<'data>(&self, data: &'data [u8]) -> Result<(), CompileError> {
@@ -114,13 +114,17 @@ impl NativeArtifact {
         ),
         CompileError,
     > {
+        if let Some(limits) = tunables.module_limits() {
+            limits.validate(data)?;
+        }
         let environ = ModuleEnvironment::new();
-        let translation = environ.translate(data).map_err(CompileError::Wasm)?;
+        let mut translation = environ.translate(data).map_err(CompileError::Wasm)?;
 
         // We try to apply the middleware first
         let mut module = translation.module;
         let middlewares = compiler.get_middlewares();
         middlewares.apply_on_module_info(&mut module);
+        middlewares.add_function_bodies(&module, &mut translation.function_body_inputs)?;
 
         let memory_styles: PrimaryMap<MemoryIndex, MemoryStyle> = module
             .memories
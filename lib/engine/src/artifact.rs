@@ -1,5 +1,6 @@
 use crate::{
-    resolve_imports, InstantiationError, Resolver, RuntimeError, SerializeError, Tunables,
+    resolve_imports, InstantiationError, LinkError, Resolver, RuntimeError, SerializeError,
+    Tunables,
 };
 use loupe::MemoryUsage;
 use std::any::Any;
@@ -13,8 +14,8 @@ use wasmer_types::{
     SignatureIndex, TableIndex,
 };
 use wasmer_vm::{
-    FuncDataRegistry, FunctionBodyPtr, InstanceAllocator, InstanceHandle, MemoryStyle, ModuleInfo,
-    TableStyle, TrapHandler, VMSharedSignatureIndex, VMTrampoline,
+    FuncDataRegistry, FunctionBodyPtr, InstanceAllocator, InstanceHandle, InstanceReservation,
+    MemoryStyle, ModuleInfo, TableStyle, TrapHandler, VMSharedSignatureIndex, VMTrampoline,
 };
 
 /// An `Artifact` is the product that the `Engine`
@@ -98,6 +99,12 @@ pub trait Artifact: Send + Sync + Upcastable + MemoryUsage {
     ) -> Result<InstanceHandle, InstantiationError> {
         self.preinstantiate()?;
 
+        let reservation = tunables
+            .resource_limiter()
+            .map(|limiter| InstanceReservation::new(limiter.clone()))
+            .transpose()
+            .map_err(|e| InstantiationError::Link(LinkError::Resource(e.to_string())))?;
+
         let module = self.module();
         let (imports, import_function_envs) = {
             let mut imports = resolve_imports(
@@ -149,6 +156,7 @@ pub trait Artifact: Send + Sync + Upcastable + MemoryUsage {
             self.func_data_registry(),
             host_state,
             import_function_envs,
+            reservation,
         )
         .map_err(|trap| InstantiationError::Start(RuntimeError::from_trap(trap)))?;
         Ok(handle)
@@ -40,6 +40,23 @@ pub trait Engine: MemoryUsage {
         tunables: &dyn Tunables,
     ) -> Result<Arc<dyn Artifact>, CompileError>;
 
+    /// Validates and compiles a WebAssembly binary received in chunks
+    ///
+    /// By default the binary is compiled once all the chunks are received,
+    /// but engines may compile its functions as they are received.
+    fn compile_streaming(
+        &self,
+        chunks: &mut dyn Iterator<Item = Vec<u8>>,
+        tunables: &dyn Tunables,
+    ) -> Result<Arc<dyn Artifact>, CompileError> {
+        let mut binary = Vec::new();
+        for chunk in chunks {
+            binary.extend_from_slice(&chunk);
+        }
+        self.validate(&binary)?;
+        self.compile(&binary, tunables)
+    }
+
     /// Deserializes a WebAssembly module
     ///
     /// # Safety
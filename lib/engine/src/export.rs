@@ -116,6 +116,45 @@ impl ExportFunctionMetadata {
             host_env_drop_fn,
         }
     }
+
+    /// Clones the host env, as it's done for every `Instance` importing
+    /// the function.
+    ///
+    /// The clone must be freed with [`ExportFunctionMetadata::drop_host_env`].
+    pub fn clone_host_env(&self) -> *mut std::ffi::c_void {
+        (self.host_env_clone_fn)(self.host_env)
+    }
+
+    /// Initializes a clone of the host env with an `Instance`.
+    ///
+    /// # Safety
+    /// - `host_env` must come from [`ExportFunctionMetadata::clone_host_env`].
+    /// - See `InstanceHandle::initialize_host_envs` for `Err` and `instance_ptr`.
+    pub unsafe fn initialize_host_env<Err: Sized>(
+        &self,
+        host_env: *mut std::ffi::c_void,
+        instance_ptr: *const std::ffi::c_void,
+    ) -> Result<(), Err> {
+        match self.import_init_function_ptr {
+            Some(initializer) => {
+                let initializer = std::mem::transmute::<
+                    ImportInitializerFuncPtr,
+                    ImportInitializerFuncPtr<Err>,
+                >(initializer);
+                initializer(host_env, instance_ptr)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Frees a clone of the host env.
+    ///
+    /// # Safety
+    /// - `host_env` must come from [`ExportFunctionMetadata::clone_host_env`]
+    ///   and must not be used afterwards.
+    pub unsafe fn drop_host_env(&self, host_env: *mut std::ffi::c_void) {
+        (self.host_env_drop_fn)(host_env)
+    }
 }
 
 // We have to free `host_env` here because we always clone it before using it
@@ -64,6 +64,14 @@ impl RuntimeError {
         )
     }
 
+    /// Creates a `RuntimeError` from a custom user `Error`, which can be
+    /// recovered with [`RuntimeError::downcast`].
+    ///
+    /// A `RuntimeError` is returned directly.
+    pub fn user(error: Box<dyn Error + Send + Sync>) -> Self {
+        Self::from_trap(Trap::User(error))
+    }
+
     /// Create a new RuntimeError from a Trap.
     pub fn from_trap(trap: Trap) -> Self {
         let info = FRAME_INFO.read().unwrap();
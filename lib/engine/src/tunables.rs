@@ -2,13 +2,14 @@ use crate::error::LinkError;
 use loupe::MemoryUsage;
 use std::ptr::NonNull;
 use std::sync::Arc;
+use wasmer_compiler::ModuleLimits;
 use wasmer_types::entity::{EntityRef, PrimaryMap};
 use wasmer_types::{
     GlobalType, LocalGlobalIndex, LocalMemoryIndex, LocalTableIndex, MemoryIndex, MemoryType,
     TableIndex, TableType,
 };
-use wasmer_vm::MemoryError;
 use wasmer_vm::{Global, Memory, ModuleInfo, Table};
+use wasmer_vm::{MemoryError, ResourceLimiter};
 use wasmer_vm::{MemoryStyle, TableStyle};
 use wasmer_vm::{VMMemoryDefinition, VMTableDefinition};
 
@@ -21,6 +22,25 @@ pub trait Tunables: MemoryUsage {
     /// Construct a `TableStyle` for the provided `TableType`
     fn table_style(&self, table: &TableType) -> TableStyle;
 
+    /// The limits the modules must satisfy to be validated and compiled,
+    /// if any.
+    fn module_limits(&self) -> Option<&ModuleLimits> {
+        None
+    }
+
+    /// The limiter consulted when creating instances, if any.
+    ///
+    /// The implementors are expected to consult it when creating and
+    /// growing the memories and tables too, with
+    /// [`LinearMemory::new_with_limiter`] and
+    /// [`LinearTable::new_with_limiter`] for instance.
+    ///
+    /// [`LinearMemory::new_with_limiter`]: wasmer_vm::LinearMemory::new_with_limiter
+    /// [`LinearTable::new_with_limiter`]: wasmer_vm::LinearTable::new_with_limiter
+    fn resource_limiter(&self) -> Option<&Arc<dyn ResourceLimiter>> {
+        None
+    }
+
     /// Create a memory owned by the host given a [`MemoryType`] and a [`MemoryStyle`].
     fn create_host_memory(
         &self,
@@ -0,0 +1,400 @@
+//! `coverage` is a middleware for counting how many times each basic block
+//! of a module is executed, and for reporting the line coverage of the
+//! source of the module in the lcov format.
+//!
+//! The middleware instruments the first instruction of every basic block
+//! with a call to a hook imported from the `wasmer_coverage` namespace,
+//! which is provided by [`Coverage::imports`]. The hit counts are kept by
+//! the host, rather than in globals or in a dedicated memory of the
+//! instance, because the globals have to be declared before the blocks are
+//! known and not every compiler supports multiple memories.
+
+mod lcov;
+
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use wasmer::wasmparser::{BinaryReaderError, Operator};
+use wasmer::{
+    imports, Function, FunctionAdditions, FunctionMiddleware, FunctionType, ImportObject,
+    LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Store, Type,
+    WasmerEnv,
+};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::FunctionIndex;
+use wasmer_vm::ModuleInfo;
+
+/// The namespace of the imported hook.
+const HOOKS_NAMESPACE: &str = "wasmer_coverage";
+
+/// The blocks and the hit counts shared by the middleware and the hooks.
+#[derive(Debug, Default)]
+struct CoverageState {
+    /// The number of imported functions of the original module.
+    num_imported_functions: u32,
+
+    /// The names of the functions of the original module.
+    function_names: HashMap<FunctionIndex, String>,
+
+    /// The offsets in the module of the first instruction of the blocks of
+    /// each local function.
+    blocks: BTreeMap<LocalFunctionIndex, Vec<u32>>,
+
+    /// The number of times each block has been entered, by offset.
+    hits: HashMap<u32, u64>,
+}
+
+/// The module-level coverage middleware.
+///
+/// The instances of a module compiled with this middleware must be given
+/// the hook of [`Coverage::imports`]. The blocks are recorded while the
+/// module is compiled, so a module loaded from a cache or deserialized
+/// can't be reported on.
+///
+/// # Panic
+///
+/// An instance of `Coverage` should not be shared among different modules, since it tracks
+/// module-specific information like the blocks of the functions. Attempts to use
+/// a `Coverage` instance from multiple modules will result in a panic.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use wasmer::CompilerConfig;
+/// use wasmer_middlewares::Coverage;
+///
+/// fn create_coverage_middleware(compiler_config: &mut dyn CompilerConfig) -> Arc<Coverage> {
+///     let coverage = Arc::new(Coverage::new());
+///
+///     // Push the middleware, and keep it to collect the hit counts.
+///     compiler_config.push_middleware(coverage.clone());
+///     coverage
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// The functions added to the module, and the index of the hook.
+    hooks: Mutex<Option<(FunctionAdditions, u32)>>,
+
+    /// The blocks and the hit counts.
+    state: Arc<Mutex<CoverageState>>,
+}
+
+/// The function-level coverage middleware.
+#[derive(Debug)]
+pub struct FunctionCoverage {
+    /// The functions added to the module.
+    additions: FunctionAdditions,
+
+    /// The index of the hook imported by the module.
+    hit: u32,
+
+    /// The index of the function.
+    local_function_index: LocalFunctionIndex,
+
+    /// The blocks and the hit counts.
+    state: Arc<Mutex<CoverageState>>,
+
+    /// Whether the next operator starts a block.
+    block_start: bool,
+}
+
+/// The hit counts of the blocks of a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionHits {
+    /// The index of the function in the module.
+    pub function_index: u32,
+
+    /// The name of the function, from the name section of the module.
+    pub name: Option<String>,
+
+    /// The blocks of the function, in the order of their offsets.
+    pub blocks: Vec<BlockHits>,
+}
+
+/// The hit count of a basic block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHits {
+    /// The offset in the module of the first instruction of the block.
+    pub offset: u32,
+
+    /// The number of times the block has been entered.
+    pub hits: u64,
+}
+
+/// An error while writing a coverage report.
+#[derive(Error, Debug)]
+pub enum CoverageError {
+    /// The module couldn't be parsed.
+    #[error("invalid Wasm module: {0}")]
+    Wasm(#[from] BinaryReaderError),
+
+    /// The DWARF debug info of the module couldn't be parsed.
+    #[error("invalid DWARF debug info: {0}")]
+    Dwarf(#[from] gimli::Error),
+
+    /// The module has no DWARF line info.
+    #[error("the module has no DWARF line info")]
+    NoDebugInfo,
+
+    /// The report couldn't be written.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl Coverage {
+    /// Creates a `Coverage` middleware.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the hook to give to the instances of the module.
+    ///
+    /// The result can be chained with the other imports of the module, for
+    /// instance with [`ChainableNamedResolver::chain_back`](wasmer::ChainableNamedResolver::chain_back).
+    /// The hits of all the instances are added together.
+    pub fn imports(&self, store: &Store) -> ImportObject {
+        let env = CoverageEnv {
+            state: self.state.clone(),
+        };
+        imports! {
+            HOOKS_NAMESPACE => {
+                "hit" => Function::new_native_with_env(store, env, hit),
+            },
+        }
+    }
+
+    /// Returns the hit counts of the blocks of the local functions of the
+    /// module, in the order of their indexes.
+    pub fn functions(&self) -> Vec<FunctionHits> {
+        let state = self.state.lock().unwrap();
+        state
+            .blocks
+            .iter()
+            .map(|(local_function_index, offsets)| {
+                let function_index = FunctionIndex::new(
+                    state.num_imported_functions as usize + local_function_index.index(),
+                );
+                FunctionHits {
+                    function_index: function_index.as_u32(),
+                    name: state.function_names.get(&function_index).cloned(),
+                    blocks: offsets
+                        .iter()
+                        .map(|offset| BlockHits {
+                            offset: *offset,
+                            hits: state.hits.get(offset).copied().unwrap_or(0),
+                        })
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Resets the hit counts of all the blocks to zero.
+    pub fn reset(&self) {
+        self.state.lock().unwrap().hits.clear();
+    }
+
+    /// Writes the line coverage of the sources of `wasm`, which must be the
+    /// module compiled with this middleware, in the lcov tracefile format.
+    ///
+    /// The offsets of the blocks are mapped to source lines through the
+    /// DWARF line info of the module, so the module must have been built
+    /// with debug info.
+    pub fn write_lcov<W: Write>(&self, wasm: &[u8], output: W) -> Result<(), CoverageError> {
+        lcov::write(&self.functions(), wasm, output)
+    }
+}
+
+impl ModuleMiddleware for Coverage {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let (additions, hit) = self.hooks.lock().unwrap().clone().unwrap();
+        Box::new(FunctionCoverage {
+            additions,
+            hit,
+            local_function_index,
+            state: self.state.clone(),
+            block_start: true,
+        })
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut hooks = self.hooks.lock().unwrap();
+
+        if hooks.is_some() {
+            panic!("Coverage::transform_module_info: Attempting to use a `Coverage` middleware from multiple modules.");
+        }
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.num_imported_functions = module_info.num_imported_functions as u32;
+            state.function_names = module_info.function_names.clone();
+        }
+
+        let mut additions = FunctionAdditions::new(module_info);
+        let hit = additions.add_import(
+            module_info,
+            HOOKS_NAMESPACE,
+            "hit",
+            FunctionType::new(vec![Type::I32], vec![]),
+        );
+        *hooks = Some((additions, hit.as_u32()));
+    }
+}
+
+impl MemoryUsage for Coverage {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self)
+    }
+}
+
+impl FunctionMiddleware for FunctionCoverage {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if self.block_start {
+            let offset = state.operator_offset() as u32;
+            self.state
+                .lock()
+                .unwrap()
+                .blocks
+                .entry(self.local_function_index)
+                .or_default()
+                .push(offset);
+
+            state.extend(&[
+                Operator::I32Const {
+                    value: offset as i32,
+                },
+                Operator::Call {
+                    function_index: self.hit,
+                },
+            ]);
+        }
+
+        // The instructions following a branch, a branch target or a call,
+        // which may not return, start a new block.
+        self.block_start = matches!(
+            operator,
+            Operator::Loop { .. }
+                | Operator::If { .. }
+                | Operator::Else
+                | Operator::End
+                | Operator::Br { .. }
+                | Operator::BrIf { .. }
+                | Operator::BrTable { .. }
+                | Operator::Return
+                | Operator::Unreachable
+                | Operator::Call { .. }
+                | Operator::CallIndirect { .. }
+        );
+
+        state.push_operator(self.additions.remap(operator));
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, WasmerEnv)]
+struct CoverageEnv {
+    state: Arc<Mutex<CoverageState>>,
+}
+
+/// Counts a hit of the block at `offset`.
+fn hit(env: &CoverageEnv, offset: u32) {
+    *env.state.lock().unwrap().hits.entry(offset).or_insert(0) += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasmer::{
+        wat2wasm, ChainableNamedResolver, CompilerConfig, Cranelift, Instance, Module, JIT,
+    };
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (import "env" "log" (func $log (param i32)))
+            (func $abs (export "abs") (param i32) (result i32)
+                (if (result i32) (i32.lt_s (local.get 0) (i32.const 0))
+                    (then (i32.sub (i32.const 0) (local.get 0)))
+                    (else (local.get 0))))
+            (func $run (export "run") (param i32) (result i32)
+                (call $log (local.get 0))
+                (call $abs (local.get 0))))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn counts_block_hits() {
+        let coverage = Arc::new(Coverage::new());
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(coverage.clone());
+        let store = Store::new(&JIT::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode()).unwrap();
+
+        let log = imports! {
+            "env" => {
+                "log" => Function::new_native(&store, |_: i32| {}),
+            },
+        };
+        let instance = Instance::new(&module, &log.chain_back(coverage.imports(&store))).unwrap();
+        let run = instance
+            .exports
+            .get_function("run")
+            .unwrap()
+            .native::<i32, i32>()
+            .unwrap();
+        assert_eq!(run.call(-3).unwrap(), 3);
+        assert_eq!(run.call(4).unwrap(), 4);
+        assert_eq!(run.call(5).unwrap(), 5);
+
+        let functions = coverage.functions();
+        assert_eq!(functions.len(), 2);
+
+        // The entry of `abs`, the `then` and `else` arms, and the end.
+        let abs = &functions[0];
+        assert_eq!(abs.function_index, 1);
+        assert_eq!(abs.name.as_deref(), Some("abs"));
+        let hits = abs
+            .blocks
+            .iter()
+            .map(|block| block.hits)
+            .collect::<Vec<_>>();
+        assert_eq!(hits, [3, 1, 2, 3]);
+
+        // The entry of `run`, and the returns of the calls.
+        let run_hits = &functions[1];
+        assert_eq!(run_hits.function_index, 2);
+        let hits = run_hits
+            .blocks
+            .iter()
+            .map(|block| block.hits)
+            .collect::<Vec<_>>();
+        assert_eq!(hits, [3, 3, 3]);
+
+        coverage.reset();
+        assert!(coverage
+            .functions()
+            .iter()
+            .flat_map(|function| &function.blocks)
+            .all(|block| block.hits == 0));
+    }
+}
@@ -0,0 +1,349 @@
+//! Reporting the line coverage of a module in the lcov tracefile format,
+//! through the DWARF line info of the module.
+
+use super::{CoverageError, FunctionHits};
+use gimli::{Dwarf, EndianSlice, LittleEndian};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::ops::Range;
+use std::path::PathBuf;
+use wasmer::wasmparser::{ImportSectionEntryType, Parser, Payload};
+
+/// A row of the DWARF line table, mapping an instruction to a source line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LineRow {
+    /// The offset of the instruction in the module.
+    offset: u64,
+
+    /// The path of the source file.
+    file: String,
+
+    /// The line in the source file.
+    line: u64,
+}
+
+/// The line coverage of a source file.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct FileCoverage {
+    /// The functions declared in the file, with their first line and the
+    /// number of times they have been called.
+    functions: Vec<(String, u64, u64)>,
+
+    /// The number of times each line has been executed.
+    lines: BTreeMap<u64, u64>,
+}
+
+/// Writes the line coverage of the sources of `wasm` with the hit counts
+/// `functions`.
+pub(super) fn write<W: Write>(
+    functions: &[FunctionHits],
+    wasm: &[u8],
+    mut output: W,
+) -> Result<(), CoverageError> {
+    let mut num_imported_functions = 0;
+    let mut code_start = 0;
+    let mut bodies = Vec::new();
+    let mut sections = HashMap::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    if let ImportSectionEntryType::Function(_) = import?.ty {
+                        num_imported_functions += 1;
+                    }
+                }
+            }
+            Payload::CodeSectionStart { range, .. } => code_start = range.start as u64,
+            Payload::CodeSectionEntry(body) => {
+                let range = body.range();
+                bodies.push(range.start as u64..range.end as u64);
+            }
+            Payload::CustomSection { name, data, .. } if name.starts_with(".debug_") => {
+                sections.insert(name, data);
+            }
+            _ => {}
+        }
+    }
+
+    let dwarf = Dwarf::load(|id| -> Result<_, gimli::Error> {
+        let data = sections.get(id.name()).copied().unwrap_or(&[]);
+        Ok(EndianSlice::new(data, LittleEndian))
+    })?;
+    let rows = line_rows(&dwarf, code_start)?;
+    if rows.is_empty() {
+        return Err(CoverageError::NoDebugInfo);
+    }
+
+    let files = report(functions, num_imported_functions, &bodies, &rows);
+    write_report(&files, &mut output)?;
+    Ok(())
+}
+
+/// Reads the rows of the line tables of `dwarf`. The addresses of the
+/// DWARF info of a module are offsets from the start of its code section,
+/// at `code_start`.
+fn line_rows(
+    dwarf: &Dwarf<EndianSlice<LittleEndian>>,
+    code_start: u64,
+) -> Result<Vec<LineRow>, gimli::Error> {
+    let mut rows = Vec::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let program = match unit.line_program.clone() {
+            Some(program) => program,
+            None => continue,
+        };
+        let mut program_rows = program.rows();
+        while let Some((header, row)) = program_rows.next_row()? {
+            let line = match row.line() {
+                Some(line) if !row.end_sequence() => line.get(),
+                _ => continue,
+            };
+            // The code of the functions removed by the linker is usually at
+            // a tombstone address such as `-1`.
+            let offset = match row.address().checked_add(code_start) {
+                Some(offset) => offset,
+                None => continue,
+            };
+            let file = match row.file(header) {
+                Some(file) => file,
+                None => continue,
+            };
+
+            let mut path = PathBuf::new();
+            if let Some(comp_dir) = &unit.comp_dir {
+                path.push(&*comp_dir.to_string_lossy());
+            }
+            if file.directory_index() != 0 {
+                if let Some(directory) = file.directory(header) {
+                    path.push(&*dwarf.attr_string(&unit, directory)?.to_string_lossy());
+                }
+            }
+            path.push(
+                &*dwarf
+                    .attr_string(&unit, file.path_name())?
+                    .to_string_lossy(),
+            );
+
+            rows.push(LineRow {
+                offset,
+                file: path.to_string_lossy().into_owned(),
+                line,
+            });
+        }
+    }
+    Ok(rows)
+}
+
+/// Computes the line coverage of each source file.
+///
+/// Each row of the line tables takes the hit count of the block containing
+/// its instruction, the rows before the first block of a function taking
+/// the hit count of the first block. A line takes the highest hit count of
+/// its rows, and a function is declared at the line of its first row.
+fn report(
+    functions: &[FunctionHits],
+    num_imported_functions: u32,
+    bodies: &[Range<u64>],
+    rows: &[LineRow],
+) -> BTreeMap<String, FileCoverage> {
+    let functions = functions
+        .iter()
+        .map(|function| (function.function_index, function))
+        .collect::<HashMap<_, _>>();
+    let mut files = BTreeMap::<String, FileCoverage>::new();
+    let mut declarations = BTreeMap::<u32, &LineRow>::new();
+
+    for row in rows {
+        let local_function_index = match bodies.binary_search_by(|body| {
+            if body.end <= row.offset {
+                Ordering::Less
+            } else if body.start > row.offset {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        }) {
+            Ok(index) => index as u32,
+            Err(_) => continue,
+        };
+        let function_index = num_imported_functions + local_function_index;
+        let function = match functions.get(&function_index) {
+            Some(function) => function,
+            None => continue,
+        };
+        let block = match function
+            .blocks
+            .binary_search_by_key(&row.offset, |block| block.offset as u64)
+        {
+            Ok(index) => index,
+            Err(index) => index.saturating_sub(1),
+        };
+        let hits = match function.blocks.get(block) {
+            Some(block) => block.hits,
+            None => continue,
+        };
+
+        let line = files
+            .entry(row.file.clone())
+            .or_default()
+            .lines
+            .entry(row.line)
+            .or_insert(0);
+        *line = (*line).max(hits);
+
+        let declaration = declarations.entry(function_index).or_insert(row);
+        if row.offset < declaration.offset {
+            *declaration = row;
+        }
+    }
+
+    for (function_index, row) in declarations {
+        let function = functions[&function_index];
+        let name = function
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("wasm-function[{}]", function_index));
+        let hits = function.blocks.first().map_or(0, |block| block.hits);
+        files
+            .get_mut(&row.file)
+            .unwrap()
+            .functions
+            .push((name, row.line, hits));
+    }
+
+    files
+}
+
+/// Writes one lcov record for each source file.
+fn write_report<W: Write>(
+    files: &BTreeMap<String, FileCoverage>,
+    output: &mut W,
+) -> std::io::Result<()> {
+    for (path, file) in files {
+        writeln!(output, "TN:")?;
+        writeln!(output, "SF:{}", path)?;
+        for (name, line, _) in &file.functions {
+            writeln!(output, "FN:{},{}", line, name)?;
+        }
+        for (name, _, hits) in &file.functions {
+            writeln!(output, "FNDA:{},{}", hits, name)?;
+        }
+        writeln!(output, "FNF:{}", file.functions.len())?;
+        writeln!(
+            output,
+            "FNH:{}",
+            file.functions
+                .iter()
+                .filter(|(_, _, hits)| *hits > 0)
+                .count()
+        )?;
+        for (line, hits) in &file.lines {
+            writeln!(output, "DA:{},{}", line, hits)?;
+        }
+        writeln!(output, "LF:{}", file.lines.len())?;
+        writeln!(
+            output,
+            "LH:{}",
+            file.lines.values().filter(|hits| **hits > 0).count()
+        )?;
+        writeln!(output, "end_of_record")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coverage::BlockHits;
+
+    fn row(offset: u64, file: &str, line: u64) -> LineRow {
+        LineRow {
+            offset,
+            file: file.to_string(),
+            line,
+        }
+    }
+
+    #[test]
+    fn maps_blocks_to_lines() {
+        let functions = [
+            FunctionHits {
+                function_index: 1,
+                name: Some("abs".to_string()),
+                blocks: vec![
+                    BlockHits {
+                        offset: 105,
+                        hits: 3,
+                    },
+                    BlockHits {
+                        offset: 120,
+                        hits: 0,
+                    },
+                    BlockHits {
+                        offset: 130,
+                        hits: 3,
+                    },
+                ],
+            },
+            FunctionHits {
+                function_index: 2,
+                name: None,
+                blocks: vec![BlockHits {
+                    offset: 155,
+                    hits: 0,
+                }],
+            },
+        ];
+        let bodies = [100..150, 150..200];
+        let rows = [
+            row(102, "/src/abs.c", 1),
+            row(110, "/src/abs.c", 2),
+            row(122, "/src/abs.c", 3),
+            row(131, "/src/abs.c", 2),
+            row(160, "/src/main.c", 7),
+            row(300, "/src/main.c", 9),
+        ];
+
+        let files = report(&functions, 1, &bodies, &rows);
+        let mut output = Vec::new();
+        write_report(&files, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "TN:\n\
+             SF:/src/abs.c\n\
+             FN:1,abs\n\
+             FNDA:3,abs\n\
+             FNF:1\n\
+             FNH:1\n\
+             DA:1,3\n\
+             DA:2,3\n\
+             DA:3,0\n\
+             LF:3\n\
+             LH:2\n\
+             end_of_record\n\
+             TN:\n\
+             SF:/src/main.c\n\
+             FN:7,wasm-function[2]\n\
+             FNDA:0,wasm-function[2]\n\
+             FNF:1\n\
+             FNH:0\n\
+             DA:7,0\n\
+             LF:1\n\
+             LH:0\n\
+             end_of_record\n"
+        );
+    }
+
+    #[test]
+    fn requires_debug_info() {
+        // An empty module.
+        let wasm = b"\0asm\x01\0\0\0";
+        assert!(matches!(
+            write(&[], wasm, Vec::new()),
+            Err(CoverageError::NoDebugInfo)
+        ));
+    }
+}
@@ -0,0 +1,525 @@
+//! `debugger` is a middleware for pausing the execution of a module before
+//! any of its instructions, to inspect its memories, globals and locals.
+//!
+//! The middleware instruments every instruction of the module with a call
+//! to hooks imported from the `wasmer_debug` namespace, which are provided
+//! by [`imports`] and forward to a [`DebugHandler`]. The
+//! [`GdbStub`](gdb::GdbStub) handler serves a debugger such as LLDB over
+//! the GDB remote serial protocol.
+
+pub mod gdb;
+
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use wasmer::wasmparser::{Operator, Type as WpType, TypeOrFuncType as WpTypeOrFuncType};
+use wasmer::{
+    imports, ExportIndex, FrameInfo, Function, FunctionAdditions, FunctionMiddleware, FunctionType,
+    Global, HostEnvInitError, ImportObject, Instance, LocalFunctionIndex, Memory, MiddlewareError,
+    MiddlewareReaderState, ModuleMiddleware, RuntimeError, Store, Type, Value, WasmerEnv,
+};
+use wasmer_types::entity::EntityRef;
+use wasmer_vm::ModuleInfo;
+
+/// The namespace of the imported hooks.
+const HOOKS_NAMESPACE: &str = "wasmer_debug";
+
+#[derive(Clone)]
+struct DebuggerHooks {
+    /// The functions added to the module.
+    additions: FunctionAdditions,
+
+    /// The hook returning whether to pause before an instruction.
+    check: u32,
+
+    /// The hook pausing before an instruction.
+    pause: u32,
+
+    /// The hooks reporting the value of a local, in the order
+    /// `local_i32`, `local_i64`, `local_f32` and `local_f64`.
+    locals: [u32; 4],
+
+    /// The types of the parameters of each local function.
+    params: Arc<Vec<Box<[Type]>>>,
+}
+
+impl DebuggerHooks {
+    /// The hook reporting the value of a local of type `ty`.
+    fn local(&self, ty: WpType) -> Option<u32> {
+        match ty {
+            WpType::I32 => Some(self.locals[0]),
+            WpType::I64 => Some(self.locals[1]),
+            WpType::F32 => Some(self.locals[2]),
+            WpType::F64 => Some(self.locals[3]),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for DebuggerHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DebuggerHooks")
+            .field("additions", &self.additions)
+            .field("check", &self.check)
+            .field("pause", &self.pause)
+            .field("locals", &self.locals)
+            .finish()
+    }
+}
+
+/// The module-level debugger middleware.
+///
+/// The instances of a module compiled with this middleware must be given
+/// the hooks of [`imports`]. The memories and globals of the module are
+/// exported as `wasmer_debug_memory_{index}` and
+/// `wasmer_debug_global_{index}`. The instrumentation makes the code much
+/// larger and slower, it's only meant for debugging.
+///
+/// # Panic
+///
+/// An instance of `Debugger` should not be shared among different modules, since it tracks
+/// module-specific information like the function indexes of the hooks. Attempts to use
+/// a `Debugger` instance from multiple modules will result in a panic.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    /// The hooks imported by the module.
+    hooks: Mutex<Option<DebuggerHooks>>,
+}
+
+/// The function-level debugger middleware.
+#[derive(Debug)]
+pub struct FunctionDebugger {
+    /// The hooks imported by the module.
+    hooks: DebuggerHooks,
+
+    /// The index of the function.
+    local_function_index: LocalFunctionIndex,
+
+    /// The locals of the function whose values can be reported, with their
+    /// index and type, once the declarations have been read.
+    locals: Option<Vec<(u32, WpType)>>,
+}
+
+impl Debugger {
+    /// Creates a `Debugger` middleware.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ModuleMiddleware for Debugger {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        Box::new(FunctionDebugger {
+            hooks: self.hooks.lock().unwrap().clone().unwrap(),
+            local_function_index,
+            locals: None,
+        })
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut hooks = self.hooks.lock().unwrap();
+
+        if hooks.is_some() {
+            panic!("Debugger::transform_module_info: Attempting to use a `Debugger` middleware from multiple modules.");
+        }
+
+        let params = module_info
+            .functions
+            .values()
+            .skip(module_info.num_imported_functions)
+            .map(|signature| module_info.signatures[*signature].params().into())
+            .collect();
+
+        let mut additions = FunctionAdditions::new(module_info);
+        let mut add_hook = |name: &str, params: Vec<Type>, results: Vec<Type>| {
+            additions
+                .add_import(
+                    module_info,
+                    HOOKS_NAMESPACE,
+                    name,
+                    FunctionType::new(params, results),
+                )
+                .as_u32()
+        };
+        let check = add_hook("check", vec![Type::I32], vec![Type::I32]);
+        let pause = add_hook("pause", vec![Type::I32], vec![]);
+        let locals = [
+            add_hook("local_i32", vec![Type::I32, Type::I32], vec![]),
+            add_hook("local_i64", vec![Type::I32, Type::I64], vec![]),
+            add_hook("local_f32", vec![Type::I32, Type::F32], vec![]),
+            add_hook("local_f64", vec![Type::I32, Type::F64], vec![]),
+        ];
+
+        for index in module_info.memories.keys() {
+            module_info.exports.insert(
+                format!("wasmer_debug_memory_{}", index.as_u32()),
+                ExportIndex::Memory(index),
+            );
+        }
+        for index in module_info.globals.keys() {
+            module_info.exports.insert(
+                format!("wasmer_debug_global_{}", index.as_u32()),
+                ExportIndex::Global(index),
+            );
+        }
+
+        *hooks = Some(DebuggerHooks {
+            additions,
+            check,
+            pause,
+            locals,
+            params: Arc::new(params),
+        });
+    }
+}
+
+impl MemoryUsage for Debugger {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self)
+    }
+}
+
+impl FunctionDebugger {
+    /// Returns the locals whose values can be reported, the parameters
+    /// followed by the declared locals.
+    fn locals(&mut self, state: &MiddlewareReaderState) -> &[(u32, WpType)] {
+        let hooks = &self.hooks;
+        let local_function_index = self.local_function_index;
+        self.locals.get_or_insert_with(|| {
+            let params = hooks.params[local_function_index.index()]
+                .iter()
+                .map(|ty| match ty {
+                    Type::I32 => WpType::I32,
+                    Type::I64 => WpType::I64,
+                    Type::F32 => WpType::F32,
+                    Type::F64 => WpType::F64,
+                    Type::V128 => WpType::V128,
+                    Type::ExternRef => WpType::ExternRef,
+                    Type::FuncRef => WpType::FuncRef,
+                });
+            let locals = state
+                .local_decls()
+                .iter()
+                .flat_map(|(count, ty)| (0..*count).map(move |_| *ty));
+            params
+                .chain(locals)
+                .enumerate()
+                .map(|(index, ty)| (index as u32, ty))
+                .filter(|(_, ty)| hooks.local(*ty).is_some())
+                .collect()
+        })
+    }
+}
+
+impl FunctionMiddleware for FunctionDebugger {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        let offset = state.operator_offset() as u32 as i32;
+        let hooks = self.hooks.clone();
+
+        // if check(offset) { local_<type>(index, local.get index)...; pause(offset); }
+        state.extend(&[
+            Operator::I32Const { value: offset },
+            Operator::Call {
+                function_index: hooks.check,
+            },
+            Operator::If {
+                ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+            },
+        ]);
+        for (index, ty) in self.locals(state).to_vec() {
+            state.extend(&[
+                Operator::I32Const {
+                    value: index as i32,
+                },
+                Operator::LocalGet { local_index: index },
+                Operator::Call {
+                    function_index: hooks.local(ty).unwrap(),
+                },
+            ]);
+        }
+        state.extend(&[
+            Operator::I32Const { value: offset },
+            Operator::Call {
+                function_index: hooks.pause,
+            },
+            Operator::End,
+        ]);
+
+        state.push_operator(hooks.additions.remap(operator));
+
+        Ok(())
+    }
+}
+
+/// Decides when to pause the execution of an instance compiled with the
+/// [`Debugger`] middleware, and what to do while it's paused.
+pub trait DebugHandler: Send {
+    /// Returns whether to pause the execution before the instruction at
+    /// `offset` in the module.
+    ///
+    /// This is called before every instruction and should be cheap.
+    fn should_pause(&mut self, offset: u32) -> bool;
+
+    /// Inspects the paused instance; the execution resumes when it returns.
+    ///
+    /// Returning an error stops the execution with a trap.
+    fn paused(&mut self, guest: &PausedGuest) -> Result<(), RuntimeError>;
+}
+
+/// An instance paused by a [`DebugHandler`].
+#[derive(Debug)]
+pub struct PausedGuest {
+    offset: u32,
+    locals: Vec<Option<Value>>,
+    memories: Vec<Memory>,
+    globals: Vec<Global>,
+    frames: Vec<FrameInfo>,
+}
+
+impl PausedGuest {
+    /// The offset in the module of the next instruction.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// The value of the local `index` of the current function, parameters
+    /// included.
+    ///
+    /// The values of `v128` and reference locals are not available.
+    pub fn local(&self, index: u32) -> Option<&Value> {
+        self.locals.get(index as usize)?.as_ref()
+    }
+
+    /// The memory `index` of the instance.
+    pub fn memory(&self, index: u32) -> Option<&Memory> {
+        self.memories.get(index as usize)
+    }
+
+    /// The global `index` of the instance.
+    pub fn global(&self, index: u32) -> Option<&Global> {
+        self.globals.get(index as usize)
+    }
+
+    /// The WebAssembly frames of the call stack, the current function
+    /// first.
+    ///
+    /// The function indexes of the frames count the hooks of the
+    /// [`Debugger`] middleware as imported functions.
+    pub fn frames(&self) -> &[FrameInfo] {
+        &self.frames
+    }
+}
+
+/// The type and the bits of the value of a local.
+type RawLocal = (Type, u64);
+
+#[derive(Clone)]
+struct DebugEnv {
+    handler: Arc<Mutex<dyn DebugHandler>>,
+    /// The reported locals.
+    locals: Arc<Mutex<Vec<Option<RawLocal>>>>,
+    memories: Vec<Memory>,
+    globals: Vec<Global>,
+}
+
+impl WasmerEnv for DebugEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        while let Ok(memory) = instance
+            .exports
+            .get_memory(&format!("wasmer_debug_memory_{}", self.memories.len()))
+        {
+            self.memories.push(memory.clone());
+        }
+        while let Ok(global) = instance
+            .exports
+            .get_global(&format!("wasmer_debug_global_{}", self.globals.len()))
+        {
+            self.globals.push(global.clone());
+        }
+        Ok(())
+    }
+}
+
+impl DebugEnv {
+    fn set_local(&self, index: u32, ty: Type, bits: u64) {
+        let mut locals = self.locals.lock().unwrap();
+        let index = index as usize;
+        if locals.len() <= index {
+            locals.resize(index + 1, None);
+        }
+        locals[index] = Some((ty, bits));
+    }
+
+    /// Takes the values of the reported locals.
+    fn take_locals(&self) -> Vec<Option<Value>> {
+        let locals = mem::take(&mut *self.locals.lock().unwrap());
+        locals
+            .into_iter()
+            .map(|local| {
+                Some(match local? {
+                    (Type::I32, bits) => Value::I32(bits as i32),
+                    (Type::I64, bits) => Value::I64(bits as i64),
+                    (Type::F32, bits) => Value::F32(f32::from_bits(bits as u32)),
+                    (_, bits) => Value::F64(f64::from_bits(bits)),
+                })
+            })
+            .collect()
+    }
+}
+
+fn check(env: &DebugEnv, offset: u32) -> u32 {
+    env.handler.lock().unwrap().should_pause(offset) as u32
+}
+
+fn pause(env: &DebugEnv, offset: u32) -> Result<(), RuntimeError> {
+    let guest = PausedGuest {
+        offset,
+        locals: env.take_locals(),
+        memories: env.memories.clone(),
+        globals: env.globals.clone(),
+        frames: RuntimeError::new("").trace().to_vec(),
+    };
+    env.handler.lock().unwrap().paused(&guest)
+}
+
+fn local_i32(env: &DebugEnv, index: u32, value: i32) {
+    env.set_local(index, Type::I32, value as u32 as u64);
+}
+
+fn local_i64(env: &DebugEnv, index: u32, value: i64) {
+    env.set_local(index, Type::I64, value as u64);
+}
+
+fn local_f32(env: &DebugEnv, index: u32, value: f32) {
+    env.set_local(index, Type::F32, value.to_bits() as u64);
+}
+
+fn local_f64(env: &DebugEnv, index: u32, value: f64) {
+    env.set_local(index, Type::F64, value.to_bits());
+}
+
+/// Returns the hooks to give to the instances of a module compiled with the
+/// [`Debugger`] middleware, forwarding to `handler`.
+///
+/// The result can be chained with the other imports of the module, for
+/// instance with [`ChainableNamedResolver::chain_back`](wasmer::ChainableNamedResolver::chain_back).
+pub fn imports<H: DebugHandler + 'static>(store: &Store, handler: Arc<Mutex<H>>) -> ImportObject {
+    let env = DebugEnv {
+        handler,
+        locals: Arc::new(Mutex::new(Vec::new())),
+        memories: Vec::new(),
+        globals: Vec::new(),
+    };
+    imports! {
+        HOOKS_NAMESPACE => {
+            "check" => Function::new_native_with_env(store, env.clone(), check),
+            "pause" => Function::new_native_with_env(store, env.clone(), pause),
+            "local_i32" => Function::new_native_with_env(store, env.clone(), local_i32),
+            "local_i64" => Function::new_native_with_env(store, env.clone(), local_i64),
+            "local_f32" => Function::new_native_with_env(store, env.clone(), local_f32),
+            "local_f64" => Function::new_native_with_env(store, env, local_f64),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use wasmer::{wat2wasm, ChainableNamedResolver, CompilerConfig, Cranelift, Module, JIT};
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (import "env" "double" (func $double_f (param i32) (result i32)))
+            (global $offset (mut i32) (i32.const 5))
+            (func $add_f (param $x i32) (param $y i32) (result i32)
+                (local $z i64)
+                local.get $x
+                local.get $y
+                i32.add
+                call $double_f
+                global.get $offset
+                i32.add)
+            (export "add" (func $add_f)))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    /// Pauses before the third instruction of the module.
+    #[derive(Default)]
+    struct Recorder {
+        offsets: Vec<u32>,
+        locals: Vec<Option<Value>>,
+        global: Option<Value>,
+        frames: usize,
+    }
+
+    impl DebugHandler for Recorder {
+        fn should_pause(&mut self, offset: u32) -> bool {
+            self.offsets.push(offset);
+            self.offsets.len() == 3
+        }
+
+        fn paused(&mut self, guest: &PausedGuest) -> Result<(), RuntimeError> {
+            assert_eq!(guest.offset(), self.offsets[2]);
+            self.locals = (0..3).map(|index| guest.local(index).cloned()).collect();
+            self.global = guest.global(0).map(|global| global.get());
+            self.frames = guest.frames().len();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pauses_with_locals_and_globals() {
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(Arc::new(Debugger::new()));
+        let store = Store::new(&JIT::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode()).unwrap();
+
+        let recorder = Arc::new(Mutex::new(Recorder::default()));
+        let env_imports = imports! {
+            "env" => {
+                "double" => Function::new_native(&store, |x: i32| x * 2),
+            },
+        };
+        let resolver = env_imports.chain_back(super::imports(&store, recorder.clone()));
+        let instance = Instance::new(&module, &resolver).unwrap();
+
+        let add = instance
+            .exports
+            .get_function("add")
+            .unwrap()
+            .native::<(i32, i32), i32>()
+            .unwrap();
+        assert_eq!(add.call(3, 4).unwrap(), 19);
+
+        let recorder = recorder.lock().unwrap();
+        // every instruction is checked, including the final `end`
+        assert_eq!(recorder.offsets.len(), 7);
+        assert!(recorder.offsets.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(
+            recorder.locals,
+            vec![
+                Some(Value::I32(3)),
+                Some(Value::I32(4)),
+                Some(Value::I64(0))
+            ]
+        );
+        assert_eq!(recorder.global, Some(Value::I32(5)));
+        assert_eq!(recorder.frames, 1);
+    }
+}
@@ -0,0 +1,629 @@
+//! A [`DebugHandler`] serving a debugger over the GDB remote serial
+//! protocol, with the WebAssembly extensions of LLDB (`qWasmMem`,
+//! `qWasmGlobal`, `qWasmLocal` and `qWasmCallStack`).
+//!
+//! The addresses of the code are the offsets in the module with the bit 62
+//! set, as LLDB expects; the other addresses are offsets in the memory 0.
+
+use super::{DebugHandler, PausedGuest};
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::TcpStream;
+use wasmer::{Memory, RuntimeError, Value};
+
+/// The address at which the code of the module is loaded.
+const CODE_ADDRESS: u64 = 0x4000_0000_0000_0000;
+
+/// The number of instructions run between two checks for an interruption
+/// from the debugger.
+const INTERRUPT_CHECK_INTERVAL: u64 = 4096;
+
+/// The byte sent by the debugger to interrupt the execution.
+const INTERRUPT: u8 = 0x03;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// The target reported to the debugger.
+const TRIPLE: &str = "wasm32-unknown-unknown-wasm";
+
+/// What to do once a packet has been handled.
+enum Next {
+    /// Reply and wait for the next packet.
+    Reply(String),
+    /// Resume the execution.
+    Resume,
+    /// Reply `OK` and resume the execution without the debugger.
+    Detach,
+    /// Stop the execution.
+    Kill,
+}
+
+/// A [`DebugHandler`] serving a debugger connected with a TCP stream.
+///
+/// The execution is paused before the first instruction, waiting for the
+/// debugger. If the debugger disconnects, the execution goes on without it.
+#[derive(Debug)]
+pub struct GdbStub {
+    stream: TcpStream,
+    module: Vec<u8>,
+    module_name: String,
+    breakpoints: BTreeSet<u32>,
+    stepping: bool,
+    interrupted: bool,
+    /// The signal reported for the current pause.
+    signal: u8,
+    /// Whether the debugger waits for the execution to stop.
+    resumed: bool,
+    /// Whether packets are acknowledged.
+    ack: bool,
+    detached: bool,
+    instructions: u64,
+}
+
+impl GdbStub {
+    /// Creates a stub serving the debugger connected to `stream`, for the
+    /// module whose binary is `module`.
+    pub fn new(stream: TcpStream, module: Vec<u8>, module_name: &str) -> Self {
+        Self {
+            stream,
+            module,
+            module_name: module_name.to_string(),
+            breakpoints: BTreeSet::new(),
+            stepping: true,
+            interrupted: false,
+            signal: SIGTRAP,
+            resumed: false,
+            ack: true,
+            detached: false,
+            instructions: 0,
+        }
+    }
+
+    /// Tells the debugger that the execution ended with the exit code
+    /// `code`.
+    pub fn exited(&mut self, code: u8) -> io::Result<()> {
+        if self.detached {
+            return Ok(());
+        }
+        self.detach();
+        self.send(&format!("W{:02x}", code))
+    }
+
+    fn detach(&mut self) {
+        self.detached = true;
+        self.breakpoints.clear();
+        self.stepping = false;
+    }
+
+    /// Checks, without blocking, whether the debugger interrupted the
+    /// execution.
+    fn poll_interrupt(&mut self) {
+        let mut byte = [0];
+        let read = self
+            .stream
+            .set_nonblocking(true)
+            .and_then(|()| self.stream.read(&mut byte));
+        match read {
+            Ok(0) => self.detach(),
+            Ok(_) => self.interrupted |= byte[0] == INTERRUPT,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(_) => self.detach(),
+        }
+        if self.stream.set_nonblocking(false).is_err() {
+            self.detach();
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Reads the next packet with a valid checksum.
+    fn receive(&mut self) -> io::Result<String> {
+        loop {
+            // Acknowledgments and interruptions are ignored while paused.
+            while self.read_byte()? != b'$' {}
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let mut sum = [0; 2];
+            self.stream.read_exact(&mut sum)?;
+            let valid = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+                == Some(checksum(&data));
+            if self.ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(String::from_utf8_lossy(&data).into_owned());
+            }
+        }
+    }
+
+    /// Sends a packet, until the debugger acknowledges it.
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        // The bytes delimiting packets are escaped.
+        let mut escaped = Vec::with_capacity(data.len());
+        for &byte in data.as_bytes() {
+            if let b'#' | b'$' | b'}' | b'*' = byte {
+                escaped.extend_from_slice(&[b'}', byte ^ 0x20]);
+            } else {
+                escaped.push(byte);
+            }
+        }
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(&escaped);
+        packet.extend_from_slice(format!("#{:02x}", checksum(&escaped)).as_bytes());
+        loop {
+            self.stream.write_all(&packet)?;
+            if !self.ack {
+                return Ok(());
+            }
+            loop {
+                match self.read_byte()? {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Serves the debugger until it resumes the execution.
+    fn serve(&mut self, guest: &PausedGuest) -> io::Result<Result<(), RuntimeError>> {
+        if mem::take(&mut self.resumed) {
+            let reply = self.stop_reply(guest);
+            self.send(&reply)?;
+        }
+        loop {
+            let packet = self.receive()?;
+            match self.handle(guest, &packet) {
+                Next::Reply(reply) => {
+                    self.send(&reply)?;
+                    if packet == "QStartNoAckMode" {
+                        self.ack = false;
+                    }
+                }
+                Next::Resume => {
+                    self.resumed = true;
+                    return Ok(Ok(()));
+                }
+                Next::Detach => {
+                    self.send("OK")?;
+                    self.detach();
+                    return Ok(Ok(()));
+                }
+                Next::Kill => {
+                    self.detach();
+                    return Ok(Err(RuntimeError::new("killed by the debugger")));
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, guest: &PausedGuest, packet: &str) -> Next {
+        let reply = match packet {
+            "?" => self.stop_reply(guest),
+            "qC" => "QC1".to_string(),
+            "qAttached" => "1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "qHostInfo" => format!("triple:{};ptrsize:4;endian:little;", hex(TRIPLE.as_bytes())),
+            "qProcessInfo" => format!(
+                "pid:1;parent-pid:1;triple:{};ptrsize:4;endian:little;",
+                hex(TRIPLE.as_bytes())
+            ),
+            "QStartNoAckMode" => "OK".to_string(),
+            "vCont?" => "vCont;c;C;s;S".to_string(),
+            "g" | "p0" => hex(&code_address(guest.offset()).to_le_bytes()),
+            "qRegisterInfo0" => "name:pc;alt-name:pc;bitsize:64;offset:0;encoding:uint;format:hex;\
+                set:General Purpose Registers;gcc:16;dwarf:16;generic:pc;"
+                .to_string(),
+            "c" => return self.resume(false),
+            "s" => return self.resume(true),
+            "k" => return Next::Kill,
+            "D" => return Next::Detach,
+            _ if packet.starts_with("vCont;c") || packet.starts_with("vCont;C") => {
+                return self.resume(false)
+            }
+            _ if packet.starts_with("vCont;s") || packet.starts_with("vCont;S") => {
+                return self.resume(true)
+            }
+            _ if packet.starts_with("vKill") => return Next::Kill,
+            _ if packet.starts_with("D;") => return Next::Detach,
+            _ if packet.starts_with('H') => "OK".to_string(),
+            _ if packet.starts_with("qSupported") => {
+                "PacketSize=1000;QStartNoAckMode+;qXfer:libraries:read+".to_string()
+            }
+            _ if packet.starts_with("qRegisterInfo") || packet.starts_with('p') => {
+                "E45".to_string()
+            }
+            _ => self
+                .query(guest, packet)
+                .unwrap_or_else(|| "E03".to_string()),
+        };
+        Next::Reply(reply)
+    }
+
+    fn resume(&mut self, stepping: bool) -> Next {
+        self.stepping = stepping;
+        Next::Resume
+    }
+
+    /// Answers the packets with arguments, `None` if they are invalid.
+    fn query(&mut self, guest: &PausedGuest, packet: &str) -> Option<String> {
+        if let Some(args) = packet.strip_prefix("qXfer:libraries:read::") {
+            let (offset, length) = split2(args, ',')?;
+            let (offset, length) = (parse_hex(offset)? as usize, parse_hex(length)? as usize);
+            let libraries = format!(
+                "<library-list><library name=\"{}\"><section address=\"0x{:x}\"/></library></library-list>",
+                escape_xml(&self.module_name),
+                CODE_ADDRESS
+            );
+            // The offset and the length are in bytes, and the document is
+            // ASCII so that any range of it is a string.
+            let libraries = libraries.as_bytes();
+            let start = offset.min(libraries.len());
+            let end = offset.saturating_add(length).min(libraries.len());
+            let marker = if end == libraries.len() { 'l' } else { 'm' };
+            let part = std::str::from_utf8(&libraries[start..end]).ok()?;
+            return Some(format!("{}{}", marker, part));
+        }
+        if let Some(args) = packet.strip_prefix("qWasmMem:") {
+            let mut args = args.split(';');
+            let _frame = args.next()?;
+            let address = parse_hex(args.next()?)?;
+            let length = parse_hex(args.next()?)?;
+            return read_memory(guest.memory(0)?, address, length).map(|bytes| hex(&bytes));
+        }
+        if let Some(args) = packet.strip_prefix("qWasmGlobal:") {
+            let (_frame, index) = split2(args, ';')?;
+            let global = guest.global(parse_hex(index)? as u32)?;
+            return value_bytes(&global.get()).map(|bytes| hex(&bytes));
+        }
+        if let Some(args) = packet.strip_prefix("qWasmLocal:") {
+            // The locals are only known for the current function.
+            let (frame, index) = split2(args, ';')?;
+            if parse_hex(frame)? != 0 {
+                return None;
+            }
+            let local = guest.local(parse_hex(index)? as u32)?;
+            return value_bytes(local).map(|bytes| hex(&bytes));
+        }
+        if packet.starts_with("qWasmCallStack") {
+            let callers = guest
+                .frames()
+                .iter()
+                .skip(1)
+                .map(|frame| frame.module_offset() as u32);
+            let stack = std::iter::once(guest.offset())
+                .chain(callers)
+                .flat_map(|offset| code_address(offset).to_le_bytes().to_vec())
+                .collect::<Vec<_>>();
+            return Some(hex(&stack));
+        }
+        if let Some(args) = packet.strip_prefix('m') {
+            let (address, length) = split2(args, ',')?;
+            let (address, length) = (parse_hex(address)?, parse_hex(length)?);
+            let bytes = if address & CODE_ADDRESS != 0 {
+                let start = (address & !CODE_ADDRESS) as usize;
+                let end = start.saturating_add(length as usize).min(self.module.len());
+                self.module.get(start..end)?.to_vec()
+            } else {
+                read_memory(guest.memory(0)?, address, length)?
+            };
+            return Some(hex(&bytes));
+        }
+        let insert = packet.starts_with("Z0,");
+        if insert || packet.starts_with("z0,") {
+            let (address, _kind) = split2(&packet[3..], ',')?;
+            let offset = (parse_hex(address)? & !CODE_ADDRESS) as u32;
+            if insert {
+                self.breakpoints.insert(offset);
+            } else {
+                self.breakpoints.remove(&offset);
+            }
+            return Some("OK".to_string());
+        }
+        // Unsupported packets get an empty reply.
+        Some(String::new())
+    }
+
+    fn stop_reply(&self, guest: &PausedGuest) -> String {
+        format!(
+            "T{:02x}thread:1;00:{};",
+            self.signal,
+            hex(&code_address(guest.offset()).to_le_bytes())
+        )
+    }
+}
+
+impl DebugHandler for GdbStub {
+    fn should_pause(&mut self, offset: u32) -> bool {
+        if self.detached {
+            return false;
+        }
+        self.instructions += 1;
+        if self.instructions % INTERRUPT_CHECK_INTERVAL == 0 {
+            self.poll_interrupt();
+        }
+        self.stepping || self.interrupted || self.breakpoints.contains(&offset)
+    }
+
+    fn paused(&mut self, guest: &PausedGuest) -> Result<(), RuntimeError> {
+        self.signal = if mem::take(&mut self.interrupted) {
+            SIGINT
+        } else {
+            SIGTRAP
+        };
+        match self.serve(guest) {
+            Ok(result) => result,
+            // The debugger is gone, the execution goes on without it.
+            Err(_) => {
+                self.detach();
+                Ok(())
+            }
+        }
+    }
+}
+
+fn code_address(offset: u32) -> u64 {
+    CODE_ADDRESS | offset as u64
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value, 16).ok()
+}
+
+fn split2(value: &str, separator: char) -> Option<(&str, &str)> {
+    let mut parts = value.splitn(2, separator);
+    Some((parts.next()?, parts.next()?))
+}
+
+/// Escapes `value` for an XML attribute, with only ASCII characters.
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c if c.is_ascii() => escaped.push(c),
+            c => escaped.push_str(&format!("&#x{:x};", c as u32)),
+        }
+    }
+    escaped
+}
+
+/// Reads up to `length` bytes of `memory` at `address`, `None` if the
+/// address is out of bounds.
+fn read_memory(memory: &Memory, address: u64, length: u64) -> Option<Vec<u8>> {
+    let view = memory.view::<u8>();
+    if address >= view.len() as u64 {
+        return None;
+    }
+    let end = address.saturating_add(length).min(view.len() as u64);
+    Some(
+        view[address as usize..end as usize]
+            .iter()
+            .map(|cell| cell.get())
+            .collect(),
+    )
+}
+
+fn value_bytes(value: &Value) -> Option<Vec<u8>> {
+    Some(match value {
+        Value::I32(value) => value.to_le_bytes().to_vec(),
+        Value::I64(value) => value.to_le_bytes().to_vec(),
+        Value::F32(value) => value.to_bits().to_le_bytes().to_vec(),
+        Value::F64(value) => value.to_bits().to_le_bytes().to_vec(),
+        Value::V128(value) => value.to_le_bytes().to_vec(),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    struct Client {
+        stream: TcpStream,
+        ack: bool,
+    }
+
+    impl Client {
+        fn request(&mut self, data: &str) -> String {
+            self.request_without_reply(data);
+            self.receive()
+        }
+
+        fn request_without_reply(&mut self, data: &str) {
+            let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+            self.stream.write_all(packet.as_bytes()).unwrap();
+            if self.ack {
+                assert_eq!(self.read_byte(), b'+');
+            }
+        }
+
+        fn read_byte(&mut self) -> u8 {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+
+        fn receive(&mut self) -> String {
+            assert_eq!(self.read_byte(), b'$');
+            let mut raw = Vec::new();
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte() {
+                    b'#' => break,
+                    b'}' => {
+                        let byte = self.read_byte();
+                        raw.extend_from_slice(&[b'}', byte]);
+                        data.push(byte ^ 0x20);
+                    }
+                    byte => {
+                        raw.push(byte);
+                        data.push(byte);
+                    }
+                }
+            }
+            let sum = [self.read_byte(), self.read_byte()];
+            assert_eq!(
+                std::str::from_utf8(&sum).unwrap(),
+                format!("{:02x}", checksum(&raw))
+            );
+            if self.ack {
+                self.stream.write_all(b"+").unwrap();
+            }
+            String::from_utf8(data).unwrap()
+        }
+    }
+
+    fn connect() -> (GdbStub, Client) {
+        connect_to("test.wasm")
+    }
+
+    fn connect_to(module_name: &str) -> (GdbStub, Client) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let stub = GdbStub::new(stream, b"\0asm\x01\0\0\0".to_vec(), module_name);
+        (
+            stub,
+            Client {
+                stream: client,
+                ack: true,
+            },
+        )
+    }
+
+    fn paused_at(offset: u32) -> PausedGuest {
+        PausedGuest {
+            offset,
+            locals: vec![Some(Value::I32(7)), None, Some(Value::F64(1.5))],
+            memories: vec![],
+            globals: vec![],
+            frames: vec![],
+        }
+    }
+
+    #[test]
+    fn breakpoints_and_stepping() {
+        let (mut stub, mut client) = connect();
+        let debugger = thread::spawn(move || {
+            assert_eq!(client.request("?"), "T05thread:1;00:0a00000000000040;");
+            assert_eq!(client.request("Z0,4000000000000014,1"), "OK");
+            client.request_without_reply("c");
+            // stopped at the breakpoint
+            assert_eq!(client.receive(), "T05thread:1;00:1400000000000040;");
+            client.request_without_reply("s");
+            assert_eq!(client.receive(), "T05thread:1;00:1500000000000040;");
+            assert_eq!(client.request("z0,4000000000000014,1"), "OK");
+            client.request_without_reply("k");
+        });
+
+        // paused before the first instruction
+        assert!(stub.should_pause(10));
+        stub.paused(&paused_at(10)).unwrap();
+        assert!(!stub.should_pause(12));
+        assert!(stub.should_pause(20));
+        stub.paused(&paused_at(20)).unwrap();
+        assert!(stub.should_pause(21));
+        assert!(stub.paused(&paused_at(21)).is_err());
+        assert!(!stub.should_pause(20));
+
+        debugger.join().unwrap();
+    }
+
+    #[test]
+    fn wasm_queries() {
+        let (mut stub, mut client) = connect();
+        let debugger = thread::spawn(move || {
+            // a corrupted packet is sent again
+            client.stream.write_all(b"$qC#00").unwrap();
+            assert_eq!(client.read_byte(), b'-');
+            assert_eq!(client.request("qC"), "QC1");
+            assert_eq!(client.request("QStartNoAckMode"), "OK");
+            client.ack = false;
+            assert_eq!(client.request("qWasmLocal:0;0"), "07000000");
+            assert_eq!(client.request("qWasmLocal:0;2"), "000000000000f83f");
+            assert_eq!(client.request("D"), "OK");
+        });
+
+        stub.paused(&paused_at(10)).unwrap();
+        // detached
+        assert!(!stub.should_pause(10));
+
+        debugger.join().unwrap();
+    }
+
+    #[test]
+    fn module_queries() {
+        let (mut stub, mut client) = connect();
+        let debugger = thread::spawn(move || {
+            assert_eq!(client.request("m4000000000000000,4"), "0061736d");
+            assert_eq!(client.request("m4000000000000006,8"), "0000");
+            assert_eq!(client.request("qWasmLocal:0;1"), "E03");
+            assert_eq!(client.request("qWasmLocal:1;0"), "E03");
+            assert_eq!(client.request("qWasmMem:0;0;4"), "E03");
+            assert_eq!(client.request("qWasmCallStack:1"), "0a00000000000040");
+            assert_eq!(
+                client.request("qXfer:libraries:read::0,1000"),
+                "l<library-list><library name=\"test.wasm\"><section address=\"0x4000000000000000\"/></library></library-list>"
+            );
+            assert_eq!(client.request("qXfer:libraries:read::0,4"), "m<lib");
+            assert_eq!(client.request("vMustReplyEmpty"), "");
+            client.request_without_reply("vCont;c");
+        });
+
+        stub.paused(&paused_at(10)).unwrap();
+        assert!(!stub.should_pause(10));
+
+        debugger.join().unwrap();
+    }
+
+    #[test]
+    fn non_ascii_module_name() {
+        let (mut stub, mut client) = connect_to("é.wasm");
+        let debugger = thread::spawn(move || {
+            assert_eq!(
+                client.request("qXfer:libraries:read::1c,c"),
+                "m\"&#xe9;.wasm"
+            );
+            client.request_without_reply("c");
+        });
+
+        stub.paused(&paused_at(10)).unwrap();
+        debugger.join().unwrap();
+    }
+
+    #[test]
+    fn disconnection() {
+        let (mut stub, client) = connect();
+        drop(client);
+        stub.paused(&paused_at(10)).unwrap();
+        assert!(!stub.should_pause(10));
+    }
+}
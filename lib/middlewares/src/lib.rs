@@ -1,5 +1,15 @@
+pub mod coverage;
+pub mod debugger;
 pub mod metering;
+pub mod operator_policy;
+pub mod profiling;
+pub mod stack_limit;
 
 // The most commonly used symbol are exported at top level of the module. Others are available
 // via modules, e.g. `wasmer_middlewares::metering::get_remaining_points`
+pub use coverage::Coverage;
+pub use debugger::Debugger;
 pub use metering::Metering;
+pub use operator_policy::OperatorPolicy;
+pub use profiling::Profiling;
+pub use stack_limit::StackLimit;
@@ -1,5 +1,10 @@
 //! `metering` is a middleware for tracking how many operators are executed in total
 //! and putting a limit on the total number of operators executed.
+//!
+//! On top of the cost of each operator, `memory.grow` and the bulk memory
+//! operators can be charged in proportion to their operands at runtime, and
+//! host functions can consume points of the calling instance through a
+//! [`MeteringEnv`].
 
 use loupe::{MemoryUsage, MemoryUsageTracker};
 use std::convert::TryInto;
@@ -8,14 +13,15 @@ use std::mem;
 use std::sync::{Arc, Mutex};
 use wasmer::wasmparser::{Operator, Type as WpType, TypeOrFuncType as WpTypeOrFuncType};
 use wasmer::{
-    ExportIndex, FunctionMiddleware, GlobalInit, GlobalType, Instance, LocalFunctionIndex,
-    MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability, Type,
+    ExportIndex, FunctionMiddleware, Global, GlobalInit, GlobalType, Instance, LazyInit,
+    LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability,
+    RuntimeError, Type, WasmerEnv,
 };
 use wasmer_types::GlobalIndex;
 use wasmer_vm::ModuleInfo;
 
 #[derive(Clone, MemoryUsage)]
-struct MeteringGlobalIndexes(GlobalIndex, GlobalIndex);
+struct MeteringGlobalIndexes(GlobalIndex, GlobalIndex, Option<GlobalIndex>);
 
 impl MeteringGlobalIndexes {
     /// The global index in the current module for remaining points.
@@ -31,6 +37,13 @@ impl MeteringGlobalIndexes {
     fn points_exhausted(&self) -> GlobalIndex {
         self.1
     }
+
+    /// The global index in the current module for an i32 scratch global
+    /// holding the operand of an operator with a dynamic cost. It is only
+    /// added if some operators have a dynamic cost.
+    fn operand(&self) -> Option<GlobalIndex> {
+        self.2
+    }
 }
 
 impl fmt::Debug for MeteringGlobalIndexes {
@@ -38,6 +51,7 @@ impl fmt::Debug for MeteringGlobalIndexes {
         f.debug_struct("MeteringGlobalIndexes")
             .field("remaining_points", &self.remaining_points())
             .field("points_exhausted", &self.points_exhausted())
+            .field("operand", &self.operand())
             .finish()
     }
 }
@@ -56,6 +70,13 @@ pub struct Metering<F: Fn(&Operator) -> u64 + Send + Sync> {
     /// Function that maps each operator to a cost in "points".
     cost_function: Arc<F>,
 
+    /// The cost of each page requested by `memory.grow`.
+    memory_grow_cost: u32,
+
+    /// The cost of each byte written by `memory.copy`, `memory.fill` and
+    /// `memory.init`.
+    bulk_memory_cost: u32,
+
     /// The global indexes for metering points.
     global_indexes: Mutex<Option<MeteringGlobalIndexes>>,
 }
@@ -65,6 +86,12 @@ pub struct FunctionMetering<F: Fn(&Operator) -> u64 + Send + Sync> {
     /// Function that maps each operator to a cost in "points".
     cost_function: Arc<F>,
 
+    /// The cost of each page requested by `memory.grow`.
+    memory_grow_cost: u32,
+
+    /// The cost of each byte written by the bulk memory operators.
+    bulk_memory_cost: u32,
+
     /// The global indexes for metering points.
     global_indexes: MeteringGlobalIndexes,
 
@@ -88,9 +115,27 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync> Metering<F> {
         Self {
             initial_limit,
             cost_function: Arc::new(cost_function),
+            memory_grow_cost: 0,
+            bulk_memory_cost: 0,
             global_indexes: Mutex::new(None),
         }
     }
+
+    /// Charges `points_per_page` points for each page requested by
+    /// `memory.grow`, on top of the cost of the operator. The pages are
+    /// charged even if the memory can't grow.
+    pub fn with_memory_grow_cost(mut self, points_per_page: u32) -> Self {
+        self.memory_grow_cost = points_per_page;
+        self
+    }
+
+    /// Charges `points_per_byte` points for each byte written by
+    /// `memory.copy`, `memory.fill` and `memory.init`, on top of the cost
+    /// of the operators.
+    pub fn with_bulk_memory_cost(mut self, points_per_byte: u32) -> Self {
+        self.bulk_memory_cost = points_per_byte;
+        self
+    }
 }
 
 impl<F: Fn(&Operator) -> u64 + Send + Sync> fmt::Debug for Metering<F> {
@@ -98,6 +143,8 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync> fmt::Debug for Metering<F> {
         f.debug_struct("Metering")
             .field("initial_limit", &self.initial_limit)
             .field("cost_function", &"<function>")
+            .field("memory_grow_cost", &self.memory_grow_cost)
+            .field("bulk_memory_cost", &self.bulk_memory_cost)
             .field("global_indexes", &self.global_indexes)
             .finish()
     }
@@ -108,6 +155,8 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync + 'static> ModuleMiddleware for Meter
     fn generate_function_middleware(&self, _: LocalFunctionIndex) -> Box<dyn FunctionMiddleware> {
         Box::new(FunctionMetering {
             cost_function: self.cost_function.clone(),
+            memory_grow_cost: self.memory_grow_cost,
+            bulk_memory_cost: self.bulk_memory_cost,
             global_indexes: self.global_indexes.lock().unwrap().clone().unwrap(),
             accumulated_cost: 0,
         })
@@ -149,9 +198,26 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync + 'static> ModuleMiddleware for Meter
             ExportIndex::Global(points_exhausted_global_index),
         );
 
+        // Append a scratch global for the operands of the operators with a
+        // dynamic cost, if any.
+        let operand_global_index = if self.memory_grow_cost > 0 || self.bulk_memory_cost > 0 {
+            let global_index = module_info
+                .globals
+                .push(GlobalType::new(Type::I32, Mutability::Var));
+
+            module_info
+                .global_initializers
+                .push(GlobalInit::I32Const(0));
+
+            Some(global_index)
+        } else {
+            None
+        };
+
         *global_indexes = Some(MeteringGlobalIndexes(
             remaining_points_global_index,
             points_exhausted_global_index,
+            operand_global_index,
         ))
     }
 }
@@ -167,6 +233,8 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync> fmt::Debug for FunctionMetering<F> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("FunctionMetering")
             .field("cost_function", &"<function>")
+            .field("memory_grow_cost", &self.memory_grow_cost)
+            .field("bulk_memory_cost", &self.bulk_memory_cost)
             .field("global_indexes", &self.global_indexes)
             .finish()
     }
@@ -219,6 +287,66 @@ impl<F: Fn(&Operator) -> u64 + Send + Sync> FunctionMiddleware for FunctionMeter
             }
             _ => {}
         }
+
+        // The operators with a dynamic cost take the number of pages or
+        // bytes as their last operand, which is charged before the operator.
+        // The cost of a unit being a `u32`, the product can't overflow.
+        let unit_cost = match operator {
+            Operator::MemoryGrow { .. } => self.memory_grow_cost,
+            Operator::MemoryCopy { .. }
+            | Operator::MemoryFill { .. }
+            | Operator::MemoryInit { .. } => self.bulk_memory_cost,
+            _ => 0,
+        };
+        if unit_cost > 0 {
+            let operand = self.global_indexes.operand().unwrap().as_u32();
+            let cost = [
+                Operator::GlobalGet {
+                    global_index: operand,
+                },
+                Operator::I64ExtendI32U,
+                Operator::I64Const {
+                    value: unit_cost as i64,
+                },
+                Operator::I64Mul,
+            ];
+            state.push_operator(Operator::GlobalSet {
+                global_index: operand,
+            });
+
+            // if unsigned(globals[remaining_points_index]) < unsigned(cost) { throw(); }
+            state.push_operator(Operator::GlobalGet {
+                global_index: self.global_indexes.remaining_points().as_u32(),
+            });
+            state.extend(&cost);
+            state.extend(&[
+                Operator::I64LtU,
+                Operator::If {
+                    ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+                },
+                Operator::I32Const { value: 1 },
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.points_exhausted().as_u32(),
+                },
+                Operator::Unreachable,
+                Operator::End,
+            ]);
+
+            // globals[remaining_points_index] -= cost;
+            state.push_operator(Operator::GlobalGet {
+                global_index: self.global_indexes.remaining_points().as_u32(),
+            });
+            state.extend(&cost);
+            state.extend(&[
+                Operator::I64Sub,
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.remaining_points().as_u32(),
+                },
+                Operator::GlobalGet {
+                    global_index: operand,
+                },
+            ]);
+        }
         state.push_operator(operator);
 
         Ok(())
@@ -283,12 +411,105 @@ pub fn set_remaining_points(instance: &Instance, points: u64) {
         .expect("Can't set `wasmer_metering_points_exhausted` in Instance");
 }
 
+/// The metering points of the calling instance, for host functions to
+/// consume points, for instance in proportion to the work they do.
+///
+/// It can be the environment of a host function, or a field of one whose
+/// `WasmerEnv::init_with_instance` calls the one of `MeteringEnv`.
+///
+/// # Example
+///
+/// ```rust
+/// use wasmer::{Function, RuntimeError, Store};
+/// use wasmer_middlewares::metering::MeteringEnv;
+///
+/// fn hash(env: &MeteringEnv, len: u32) -> Result<u64, RuntimeError> {
+///     // Charge one point per byte hashed.
+///     env.consume_points(len as u64)?;
+///     Ok(0)
+/// }
+///
+/// fn hash_function(store: &Store) -> Function {
+///     Function::new_native_with_env(store, MeteringEnv::default(), hash)
+/// }
+/// ```
+///
+/// # Panic
+///
+/// The methods of `MeteringEnv` panic if it hasn't been initialized with an
+/// instance processed with the [`Metering`] middleware.
+#[derive(Clone, Default, WasmerEnv)]
+pub struct MeteringEnv {
+    #[wasmer(export(name = "wasmer_metering_remaining_points"))]
+    remaining_points: LazyInit<Global>,
+
+    #[wasmer(export(name = "wasmer_metering_points_exhausted"))]
+    points_exhausted: LazyInit<Global>,
+}
+
+impl MeteringEnv {
+    /// Get the remaining points of the calling instance.
+    pub fn get_remaining_points(&self) -> MeteringPoints {
+        let exhausted: i32 = self
+            .points_exhausted()
+            .get()
+            .try_into()
+            .expect("`wasmer_metering_points_exhausted` from Instance has wrong type");
+
+        if exhausted > 0 {
+            return MeteringPoints::Exhausted;
+        }
+
+        let points = self
+            .remaining_points()
+            .get()
+            .try_into()
+            .expect("`wasmer_metering_remaining_points` from Instance has wrong type");
+
+        MeteringPoints::Remaining(points)
+    }
+
+    /// Consume `points` points of the calling instance.
+    ///
+    /// If there aren't enough points, the points are exhausted, like when
+    /// the instance runs out of points, and the error should be returned
+    /// by the host function to terminate the execution.
+    pub fn consume_points(&self, points: u64) -> Result<(), RuntimeError> {
+        let remaining_points = match self.get_remaining_points() {
+            MeteringPoints::Remaining(remaining_points) if remaining_points >= points => {
+                remaining_points
+            }
+            _ => {
+                self.points_exhausted()
+                    .set(1i32.into())
+                    .expect("Can't set `wasmer_metering_points_exhausted` in Instance");
+                return Err(RuntimeError::new("metering points exhausted"));
+            }
+        };
+
+        self.remaining_points()
+            .set((remaining_points - points).into())
+            .expect("Can't set `wasmer_metering_remaining_points` in Instance");
+        Ok(())
+    }
+
+    fn remaining_points(&self) -> &Global {
+        self.remaining_points_ref()
+            .expect("`MeteringEnv` has not been initialized with an Instance")
+    }
+
+    fn points_exhausted(&self) -> &Global {
+        self.points_exhausted_ref()
+            .expect("`MeteringEnv` has not been initialized with an Instance")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use std::sync::Arc;
-    use wasmer::{imports, wat2wasm, CompilerConfig, Cranelift, Module, Store, JIT};
+    use wasmer::{imports, wat2wasm, CompilerConfig, Cranelift, Function, Module, Store, JIT};
 
     fn cost_function(operator: &Operator) -> u64 {
         match operator {
@@ -412,4 +633,107 @@ mod tests {
             MeteringPoints::Remaining(4)
         );
     }
+
+    #[test]
+    fn dynamic_costs_are_metered() {
+        let metering = Arc::new(
+            Metering::new(100, cost_function)
+                .with_memory_grow_cost(10)
+                .with_bulk_memory_cost(1),
+        );
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(metering.clone());
+        let store = Store::new(&JIT::new(compiler_config).engine());
+        let bytecode = wat2wasm(
+            br#"
+            (module
+            (memory 1)
+            (func (export "fill") (param $len i32)
+                (memory.fill (i32.const 0) (i32.const 0) (local.get $len)))
+            (func (export "grow") (param $pages i32) (result i32)
+                (memory.grow (local.get $pages))))
+            "#,
+        )
+        .unwrap();
+        let module = Module::new(&store, bytecode).unwrap();
+
+        // Instantiate
+        let instance = Instance::new(&module, &imports! {}).unwrap();
+        let fill = instance
+            .exports
+            .get_function("fill")
+            .unwrap()
+            .native::<i32, ()>()
+            .unwrap();
+        let grow = instance
+            .exports
+            .get_function("grow")
+            .unwrap()
+            .native::<i32, i32>()
+            .unwrap();
+
+        // 3 points for the operands, and 1 point per byte.
+        fill.call(10).unwrap();
+        assert_eq!(
+            get_remaining_points(&instance),
+            MeteringPoints::Remaining(87)
+        );
+
+        // 1 point for the operand, and 10 points per page.
+        assert_eq!(grow.call(2).unwrap(), 1);
+        assert_eq!(
+            get_remaining_points(&instance),
+            MeteringPoints::Remaining(66)
+        );
+
+        // Filling more bytes than the remaining points fails.
+        assert!(fill.call(100).is_err());
+        assert_eq!(get_remaining_points(&instance), MeteringPoints::Exhausted);
+    }
+
+    #[test]
+    fn host_functions_consume_points() {
+        let metering = Arc::new(Metering::new(10, cost_function));
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(metering.clone());
+        let store = Store::new(&JIT::new(compiler_config).engine());
+        let bytecode = wat2wasm(
+            br#"
+            (module
+            (import "env" "work" (func $work (param i32)))
+            (func (export "work") (param $points i32)
+                (call $work (local.get $points))))
+            "#,
+        )
+        .unwrap();
+        let module = Module::new(&store, bytecode).unwrap();
+
+        fn work(env: &MeteringEnv, points: u32) -> Result<(), RuntimeError> {
+            env.consume_points(points as u64)
+        }
+        let import_object = imports! {
+            "env" => {
+                "work" => Function::new_native_with_env(&store, MeteringEnv::default(), work),
+            },
+        };
+
+        // Instantiate
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let work = instance
+            .exports
+            .get_function("work")
+            .unwrap()
+            .native::<i32, ()>()
+            .unwrap();
+
+        // 1 point for the operand, and the points consumed by the host.
+        work.call(4).unwrap();
+        assert_eq!(
+            get_remaining_points(&instance),
+            MeteringPoints::Remaining(5)
+        );
+
+        assert!(work.call(5).is_err());
+        assert_eq!(get_remaining_points(&instance), MeteringPoints::Exhausted);
+    }
 }
@@ -0,0 +1,795 @@
+//! `operator_policy` is a middleware for rejecting the modules using some
+//! classes of operators, such as the non-deterministic floating-point
+//! operators, and for canonicalizing the NaNs produced by the floating-point
+//! operators.
+
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem;
+use std::sync::Mutex;
+use wasmer::wasmparser::Operator;
+use wasmer::{
+    FunctionMiddleware, GlobalInit, GlobalType, LocalFunctionIndex, MiddlewareError,
+    MiddlewareReaderState, ModuleMiddleware, Mutability, Type,
+};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::{FunctionIndex, GlobalIndex};
+use wasmer_vm::ModuleInfo;
+
+/// The bits of the canonical 32-bit NaN.
+const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+
+/// The bits of the canonical 64-bit NaN.
+const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
+
+/// A class of operators that can be rejected by an [`OperatorPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperatorClass {
+    /// The scalar floating-point operators, including the loads, stores,
+    /// constants and conversions.
+    Float,
+
+    /// The SIMD operators.
+    Simd,
+
+    /// The `memory.grow` operator.
+    MemoryGrow,
+
+    /// The atomic operators of the threads proposal.
+    Atomic,
+}
+
+impl OperatorClass {
+    /// Returns the class of an operator, if any.
+    pub fn of(operator: &Operator) -> Option<Self> {
+        match operator {
+            Operator::MemoryGrow { .. } => Some(Self::MemoryGrow),
+            Operator::F32Load { .. }
+            | Operator::F64Load { .. }
+            | Operator::F32Store { .. }
+            | Operator::F64Store { .. }
+            | Operator::F32Const { .. }
+            | Operator::F64Const { .. }
+            | Operator::F32Eq
+            | Operator::F32Ne
+            | Operator::F32Lt
+            | Operator::F32Gt
+            | Operator::F32Le
+            | Operator::F32Ge
+            | Operator::F64Eq
+            | Operator::F64Ne
+            | Operator::F64Lt
+            | Operator::F64Gt
+            | Operator::F64Le
+            | Operator::F64Ge
+            | Operator::F32Abs
+            | Operator::F32Neg
+            | Operator::F32Ceil
+            | Operator::F32Floor
+            | Operator::F32Trunc
+            | Operator::F32Nearest
+            | Operator::F32Sqrt
+            | Operator::F32Add
+            | Operator::F32Sub
+            | Operator::F32Mul
+            | Operator::F32Div
+            | Operator::F32Min
+            | Operator::F32Max
+            | Operator::F32Copysign
+            | Operator::F64Abs
+            | Operator::F64Neg
+            | Operator::F64Ceil
+            | Operator::F64Floor
+            | Operator::F64Trunc
+            | Operator::F64Nearest
+            | Operator::F64Sqrt
+            | Operator::F64Add
+            | Operator::F64Sub
+            | Operator::F64Mul
+            | Operator::F64Div
+            | Operator::F64Min
+            | Operator::F64Max
+            | Operator::F64Copysign
+            | Operator::I32TruncF32S
+            | Operator::I32TruncF32U
+            | Operator::I32TruncF64S
+            | Operator::I32TruncF64U
+            | Operator::I64TruncF32S
+            | Operator::I64TruncF32U
+            | Operator::I64TruncF64S
+            | Operator::I64TruncF64U
+            | Operator::F32ConvertI32S
+            | Operator::F32ConvertI32U
+            | Operator::F32ConvertI64S
+            | Operator::F32ConvertI64U
+            | Operator::F32DemoteF64
+            | Operator::F64ConvertI32S
+            | Operator::F64ConvertI32U
+            | Operator::F64ConvertI64S
+            | Operator::F64ConvertI64U
+            | Operator::F64PromoteF32
+            | Operator::I32ReinterpretF32
+            | Operator::I64ReinterpretF64
+            | Operator::F32ReinterpretI32
+            | Operator::F64ReinterpretI64
+            | Operator::I32TruncSatF32S
+            | Operator::I32TruncSatF32U
+            | Operator::I32TruncSatF64S
+            | Operator::I32TruncSatF64U
+            | Operator::I64TruncSatF32S
+            | Operator::I64TruncSatF32U
+            | Operator::I64TruncSatF64S
+            | Operator::I64TruncSatF64U => Some(Self::Float),
+            Operator::V128Load { .. }
+            | Operator::V128Load8x8S { .. }
+            | Operator::V128Load8x8U { .. }
+            | Operator::V128Load16x4S { .. }
+            | Operator::V128Load16x4U { .. }
+            | Operator::V128Load32x2S { .. }
+            | Operator::V128Load32x2U { .. }
+            | Operator::V128Load8Splat { .. }
+            | Operator::V128Load16Splat { .. }
+            | Operator::V128Load32Splat { .. }
+            | Operator::V128Load64Splat { .. }
+            | Operator::V128Load32Zero { .. }
+            | Operator::V128Load64Zero { .. }
+            | Operator::V128Store { .. }
+            | Operator::V128Load8Lane { .. }
+            | Operator::V128Load16Lane { .. }
+            | Operator::V128Load32Lane { .. }
+            | Operator::V128Load64Lane { .. }
+            | Operator::V128Store8Lane { .. }
+            | Operator::V128Store16Lane { .. }
+            | Operator::V128Store32Lane { .. }
+            | Operator::V128Store64Lane { .. }
+            | Operator::V128Const { .. }
+            | Operator::I8x16Shuffle { .. }
+            | Operator::I8x16ExtractLaneS { .. }
+            | Operator::I8x16ExtractLaneU { .. }
+            | Operator::I8x16ReplaceLane { .. }
+            | Operator::I16x8ExtractLaneS { .. }
+            | Operator::I16x8ExtractLaneU { .. }
+            | Operator::I16x8ReplaceLane { .. }
+            | Operator::I32x4ExtractLane { .. }
+            | Operator::I32x4ReplaceLane { .. }
+            | Operator::I64x2ExtractLane { .. }
+            | Operator::I64x2ReplaceLane { .. }
+            | Operator::F32x4ExtractLane { .. }
+            | Operator::F32x4ReplaceLane { .. }
+            | Operator::F64x2ExtractLane { .. }
+            | Operator::F64x2ReplaceLane { .. }
+            | Operator::I8x16Swizzle
+            | Operator::I8x16Splat
+            | Operator::I16x8Splat
+            | Operator::I32x4Splat
+            | Operator::I64x2Splat
+            | Operator::F32x4Splat
+            | Operator::F64x2Splat
+            | Operator::I8x16Eq
+            | Operator::I8x16Ne
+            | Operator::I8x16LtS
+            | Operator::I8x16LtU
+            | Operator::I8x16GtS
+            | Operator::I8x16GtU
+            | Operator::I8x16LeS
+            | Operator::I8x16LeU
+            | Operator::I8x16GeS
+            | Operator::I8x16GeU
+            | Operator::I16x8Eq
+            | Operator::I16x8Ne
+            | Operator::I16x8LtS
+            | Operator::I16x8LtU
+            | Operator::I16x8GtS
+            | Operator::I16x8GtU
+            | Operator::I16x8LeS
+            | Operator::I16x8LeU
+            | Operator::I16x8GeS
+            | Operator::I16x8GeU
+            | Operator::I32x4Eq
+            | Operator::I32x4Ne
+            | Operator::I32x4LtS
+            | Operator::I32x4LtU
+            | Operator::I32x4GtS
+            | Operator::I32x4GtU
+            | Operator::I32x4LeS
+            | Operator::I32x4LeU
+            | Operator::I32x4GeS
+            | Operator::I32x4GeU
+            | Operator::I64x2Eq
+            | Operator::I64x2Ne
+            | Operator::I64x2LtS
+            | Operator::I64x2GtS
+            | Operator::I64x2LeS
+            | Operator::I64x2GeS
+            | Operator::F32x4Eq
+            | Operator::F32x4Ne
+            | Operator::F32x4Lt
+            | Operator::F32x4Gt
+            | Operator::F32x4Le
+            | Operator::F32x4Ge
+            | Operator::F64x2Eq
+            | Operator::F64x2Ne
+            | Operator::F64x2Lt
+            | Operator::F64x2Gt
+            | Operator::F64x2Le
+            | Operator::F64x2Ge
+            | Operator::V128Not
+            | Operator::V128And
+            | Operator::V128AndNot
+            | Operator::V128Or
+            | Operator::V128Xor
+            | Operator::V128Bitselect
+            | Operator::V128AnyTrue
+            | Operator::I8x16Abs
+            | Operator::I8x16Neg
+            | Operator::I8x16Popcnt
+            | Operator::I8x16AllTrue
+            | Operator::I8x16Bitmask
+            | Operator::I8x16NarrowI16x8S
+            | Operator::I8x16NarrowI16x8U
+            | Operator::I8x16Shl
+            | Operator::I8x16ShrS
+            | Operator::I8x16ShrU
+            | Operator::I8x16Add
+            | Operator::I8x16AddSatS
+            | Operator::I8x16AddSatU
+            | Operator::I8x16Sub
+            | Operator::I8x16SubSatS
+            | Operator::I8x16SubSatU
+            | Operator::I8x16MinS
+            | Operator::I8x16MinU
+            | Operator::I8x16MaxS
+            | Operator::I8x16MaxU
+            | Operator::I8x16RoundingAverageU
+            | Operator::I16x8ExtAddPairwiseI8x16S
+            | Operator::I16x8ExtAddPairwiseI8x16U
+            | Operator::I16x8Abs
+            | Operator::I16x8Neg
+            | Operator::I16x8Q15MulrSatS
+            | Operator::I16x8AllTrue
+            | Operator::I16x8Bitmask
+            | Operator::I16x8NarrowI32x4S
+            | Operator::I16x8NarrowI32x4U
+            | Operator::I16x8ExtendLowI8x16S
+            | Operator::I16x8ExtendHighI8x16S
+            | Operator::I16x8ExtendLowI8x16U
+            | Operator::I16x8ExtendHighI8x16U
+            | Operator::I16x8Shl
+            | Operator::I16x8ShrS
+            | Operator::I16x8ShrU
+            | Operator::I16x8Add
+            | Operator::I16x8AddSatS
+            | Operator::I16x8AddSatU
+            | Operator::I16x8Sub
+            | Operator::I16x8SubSatS
+            | Operator::I16x8SubSatU
+            | Operator::I16x8Mul
+            | Operator::I16x8MinS
+            | Operator::I16x8MinU
+            | Operator::I16x8MaxS
+            | Operator::I16x8MaxU
+            | Operator::I16x8RoundingAverageU
+            | Operator::I16x8ExtMulLowI8x16S
+            | Operator::I16x8ExtMulHighI8x16S
+            | Operator::I16x8ExtMulLowI8x16U
+            | Operator::I16x8ExtMulHighI8x16U
+            | Operator::I32x4ExtAddPairwiseI16x8S
+            | Operator::I32x4ExtAddPairwiseI16x8U
+            | Operator::I32x4Abs
+            | Operator::I32x4Neg
+            | Operator::I32x4AllTrue
+            | Operator::I32x4Bitmask
+            | Operator::I32x4ExtendLowI16x8S
+            | Operator::I32x4ExtendHighI16x8S
+            | Operator::I32x4ExtendLowI16x8U
+            | Operator::I32x4ExtendHighI16x8U
+            | Operator::I32x4Shl
+            | Operator::I32x4ShrS
+            | Operator::I32x4ShrU
+            | Operator::I32x4Add
+            | Operator::I32x4Sub
+            | Operator::I32x4Mul
+            | Operator::I32x4MinS
+            | Operator::I32x4MinU
+            | Operator::I32x4MaxS
+            | Operator::I32x4MaxU
+            | Operator::I32x4DotI16x8S
+            | Operator::I32x4ExtMulLowI16x8S
+            | Operator::I32x4ExtMulHighI16x8S
+            | Operator::I32x4ExtMulLowI16x8U
+            | Operator::I32x4ExtMulHighI16x8U
+            | Operator::I64x2Abs
+            | Operator::I64x2Neg
+            | Operator::I64x2AllTrue
+            | Operator::I64x2Bitmask
+            | Operator::I64x2ExtendLowI32x4S
+            | Operator::I64x2ExtendHighI32x4S
+            | Operator::I64x2ExtendLowI32x4U
+            | Operator::I64x2ExtendHighI32x4U
+            | Operator::I64x2Shl
+            | Operator::I64x2ShrS
+            | Operator::I64x2ShrU
+            | Operator::I64x2Add
+            | Operator::I64x2Sub
+            | Operator::I64x2Mul
+            | Operator::I64x2ExtMulLowI32x4S
+            | Operator::I64x2ExtMulHighI32x4S
+            | Operator::I64x2ExtMulLowI32x4U
+            | Operator::I64x2ExtMulHighI32x4U
+            | Operator::F32x4Ceil
+            | Operator::F32x4Floor
+            | Operator::F32x4Trunc
+            | Operator::F32x4Nearest
+            | Operator::F32x4Abs
+            | Operator::F32x4Neg
+            | Operator::F32x4Sqrt
+            | Operator::F32x4Add
+            | Operator::F32x4Sub
+            | Operator::F32x4Mul
+            | Operator::F32x4Div
+            | Operator::F32x4Min
+            | Operator::F32x4Max
+            | Operator::F32x4PMin
+            | Operator::F32x4PMax
+            | Operator::F64x2Ceil
+            | Operator::F64x2Floor
+            | Operator::F64x2Trunc
+            | Operator::F64x2Nearest
+            | Operator::F64x2Abs
+            | Operator::F64x2Neg
+            | Operator::F64x2Sqrt
+            | Operator::F64x2Add
+            | Operator::F64x2Sub
+            | Operator::F64x2Mul
+            | Operator::F64x2Div
+            | Operator::F64x2Min
+            | Operator::F64x2Max
+            | Operator::F64x2PMin
+            | Operator::F64x2PMax
+            | Operator::I32x4TruncSatF32x4S
+            | Operator::I32x4TruncSatF32x4U
+            | Operator::F32x4ConvertI32x4S
+            | Operator::F32x4ConvertI32x4U
+            | Operator::I32x4TruncSatF64x2SZero
+            | Operator::I32x4TruncSatF64x2UZero
+            | Operator::F64x2ConvertLowI32x4S
+            | Operator::F64x2ConvertLowI32x4U
+            | Operator::F32x4DemoteF64x2Zero
+            | Operator::F64x2PromoteLowF32x4 => Some(Self::Simd),
+            Operator::MemoryAtomicNotify { .. }
+            | Operator::MemoryAtomicWait32 { .. }
+            | Operator::MemoryAtomicWait64 { .. }
+            | Operator::AtomicFence { .. }
+            | Operator::I32AtomicLoad { .. }
+            | Operator::I64AtomicLoad { .. }
+            | Operator::I32AtomicLoad8U { .. }
+            | Operator::I32AtomicLoad16U { .. }
+            | Operator::I64AtomicLoad8U { .. }
+            | Operator::I64AtomicLoad16U { .. }
+            | Operator::I64AtomicLoad32U { .. }
+            | Operator::I32AtomicStore { .. }
+            | Operator::I64AtomicStore { .. }
+            | Operator::I32AtomicStore8 { .. }
+            | Operator::I32AtomicStore16 { .. }
+            | Operator::I64AtomicStore8 { .. }
+            | Operator::I64AtomicStore16 { .. }
+            | Operator::I64AtomicStore32 { .. }
+            | Operator::I32AtomicRmwAdd { .. }
+            | Operator::I64AtomicRmwAdd { .. }
+            | Operator::I32AtomicRmw8AddU { .. }
+            | Operator::I32AtomicRmw16AddU { .. }
+            | Operator::I64AtomicRmw8AddU { .. }
+            | Operator::I64AtomicRmw16AddU { .. }
+            | Operator::I64AtomicRmw32AddU { .. }
+            | Operator::I32AtomicRmwSub { .. }
+            | Operator::I64AtomicRmwSub { .. }
+            | Operator::I32AtomicRmw8SubU { .. }
+            | Operator::I32AtomicRmw16SubU { .. }
+            | Operator::I64AtomicRmw8SubU { .. }
+            | Operator::I64AtomicRmw16SubU { .. }
+            | Operator::I64AtomicRmw32SubU { .. }
+            | Operator::I32AtomicRmwAnd { .. }
+            | Operator::I64AtomicRmwAnd { .. }
+            | Operator::I32AtomicRmw8AndU { .. }
+            | Operator::I32AtomicRmw16AndU { .. }
+            | Operator::I64AtomicRmw8AndU { .. }
+            | Operator::I64AtomicRmw16AndU { .. }
+            | Operator::I64AtomicRmw32AndU { .. }
+            | Operator::I32AtomicRmwOr { .. }
+            | Operator::I64AtomicRmwOr { .. }
+            | Operator::I32AtomicRmw8OrU { .. }
+            | Operator::I32AtomicRmw16OrU { .. }
+            | Operator::I64AtomicRmw8OrU { .. }
+            | Operator::I64AtomicRmw16OrU { .. }
+            | Operator::I64AtomicRmw32OrU { .. }
+            | Operator::I32AtomicRmwXor { .. }
+            | Operator::I64AtomicRmwXor { .. }
+            | Operator::I32AtomicRmw8XorU { .. }
+            | Operator::I32AtomicRmw16XorU { .. }
+            | Operator::I64AtomicRmw8XorU { .. }
+            | Operator::I64AtomicRmw16XorU { .. }
+            | Operator::I64AtomicRmw32XorU { .. }
+            | Operator::I32AtomicRmwXchg { .. }
+            | Operator::I64AtomicRmwXchg { .. }
+            | Operator::I32AtomicRmw8XchgU { .. }
+            | Operator::I32AtomicRmw16XchgU { .. }
+            | Operator::I64AtomicRmw8XchgU { .. }
+            | Operator::I64AtomicRmw16XchgU { .. }
+            | Operator::I64AtomicRmw32XchgU { .. }
+            | Operator::I32AtomicRmwCmpxchg { .. }
+            | Operator::I64AtomicRmwCmpxchg { .. }
+            | Operator::I32AtomicRmw8CmpxchgU { .. }
+            | Operator::I32AtomicRmw16CmpxchgU { .. }
+            | Operator::I64AtomicRmw8CmpxchgU { .. }
+            | Operator::I64AtomicRmw16CmpxchgU { .. }
+            | Operator::I64AtomicRmw32CmpxchgU { .. } => Some(Self::Atomic),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OperatorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Float => "floating-point",
+            Self::Simd => "SIMD",
+            Self::MemoryGrow => "`memory.grow`",
+            Self::Atomic => "atomic",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct OperatorPolicyState {
+    /// The number of imported functions of the module.
+    num_imported_functions: usize,
+
+    /// The names of the functions of the module.
+    function_names: HashMap<FunctionIndex, String>,
+
+    /// The global indexes in the current module for the scratch `f32` and
+    /// `f64` values of the NaN canonicalization, if enabled.
+    scratch_globals: Option<(GlobalIndex, GlobalIndex)>,
+}
+
+/// The module-level operator policy middleware.
+///
+/// The modules using a rejected operator fail to compile with a
+/// [`MiddlewareError`] naming the function and the offset of the operator.
+///
+/// When NaN canonicalization is enabled, the NaNs produced by the scalar
+/// floating-point operators are replaced by the canonical NaN, like with
+/// the `canonicalize_nans` option of the Cranelift and LLVM compilers. This
+/// also works with the compilers lacking that option, such as Singlepass.
+/// The SIMD operators are not canonicalized, and should be rejected for a
+/// deterministic execution.
+///
+/// # Panic
+///
+/// An instance of `OperatorPolicy` should not be shared among different modules, since it tracks
+/// module-specific information like the names of the functions. Attempts to use
+/// an `OperatorPolicy` instance from multiple modules will result in a panic.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use wasmer::CompilerConfig;
+/// use wasmer_middlewares::operator_policy::{OperatorClass, OperatorPolicy};
+///
+/// fn create_deterministic_policy(compiler_config: &mut dyn CompilerConfig) {
+///     let mut policy = OperatorPolicy::new();
+///     policy
+///         .reject(OperatorClass::Simd)
+///         .reject(OperatorClass::Atomic)
+///         .canonicalize_nans(true);
+///
+///     compiler_config.push_middleware(Arc::new(policy));
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct OperatorPolicy {
+    /// The rejected classes of operators.
+    rejected: HashSet<OperatorClass>,
+
+    /// Whether the NaNs are canonicalized.
+    canonicalize_nans: bool,
+
+    /// The module-specific state.
+    state: Mutex<Option<OperatorPolicyState>>,
+}
+
+/// The function-level operator policy middleware.
+#[derive(Debug)]
+pub struct FunctionOperatorPolicy {
+    /// The rejected classes of operators.
+    rejected: HashSet<OperatorClass>,
+
+    /// The module-specific state.
+    state: OperatorPolicyState,
+
+    /// The index of the function.
+    local_function_index: LocalFunctionIndex,
+}
+
+impl OperatorPolicy {
+    /// Creates an `OperatorPolicy` middleware, which accepts all the
+    /// operators and doesn't canonicalize the NaNs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rejects the modules using the operators of the class `class`.
+    pub fn reject(&mut self, class: OperatorClass) -> &mut Self {
+        self.rejected.insert(class);
+        self
+    }
+
+    /// Enable NaN canonicalization.
+    ///
+    /// NaN canonicalization is useful when trying to run WebAssembly
+    /// deterministically across different architectures.
+    pub fn canonicalize_nans(&mut self, enable: bool) -> &mut Self {
+        self.canonicalize_nans = enable;
+        self
+    }
+}
+
+impl ModuleMiddleware for OperatorPolicy {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        Box::new(FunctionOperatorPolicy {
+            rejected: self.rejected.clone(),
+            state: self.state.lock().unwrap().clone().unwrap(),
+            local_function_index,
+        })
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.is_some() {
+            panic!("OperatorPolicy::transform_module_info: Attempting to use an `OperatorPolicy` middleware from multiple modules.");
+        }
+
+        // Append the globals for the scratch values and initialize them.
+        let scratch_globals = if self.canonicalize_nans {
+            let f32_global_index = module_info
+                .globals
+                .push(GlobalType::new(Type::F32, Mutability::Var));
+            module_info
+                .global_initializers
+                .push(GlobalInit::F32Const(0.0));
+
+            let f64_global_index = module_info
+                .globals
+                .push(GlobalType::new(Type::F64, Mutability::Var));
+            module_info
+                .global_initializers
+                .push(GlobalInit::F64Const(0.0));
+
+            Some((f32_global_index, f64_global_index))
+        } else {
+            None
+        };
+
+        *state = Some(OperatorPolicyState {
+            num_imported_functions: module_info.num_imported_functions,
+            function_names: module_info.function_names.clone(),
+            scratch_globals,
+        });
+    }
+}
+
+impl MemoryUsage for OperatorPolicy {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self)
+    }
+}
+
+impl FunctionOperatorPolicy {
+    /// Returns the error for a rejected operator.
+    fn rejection(
+        &self,
+        class: OperatorClass,
+        operator: &Operator,
+        state: &MiddlewareReaderState,
+    ) -> MiddlewareError {
+        let function_index = FunctionIndex::new(
+            self.state.num_imported_functions + self.local_function_index.index(),
+        );
+        let function = match self.state.function_names.get(&function_index) {
+            Some(name) => format!("function {} (`{}`)", function_index.index(), name),
+            None => format!("function {}", function_index.index()),
+        };
+        MiddlewareError::new(
+            "operator_policy",
+            format!(
+                "{} operator `{:?}` is not allowed, in {} at offset {:#x}",
+                class,
+                operator,
+                function,
+                state.operator_offset()
+            ),
+        )
+    }
+}
+
+impl FunctionMiddleware for FunctionOperatorPolicy {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if let Some(class) = OperatorClass::of(&operator) {
+            if self.rejected.contains(&class) {
+                return Err(self.rejection(class, &operator, state));
+            }
+        }
+
+        let canonicalization = match (self.state.scratch_globals, &operator) {
+            (
+                Some((f32_global_index, _)),
+                Operator::F32Ceil
+                | Operator::F32Floor
+                | Operator::F32Trunc
+                | Operator::F32Nearest
+                | Operator::F32Sqrt
+                | Operator::F32Add
+                | Operator::F32Sub
+                | Operator::F32Mul
+                | Operator::F32Div
+                | Operator::F32Min
+                | Operator::F32Max
+                | Operator::F32DemoteF64,
+            ) => Some((
+                f32_global_index,
+                Operator::I32Const {
+                    value: CANONICAL_NAN_F32 as i32,
+                },
+                Operator::F32ReinterpretI32,
+                Operator::F32Ne,
+            )),
+            (
+                Some((_, f64_global_index)),
+                Operator::F64Ceil
+                | Operator::F64Floor
+                | Operator::F64Trunc
+                | Operator::F64Nearest
+                | Operator::F64Sqrt
+                | Operator::F64Add
+                | Operator::F64Sub
+                | Operator::F64Mul
+                | Operator::F64Div
+                | Operator::F64Min
+                | Operator::F64Max
+                | Operator::F64PromoteF32,
+            ) => Some((
+                f64_global_index,
+                Operator::I64Const {
+                    value: CANONICAL_NAN_F64 as i64,
+                },
+                Operator::F64ReinterpretI64,
+                Operator::F64Ne,
+            )),
+            _ => None,
+        };
+
+        state.push_operator(operator);
+
+        if let Some((scratch, nan_bits, reinterpret, ne)) = canonicalization {
+            let scratch = scratch.as_u32();
+            state.extend(&[
+                // scratch = result; select(canonical_nan, scratch, scratch != scratch)
+                Operator::GlobalSet {
+                    global_index: scratch,
+                },
+                nan_bits,
+                reinterpret,
+                Operator::GlobalGet {
+                    global_index: scratch,
+                },
+                Operator::GlobalGet {
+                    global_index: scratch,
+                },
+                Operator::GlobalGet {
+                    global_index: scratch,
+                },
+                ne,
+                Operator::Select,
+            ]);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use wasmer::{imports, wat2wasm, CompilerConfig, Cranelift, Instance, Module, Store, JIT};
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (func $grow (export "grow") (param i32) (result i32)
+                (memory.grow (local.get 0)))
+            (func $nan (export "nan") (param f32) (result i32)
+                (i32.reinterpret_f32 (f32.div (local.get 0) (local.get 0))))
+            (memory 1))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn store(policy: OperatorPolicy) -> Store {
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(Arc::new(policy));
+        Store::new(&JIT::new(compiler_config).engine())
+    }
+
+    #[test]
+    fn rejects_operators() {
+        let mut policy = OperatorPolicy::new();
+        policy.reject(OperatorClass::MemoryGrow);
+
+        let message = match Module::new(&store(policy), bytecode()) {
+            Ok(_) => panic!("the module should have been rejected"),
+            Err(err) => err.to_string(),
+        };
+        assert!(
+            message.contains("`memory.grow` operator")
+                && message.contains("in function 0 (`grow`)"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn canonicalizes_nans() {
+        let mut policy = OperatorPolicy::new();
+        policy.canonicalize_nans(true);
+        let store = store(policy);
+        let module = Module::new(&store, bytecode()).unwrap();
+        let instance = Instance::new(&module, &imports! {}).unwrap();
+        let nan = instance
+            .exports
+            .get_function("nan")
+            .unwrap()
+            .native::<f32, i32>()
+            .unwrap();
+
+        assert_eq!(nan.call(0.0).unwrap() as u32, CANONICAL_NAN_F32);
+        assert_eq!(
+            nan.call(f32::from_bits(0x7fa0_0001)).unwrap() as u32,
+            CANONICAL_NAN_F32
+        );
+        assert_eq!(nan.call(2.0).unwrap(), 1.0f32.to_bits() as i32);
+    }
+
+    #[test]
+    fn classifies_operators() {
+        assert_eq!(
+            OperatorClass::of(&Operator::F64PromoteF32),
+            Some(OperatorClass::Float)
+        );
+        assert_eq!(
+            OperatorClass::of(&Operator::F32x4Add),
+            Some(OperatorClass::Simd)
+        );
+        assert_eq!(
+            OperatorClass::of(&Operator::AtomicFence { flags: 0 }),
+            Some(OperatorClass::Atomic)
+        );
+        assert_eq!(OperatorClass::of(&Operator::I32Add), None);
+    }
+}
@@ -0,0 +1,396 @@
+//! `profiling` is a middleware for tracking how many times each function is
+//! called and how many points each function consumes.
+
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::convert::TryInto;
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use wasmer::wasmparser::Operator;
+use wasmer::{
+    ExportIndex, FunctionMiddleware, GlobalInit, GlobalType, Instance, LocalFunctionIndex,
+    MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability, Type,
+};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::{FunctionIndex, GlobalIndex};
+use wasmer_vm::ModuleInfo;
+
+#[derive(Clone, Copy, MemoryUsage)]
+struct ProfilingGlobalIndexes(GlobalIndex);
+
+impl ProfilingGlobalIndexes {
+    /// The global index in the current module for the number of calls of
+    /// a local function.
+    fn calls(&self, local_function_index: LocalFunctionIndex) -> GlobalIndex {
+        GlobalIndex::new(self.0.index() + 2 * local_function_index.index())
+    }
+
+    /// The global index in the current module for the points consumed by
+    /// a local function.
+    fn points(&self, local_function_index: LocalFunctionIndex) -> GlobalIndex {
+        GlobalIndex::new(self.0.index() + 2 * local_function_index.index() + 1)
+    }
+}
+
+impl fmt::Debug for ProfilingGlobalIndexes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProfilingGlobalIndexes")
+            .field("first", &self.0)
+            .finish()
+    }
+}
+
+/// The module-level profiling middleware.
+///
+/// The points of an operator are counted with the same cost function as
+/// the [`Metering`](crate::Metering) middleware. When both middlewares are
+/// used, `Profiling` should be pushed first, so that it doesn't count the
+/// operators added by `Metering`.
+///
+/// # Panic
+///
+/// An instance of `Profiling` should not be shared among different modules, since it tracks
+/// module-specific information like the global indexes to store profiling state. Attempts to use
+/// a `Profiling` instance from multiple modules will result in a panic.
+pub struct Profiling<F: Fn(&Operator) -> u64 + Send + Sync> {
+    /// Function that maps each operator to a cost in "points".
+    cost_function: Arc<F>,
+
+    /// The global indexes for the profiling counters.
+    global_indexes: Mutex<Option<ProfilingGlobalIndexes>>,
+}
+
+/// The function-level profiling middleware.
+pub struct FunctionProfiling<F: Fn(&Operator) -> u64 + Send + Sync> {
+    /// Function that maps each operator to a cost in "points".
+    cost_function: Arc<F>,
+
+    /// The global indexes for the profiling counters.
+    global_indexes: ProfilingGlobalIndexes,
+
+    /// The index of the function.
+    local_function_index: LocalFunctionIndex,
+
+    /// Whether the call of the function has been counted.
+    call_counted: bool,
+
+    /// Accumulated cost of the current basic block.
+    accumulated_cost: u64,
+}
+
+/// The profile of a local function of an `Instance`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    /// The index of the function in the module.
+    pub function_index: u32,
+
+    /// The name of the function, from the name section of the module.
+    pub name: Option<String>,
+
+    /// The number of times the function has been called.
+    pub calls: u64,
+
+    /// The number of points consumed by the function itself, without the
+    /// functions it called.
+    pub points: u64,
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync> Profiling<F> {
+    /// Creates a `Profiling` middleware.
+    pub fn new(cost_function: F) -> Self {
+        Self {
+            cost_function: Arc::new(cost_function),
+            global_indexes: Mutex::new(None),
+        }
+    }
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync> fmt::Debug for Profiling<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profiling")
+            .field("cost_function", &"<function>")
+            .field("global_indexes", &self.global_indexes)
+            .finish()
+    }
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync + 'static> ModuleMiddleware for Profiling<F> {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        Box::new(FunctionProfiling {
+            cost_function: self.cost_function.clone(),
+            global_indexes: self.global_indexes.lock().unwrap().unwrap(),
+            local_function_index,
+            call_counted: false,
+            accumulated_cost: 0,
+        })
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut global_indexes = self.global_indexes.lock().unwrap();
+
+        if global_indexes.is_some() {
+            panic!("Profiling::transform_module_info: Attempting to use a `Profiling` middleware from multiple modules.");
+        }
+
+        // Append a global for the calls and a global for the points of
+        // each local function, and initialize them.
+        let first_global_index = GlobalIndex::new(module_info.globals.len());
+        let num_local_functions = module_info.functions.len() - module_info.num_imported_functions;
+        for index in 0..num_local_functions {
+            for counter in &["calls", "points"] {
+                let global_index = module_info
+                    .globals
+                    .push(GlobalType::new(Type::I64, Mutability::Var));
+
+                module_info
+                    .global_initializers
+                    .push(GlobalInit::I64Const(0));
+
+                module_info.exports.insert(
+                    format!("wasmer_profiling_{}_{}", counter, index),
+                    ExportIndex::Global(global_index),
+                );
+            }
+        }
+
+        *global_indexes = Some(ProfilingGlobalIndexes(first_global_index));
+    }
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync + 'static> MemoryUsage for Profiling<F> {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.global_indexes.size_of_val(tracker)
+            - mem::size_of_val(&self.global_indexes)
+    }
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync> fmt::Debug for FunctionProfiling<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FunctionProfiling")
+            .field("cost_function", &"<function>")
+            .field("global_indexes", &self.global_indexes)
+            .field("local_function_index", &self.local_function_index)
+            .finish()
+    }
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync> FunctionMiddleware for FunctionProfiling<F> {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        // Count the call before the first operator of the function.
+        if !self.call_counted {
+            let calls = self.global_indexes.calls(self.local_function_index);
+            state.extend(&[
+                // globals[calls_index] += 1;
+                Operator::GlobalGet {
+                    global_index: calls.as_u32(),
+                },
+                Operator::I64Const { value: 1 },
+                Operator::I64Add,
+                Operator::GlobalSet {
+                    global_index: calls.as_u32(),
+                },
+            ]);
+            self.call_counted = true;
+        }
+
+        // Get the cost of the current operator, and add it to the accumulator.
+        // This is done before the operator is counted, like in `Metering`, so
+        // that the costs of both middlewares are the same.
+        self.accumulated_cost += (self.cost_function)(&operator);
+
+        // Possible sources and targets of a branch. Add the cost of the previous basic block.
+        match operator {
+            Operator::Loop { .. } // loop headers are branch targets
+            | Operator::End // block ends are branch targets
+            | Operator::Else // "else" is the "end" of an if branch
+            | Operator::Br { .. } // branch source
+            | Operator::BrTable { .. } // branch source
+            | Operator::BrIf { .. } // branch source
+            | Operator::Call { .. } // function call - branch source
+            | Operator::CallIndirect { .. } // function call - branch source
+            | Operator::Return // end of function - branch source
+            => {
+                if self.accumulated_cost > 0 {
+                    let points = self.global_indexes.points(self.local_function_index);
+                    state.extend(&[
+                        // globals[points_index] += self.accumulated_cost;
+                        Operator::GlobalGet { global_index: points.as_u32() },
+                        Operator::I64Const { value: self.accumulated_cost as i64 },
+                        Operator::I64Add,
+                        Operator::GlobalSet { global_index: points.as_u32() },
+                    ]);
+
+                    self.accumulated_cost = 0;
+                }
+            }
+            _ => {}
+        }
+        state.push_operator(operator);
+
+        Ok(())
+    }
+}
+
+/// Get the profile of each local function of an `Instance`, in the order
+/// of their indexes.
+///
+/// This can be used in a headless engine after an ahead-of-time compilation
+/// as all required state lives in the instance.
+///
+/// # Panic
+///
+/// The instance Module must have been processed with the [`Profiling`] middleware
+/// at compile time, otherwise this will panic.
+pub fn get_function_profiles(instance: &Instance) -> Vec<FunctionProfile> {
+    let module_info = instance.module().info();
+    (0..num_profiled_functions(module_info))
+        .map(|index| {
+            let function_index = FunctionIndex::new(module_info.num_imported_functions + index);
+            FunctionProfile {
+                function_index: function_index.as_u32(),
+                name: module_info.function_names.get(&function_index).cloned(),
+                calls: get_counter(instance, &format!("wasmer_profiling_calls_{}", index)),
+                points: get_counter(instance, &format!("wasmer_profiling_points_{}", index)),
+            }
+        })
+        .collect()
+}
+
+/// Reset the profiles of the functions of an `Instance` to zero.
+///
+/// # Panic
+///
+/// The instance Module must have been processed with the [`Profiling`] middleware
+/// at compile time, otherwise this will panic.
+pub fn reset_function_profiles(instance: &Instance) {
+    for index in 0..num_profiled_functions(instance.module().info()) {
+        for counter in &["calls", "points"] {
+            let name = format!("wasmer_profiling_{}_{}", counter, index);
+            instance
+                .exports
+                .get_global(&name)
+                .unwrap_or_else(|_| panic!("Can't get `{}` from Instance", name))
+                .set(0i64.into())
+                .unwrap_or_else(|_| panic!("Can't set `{}` in Instance", name));
+        }
+    }
+}
+
+/// The number of local functions with profiling counters. The functions
+/// added by the middlewares following [`Profiling`] come last and have no
+/// counters. A module without any counter yields all its local functions,
+/// so that getting their counters panics.
+fn num_profiled_functions(module_info: &ModuleInfo) -> usize {
+    let num_local_functions = module_info.functions.len() - module_info.num_imported_functions;
+    (1..num_local_functions)
+        .find(|index| {
+            !module_info
+                .exports
+                .contains_key(&format!("wasmer_profiling_calls_{}", index))
+        })
+        .unwrap_or(num_local_functions)
+}
+
+/// Get the value of a profiling counter of an `Instance`.
+fn get_counter(instance: &Instance, name: &str) -> u64 {
+    let value: i64 = instance
+        .exports
+        .get_global(name)
+        .unwrap_or_else(|_| panic!("Can't get `{}` from Instance", name))
+        .get()
+        .try_into()
+        .unwrap_or_else(|_| panic!("`{}` from Instance has wrong type", name));
+
+    value as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use wasmer::{imports, wat2wasm, CompilerConfig, Cranelift, Module, Store, JIT};
+
+    fn cost_function(operator: &Operator) -> u64 {
+        match operator {
+            Operator::LocalGet { .. } | Operator::I32Const { .. } => 1,
+            Operator::I32Add { .. } => 2,
+            _ => 0,
+        }
+    }
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (type $add_t (func (param i32) (result i32)))
+            (func $add_one_f (type $add_t) (param $value i32) (result i32)
+                local.get $value
+                i32.const 1
+                i32.add)
+            (func $add_two_f (type $add_t) (param $value i32) (result i32)
+                local.get $value
+                call $add_one_f
+                call $add_one_f)
+            (export "add_one" (func $add_one_f))
+            (export "add_two" (func $add_two_f)))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn get_function_profiles_works() {
+        let profiling = Arc::new(Profiling::new(cost_function));
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(profiling.clone());
+        let store = Store::new(&JIT::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode()).unwrap();
+
+        // Instantiate
+        let instance = Instance::new(&module, &imports! {}).unwrap();
+        let add_two = instance
+            .exports
+            .get_function("add_two")
+            .unwrap()
+            .native::<i32, i32>()
+            .unwrap();
+        assert_eq!(add_two.call(1).unwrap(), 3);
+        assert_eq!(add_two.call(1).unwrap(), 3);
+
+        // Each call of add_one costs 4 points, each call of add_two costs
+        // 1 point for `local.get $value`.
+        assert_eq!(
+            get_function_profiles(&instance),
+            vec![
+                FunctionProfile {
+                    function_index: 0,
+                    name: Some("add_one_f".to_string()),
+                    calls: 4,
+                    points: 16,
+                },
+                FunctionProfile {
+                    function_index: 1,
+                    name: Some("add_two_f".to_string()),
+                    calls: 2,
+                    points: 2,
+                },
+            ]
+        );
+
+        reset_function_profiles(&instance);
+        assert!(get_function_profiles(&instance)
+            .iter()
+            .all(|profile| profile.calls == 0 && profile.points == 0));
+    }
+}
@@ -0,0 +1,1123 @@
+//! `stack_limit` is a middleware for putting a deterministic limit on the
+//! height of the stack of a module, independent of the native stack and of
+//! the compiler.
+//!
+//! Each function is given a cost, the number of its parameters and locals
+//! plus the maximum height of its operand stack. The cost is added to a
+//! global stack height on entry to the function, and subtracted on exit.
+//! The execution traps when the stack height exceeds the limit.
+
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::convert::TryInto;
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use wasmer::wasmparser::{
+    BinaryReader, Operator, Type as WpType, TypeOrFuncType as WpTypeOrFuncType,
+};
+use wasmer::{
+    ExportIndex, FunctionMiddleware, GlobalInit, GlobalType, Instance, LocalFunctionIndex,
+    MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability, Type,
+};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::{GlobalIndex, SignatureIndex};
+use wasmer_vm::ModuleInfo;
+
+/// The types of the functions of the original module.
+#[derive(Debug)]
+struct ModuleTypes {
+    /// The number of parameters and results of each signature.
+    signatures: Vec<(u32, u32)>,
+
+    /// The signature of each function.
+    functions: Vec<SignatureIndex>,
+
+    /// The number of imported functions.
+    num_imported_functions: usize,
+
+    /// The type of the block wrapping the body of each local function, or
+    /// `None` if the body isn't wrapped.
+    body_blocks: Vec<Option<WpTypeOrFuncType>>,
+}
+
+impl ModuleTypes {
+    /// The number of parameters and results of the function `function_index`.
+    fn function(&self, function_index: u32) -> (u32, u32) {
+        self.signatures[self.functions[function_index as usize].index()]
+    }
+
+    /// The number of parameters and results of a block of type `ty`.
+    fn block(&self, ty: WpTypeOrFuncType) -> (u32, u32) {
+        match ty {
+            WpTypeOrFuncType::Type(WpType::EmptyBlockType) => (0, 0),
+            WpTypeOrFuncType::Type(_) => (0, 1),
+            WpTypeOrFuncType::FuncType(index) => self.signatures[index as usize],
+        }
+    }
+}
+
+#[derive(Clone)]
+struct StackLimitGlobals {
+    /// The global index in the current module for the stack height.
+    stack_height: GlobalIndex,
+
+    /// The global index in the current module for the operand of the
+    /// branches out of the functions whose body isn't wrapped, if any.
+    branch_operand: Option<GlobalIndex>,
+
+    /// The types of the functions of the original module.
+    types: Arc<ModuleTypes>,
+}
+
+impl fmt::Debug for StackLimitGlobals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StackLimitGlobals")
+            .field("stack_height", &self.stack_height)
+            .field("branch_operand", &self.branch_operand)
+            .finish()
+    }
+}
+
+/// The module-level stack limit middleware.
+///
+/// The stack height is exported as the `wasmer_stack_height` global. It
+/// isn't decreased when the execution traps, so it should be reset with
+/// [`set_stack_height`] before calling the instance again.
+///
+/// The operators added by the other middlewares are not counted, so the
+/// limit stays the same whichever middlewares are used. `StackLimit` must
+/// be pushed before the middlewares adding imported functions or
+/// signatures, such as [`Coverage`](crate::Coverage), which shift the
+/// indexes of the functions.
+///
+/// # Panic
+///
+/// An instance of `StackLimit` should not be shared among different modules, since it tracks
+/// module-specific information like the global index to store the stack height. Attempts to use
+/// a `StackLimit` instance from multiple modules will result in a panic.
+#[derive(Debug)]
+pub struct StackLimit {
+    /// The maximum stack height.
+    limit: u32,
+
+    /// The stack height global and the types of the module.
+    globals: Mutex<Option<StackLimitGlobals>>,
+}
+
+/// The function-level stack limit middleware.
+#[derive(Debug)]
+pub struct FunctionStackLimit {
+    /// The maximum stack height.
+    limit: u32,
+
+    /// The stack height global and the types of the module.
+    globals: StackLimitGlobals,
+
+    /// The index of the function.
+    local_function_index: LocalFunctionIndex,
+
+    /// The cost of the function, once its first operator has been read.
+    cost: Option<u32>,
+
+    /// The number of control frames opened, including the function body.
+    depth: u32,
+}
+
+impl StackLimit {
+    /// Creates a `StackLimit` middleware, with a limit on the stack height
+    /// in number of values.
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            globals: Mutex::new(None),
+        }
+    }
+}
+
+impl ModuleMiddleware for StackLimit {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        Box::new(FunctionStackLimit {
+            limit: self.limit,
+            globals: self.globals.lock().unwrap().clone().unwrap(),
+            local_function_index,
+            cost: None,
+            depth: 1,
+        })
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut globals = self.globals.lock().unwrap();
+
+        if globals.is_some() {
+            panic!("StackLimit::transform_module_info: Attempting to use a `StackLimit` middleware from multiple modules.");
+        }
+
+        let signatures = module_info
+            .signatures
+            .values()
+            .map(|ty| (ty.params().len() as u32, ty.results().len() as u32))
+            .collect();
+        let functions = module_info.functions.values().copied().collect::<Vec<_>>();
+
+        // The body of each function is wrapped in a block with the results
+        // of the function, so that the branches out of the body go through
+        // the end of the function. The type of a block with several results
+        // must be a signature of the type section, so the body of a function
+        // with several results isn't wrapped if none matches them.
+        let body_blocks = functions[module_info.num_imported_functions..]
+            .iter()
+            .map(|&signature| {
+                let results = module_info.signatures[signature].results();
+                match results {
+                    [] => Some(WpTypeOrFuncType::Type(WpType::EmptyBlockType)),
+                    [ty] => Some(WpTypeOrFuncType::Type(wp_type(*ty))),
+                    _ => module_info
+                        .signatures
+                        .iter()
+                        .find(|(_, ty)| ty.params().is_empty() && ty.results() == results)
+                        .map(|(index, _)| WpTypeOrFuncType::FuncType(index.as_u32())),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Append a global for the stack height and initialize it.
+        let stack_height_global_index = module_info
+            .globals
+            .push(GlobalType::new(Type::I32, Mutability::Var));
+
+        module_info
+            .global_initializers
+            .push(GlobalInit::I32Const(0));
+
+        module_info.exports.insert(
+            "wasmer_stack_height".to_string(),
+            ExportIndex::Global(stack_height_global_index),
+        );
+
+        // Append a global for the operand of the branches out of the
+        // bodies that aren't wrapped, if any.
+        let branch_operand_global_index = if body_blocks.iter().any(Option::is_none) {
+            let index = module_info
+                .globals
+                .push(GlobalType::new(Type::I32, Mutability::Var));
+            module_info
+                .global_initializers
+                .push(GlobalInit::I32Const(0));
+            Some(index)
+        } else {
+            None
+        };
+
+        *globals = Some(StackLimitGlobals {
+            stack_height: stack_height_global_index,
+            branch_operand: branch_operand_global_index,
+            types: Arc::new(ModuleTypes {
+                signatures,
+                functions,
+                num_imported_functions: module_info.num_imported_functions,
+                body_blocks,
+            }),
+        });
+    }
+}
+
+impl MemoryUsage for StackLimit {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self)
+    }
+}
+
+impl FunctionStackLimit {
+    /// Computes the cost of the function, from the reader of its operators.
+    fn cost(
+        &self,
+        reader: BinaryReader,
+        state: &MiddlewareReaderState,
+    ) -> Result<u32, MiddlewareError> {
+        let types = &self.globals.types;
+        let function_index = types.num_imported_functions + self.local_function_index.index();
+        let (params, results) = types.function(function_index as u32);
+        let locals = state
+            .local_decls()
+            .iter()
+            .map(|(count, _)| *count)
+            .sum::<u32>();
+        let operands = max_operand_height(reader, types, results)
+            .map_err(|err| MiddlewareError::new("stack_limit", err.message()))?;
+
+        Ok(params + locals + operands)
+    }
+
+    /// The type of the block wrapping the body of the function, if it's
+    /// wrapped.
+    fn body_block(&self) -> Option<WpTypeOrFuncType> {
+        self.globals.types.body_blocks[self.local_function_index.index()]
+    }
+
+    /// Whether a branch to the label `relative_depth` leaves the function
+    /// without going through its end, because its body isn't wrapped.
+    fn branches_out(&self, relative_depth: u32) -> bool {
+        self.body_block().is_none() && relative_depth == self.depth - 1
+    }
+
+    /// Returns the operators subtracting the cost of the function from the
+    /// stack height.
+    fn exit<'a>(&self, cost: u32) -> [Operator<'a>; 4] {
+        let stack_height = self.globals.stack_height.as_u32();
+        [
+            // globals[stack_height_index] -= cost;
+            Operator::GlobalGet {
+                global_index: stack_height,
+            },
+            Operator::I32Const { value: cost as i32 },
+            Operator::I32Sub,
+            Operator::GlobalSet {
+                global_index: stack_height,
+            },
+        ]
+    }
+
+    /// Returns the operators subtracting the cost of the function from the
+    /// stack height before a branch, if the `taken` operators compute a
+    /// non-zero value from the operand of the branch, in the branch operand
+    /// global.
+    fn conditional_exit<'a>(&self, cost: u32, taken: Vec<Operator<'a>>) -> Vec<Operator<'a>> {
+        let stack_height = self.globals.stack_height.as_u32();
+        let branch_operand = self
+            .globals
+            .branch_operand
+            .expect("the branch operand global is added for the bodies that aren't wrapped")
+            .as_u32();
+        let mut operators = vec![
+            Operator::GlobalSet {
+                global_index: branch_operand,
+            },
+            // globals[stack_height_index] -= if taken { cost } else { 0 };
+            Operator::GlobalGet {
+                global_index: stack_height,
+            },
+            Operator::I32Const { value: cost as i32 },
+            Operator::I32Const { value: 0 },
+        ];
+        operators.extend(taken);
+        operators.extend(vec![
+            Operator::Select,
+            Operator::I32Sub,
+            Operator::GlobalSet {
+                global_index: stack_height,
+            },
+            Operator::GlobalGet {
+                global_index: branch_operand,
+            },
+        ]);
+        operators
+    }
+}
+
+impl FunctionMiddleware for FunctionStackLimit {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        let stack_height = self.globals.stack_height.as_u32();
+
+        let cost = match self.cost {
+            Some(cost) => cost,
+            None => {
+                let cost = self.cost(state.operator_reader(), state)?;
+                let body_block = self.body_block();
+                state.extend(&[
+                    // globals[stack_height_index] += cost;
+                    Operator::GlobalGet {
+                        global_index: stack_height,
+                    },
+                    Operator::I32Const { value: cost as i32 },
+                    Operator::I32Add,
+                    Operator::GlobalSet {
+                        global_index: stack_height,
+                    },
+                    // if unsigned(globals[stack_height_index]) > unsigned(self.limit) { throw(); }
+                    Operator::GlobalGet {
+                        global_index: stack_height,
+                    },
+                    Operator::I32Const {
+                        value: self.limit as i32,
+                    },
+                    Operator::I32GtU,
+                    Operator::If {
+                        ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+                    },
+                    Operator::Unreachable,
+                    Operator::End,
+                ]);
+                if let Some(ty) = body_block {
+                    state.push_operator(Operator::Block { ty });
+                }
+                self.cost = Some(cost);
+                cost
+            }
+        };
+
+        match operator {
+            Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Try { .. } => self.depth += 1,
+            Operator::End | Operator::Delegate { .. } => {
+                self.depth -= 1;
+                if self.depth == 0 {
+                    // Close the block wrapping the body before the end of
+                    // the function.
+                    if self.body_block().is_some() {
+                        state.push_operator(Operator::End);
+                    }
+                    state.extend(&self.exit(cost));
+                }
+            }
+            Operator::Return
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. } => state.extend(&self.exit(cost)),
+            // The branches out of a body that isn't wrapped subtract the
+            // cost themselves.
+            Operator::Br { relative_depth } if self.branches_out(relative_depth) => {
+                state.extend(&self.exit(cost))
+            }
+            Operator::BrIf { relative_depth } if self.branches_out(relative_depth) => {
+                let branch_operand = self.globals.branch_operand.unwrap().as_u32();
+                let taken = vec![Operator::GlobalGet {
+                    global_index: branch_operand,
+                }];
+                state.extend(&self.conditional_exit(cost, taken));
+            }
+            Operator::BrTable { ref table } if self.body_block().is_none() => {
+                let targets = table
+                    .targets()
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| MiddlewareError::new("stack_limit", err.message()))?;
+                if targets.iter().any(|&(target, _)| self.branches_out(target)) {
+                    // taken = index == i || ... || index >= len
+                    let branch_operand = self.globals.branch_operand.unwrap().as_u32();
+                    let mut taken = vec![Operator::I32Const { value: 0 }];
+                    for (index, &(target, default)) in targets.iter().enumerate() {
+                        if !self.branches_out(target) {
+                            continue;
+                        }
+                        taken.push(Operator::GlobalGet {
+                            global_index: branch_operand,
+                        });
+                        if default {
+                            taken.push(Operator::I32Const {
+                                value: table.len() as i32,
+                            });
+                            taken.push(Operator::I32GeU);
+                        } else {
+                            taken.push(Operator::I32Const {
+                                value: index as i32,
+                            });
+                            taken.push(Operator::I32Eq);
+                        }
+                        taken.push(Operator::I32Or);
+                    }
+                    state.extend(&self.conditional_exit(cost, taken));
+                }
+            }
+            _ => {}
+        }
+        state.push_operator(operator);
+
+        Ok(())
+    }
+}
+
+/// A control frame of the operand stack analysis.
+struct Frame {
+    /// The height of the operand stack below the parameters of the block.
+    height: u32,
+
+    /// The number of parameters of the block.
+    params: u32,
+
+    /// The number of results of the block.
+    results: u32,
+}
+
+/// Computes the maximum height of the operand stack of a function body
+/// with `results` results, from the reader of its operators.
+fn max_operand_height(
+    mut reader: BinaryReader,
+    types: &ModuleTypes,
+    results: u32,
+) -> Result<u32, wasmer::wasmparser::BinaryReaderError> {
+    let mut frames = vec![Frame {
+        height: 0,
+        params: 0,
+        results,
+    }];
+    let mut height = 0u32;
+    let mut max_height = 0u32;
+
+    while !reader.eof() {
+        match reader.read_operator()? {
+            Operator::Block { ty } | Operator::Loop { ty } | Operator::Try { ty } => {
+                let (params, results) = types.block(ty);
+                frames.push(Frame {
+                    height: height.saturating_sub(params),
+                    params,
+                    results,
+                });
+            }
+            Operator::If { ty } => {
+                height = height.saturating_sub(1);
+                let (params, results) = types.block(ty);
+                frames.push(Frame {
+                    height: height.saturating_sub(params),
+                    params,
+                    results,
+                });
+            }
+            Operator::Else | Operator::Catch { .. } | Operator::CatchAll | Operator::Unwind => {
+                if let Some(frame) = frames.last() {
+                    height = frame.height + frame.params;
+                }
+            }
+            Operator::End | Operator::Delegate { .. } => {
+                if let Some(frame) = frames.pop() {
+                    height = frame.height + frame.results;
+                }
+                if frames.is_empty() {
+                    break;
+                }
+            }
+            // The rest of the block is unreachable.
+            Operator::Unreachable
+            | Operator::Br { .. }
+            | Operator::BrTable { .. }
+            | Operator::Return
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. }
+            | Operator::Throw { .. }
+            | Operator::Rethrow { .. } => {
+                if let Some(frame) = frames.last() {
+                    height = frame.height;
+                }
+            }
+            Operator::Call { function_index } => {
+                let (params, results) = types.function(function_index);
+                height = height.saturating_sub(params) + results;
+            }
+            Operator::CallIndirect { index, .. } => {
+                let (params, results) = types.signatures[index as usize];
+                height = height.saturating_sub(params + 1) + results;
+            }
+            operator => {
+                let (pops, pushes) = stack_effect(&operator);
+                height = height.saturating_sub(pops) + pushes;
+            }
+        }
+        max_height = max_height.max(height);
+    }
+
+    Ok(max_height)
+}
+
+/// Returns the number of values popped and pushed by a non-control
+/// operator. The unary operators, which pop and push one value, are the
+/// default.
+fn stack_effect(operator: &Operator) -> (u32, u32) {
+    match operator {
+        Operator::Nop
+        | Operator::DataDrop { .. }
+        | Operator::ElemDrop { .. }
+        | Operator::AtomicFence { .. } => (0, 0),
+
+        Operator::I32Const { .. }
+        | Operator::I64Const { .. }
+        | Operator::F32Const { .. }
+        | Operator::F64Const { .. }
+        | Operator::V128Const { .. }
+        | Operator::LocalGet { .. }
+        | Operator::GlobalGet { .. }
+        | Operator::MemorySize { .. }
+        | Operator::RefNull { .. }
+        | Operator::RefFunc { .. }
+        | Operator::TableSize { .. } => (0, 1),
+
+        Operator::Drop
+        | Operator::LocalSet { .. }
+        | Operator::GlobalSet { .. }
+        | Operator::BrIf { .. } => (1, 0),
+
+        Operator::I32Store { .. }
+        | Operator::I64Store { .. }
+        | Operator::F32Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::I32Store8 { .. }
+        | Operator::I32Store16 { .. }
+        | Operator::I64Store8 { .. }
+        | Operator::I64Store16 { .. }
+        | Operator::I64Store32 { .. }
+        | Operator::I32AtomicStore { .. }
+        | Operator::I64AtomicStore { .. }
+        | Operator::I32AtomicStore8 { .. }
+        | Operator::I32AtomicStore16 { .. }
+        | Operator::I64AtomicStore8 { .. }
+        | Operator::I64AtomicStore16 { .. }
+        | Operator::I64AtomicStore32 { .. }
+        | Operator::V128Store { .. }
+        | Operator::V128Store8Lane { .. }
+        | Operator::V128Store16Lane { .. }
+        | Operator::V128Store32Lane { .. }
+        | Operator::V128Store64Lane { .. }
+        | Operator::TableSet { .. } => (2, 0),
+
+        Operator::MemoryInit { .. }
+        | Operator::MemoryCopy { .. }
+        | Operator::MemoryFill { .. }
+        | Operator::TableInit { .. }
+        | Operator::TableCopy { .. }
+        | Operator::TableFill { .. } => (3, 0),
+
+        Operator::Select
+        | Operator::TypedSelect { .. }
+        | Operator::MemoryAtomicWait32 { .. }
+        | Operator::MemoryAtomicWait64 { .. }
+        | Operator::I32AtomicRmwCmpxchg { .. }
+        | Operator::I64AtomicRmwCmpxchg { .. }
+        | Operator::I32AtomicRmw8CmpxchgU { .. }
+        | Operator::I32AtomicRmw16CmpxchgU { .. }
+        | Operator::I64AtomicRmw8CmpxchgU { .. }
+        | Operator::I64AtomicRmw16CmpxchgU { .. }
+        | Operator::I64AtomicRmw32CmpxchgU { .. }
+        | Operator::V128Bitselect => (3, 1),
+
+        // Binary operators.
+        Operator::I32Eq
+        | Operator::I32Ne
+        | Operator::I32LtS
+        | Operator::I32LtU
+        | Operator::I32GtS
+        | Operator::I32GtU
+        | Operator::I32LeS
+        | Operator::I32LeU
+        | Operator::I32GeS
+        | Operator::I32GeU
+        | Operator::I64Eq
+        | Operator::I64Ne
+        | Operator::I64LtS
+        | Operator::I64LtU
+        | Operator::I64GtS
+        | Operator::I64GtU
+        | Operator::I64LeS
+        | Operator::I64LeU
+        | Operator::I64GeS
+        | Operator::I64GeU
+        | Operator::F32Eq
+        | Operator::F32Ne
+        | Operator::F32Lt
+        | Operator::F32Gt
+        | Operator::F32Le
+        | Operator::F32Ge
+        | Operator::F64Eq
+        | Operator::F64Ne
+        | Operator::F64Lt
+        | Operator::F64Gt
+        | Operator::F64Le
+        | Operator::F64Ge
+        | Operator::I32Add
+        | Operator::I32Sub
+        | Operator::I32Mul
+        | Operator::I32DivS
+        | Operator::I32DivU
+        | Operator::I32RemS
+        | Operator::I32RemU
+        | Operator::I32And
+        | Operator::I32Or
+        | Operator::I32Xor
+        | Operator::I32Shl
+        | Operator::I32ShrS
+        | Operator::I32ShrU
+        | Operator::I32Rotl
+        | Operator::I32Rotr
+        | Operator::I64Add
+        | Operator::I64Sub
+        | Operator::I64Mul
+        | Operator::I64DivS
+        | Operator::I64DivU
+        | Operator::I64RemS
+        | Operator::I64RemU
+        | Operator::I64And
+        | Operator::I64Or
+        | Operator::I64Xor
+        | Operator::I64Shl
+        | Operator::I64ShrS
+        | Operator::I64ShrU
+        | Operator::I64Rotl
+        | Operator::I64Rotr
+        | Operator::F32Add
+        | Operator::F32Sub
+        | Operator::F32Mul
+        | Operator::F32Div
+        | Operator::F32Min
+        | Operator::F32Max
+        | Operator::F32Copysign
+        | Operator::F64Add
+        | Operator::F64Sub
+        | Operator::F64Mul
+        | Operator::F64Div
+        | Operator::F64Min
+        | Operator::F64Max
+        | Operator::F64Copysign
+        | Operator::TableGrow { .. }
+        | Operator::MemoryAtomicNotify { .. }
+        | Operator::I32AtomicRmwAdd { .. }
+        | Operator::I64AtomicRmwAdd { .. }
+        | Operator::I32AtomicRmw8AddU { .. }
+        | Operator::I32AtomicRmw16AddU { .. }
+        | Operator::I64AtomicRmw8AddU { .. }
+        | Operator::I64AtomicRmw16AddU { .. }
+        | Operator::I64AtomicRmw32AddU { .. }
+        | Operator::I32AtomicRmwSub { .. }
+        | Operator::I64AtomicRmwSub { .. }
+        | Operator::I32AtomicRmw8SubU { .. }
+        | Operator::I32AtomicRmw16SubU { .. }
+        | Operator::I64AtomicRmw8SubU { .. }
+        | Operator::I64AtomicRmw16SubU { .. }
+        | Operator::I64AtomicRmw32SubU { .. }
+        | Operator::I32AtomicRmwAnd { .. }
+        | Operator::I64AtomicRmwAnd { .. }
+        | Operator::I32AtomicRmw8AndU { .. }
+        | Operator::I32AtomicRmw16AndU { .. }
+        | Operator::I64AtomicRmw8AndU { .. }
+        | Operator::I64AtomicRmw16AndU { .. }
+        | Operator::I64AtomicRmw32AndU { .. }
+        | Operator::I32AtomicRmwOr { .. }
+        | Operator::I64AtomicRmwOr { .. }
+        | Operator::I32AtomicRmw8OrU { .. }
+        | Operator::I32AtomicRmw16OrU { .. }
+        | Operator::I64AtomicRmw8OrU { .. }
+        | Operator::I64AtomicRmw16OrU { .. }
+        | Operator::I64AtomicRmw32OrU { .. }
+        | Operator::I32AtomicRmwXor { .. }
+        | Operator::I64AtomicRmwXor { .. }
+        | Operator::I32AtomicRmw8XorU { .. }
+        | Operator::I32AtomicRmw16XorU { .. }
+        | Operator::I64AtomicRmw8XorU { .. }
+        | Operator::I64AtomicRmw16XorU { .. }
+        | Operator::I64AtomicRmw32XorU { .. }
+        | Operator::I32AtomicRmwXchg { .. }
+        | Operator::I64AtomicRmwXchg { .. }
+        | Operator::I32AtomicRmw8XchgU { .. }
+        | Operator::I32AtomicRmw16XchgU { .. }
+        | Operator::I64AtomicRmw8XchgU { .. }
+        | Operator::I64AtomicRmw16XchgU { .. }
+        | Operator::I64AtomicRmw32XchgU { .. }
+        | Operator::V128Load8Lane { .. }
+        | Operator::V128Load16Lane { .. }
+        | Operator::V128Load32Lane { .. }
+        | Operator::V128Load64Lane { .. }
+        | Operator::I8x16Shuffle { .. }
+        | Operator::I8x16ReplaceLane { .. }
+        | Operator::I16x8ReplaceLane { .. }
+        | Operator::I32x4ReplaceLane { .. }
+        | Operator::I64x2ReplaceLane { .. }
+        | Operator::F32x4ReplaceLane { .. }
+        | Operator::F64x2ReplaceLane { .. }
+        | Operator::I8x16Swizzle
+        | Operator::I8x16Eq
+        | Operator::I8x16Ne
+        | Operator::I8x16LtS
+        | Operator::I8x16LtU
+        | Operator::I8x16GtS
+        | Operator::I8x16GtU
+        | Operator::I8x16LeS
+        | Operator::I8x16LeU
+        | Operator::I8x16GeS
+        | Operator::I8x16GeU
+        | Operator::I16x8Eq
+        | Operator::I16x8Ne
+        | Operator::I16x8LtS
+        | Operator::I16x8LtU
+        | Operator::I16x8GtS
+        | Operator::I16x8GtU
+        | Operator::I16x8LeS
+        | Operator::I16x8LeU
+        | Operator::I16x8GeS
+        | Operator::I16x8GeU
+        | Operator::I32x4Eq
+        | Operator::I32x4Ne
+        | Operator::I32x4LtS
+        | Operator::I32x4LtU
+        | Operator::I32x4GtS
+        | Operator::I32x4GtU
+        | Operator::I32x4LeS
+        | Operator::I32x4LeU
+        | Operator::I32x4GeS
+        | Operator::I32x4GeU
+        | Operator::I64x2Eq
+        | Operator::I64x2Ne
+        | Operator::I64x2LtS
+        | Operator::I64x2GtS
+        | Operator::I64x2LeS
+        | Operator::I64x2GeS
+        | Operator::F32x4Eq
+        | Operator::F32x4Ne
+        | Operator::F32x4Lt
+        | Operator::F32x4Gt
+        | Operator::F32x4Le
+        | Operator::F32x4Ge
+        | Operator::F64x2Eq
+        | Operator::F64x2Ne
+        | Operator::F64x2Lt
+        | Operator::F64x2Gt
+        | Operator::F64x2Le
+        | Operator::F64x2Ge
+        | Operator::V128And
+        | Operator::V128AndNot
+        | Operator::V128Or
+        | Operator::V128Xor
+        | Operator::I8x16NarrowI16x8S
+        | Operator::I8x16NarrowI16x8U
+        | Operator::I8x16Shl
+        | Operator::I8x16ShrS
+        | Operator::I8x16ShrU
+        | Operator::I8x16Add
+        | Operator::I8x16AddSatS
+        | Operator::I8x16AddSatU
+        | Operator::I8x16Sub
+        | Operator::I8x16SubSatS
+        | Operator::I8x16SubSatU
+        | Operator::I8x16MinS
+        | Operator::I8x16MinU
+        | Operator::I8x16MaxS
+        | Operator::I8x16MaxU
+        | Operator::I8x16RoundingAverageU
+        | Operator::I16x8Q15MulrSatS
+        | Operator::I16x8NarrowI32x4S
+        | Operator::I16x8NarrowI32x4U
+        | Operator::I16x8Shl
+        | Operator::I16x8ShrS
+        | Operator::I16x8ShrU
+        | Operator::I16x8Add
+        | Operator::I16x8AddSatS
+        | Operator::I16x8AddSatU
+        | Operator::I16x8Sub
+        | Operator::I16x8SubSatS
+        | Operator::I16x8SubSatU
+        | Operator::I16x8Mul
+        | Operator::I16x8MinS
+        | Operator::I16x8MinU
+        | Operator::I16x8MaxS
+        | Operator::I16x8MaxU
+        | Operator::I16x8RoundingAverageU
+        | Operator::I16x8ExtMulLowI8x16S
+        | Operator::I16x8ExtMulHighI8x16S
+        | Operator::I16x8ExtMulLowI8x16U
+        | Operator::I16x8ExtMulHighI8x16U
+        | Operator::I32x4Shl
+        | Operator::I32x4ShrS
+        | Operator::I32x4ShrU
+        | Operator::I32x4Add
+        | Operator::I32x4Sub
+        | Operator::I32x4Mul
+        | Operator::I32x4MinS
+        | Operator::I32x4MinU
+        | Operator::I32x4MaxS
+        | Operator::I32x4MaxU
+        | Operator::I32x4DotI16x8S
+        | Operator::I32x4ExtMulLowI16x8S
+        | Operator::I32x4ExtMulHighI16x8S
+        | Operator::I32x4ExtMulLowI16x8U
+        | Operator::I32x4ExtMulHighI16x8U
+        | Operator::I64x2Shl
+        | Operator::I64x2ShrS
+        | Operator::I64x2ShrU
+        | Operator::I64x2Add
+        | Operator::I64x2Sub
+        | Operator::I64x2Mul
+        | Operator::I64x2ExtMulLowI32x4S
+        | Operator::I64x2ExtMulHighI32x4S
+        | Operator::I64x2ExtMulLowI32x4U
+        | Operator::I64x2ExtMulHighI32x4U
+        | Operator::F32x4Add
+        | Operator::F32x4Sub
+        | Operator::F32x4Mul
+        | Operator::F32x4Div
+        | Operator::F32x4Min
+        | Operator::F32x4Max
+        | Operator::F32x4PMin
+        | Operator::F32x4PMax
+        | Operator::F64x2Add
+        | Operator::F64x2Sub
+        | Operator::F64x2Mul
+        | Operator::F64x2Div
+        | Operator::F64x2Min
+        | Operator::F64x2Max
+        | Operator::F64x2PMin
+        | Operator::F64x2PMax => (2, 1),
+
+        _ => (1, 1),
+    }
+}
+
+/// Converts a value type to its `wasmparser` equivalent.
+fn wp_type(ty: Type) -> WpType {
+    match ty {
+        Type::I32 => WpType::I32,
+        Type::I64 => WpType::I64,
+        Type::F32 => WpType::F32,
+        Type::F64 => WpType::F64,
+        Type::V128 => WpType::V128,
+        Type::ExternRef => WpType::ExternRef,
+        Type::FuncRef => WpType::FuncRef,
+    }
+}
+
+/// Get the stack height of an `Instance`.
+///
+/// The stack height is above the limit after the execution trapped because
+/// of the limit, until it is reset with [`set_stack_height`].
+///
+/// # Panic
+///
+/// The instance Module must have been processed with the [`StackLimit`] middleware
+/// at compile time, otherwise this will panic.
+pub fn get_stack_height(instance: &Instance) -> u32 {
+    let height: i32 = instance
+        .exports
+        .get_global("wasmer_stack_height")
+        .expect("Can't get `wasmer_stack_height` from Instance")
+        .get()
+        .try_into()
+        .expect("`wasmer_stack_height` from Instance has wrong type");
+
+    height as u32
+}
+
+/// Set the stack height of an `Instance`.
+///
+/// The stack height must be reset to zero after the execution trapped,
+/// since the functions that were running didn't subtract their cost.
+///
+/// # Panic
+///
+/// The instance Module must have been processed with the [`StackLimit`] middleware
+/// at compile time, otherwise this will panic.
+pub fn set_stack_height(instance: &Instance, height: u32) {
+    instance
+        .exports
+        .get_global("wasmer_stack_height")
+        .expect("Can't get `wasmer_stack_height` from Instance")
+        .set((height as i32).into())
+        .expect("Can't set `wasmer_stack_height` in Instance");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use wasmer::{imports, wat2wasm, CompilerConfig, Cranelift, FunctionType, Module, Store, JIT};
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (func $count (export "count") (param $n i32) (result i32)
+                (if (result i32) (i32.eqz (local.get $n))
+                    (then (i32.const 0))
+                    (else
+                        (i32.add
+                            (i32.const 1)
+                            (call $count (i32.sub (local.get $n) (i32.const 1)))))))
+            (func $early (export "early") (param $n i32) (result i32)
+                (drop (br_if 0 (i32.const 2) (local.get $n)))
+                (return (i32.const 1))))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    /// Functions with several results, whose bodies can't be wrapped since
+    /// there's no `() -> (i32 i32)` signature.
+    fn multi_value_bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (func $pair (export "pair") (param $n i32) (result i32 i32)
+                (i32.const 1) (i32.const 2)
+                (br_if 0 (i32.eqz (local.get $n)))
+                (drop) (drop)
+                (i32.const 0)
+                (block $b (param i32) (result i32 i32)
+                    (drop)
+                    (i32.const 3) (i32.const 4)
+                    (br_table $b 1 (i32.sub (local.get $n) (i32.const 1))))
+                (i32.const 10)
+                (i32.add))
+            (func $swap (export "swap") (param i32 i32) (result i32 i32)
+                (if (i32.eqz (local.get 0))
+                    (then (br 1 (local.get 1) (local.get 0))))
+                (return (local.get 1) (local.get 0))))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn instance(limit: u32, wasm: Vec<u8>) -> Instance {
+        let stack_limit = Arc::new(StackLimit::new(limit));
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(stack_limit);
+        let store = Store::new(&JIT::new(compiler_config).engine());
+        let module = Module::new(&store, wasm).unwrap();
+        Instance::new(&module, &imports! {}).unwrap()
+    }
+
+    #[test]
+    fn max_operand_height_works() {
+        let wasm = bytecode();
+        let mut bodies = vec![];
+        for payload in wasmer::wasmparser::Parser::new(0).parse_all(&wasm) {
+            if let wasmer::wasmparser::Payload::CodeSectionEntry(body) = payload.unwrap() {
+                bodies.push(body.get_operators_reader().unwrap());
+            }
+        }
+        let types = ModuleTypes {
+            signatures: vec![(1, 1)],
+            functions: vec![SignatureIndex::new(0), SignatureIndex::new(0)],
+            num_imported_functions: 0,
+            body_blocks: vec![],
+        };
+
+        // `i32.const 1`, `local.get $n` and `i32.const 1` for the call.
+        let count = BinaryReader::new_with_offset(
+            &wasm[bodies[0].original_position()..],
+            bodies[0].original_position(),
+        );
+        assert_eq!(max_operand_height(count, &types, 1).unwrap(), 3);
+
+        // `i32.const 2` and `local.get $n` for `br_if`.
+        let early = BinaryReader::new_with_offset(
+            &wasm[bodies[1].original_position()..],
+            bodies[1].original_position(),
+        );
+        assert_eq!(max_operand_height(early, &types, 1).unwrap(), 2);
+    }
+
+    #[test]
+    fn stack_height_is_limited() {
+        // Each call of `count` costs 1 parameter and 3 operands.
+        let instance = instance(40, bytecode());
+        let count = instance
+            .exports
+            .get_function("count")
+            .unwrap()
+            .native::<i32, i32>()
+            .unwrap();
+        let early = instance
+            .exports
+            .get_function("early")
+            .unwrap()
+            .native::<i32, i32>()
+            .unwrap();
+
+        assert_eq!(count.call(9).unwrap(), 9);
+        assert_eq!(get_stack_height(&instance), 0);
+        assert_eq!(early.call(0).unwrap(), 1);
+        assert_eq!(early.call(1).unwrap(), 2);
+        assert_eq!(get_stack_height(&instance), 0);
+
+        // 11 nested calls need a stack height of 44.
+        assert!(count.call(10).is_err());
+        assert!(get_stack_height(&instance) > 40);
+
+        set_stack_height(&instance, 0);
+        assert_eq!(count.call(9).unwrap(), 9);
+    }
+
+    #[test]
+    fn bodies_with_several_results() {
+        let mut module_info = ModuleInfo::new();
+        let pair = module_info.signatures.push(FunctionType::new(
+            vec![Type::I32],
+            vec![Type::I32, Type::I32],
+        ));
+        let both = module_info
+            .signatures
+            .push(FunctionType::new(vec![], vec![Type::I64, Type::I64]));
+        module_info.functions.push(pair);
+        module_info.functions.push(both);
+
+        let stack_limit = StackLimit::new(10);
+        stack_limit.transform_module_info(&mut module_info);
+        let globals = stack_limit.globals.lock().unwrap().clone().unwrap();
+
+        // Only the signatures of the type section can be block types.
+        assert_eq!(module_info.signatures.len(), 2);
+        assert_eq!(
+            globals.types.body_blocks,
+            vec![None, Some(WpTypeOrFuncType::FuncType(1))]
+        );
+        assert!(globals.branch_operand.is_some());
+    }
+
+    #[test]
+    fn functions_with_several_results() {
+        let instance = instance(40, multi_value_bytecode());
+        let pair = instance
+            .exports
+            .get_function("pair")
+            .unwrap()
+            .native::<i32, (i32, i32)>()
+            .unwrap();
+        let swap = instance
+            .exports
+            .get_function("swap")
+            .unwrap()
+            .native::<(i32, i32), (i32, i32)>()
+            .unwrap();
+
+        assert_eq!(pair.call(0).unwrap(), (1, 2));
+        assert_eq!(pair.call(1).unwrap(), (3, 14));
+        assert_eq!(pair.call(5).unwrap(), (3, 4));
+        assert_eq!(get_stack_height(&instance), 0);
+        assert_eq!(swap.call(0, 7).unwrap(), (7, 0));
+        assert_eq!(swap.call(1, 2).unwrap(), (2, 1));
+        assert_eq!(get_stack_height(&instance), 0);
+
+        // The body is wrapped when the type section has a signature with
+        // the results of the function.
+        let wrapped = instance(
+            40,
+            wat2wasm(
+                br#"
+                (module
+                (type (func (result i32 i32)))
+                (func (export "pair") (param $n i32) (result i32 i32)
+                    (i32.const 1) (i32.const 2)
+                    (br_if 0 (local.get $n))
+                    (drop) (drop)
+                    (i32.const 3) (i32.const 4)))
+                "#,
+            )
+            .unwrap()
+            .into(),
+        );
+        let pair = wrapped
+            .exports
+            .get_function("pair")
+            .unwrap()
+            .native::<i32, (i32, i32)>()
+            .unwrap();
+        assert_eq!(pair.call(1).unwrap(), (1, 2));
+        assert_eq!(pair.call(0).unwrap(), (3, 4));
+        assert_eq!(get_stack_height(&wrapped), 0);
+    }
+}
@@ -253,18 +253,59 @@ mod test_native_type {
 /// a valid `u32`. However a `bool` is _not_ a Value type because any bit patterns
 /// other than `0` and `1` are invalid in Rust and may cause undefined behavior if
 /// a `bool` is constructed from those bytes.
+///
+/// Implement `ValueType` for your structs with `#[derive(ValueType)]`,
+/// which checks these guarantees.
 pub unsafe trait ValueType: Copy
 where
     Self: Sized,
 {
+    /// Converts the value between the little-endian layout of the
+    /// WebAssembly memories and the layout of the host.
+    ///
+    /// Both conversions are the same, and do nothing on little-endian
+    /// hosts. The default implementation does nothing.
+    #[inline]
+    fn convert_le(self) -> Self {
+        self
+    }
 }
 
 macro_rules! impl_value_type_for {
     ( $($type:ty),* ) => {
         $(
-            unsafe impl ValueType for $type {}
+            unsafe impl ValueType for $type {
+                #[inline]
+                fn convert_le(self) -> Self {
+                    Self::from_le(self)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_value_type_for_float {
+    ( $($type:ty => $bits:ty),* ) => {
+        $(
+            unsafe impl ValueType for $type {
+                #[inline]
+                fn convert_le(self) -> Self {
+                    Self::from_bits(<$bits>::from_le(self.to_bits()))
+                }
+            }
         )*
     };
 }
 
-impl_value_type_for!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+impl_value_type_for!(u8, i8, u16, i16, u32, i32, u64, i64);
+impl_value_type_for_float!(f32 => u32, f64 => u64);
+
+unsafe impl<T: ValueType, const N: usize> ValueType for [T; N] {
+    #[inline]
+    fn convert_le(mut self) -> Self {
+        for element in self.iter_mut() {
+            *element = element.convert_le();
+        }
+        self
+    }
+}
@@ -17,8 +17,9 @@ use crate::export::VMExtern;
 use crate::func_data_registry::{FuncDataRegistry, VMFuncRef};
 use crate::global::Global;
 use crate::imports::Imports;
+use crate::limiter::InstanceReservation;
 use crate::memory::{Memory, MemoryError};
-use crate::table::{Table, TableElement};
+use crate::table::{Table, TableElement, TableError};
 use crate::trap::{catch_traps, Trap, TrapCode, TrapHandler};
 use crate::vmcontext::{
     VMBuiltinFunctionsArray, VMCallerCheckedAnyfunc, VMContext, VMFunctionBody,
@@ -98,6 +99,11 @@ pub(crate) struct Instance {
     /// Hosts can store arbitrary per-instance information here.
     host_state: Box<dyn Any>,
 
+    /// The reservation of this instance with the resource limiter of its
+    /// store, if any, released when it's dropped.
+    #[loupe(skip)]
+    reservation: Option<InstanceReservation>,
+
     /// Functions to operate on host environments in the imports
     /// and pointers to the environments.
     ///
@@ -539,14 +545,14 @@ impl Instance {
 
     /// Grow table by the specified amount of elements.
     ///
-    /// Returns `None` if table can't be grown by the specified amount
+    /// Returns an error if table can't be grown by the specified amount
     /// of elements.
     pub(crate) fn table_grow(
         &self,
         table_index: LocalTableIndex,
         delta: u32,
         init_value: TableElement,
-    ) -> Option<u32> {
+    ) -> Result<u32, TableError> {
         let result = self
             .tables
             .get(table_index)
@@ -565,7 +571,7 @@ impl Instance {
         table_index: TableIndex,
         delta: u32,
         init_value: TableElement,
-    ) -> Option<u32> {
+    ) -> Result<u32, TableError> {
         let import = self.imported_table(table_index);
         let from = import.from.as_ref();
         from.grow(delta.into(), init_value)
@@ -906,6 +912,7 @@ impl InstanceHandle {
         func_data_registry: &FuncDataRegistry,
         host_state: Box<dyn Any>,
         imported_function_envs: BoxedSlice<FunctionIndex, ImportFunctionEnv>,
+        reservation: Option<InstanceReservation>,
     ) -> Result<Self, Trap> {
         let vmctx_globals = finished_globals
             .values()
@@ -930,6 +937,7 @@ impl InstanceHandle {
                 passive_elements: Default::default(),
                 passive_data,
                 host_state,
+                reservation,
                 funcrefs,
                 imported_function_envs,
                 vmctx: VMContext {},
@@ -1189,14 +1197,14 @@ impl InstanceHandle {
 
     /// Grow table in this instance by the specified amount of pages.
     ///
-    /// Returns `None` if memory can't be grown by the specified amount
+    /// Returns an error if memory can't be grown by the specified amount
     /// of pages.
     pub fn table_grow(
         &self,
         table_index: LocalTableIndex,
         delta: u32,
         init_value: TableElement,
-    ) -> Option<u32> {
+    ) -> Result<u32, TableError> {
         self.instance()
             .as_ref()
             .table_grow(table_index, delta, init_value)
@@ -26,6 +26,7 @@ mod func_data_registry;
 mod global;
 mod imports;
 mod instance;
+mod limiter;
 mod memory;
 mod mmap;
 mod module;
@@ -45,12 +46,15 @@ pub use crate::imports::Imports;
 pub use crate::instance::{
     ImportFunctionEnv, ImportInitializerFuncPtr, InstanceAllocator, InstanceHandle,
 };
+pub use crate::limiter::{
+    InstanceReservation, ResourceBudget, ResourceLimitError, ResourceLimiter,
+};
 pub use crate::memory::{LinearMemory, Memory, MemoryError, MemoryStyle};
 pub use crate::mmap::Mmap;
 pub use crate::module::{ExportsIterator, ImportsIterator, ModuleInfo};
 pub use crate::probestack::PROBESTACK;
 pub use crate::sig_registry::SignatureRegistry;
-pub use crate::table::{LinearTable, Table, TableElement, TableStyle};
+pub use crate::table::{LinearTable, Table, TableElement, TableError, TableStyle};
 pub use crate::trap::*;
 pub use crate::vmcontext::{
     VMBuiltinFunctionIndex, VMCallerCheckedAnyfunc, VMContext, VMDynamicFunctionContext,
@@ -38,9 +38,10 @@
 #![allow(missing_docs)] // For some reason lint fails saying that `LibCall` is not documented, when it actually is
 
 use crate::func_data_registry::VMFuncRef;
+use crate::memory::MemoryError;
 use crate::probestack::PROBESTACK;
-use crate::table::{RawTableElement, TableElement};
-use crate::trap::{raise_lib_trap, Trap, TrapCode};
+use crate::table::{RawTableElement, TableElement, TableError};
+use crate::trap::{raise_lib_trap, raise_user_trap, Trap, TrapCode};
 use crate::vmcontext::VMContext;
 use crate::VMExternRef;
 use loupe::MemoryUsage;
@@ -157,10 +158,12 @@ pub unsafe extern "C" fn wasmer_vm_memory32_grow(
     let instance = (&*vmctx).instance();
     let memory_index = LocalMemoryIndex::from_u32(memory_index);
 
-    instance
-        .memory_grow(memory_index, delta)
-        .map(|pages| pages.0)
-        .unwrap_or(u32::max_value())
+    match instance.memory_grow(memory_index, delta) {
+        Ok(pages) => pages.0,
+        // The resource limiter denying the growth traps
+        Err(MemoryError::ResourceLimit(error)) => raise_user_trap(Box::new(error)),
+        Err(_) => u32::max_value(),
+    }
 }
 
 /// Implementation of memory.grow for imported 32-bit memories.
@@ -177,10 +180,12 @@ pub unsafe extern "C" fn wasmer_vm_imported_memory32_grow(
     let instance = (&*vmctx).instance();
     let memory_index = MemoryIndex::from_u32(memory_index);
 
-    instance
-        .imported_memory_grow(memory_index, delta)
-        .map(|pages| pages.0)
-        .unwrap_or(u32::max_value())
+    match instance.imported_memory_grow(memory_index, delta) {
+        Ok(pages) => pages.0,
+        // The resource limiter denying the growth traps
+        Err(MemoryError::ResourceLimit(error)) => raise_user_trap(Box::new(error)),
+        Err(_) => u32::max_value(),
+    }
 }
 
 /// Implementation of memory.size for locally-defined 32-bit memories.
@@ -448,9 +453,12 @@ pub unsafe extern "C" fn wasmer_vm_table_grow(
         _ => panic!("Unrecognized table type: does not contain references"),
     };
 
-    instance
-        .table_grow(table_index, delta, init_value)
-        .unwrap_or(u32::max_value())
+    match instance.table_grow(table_index, delta, init_value) {
+        Ok(size) => size,
+        // The resource limiter denying the growth traps
+        Err(TableError::ResourceLimit(error)) => raise_user_trap(Box::new(error)),
+        Err(_) => u32::max_value(),
+    }
 }
 
 /// Implementation of `table.grow` for imported tables.
@@ -473,9 +481,12 @@ pub unsafe extern "C" fn wasmer_vm_imported_table_grow(
         _ => panic!("Unrecognized table type: does not contain references"),
     };
 
-    instance
-        .imported_table_grow(table_index, delta, init_value)
-        .unwrap_or(u32::max_value())
+    match instance.imported_table_grow(table_index, delta, init_value) {
+        Ok(size) => size,
+        // The resource limiter denying the growth traps
+        Err(TableError::ResourceLimit(error)) => raise_user_trap(Box::new(error)),
+        Err(_) => u32::max_value(),
+    }
 }
 
 /// Implementation of `func.ref`.
@@ -0,0 +1,274 @@
+//! Limits on the resources used by the memories, tables and instances of a
+//! store.
+//!
+//! A [`ResourceLimiter`] is consulted whenever a memory or a table is
+//! created or grown and whenever an instance is created, and notified when
+//! they are released, so that it can keep track of the resources in use.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use thiserror::Error;
+use wasmer_types::{Pages, WASM_PAGE_SIZE};
+
+/// An error returned by a [`ResourceLimiter`] to deny the creation or the
+/// growth of a resource.
+///
+/// Growing a memory or a table from WebAssembly raises it as a trap.
+#[derive(Error, Debug, Clone, PartialEq, Eq, Hash)]
+#[error("Resource limit exceeded: {message}")]
+pub struct ResourceLimitError {
+    /// The error message
+    pub message: String,
+}
+
+impl ResourceLimitError {
+    /// Create a new `ResourceLimitError`
+    pub fn new<A: Into<String>>(message: A) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+/// A hook consulted when memories, tables and instances are created or
+/// grown, to limit the resources they use.
+///
+/// Creating a memory or a table is considered as growing it from zero. All
+/// the methods allow the request by default.
+pub trait ResourceLimiter: fmt::Debug + Send + Sync {
+    /// Called before a memory grows from `current` to `desired` pages.
+    ///
+    /// `maximum` is the maximum declared by the memory, if any.
+    fn memory_growing(
+        &self,
+        current: Pages,
+        desired: Pages,
+        maximum: Option<Pages>,
+    ) -> Result<(), ResourceLimitError> {
+        let _ = (current, desired, maximum);
+        Ok(())
+    }
+
+    /// Called when `pages` of a memory are released, because it's dropped
+    /// or because growing it failed after [`Self::memory_growing`]
+    /// allowed it.
+    fn memory_released(&self, pages: Pages) {
+        let _ = pages;
+    }
+
+    /// Called before a table grows from `current` to `desired` elements.
+    ///
+    /// `maximum` is the maximum declared by the table, if any.
+    fn table_growing(
+        &self,
+        current: u32,
+        desired: u32,
+        maximum: Option<u32>,
+    ) -> Result<(), ResourceLimitError> {
+        let _ = (current, desired, maximum);
+        Ok(())
+    }
+
+    /// Called when the `elements` of a table are released, because it's
+    /// dropped.
+    fn table_released(&self, elements: u32) {
+        let _ = elements;
+    }
+
+    /// Called before an instance is created.
+    fn instance_creating(&self) -> Result<(), ResourceLimitError> {
+        Ok(())
+    }
+
+    /// Called when an instance is dropped.
+    fn instance_released(&self) {}
+}
+
+/// An instance counted by a [`ResourceLimiter`], released when dropped.
+#[derive(Debug)]
+pub struct InstanceReservation {
+    limiter: Arc<dyn ResourceLimiter>,
+}
+
+impl InstanceReservation {
+    /// Reserves an instance, if `limiter` allows it.
+    pub fn new(limiter: Arc<dyn ResourceLimiter>) -> Result<Self, ResourceLimitError> {
+        limiter.instance_creating()?;
+        Ok(Self { limiter })
+    }
+}
+
+impl Drop for InstanceReservation {
+    fn drop(&mut self) {
+        self.limiter.instance_released();
+    }
+}
+
+/// A [`ResourceLimiter`] enforcing a budget on the total size of the
+/// memories and tables, and on the number of instances.
+///
+/// The budget is shared by everything created with it, such as all the
+/// instances of a store, and the resources are given back to it when
+/// they're dropped.
+#[derive(Debug, Default)]
+pub struct ResourceBudget {
+    max_memory_bytes: Option<u64>,
+    max_table_elements: Option<u64>,
+    max_instances: Option<u64>,
+    memory_bytes: AtomicU64,
+    table_elements: AtomicU64,
+    instances: AtomicU64,
+}
+
+impl ResourceBudget {
+    /// Creates a budget without limits, only keeping track of the
+    /// resources in use.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the total size in bytes of the memories.
+    pub fn with_max_memory_bytes(mut self, bytes: u64) -> Self {
+        self.max_memory_bytes = Some(bytes);
+        self
+    }
+
+    /// Limits the total number of elements of the tables.
+    pub fn with_max_table_elements(mut self, elements: u64) -> Self {
+        self.max_table_elements = Some(elements);
+        self
+    }
+
+    /// Limits the number of instances.
+    pub fn with_max_instances(mut self, instances: u64) -> Self {
+        self.max_instances = Some(instances);
+        self
+    }
+
+    /// Returns the total size in bytes of the memories.
+    pub fn memory_bytes(&self) -> u64 {
+        self.memory_bytes.load(Ordering::SeqCst)
+    }
+
+    /// Returns the total number of elements of the tables.
+    pub fn table_elements(&self) -> u64 {
+        self.table_elements.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of instances.
+    pub fn instances(&self) -> u64 {
+        self.instances.load(Ordering::SeqCst)
+    }
+}
+
+/// Adds `amount` to `used` if it stays within `maximum`.
+fn reserve(
+    used: &AtomicU64,
+    amount: u64,
+    maximum: Option<u64>,
+    resource: &str,
+) -> Result<(), ResourceLimitError> {
+    used.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+        let new = used.checked_add(amount)?;
+        match maximum {
+            Some(maximum) if new > maximum => None,
+            _ => Some(new),
+        }
+    })
+    .map(|_| ())
+    .map_err(|used| {
+        ResourceLimitError::new(format!(
+            "{} {} more would exceed the budget of {} ({} in use)",
+            amount,
+            resource,
+            maximum.unwrap_or(u64::MAX),
+            used
+        ))
+    })
+}
+
+impl ResourceLimiter for ResourceBudget {
+    fn memory_growing(
+        &self,
+        current: Pages,
+        desired: Pages,
+        _maximum: Option<Pages>,
+    ) -> Result<(), ResourceLimitError> {
+        let pages = u64::from(desired.0.saturating_sub(current.0));
+        reserve(
+            &self.memory_bytes,
+            pages * WASM_PAGE_SIZE as u64,
+            self.max_memory_bytes,
+            "memory bytes",
+        )
+    }
+
+    fn memory_released(&self, pages: Pages) {
+        self.memory_bytes
+            .fetch_sub(u64::from(pages.0) * WASM_PAGE_SIZE as u64, Ordering::SeqCst);
+    }
+
+    fn table_growing(
+        &self,
+        current: u32,
+        desired: u32,
+        _maximum: Option<u32>,
+    ) -> Result<(), ResourceLimitError> {
+        reserve(
+            &self.table_elements,
+            u64::from(desired.saturating_sub(current)),
+            self.max_table_elements,
+            "table elements",
+        )
+    }
+
+    fn table_released(&self, elements: u32) {
+        self.table_elements
+            .fetch_sub(u64::from(elements), Ordering::SeqCst);
+    }
+
+    fn instance_creating(&self) -> Result<(), ResourceLimitError> {
+        reserve(&self.instances, 1, self.max_instances, "instances")
+    }
+
+    fn instance_released(&self) {
+        self.instances.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_tracks_resources_in_use() {
+        let budget = ResourceBudget::new()
+            .with_max_memory_bytes(3 * WASM_PAGE_SIZE as u64)
+            .with_max_table_elements(10);
+
+        budget.memory_growing(Pages(0), Pages(2), None).unwrap();
+        assert_eq!(budget.memory_bytes(), 2 * WASM_PAGE_SIZE as u64);
+        assert!(budget.memory_growing(Pages(2), Pages(4), None).is_err());
+        assert_eq!(budget.memory_bytes(), 2 * WASM_PAGE_SIZE as u64);
+        budget.memory_released(Pages(2));
+        budget.memory_growing(Pages(0), Pages(3), None).unwrap();
+        assert_eq!(budget.memory_bytes(), 3 * WASM_PAGE_SIZE as u64);
+
+        budget.table_growing(0, 10, None).unwrap();
+        assert!(budget.table_growing(10, 11, None).is_err());
+        budget.table_released(10);
+        assert_eq!(budget.table_elements(), 0);
+    }
+
+    #[test]
+    fn reservations_release_instances() {
+        let budget = Arc::new(ResourceBudget::new().with_max_instances(1));
+        let reservation = InstanceReservation::new(budget.clone()).unwrap();
+        assert_eq!(budget.instances(), 1);
+        assert!(InstanceReservation::new(budget.clone()).is_err());
+        drop(reservation);
+        assert_eq!(budget.instances(), 0);
+        InstanceReservation::new(budget.clone()).unwrap();
+    }
+}
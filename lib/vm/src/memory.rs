@@ -5,6 +5,7 @@
 //!
 //! `LinearMemory` is to WebAssembly linear memories what `Table` is to WebAssembly tables.
 
+use crate::limiter::{ResourceLimitError, ResourceLimiter};
 use crate::mmap::Mmap;
 use crate::vmcontext::VMMemoryDefinition;
 use loupe::MemoryUsage;
@@ -17,7 +18,7 @@ use std::cell::UnsafeCell;
 use std::convert::TryInto;
 use std::fmt;
 use std::ptr::NonNull;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
 use wasmer_types::{Bytes, MemoryType, Pages};
 
@@ -58,6 +59,9 @@ pub enum MemoryError {
         /// The number of pages requested as the maximum amount of memory.
         max_allowed: Pages,
     },
+    /// The [`ResourceLimiter`] of the memory denied its creation or growth.
+    #[error("{0}")]
+    ResourceLimit(ResourceLimitError),
     /// A user defined error value, used for error cases not listed above.
     #[error("A user-defined error occurred: {0}")]
     Generic(String),
@@ -147,6 +151,10 @@ pub struct LinearMemory {
     // Records whether we're using a bounds-checking strategy which requires
     // handlers to catch trapping accesses.
     pub(crate) needs_signal_handlers: bool,
+
+    /// The limiter consulted when the memory grows, if any.
+    #[loupe(skip)]
+    limiter: Option<Arc<dyn ResourceLimiter>>,
 }
 
 /// A type to help manage who is responsible for the backing memory of them
@@ -182,13 +190,65 @@ struct WasmMmap {
     size: Pages,
 }
 
+impl WasmMmap {
+    /// Grow the allocation from `prev_pages` to `new_pages`, moving it if
+    /// it's too small.
+    fn grow(
+        &mut self,
+        prev_pages: Pages,
+        new_pages: Pages,
+        offset_guard_size: usize,
+    ) -> Result<(), MemoryError> {
+        let prev_bytes = prev_pages.bytes().0;
+        let new_bytes = new_pages.bytes().0;
+        let delta_bytes = new_bytes - prev_bytes;
+
+        if new_bytes > self.alloc.len() - offset_guard_size {
+            // If the new size is within the declared maximum, but needs more memory than we
+            // have on hand, it's a dynamic heap and it can move.
+            let guard_bytes = offset_guard_size;
+            let request_bytes =
+                new_bytes
+                    .checked_add(guard_bytes)
+                    .ok_or_else(|| MemoryError::CouldNotGrow {
+                        current: new_pages,
+                        attempted_delta: Bytes(guard_bytes).try_into().unwrap(),
+                    })?;
+
+            let mut new_mmap =
+                Mmap::accessible_reserved(new_bytes, request_bytes).map_err(MemoryError::Region)?;
+
+            let copy_len = self.alloc.len() - offset_guard_size;
+            new_mmap.as_mut_slice()[..copy_len].copy_from_slice(&self.alloc.as_slice()[..copy_len]);
+
+            self.alloc = new_mmap;
+        } else if delta_bytes > 0 {
+            // Make the newly allocated pages accessible.
+            self.alloc
+                .make_accessible(prev_bytes, delta_bytes)
+                .map_err(MemoryError::Region)?;
+        }
+        Ok(())
+    }
+}
+
 impl LinearMemory {
     /// Create a new linear memory instance with specified minimum and maximum number of wasm pages.
     ///
     /// This creates a `LinearMemory` with owned metadata: this can be used to create a memory
     /// that will be imported into Wasm modules.
     pub fn new(memory: &MemoryType, style: &MemoryStyle) -> Result<Self, MemoryError> {
-        unsafe { Self::new_internal(memory, style, None) }
+        unsafe { Self::new_internal(memory, style, None, None) }
+    }
+
+    /// Create a new linear memory instance like [`LinearMemory::new`],
+    /// consulting `limiter` when it's created and when it grows.
+    pub fn new_with_limiter(
+        memory: &MemoryType,
+        style: &MemoryStyle,
+        limiter: Arc<dyn ResourceLimiter>,
+    ) -> Result<Self, MemoryError> {
+        unsafe { Self::new_internal(memory, style, None, Some(limiter)) }
     }
 
     /// Create a new linear memory instance with specified minimum and maximum number of wasm pages.
@@ -203,7 +263,22 @@ impl LinearMemory {
         style: &MemoryStyle,
         vm_memory_location: NonNull<VMMemoryDefinition>,
     ) -> Result<Self, MemoryError> {
-        Self::new_internal(memory, style, Some(vm_memory_location))
+        Self::new_internal(memory, style, Some(vm_memory_location), None)
+    }
+
+    /// Create a new linear memory instance like
+    /// [`LinearMemory::from_definition`], consulting `limiter` when it's
+    /// created and when it grows.
+    ///
+    /// # Safety
+    /// - `vm_memory_location` must point to a valid location in VM memory.
+    pub unsafe fn from_definition_with_limiter(
+        memory: &MemoryType,
+        style: &MemoryStyle,
+        vm_memory_location: NonNull<VMMemoryDefinition>,
+        limiter: Arc<dyn ResourceLimiter>,
+    ) -> Result<Self, MemoryError> {
+        Self::new_internal(memory, style, Some(vm_memory_location), Some(limiter))
     }
 
     /// Build a `LinearMemory` with either self-owned or VM owned metadata.
@@ -211,6 +286,7 @@ impl LinearMemory {
         memory: &MemoryType,
         style: &MemoryStyle,
         vm_memory_location: Option<NonNull<VMMemoryDefinition>>,
+        limiter: Option<Arc<dyn ResourceLimiter>>,
     ) -> Result<Self, MemoryError> {
         if memory.minimum > Pages::max_value() {
             return Err(MemoryError::MinimumMemoryTooLarge {
@@ -259,9 +335,19 @@ impl LinearMemory {
         let mapped_pages = memory.minimum;
         let mapped_bytes = mapped_pages.bytes();
 
+        if let Some(limiter) = &limiter {
+            limiter
+                .memory_growing(Pages(0), memory.minimum, memory.maximum)
+                .map_err(MemoryError::ResourceLimit)?;
+        }
+        let alloc = Mmap::accessible_reserved(mapped_bytes.0, request_bytes).map_err(|e| {
+            if let Some(limiter) = &limiter {
+                limiter.memory_released(memory.minimum);
+            }
+            MemoryError::Region(e)
+        })?;
         let mut mmap = WasmMmap {
-            alloc: Mmap::accessible_reserved(mapped_bytes.0, request_bytes)
-                .map_err(MemoryError::Region)?,
+            alloc,
             size: memory.minimum,
         };
 
@@ -290,6 +376,7 @@ impl LinearMemory {
             },
             memory: *memory,
             style: style.clone(),
+            limiter,
         })
     }
 
@@ -308,6 +395,14 @@ impl LinearMemory {
     }
 }
 
+impl Drop for LinearMemory {
+    fn drop(&mut self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.memory_released(self.mmap.get_mut().unwrap_or_else(|e| e.into_inner()).size);
+        }
+    }
+}
+
 impl Memory for LinearMemory {
     /// Returns the type for this memory.
     fn ty(&self) -> MemoryType {
@@ -374,34 +469,16 @@ impl Memory for LinearMemory {
             });
         }
 
-        let delta_bytes = delta.bytes().0;
-        let prev_bytes = prev_pages.bytes().0;
-        let new_bytes = new_pages.bytes().0;
-
-        if new_bytes > mmap.alloc.len() - self.offset_guard_size {
-            // If the new size is within the declared maximum, but needs more memory than we
-            // have on hand, it's a dynamic heap and it can move.
-            let guard_bytes = self.offset_guard_size;
-            let request_bytes =
-                new_bytes
-                    .checked_add(guard_bytes)
-                    .ok_or_else(|| MemoryError::CouldNotGrow {
-                        current: new_pages,
-                        attempted_delta: Bytes(guard_bytes).try_into().unwrap(),
-                    })?;
-
-            let mut new_mmap =
-                Mmap::accessible_reserved(new_bytes, request_bytes).map_err(MemoryError::Region)?;
-
-            let copy_len = mmap.alloc.len() - self.offset_guard_size;
-            new_mmap.as_mut_slice()[..copy_len].copy_from_slice(&mmap.alloc.as_slice()[..copy_len]);
-
-            mmap.alloc = new_mmap;
-        } else if delta_bytes > 0 {
-            // Make the newly allocated pages accessible.
-            mmap.alloc
-                .make_accessible(prev_bytes, delta_bytes)
-                .map_err(MemoryError::Region)?;
+        if let Some(limiter) = &self.limiter {
+            limiter
+                .memory_growing(prev_pages, new_pages, self.maximum)
+                .map_err(MemoryError::ResourceLimit)?;
+        }
+        if let Err(e) = mmap.grow(prev_pages, new_pages, self.offset_guard_size) {
+            if let Some(limiter) = &self.limiter {
+                limiter.memory_released(delta);
+            }
+            return Err(e);
         }
 
         mmap.size = new_pages;
@@ -6,6 +6,7 @@
 //! `Table` is to WebAssembly tables what `LinearMemory` is to WebAssembly linear memories.
 
 use crate::func_data_registry::VMFuncRef;
+use crate::limiter::{ResourceLimitError, ResourceLimiter};
 use crate::trap::{Trap, TrapCode};
 use crate::vmcontext::VMTableDefinition;
 use crate::VMExternRef;
@@ -18,7 +19,8 @@ use std::cell::UnsafeCell;
 use std::convert::TryFrom;
 use std::fmt;
 use std::ptr::NonNull;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
 use wasmer_types::{ExternRef, TableType, Type as ValType};
 
 /// Implementation styles for WebAssembly tables.
@@ -32,6 +34,26 @@ pub enum TableStyle {
     CallerChecksSignature,
 }
 
+/// Error type describing things that can go wrong when creating or growing a Wasm table.
+#[derive(Error, Debug, Clone, PartialEq, Hash)]
+pub enum TableError {
+    /// The operation would cause the size of the table to exceed the maximum or would cause
+    /// an overflow.
+    #[error("The table could not grow: current size {current} elements, requested increase: {attempted_delta} elements")]
+    CouldNotGrow {
+        /// The current size in elements.
+        current: u32,
+        /// The attempted amount to grow by in elements.
+        attempted_delta: u32,
+    },
+    /// The [`ResourceLimiter`] of the table denied its creation or its growth.
+    #[error("{0}")]
+    ResourceLimit(ResourceLimitError),
+    /// The type of the table isn't supported or its limits are invalid.
+    #[error("{0}")]
+    InvalidType(String),
+}
+
 /// Trait for implementing the interface of a Wasm table.
 pub trait Table: fmt::Debug + Send + Sync + MemoryUsage {
     /// Returns the style for this Table.
@@ -45,9 +67,9 @@ pub trait Table: fmt::Debug + Send + Sync + MemoryUsage {
 
     /// Grow table by the specified amount of elements.
     ///
-    /// Returns `None` if table can't be grown by the specified amount
+    /// Returns an error if table can't be grown by the specified amount
     /// of elements, otherwise returns the previous size of the table.
-    fn grow(&self, delta: u32, init_value: TableElement) -> Option<u32>;
+    fn grow(&self, delta: u32, init_value: TableElement) -> Result<u32, TableError>;
 
     /// Get reference to the specified element.
     ///
@@ -185,6 +207,9 @@ pub struct LinearTable {
     /// Our chosen implementation style.
     style: TableStyle,
     vm_table_definition: VMTableDefinitionOwnership,
+    /// The limiter consulted when the table grows, if any.
+    #[loupe(skip)]
+    limiter: Option<Arc<dyn ResourceLimiter>>,
 }
 
 /// A type to help manage who is responsible for the backing table of the
@@ -211,7 +236,17 @@ impl LinearTable {
     /// This creates a `LinearTable` with metadata owned by a VM, pointed to by
     /// `vm_table_location`: this can be used to create a local table.
     pub fn new(table: &TableType, style: &TableStyle) -> Result<Self, String> {
-        unsafe { Self::new_inner(table, style, None) }
+        unsafe { Self::new_inner(table, style, None, None) }.map_err(|e| e.to_string())
+    }
+
+    /// Create a new linear table instance like [`LinearTable::new`],
+    /// consulting `limiter` when it's created and when it grows.
+    pub fn new_with_limiter(
+        table: &TableType,
+        style: &TableStyle,
+        limiter: Arc<dyn ResourceLimiter>,
+    ) -> Result<Self, TableError> {
+        unsafe { Self::new_inner(table, style, None, Some(limiter)) }
     }
 
     /// Create a new linear table instance with specified minimum and maximum number of elements.
@@ -226,7 +261,22 @@ impl LinearTable {
         style: &TableStyle,
         vm_table_location: NonNull<VMTableDefinition>,
     ) -> Result<Self, String> {
-        Self::new_inner(table, style, Some(vm_table_location))
+        Self::new_inner(table, style, Some(vm_table_location), None).map_err(|e| e.to_string())
+    }
+
+    /// Create a new linear table instance like
+    /// [`LinearTable::from_definition`], consulting `limiter` when it's
+    /// created and when it grows.
+    ///
+    /// # Safety
+    /// - `vm_table_location` must point to a valid location in VM memory.
+    pub unsafe fn from_definition_with_limiter(
+        table: &TableType,
+        style: &TableStyle,
+        vm_table_location: NonNull<VMTableDefinition>,
+        limiter: Arc<dyn ResourceLimiter>,
+    ) -> Result<Self, TableError> {
+        Self::new_inner(table, style, Some(vm_table_location), Some(limiter))
     }
 
     /// Create a new `LinearTable` with either self-owned or VM owned metadata.
@@ -234,26 +284,33 @@ impl LinearTable {
         table: &TableType,
         style: &TableStyle,
         vm_table_location: Option<NonNull<VMTableDefinition>>,
-    ) -> Result<Self, String> {
+        limiter: Option<Arc<dyn ResourceLimiter>>,
+    ) -> Result<Self, TableError> {
         match table.ty {
             ValType::FuncRef | ValType::ExternRef => (),
             ty => {
-                return Err(format!(
+                return Err(TableError::InvalidType(format!(
                     "tables of types other than funcref or externref ({})",
                     ty
-                ))
+                )))
             }
         };
         if let Some(max) = table.maximum {
             if max < table.minimum {
-                return Err(format!(
+                return Err(TableError::InvalidType(format!(
                     "Table minimum ({}) is larger than maximum ({})!",
                     table.minimum, max
-                ));
+                )));
             }
         }
-        let table_minimum = usize::try_from(table.minimum)
-            .map_err(|_| "Table minimum is bigger than usize".to_string())?;
+        let table_minimum = usize::try_from(table.minimum).map_err(|_| {
+            TableError::InvalidType("Table minimum is bigger than usize".to_string())
+        })?;
+        if let Some(limiter) = &limiter {
+            limiter
+                .table_growing(0, table.minimum, table.maximum)
+                .map_err(TableError::ResourceLimit)?;
+        }
         let mut vec = vec![RawTableElement::default(); table_minimum];
         let base = vec.as_mut_ptr();
         match style {
@@ -278,6 +335,7 @@ impl LinearTable {
                         },
                     )))
                 },
+                limiter,
             }),
         }
     }
@@ -297,6 +355,15 @@ impl LinearTable {
     }
 }
 
+impl Drop for LinearTable {
+    fn drop(&mut self) {
+        if let Some(limiter) = &self.limiter {
+            let len = self.vec.get_mut().unwrap_or_else(|e| e.into_inner()).len();
+            limiter.table_released(len as u32);
+        }
+    }
+}
+
 impl Table for LinearTable {
     /// Returns the type for this Table.
     fn ty(&self) -> &TableType {
@@ -320,19 +387,30 @@ impl Table for LinearTable {
 
     /// Grow table by the specified amount of elements.
     ///
-    /// Returns `None` if table can't be grown by the specified amount
+    /// Returns an error if table can't be grown by the specified amount
     /// of elements, otherwise returns the previous size of the table.
-    fn grow(&self, delta: u32, init_value: TableElement) -> Option<u32> {
+    fn grow(&self, delta: u32, init_value: TableElement) -> Result<u32, TableError> {
         let mut vec_guard = self.vec.lock().unwrap();
         let vec = vec_guard.borrow_mut();
         let size = self.size();
-        let new_len = size.checked_add(delta)?;
+        let could_not_grow = TableError::CouldNotGrow {
+            current: size,
+            attempted_delta: delta,
+        };
+        let new_len = size
+            .checked_add(delta)
+            .ok_or_else(|| could_not_grow.clone())?;
         if self.maximum.map_or(false, |max| new_len > max) {
-            return None;
+            return Err(could_not_grow);
         }
         if new_len == size {
             debug_assert_eq!(delta, 0);
-            return Some(size);
+            return Ok(size);
+        }
+        if let Some(limiter) = &self.limiter {
+            limiter
+                .table_growing(size, new_len, self.maximum)
+                .map_err(TableError::ResourceLimit)?;
         }
 
         // Update the ref count
@@ -358,7 +436,7 @@ impl Table for LinearTable {
             td.current_elements = new_len;
             td.base = vec.as_mut_ptr() as _;
         }
-        Some(size)
+        Ok(size)
     }
 
     /// Get reference to the specified element.
@@ -430,6 +430,15 @@ pub unsafe fn resume_panic(payload: Box<dyn Any + Send>) -> ! {
     tls::with(|info| info.unwrap().unwind_with(UnwindReason::Panic(payload)))
 }
 
+/// Calls `closure` with the [`TrapHandler`] of the innermost call into wasm
+/// code on the current thread, or with `None` if there's no wasm code on the
+/// stack.
+///
+/// This lets host functions find out on behalf of whom they are called.
+pub fn with_current_trap_handler<R>(closure: impl FnOnce(Option<&dyn TrapHandler>) -> R) -> R {
+    tls::with(|info| closure(info.map(|info| info.trap_handler)))
+}
+
 #[cfg(target_os = "windows")]
 fn reset_guard_page() {
     extern "C" {
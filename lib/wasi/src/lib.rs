@@ -15,17 +15,24 @@
 #[macro_use]
 mod macros;
 mod ptr;
+mod snapshot;
 mod state;
 mod syscalls;
+mod trace;
 mod utils;
 
 use crate::syscalls::*;
+use crate::trace::Tracer;
 
+pub use crate::snapshot::{WasiSnapshot, WasiSnapshotError};
 pub use crate::state::{
-    Fd, Pipe, Stderr, Stdin, Stdout, WasiFile, WasiFs, WasiFsError, WasiState, WasiStateBuilder,
-    WasiStateCreationError, ALL_RIGHTS, VIRTUAL_ROOT_FD,
+    DirEntry, Fd, FileSystem, FileType, HostFileSystem, MemFile, MemFileSystem, Metadata,
+    OpenOptions, OverlayFileSystem, Pipe, SeededRng, Stderr, Stdin, Stdout, VirtualClock, WasiFile,
+    WasiFs, WasiFsError, WasiQuotas, WasiState, WasiStateBuilder, WasiStateCreationError,
+    WasiUsage, ALL_RIGHTS, VIRTUAL_ROOT_FD,
 };
 pub use crate::syscalls::types;
+pub use crate::trace::{SyscallEvent, TraceValue};
 pub use crate::utils::{get_wasi_version, get_wasi_versions, is_wasi_module, WasiVersion};
 
 use thiserror::Error;
@@ -34,6 +41,7 @@ use wasmer::{
     NamedResolver, Store, WasmerEnv,
 };
 
+use std::io::Write;
 use std::sync::{Arc, Mutex, MutexGuard};
 
 /// This is returned in `RuntimeError`.
@@ -58,6 +66,7 @@ pub struct WasiEnv {
     pub state: Arc<Mutex<WasiState>>,
     #[wasmer(export)]
     memory: LazyInit<Memory>,
+    pub(crate) tracer: Option<Tracer>,
 }
 
 impl WasiEnv {
@@ -65,9 +74,37 @@ impl WasiEnv {
         Self {
             state: Arc::new(Mutex::new(state)),
             memory: LazyInit::new(),
+            tracer: None,
         }
     }
 
+    /// Report every syscall made by the program to `tracer`, along with its
+    /// decoded arguments, errno, duration and the number of bytes it
+    /// transferred.
+    ///
+    /// Only the imports created after this call are traced, so it must be
+    /// called before `import_object`.
+    pub fn set_tracer(
+        &mut self,
+        tracer: impl Fn(&SyscallEvent) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.tracer = Some(Tracer(Arc::new(tracer)));
+
+        self
+    }
+
+    /// Write every syscall made by the program to `writer`, as JSON lines
+    /// formatted by [`SyscallEvent::to_json`].
+    ///
+    /// Like [`WasiEnv::set_tracer`], it must be called before `import_object`.
+    pub fn trace_to_writer(&mut self, writer: impl Write + Send + 'static) -> &mut Self {
+        let writer = Mutex::new(writer);
+        self.set_tracer(move |event| {
+            let mut writer = writer.lock().unwrap();
+            let _ = writeln!(writer, "{}", event.to_json());
+        })
+    }
+
     /// Get an `ImportObject` for a specific version of WASI detected in the module.
     pub fn import_object(&mut self, module: &Module) -> Result<ImportObject, WasiError> {
         let wasi_version = get_wasi_version(module, false).ok_or(WasiError::UnknownWasiVersion)?;
@@ -148,51 +185,51 @@ pub fn generate_import_object_from_env(
 fn generate_import_object_snapshot0(store: &Store, env: WasiEnv) -> ImportObject {
     imports! {
         "wasi_unstable" => {
-            "args_get" => Function::new_native_with_env(store, env.clone(), args_get),
-            "args_sizes_get" => Function::new_native_with_env(store, env.clone(), args_sizes_get),
-            "clock_res_get" => Function::new_native_with_env(store, env.clone(), clock_res_get),
-            "clock_time_get" => Function::new_native_with_env(store, env.clone(), clock_time_get),
-            "environ_get" => Function::new_native_with_env(store, env.clone(), environ_get),
-            "environ_sizes_get" => Function::new_native_with_env(store, env.clone(), environ_sizes_get),
-            "fd_advise" => Function::new_native_with_env(store, env.clone(), fd_advise),
-            "fd_allocate" => Function::new_native_with_env(store, env.clone(), fd_allocate),
-            "fd_close" => Function::new_native_with_env(store, env.clone(), fd_close),
-            "fd_datasync" => Function::new_native_with_env(store, env.clone(), fd_datasync),
-            "fd_fdstat_get" => Function::new_native_with_env(store, env.clone(), fd_fdstat_get),
-            "fd_fdstat_set_flags" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_flags),
-            "fd_fdstat_set_rights" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_rights),
-            "fd_filestat_get" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::fd_filestat_get),
-            "fd_filestat_set_size" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_size),
-            "fd_filestat_set_times" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_times),
-            "fd_pread" => Function::new_native_with_env(store, env.clone(), fd_pread),
-            "fd_prestat_get" => Function::new_native_with_env(store, env.clone(), fd_prestat_get),
-            "fd_prestat_dir_name" => Function::new_native_with_env(store, env.clone(), fd_prestat_dir_name),
-            "fd_pwrite" => Function::new_native_with_env(store, env.clone(), fd_pwrite),
-            "fd_read" => Function::new_native_with_env(store, env.clone(), fd_read),
-            "fd_readdir" => Function::new_native_with_env(store, env.clone(), fd_readdir),
-            "fd_renumber" => Function::new_native_with_env(store, env.clone(), fd_renumber),
-            "fd_seek" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::fd_seek),
-            "fd_sync" => Function::new_native_with_env(store, env.clone(), fd_sync),
-            "fd_tell" => Function::new_native_with_env(store, env.clone(), fd_tell),
-            "fd_write" => Function::new_native_with_env(store, env.clone(), fd_write),
-            "path_create_directory" => Function::new_native_with_env(store, env.clone(), path_create_directory),
-            "path_filestat_get" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::path_filestat_get),
-            "path_filestat_set_times" => Function::new_native_with_env(store, env.clone(), path_filestat_set_times),
-            "path_link" => Function::new_native_with_env(store, env.clone(), path_link),
-            "path_open" => Function::new_native_with_env(store, env.clone(), path_open),
-            "path_readlink" => Function::new_native_with_env(store, env.clone(), path_readlink),
-            "path_remove_directory" => Function::new_native_with_env(store, env.clone(), path_remove_directory),
-            "path_rename" => Function::new_native_with_env(store, env.clone(), path_rename),
-            "path_symlink" => Function::new_native_with_env(store, env.clone(), path_symlink),
-            "path_unlink_file" => Function::new_native_with_env(store, env.clone(), path_unlink_file),
-            "poll_oneoff" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::poll_oneoff),
-            "proc_exit" => Function::new_native_with_env(store, env.clone(), proc_exit),
-            "proc_raise" => Function::new_native_with_env(store, env.clone(), proc_raise),
-            "random_get" => Function::new_native_with_env(store, env.clone(), random_get),
-            "sched_yield" => Function::new_native_with_env(store, env.clone(), sched_yield),
-            "sock_recv" => Function::new_native_with_env(store, env.clone(), sock_recv),
-            "sock_send" => Function::new_native_with_env(store, env.clone(), sock_send),
-            "sock_shutdown" => Function::new_native_with_env(store, env.clone(), sock_shutdown),
+            "args_get" => Function::new_native_with_env(store, env.clone(), traced!(args_get(argv, argv_buf))),
+            "args_sizes_get" => Function::new_native_with_env(store, env.clone(), traced!(args_sizes_get(argc, argv_buf_size))),
+            "clock_res_get" => Function::new_native_with_env(store, env.clone(), traced!(clock_res_get(clock_id, resolution))),
+            "clock_time_get" => Function::new_native_with_env(store, env.clone(), traced!(clock_time_get(clock_id, precision, time))),
+            "environ_get" => Function::new_native_with_env(store, env.clone(), traced!(environ_get(environ, environ_buf))),
+            "environ_sizes_get" => Function::new_native_with_env(store, env.clone(), traced!(environ_sizes_get(environ_count, environ_buf_size))),
+            "fd_advise" => Function::new_native_with_env(store, env.clone(), traced!(fd_advise(fd, offset, len, advice))),
+            "fd_allocate" => Function::new_native_with_env(store, env.clone(), traced!(fd_allocate(fd, offset, len))),
+            "fd_close" => Function::new_native_with_env(store, env.clone(), traced!(fd_close(fd))),
+            "fd_datasync" => Function::new_native_with_env(store, env.clone(), traced!(fd_datasync(fd))),
+            "fd_fdstat_get" => Function::new_native_with_env(store, env.clone(), traced!(fd_fdstat_get(fd, buf_ptr))),
+            "fd_fdstat_set_flags" => Function::new_native_with_env(store, env.clone(), traced!(fd_fdstat_set_flags(fd, flags))),
+            "fd_fdstat_set_rights" => Function::new_native_with_env(store, env.clone(), traced!(fd_fdstat_set_rights(fd, fs_rights_base, fs_rights_inheriting))),
+            "fd_filestat_get" => Function::new_native_with_env(store, env.clone(), traced!(legacy::snapshot0::fd_filestat_get(fd, buf))),
+            "fd_filestat_set_size" => Function::new_native_with_env(store, env.clone(), traced!(fd_filestat_set_size(fd, st_size))),
+            "fd_filestat_set_times" => Function::new_native_with_env(store, env.clone(), traced!(fd_filestat_set_times(fd, st_atim, st_mtim, fst_flags))),
+            "fd_pread" => Function::new_native_with_env(store, env.clone(), traced!(fd_pread(fd, iovs, iovs_len, offset, nread))),
+            "fd_prestat_get" => Function::new_native_with_env(store, env.clone(), traced!(fd_prestat_get(fd, buf))),
+            "fd_prestat_dir_name" => Function::new_native_with_env(store, env.clone(), traced!(fd_prestat_dir_name(fd, path, path_len))),
+            "fd_pwrite" => Function::new_native_with_env(store, env.clone(), traced!(fd_pwrite(fd, iovs, iovs_len, offset, nwritten))),
+            "fd_read" => Function::new_native_with_env(store, env.clone(), traced!(fd_read(fd, iovs, iovs_len, nread))),
+            "fd_readdir" => Function::new_native_with_env(store, env.clone(), traced!(fd_readdir(fd, buf, buf_len, cookie, bufused))),
+            "fd_renumber" => Function::new_native_with_env(store, env.clone(), traced!(fd_renumber(from, to))),
+            "fd_seek" => Function::new_native_with_env(store, env.clone(), traced!(legacy::snapshot0::fd_seek(fd, offset, whence, newoffset))),
+            "fd_sync" => Function::new_native_with_env(store, env.clone(), traced!(fd_sync(fd))),
+            "fd_tell" => Function::new_native_with_env(store, env.clone(), traced!(fd_tell(fd, offset))),
+            "fd_write" => Function::new_native_with_env(store, env.clone(), traced!(fd_write(fd, iovs, iovs_len, nwritten))),
+            "path_create_directory" => Function::new_native_with_env(store, env.clone(), traced!(path_create_directory(fd, path, path_len))),
+            "path_filestat_get" => Function::new_native_with_env(store, env.clone(), traced!(legacy::snapshot0::path_filestat_get(fd, flags, path, path_len, buf))),
+            "path_filestat_set_times" => Function::new_native_with_env(store, env.clone(), traced!(path_filestat_set_times(fd, flags, path, path_len, st_atim, st_mtim, fst_flags))),
+            "path_link" => Function::new_native_with_env(store, env.clone(), traced!(path_link(old_fd, old_flags, old_path, old_path_len, new_fd, new_path, new_path_len))),
+            "path_open" => Function::new_native_with_env(store, env.clone(), traced!(path_open(dirfd, dirflags, path, path_len, o_flags, fs_rights_base, fs_rights_inheriting, fs_flags, fd))),
+            "path_readlink" => Function::new_native_with_env(store, env.clone(), traced!(path_readlink(dir_fd, path, path_len, buf, buf_len, buf_used))),
+            "path_remove_directory" => Function::new_native_with_env(store, env.clone(), traced!(path_remove_directory(fd, path, path_len))),
+            "path_rename" => Function::new_native_with_env(store, env.clone(), traced!(path_rename(old_fd, old_path, old_path_len, new_fd, new_path, new_path_len))),
+            "path_symlink" => Function::new_native_with_env(store, env.clone(), traced!(path_symlink(old_path, old_path_len, fd, new_path, new_path_len))),
+            "path_unlink_file" => Function::new_native_with_env(store, env.clone(), traced!(path_unlink_file(fd, path, path_len))),
+            "poll_oneoff" => Function::new_native_with_env(store, env.clone(), traced!(legacy::snapshot0::poll_oneoff(in_, out_, nsubscriptions, nevents))),
+            "proc_exit" => Function::new_native_with_env(store, env.clone(), traced!(proc_exit(code))),
+            "proc_raise" => Function::new_native_with_env(store, env.clone(), traced!(proc_raise(sig))),
+            "random_get" => Function::new_native_with_env(store, env.clone(), traced!(random_get(buf, buf_len))),
+            "sched_yield" => Function::new_native_with_env(store, env.clone(), traced!(sched_yield())),
+            "sock_recv" => Function::new_native_with_env(store, env.clone(), traced!(sock_recv(sock, ri_data, ri_data_len, ri_flags, ro_datalen, ro_flags))),
+            "sock_send" => Function::new_native_with_env(store, env.clone(), traced!(sock_send(sock, si_data, si_data_len, si_flags, so_datalen))),
+            "sock_shutdown" => Function::new_native_with_env(store, env.clone(), traced!(sock_shutdown(sock, how))),
         },
     }
 }
@@ -201,51 +238,51 @@ fn generate_import_object_snapshot0(store: &Store, env: WasiEnv) -> ImportObject
 fn generate_import_object_snapshot1(store: &Store, env: WasiEnv) -> ImportObject {
     imports! {
         "wasi_snapshot_preview1" => {
-            "args_get" => Function::new_native_with_env(store, env.clone(), args_get),
-            "args_sizes_get" => Function::new_native_with_env(store, env.clone(), args_sizes_get),
-            "clock_res_get" => Function::new_native_with_env(store, env.clone(), clock_res_get),
-            "clock_time_get" => Function::new_native_with_env(store, env.clone(), clock_time_get),
-            "environ_get" => Function::new_native_with_env(store, env.clone(), environ_get),
-            "environ_sizes_get" => Function::new_native_with_env(store, env.clone(), environ_sizes_get),
-            "fd_advise" => Function::new_native_with_env(store, env.clone(), fd_advise),
-            "fd_allocate" => Function::new_native_with_env(store, env.clone(), fd_allocate),
-            "fd_close" => Function::new_native_with_env(store, env.clone(), fd_close),
-            "fd_datasync" => Function::new_native_with_env(store, env.clone(), fd_datasync),
-            "fd_fdstat_get" => Function::new_native_with_env(store, env.clone(), fd_fdstat_get),
-            "fd_fdstat_set_flags" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_flags),
-            "fd_fdstat_set_rights" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_rights),
-            "fd_filestat_get" => Function::new_native_with_env(store, env.clone(), fd_filestat_get),
-            "fd_filestat_set_size" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_size),
-            "fd_filestat_set_times" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_times),
-            "fd_pread" => Function::new_native_with_env(store, env.clone(), fd_pread),
-            "fd_prestat_get" => Function::new_native_with_env(store, env.clone(), fd_prestat_get),
-            "fd_prestat_dir_name" => Function::new_native_with_env(store, env.clone(), fd_prestat_dir_name),
-            "fd_pwrite" => Function::new_native_with_env(store, env.clone(), fd_pwrite),
-            "fd_read" => Function::new_native_with_env(store, env.clone(), fd_read),
-            "fd_readdir" => Function::new_native_with_env(store, env.clone(), fd_readdir),
-            "fd_renumber" => Function::new_native_with_env(store, env.clone(), fd_renumber),
-            "fd_seek" => Function::new_native_with_env(store, env.clone(), fd_seek),
-            "fd_sync" => Function::new_native_with_env(store, env.clone(), fd_sync),
-            "fd_tell" => Function::new_native_with_env(store, env.clone(), fd_tell),
-            "fd_write" => Function::new_native_with_env(store, env.clone(), fd_write),
-            "path_create_directory" => Function::new_native_with_env(store, env.clone(), path_create_directory),
-            "path_filestat_get" => Function::new_native_with_env(store, env.clone(), path_filestat_get),
-            "path_filestat_set_times" => Function::new_native_with_env(store, env.clone(), path_filestat_set_times),
-            "path_link" => Function::new_native_with_env(store, env.clone(), path_link),
-            "path_open" => Function::new_native_with_env(store, env.clone(), path_open),
-            "path_readlink" => Function::new_native_with_env(store, env.clone(), path_readlink),
-            "path_remove_directory" => Function::new_native_with_env(store, env.clone(), path_remove_directory),
-            "path_rename" => Function::new_native_with_env(store, env.clone(), path_rename),
-            "path_symlink" => Function::new_native_with_env(store, env.clone(), path_symlink),
-            "path_unlink_file" => Function::new_native_with_env(store, env.clone(), path_unlink_file),
-            "poll_oneoff" => Function::new_native_with_env(store, env.clone(), poll_oneoff),
-            "proc_exit" => Function::new_native_with_env(store, env.clone(), proc_exit),
-            "proc_raise" => Function::new_native_with_env(store, env.clone(), proc_raise),
-            "random_get" => Function::new_native_with_env(store, env.clone(), random_get),
-            "sched_yield" => Function::new_native_with_env(store, env.clone(), sched_yield),
-            "sock_recv" => Function::new_native_with_env(store, env.clone(), sock_recv),
-            "sock_send" => Function::new_native_with_env(store, env.clone(), sock_send),
-            "sock_shutdown" => Function::new_native_with_env(store, env.clone(), sock_shutdown),
+            "args_get" => Function::new_native_with_env(store, env.clone(), traced!(args_get(argv, argv_buf))),
+            "args_sizes_get" => Function::new_native_with_env(store, env.clone(), traced!(args_sizes_get(argc, argv_buf_size))),
+            "clock_res_get" => Function::new_native_with_env(store, env.clone(), traced!(clock_res_get(clock_id, resolution))),
+            "clock_time_get" => Function::new_native_with_env(store, env.clone(), traced!(clock_time_get(clock_id, precision, time))),
+            "environ_get" => Function::new_native_with_env(store, env.clone(), traced!(environ_get(environ, environ_buf))),
+            "environ_sizes_get" => Function::new_native_with_env(store, env.clone(), traced!(environ_sizes_get(environ_count, environ_buf_size))),
+            "fd_advise" => Function::new_native_with_env(store, env.clone(), traced!(fd_advise(fd, offset, len, advice))),
+            "fd_allocate" => Function::new_native_with_env(store, env.clone(), traced!(fd_allocate(fd, offset, len))),
+            "fd_close" => Function::new_native_with_env(store, env.clone(), traced!(fd_close(fd))),
+            "fd_datasync" => Function::new_native_with_env(store, env.clone(), traced!(fd_datasync(fd))),
+            "fd_fdstat_get" => Function::new_native_with_env(store, env.clone(), traced!(fd_fdstat_get(fd, buf_ptr))),
+            "fd_fdstat_set_flags" => Function::new_native_with_env(store, env.clone(), traced!(fd_fdstat_set_flags(fd, flags))),
+            "fd_fdstat_set_rights" => Function::new_native_with_env(store, env.clone(), traced!(fd_fdstat_set_rights(fd, fs_rights_base, fs_rights_inheriting))),
+            "fd_filestat_get" => Function::new_native_with_env(store, env.clone(), traced!(fd_filestat_get(fd, buf))),
+            "fd_filestat_set_size" => Function::new_native_with_env(store, env.clone(), traced!(fd_filestat_set_size(fd, st_size))),
+            "fd_filestat_set_times" => Function::new_native_with_env(store, env.clone(), traced!(fd_filestat_set_times(fd, st_atim, st_mtim, fst_flags))),
+            "fd_pread" => Function::new_native_with_env(store, env.clone(), traced!(fd_pread(fd, iovs, iovs_len, offset, nread))),
+            "fd_prestat_get" => Function::new_native_with_env(store, env.clone(), traced!(fd_prestat_get(fd, buf))),
+            "fd_prestat_dir_name" => Function::new_native_with_env(store, env.clone(), traced!(fd_prestat_dir_name(fd, path, path_len))),
+            "fd_pwrite" => Function::new_native_with_env(store, env.clone(), traced!(fd_pwrite(fd, iovs, iovs_len, offset, nwritten))),
+            "fd_read" => Function::new_native_with_env(store, env.clone(), traced!(fd_read(fd, iovs, iovs_len, nread))),
+            "fd_readdir" => Function::new_native_with_env(store, env.clone(), traced!(fd_readdir(fd, buf, buf_len, cookie, bufused))),
+            "fd_renumber" => Function::new_native_with_env(store, env.clone(), traced!(fd_renumber(from, to))),
+            "fd_seek" => Function::new_native_with_env(store, env.clone(), traced!(fd_seek(fd, offset, whence, newoffset))),
+            "fd_sync" => Function::new_native_with_env(store, env.clone(), traced!(fd_sync(fd))),
+            "fd_tell" => Function::new_native_with_env(store, env.clone(), traced!(fd_tell(fd, offset))),
+            "fd_write" => Function::new_native_with_env(store, env.clone(), traced!(fd_write(fd, iovs, iovs_len, nwritten))),
+            "path_create_directory" => Function::new_native_with_env(store, env.clone(), traced!(path_create_directory(fd, path, path_len))),
+            "path_filestat_get" => Function::new_native_with_env(store, env.clone(), traced!(path_filestat_get(fd, flags, path, path_len, buf))),
+            "path_filestat_set_times" => Function::new_native_with_env(store, env.clone(), traced!(path_filestat_set_times(fd, flags, path, path_len, st_atim, st_mtim, fst_flags))),
+            "path_link" => Function::new_native_with_env(store, env.clone(), traced!(path_link(old_fd, old_flags, old_path, old_path_len, new_fd, new_path, new_path_len))),
+            "path_open" => Function::new_native_with_env(store, env.clone(), traced!(path_open(dirfd, dirflags, path, path_len, o_flags, fs_rights_base, fs_rights_inheriting, fs_flags, fd))),
+            "path_readlink" => Function::new_native_with_env(store, env.clone(), traced!(path_readlink(dir_fd, path, path_len, buf, buf_len, buf_used))),
+            "path_remove_directory" => Function::new_native_with_env(store, env.clone(), traced!(path_remove_directory(fd, path, path_len))),
+            "path_rename" => Function::new_native_with_env(store, env.clone(), traced!(path_rename(old_fd, old_path, old_path_len, new_fd, new_path, new_path_len))),
+            "path_symlink" => Function::new_native_with_env(store, env.clone(), traced!(path_symlink(old_path, old_path_len, fd, new_path, new_path_len))),
+            "path_unlink_file" => Function::new_native_with_env(store, env.clone(), traced!(path_unlink_file(fd, path, path_len))),
+            "poll_oneoff" => Function::new_native_with_env(store, env.clone(), traced!(poll_oneoff(in_, out_, nsubscriptions, nevents))),
+            "proc_exit" => Function::new_native_with_env(store, env.clone(), traced!(proc_exit(code))),
+            "proc_raise" => Function::new_native_with_env(store, env.clone(), traced!(proc_raise(sig))),
+            "random_get" => Function::new_native_with_env(store, env.clone(), traced!(random_get(buf, buf_len))),
+            "sched_yield" => Function::new_native_with_env(store, env.clone(), traced!(sched_yield())),
+            "sock_recv" => Function::new_native_with_env(store, env.clone(), traced!(sock_recv(sock, ri_data, ri_data_len, ri_flags, ro_datalen, ro_flags))),
+            "sock_send" => Function::new_native_with_env(store, env.clone(), traced!(sock_send(sock, si_data, si_data_len, si_flags, so_datalen))),
+            "sock_shutdown" => Function::new_native_with_env(store, env.clone(), traced!(sock_shutdown(sock, how))),
         }
     }
 }
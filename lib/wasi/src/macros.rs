@@ -34,3 +34,52 @@ macro_rules! get_input_str {
         wasi_try!($data.get_utf8_str($memory, $len), __WASI_EINVAL)
     }};
 }
+
+/// Wraps the syscall `$name` into a host function reporting every call to
+/// the tracer of the [`WasiEnv`](crate::WasiEnv), if any.
+///
+/// The syscall is called before the arguments are used, to infer their
+/// types from its signature.
+macro_rules! traced {
+    (@wrap $func:path, $name:ident ( $($arg:ident),* )) => {
+        |env: &crate::WasiEnv, $($arg),*| {
+            let tracer = match &env.tracer {
+                None => return $func(env, $($arg),*),
+                Some(tracer) => tracer,
+            };
+            let start = std::time::Instant::now();
+            let errno = $func(env, $($arg),*);
+            crate::trace::report(
+                env,
+                tracer,
+                stringify!($name),
+                &[$((stringify!($arg), crate::trace::TraceArg::raw($arg))),*],
+                Some(errno),
+                start.elapsed(),
+            );
+            errno
+        }
+    };
+    (proc_exit ( $code:ident )) => {
+        |env: &crate::WasiEnv, $code| {
+            // `proc_exit` never returns, so it is reported beforehand
+            if let Some(tracer) = &env.tracer {
+                crate::trace::report(
+                    env,
+                    tracer,
+                    "proc_exit",
+                    &[(stringify!($code), crate::trace::TraceArg::raw($code))],
+                    None,
+                    std::time::Duration::default(),
+                );
+            }
+            proc_exit(env, $code)
+        }
+    };
+    (legacy::snapshot0::$name:ident ( $($arg:ident),* )) => {
+        traced!(@wrap legacy::snapshot0::$name, $name ( $($arg),* ))
+    };
+    ($name:ident ( $($arg:ident),* )) => {
+        traced!(@wrap $name, $name ( $($arg),* ))
+    };
+}
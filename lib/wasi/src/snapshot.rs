@@ -0,0 +1,202 @@
+use crate::{WasiEnv, WasiError, WasiState};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use wasmer::{Instance, InstanceSnapshot, Module, SnapshotError};
+
+/// A snapshot of a WASI program: the state of its [`Instance`] together with
+/// the state of the [`WasiEnv`] it was instantiated with.
+///
+/// Take one with [`WasiEnv::snapshot`] and restore it, possibly on another
+/// host, with [`WasiSnapshot::restore`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WasiSnapshot {
+    /// The memories, globals and tables of the instance.
+    pub instance: InstanceSnapshot,
+    /// The frozen [`WasiState`], see [`WasiState::freeze`].
+    pub state: Vec<u8>,
+}
+
+/// An error while taking or restoring a [`WasiSnapshot`].
+#[derive(Error, Debug)]
+pub enum WasiSnapshotError {
+    #[error(transparent)]
+    Instance(#[from] SnapshotError),
+    #[error("the WASI state could not be frozen")]
+    Freeze,
+    #[error("the WASI state could not be unfrozen")]
+    Unfreeze,
+    #[error(transparent)]
+    Wasi(#[from] WasiError),
+}
+
+impl WasiEnv {
+    /// Take a snapshot of `instance`, which must have been instantiated with
+    /// the imports of this `WasiEnv`, and of the WASI state.
+    ///
+    /// This should be called while the WASI program isn't running, for
+    /// example between two calls into the instance.
+    pub fn snapshot(&self, instance: &Instance) -> Result<WasiSnapshot, WasiSnapshotError> {
+        let instance = instance.snapshot()?;
+        let state = self.state().freeze().ok_or(WasiSnapshotError::Freeze)?;
+        Ok(WasiSnapshot { instance, state })
+    }
+}
+
+impl WasiSnapshot {
+    /// Instantiate `module`, which must be the module the snapshot was taken
+    /// from, with a new [`WasiEnv`] holding the WASI state of the snapshot,
+    /// and restore the instance's state in it.
+    pub fn restore(&self, module: &Module) -> Result<(Instance, WasiEnv), WasiSnapshotError> {
+        let state = WasiState::unfreeze(&self.state).ok_or(WasiSnapshotError::Unfreeze)?;
+        let mut wasi_env = WasiEnv::new(state);
+        let import_object = wasi_env.import_object(module)?;
+        let instance = Instance::restore(module, &import_object, &self.instance)?;
+        Ok((instance, wasi_env))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{FileSystem, MemFileSystem, OpenOptions, WasiFs};
+    use std::io::Write;
+    use std::path::Path;
+    use wasmer::{Cranelift, Store, Value, JIT};
+
+    #[test]
+    fn round_trip() {
+        let store = Store::new(&JIT::new(Cranelift::default()).engine());
+        let module = Module::new(
+            &store,
+            r#"
+            (module
+              (import "wasi_snapshot_preview1" "path_open"
+                (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+              (import "wasi_snapshot_preview1" "fd_seek"
+                (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+              (import "wasi_snapshot_preview1" "fd_read"
+                (func $fd_read (param i32 i32 i32 i32) (result i32)))
+              (import "wasi_snapshot_preview1" "fd_write"
+                (func $fd_write (param i32 i32 i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 0) "file")
+              (data (i32.const 8) "!")
+              ;; an iovec pointing to a 16 bytes buffer at 32
+              (data (i32.const 16) "\20\00\00\00\10\00\00\00")
+              ;; an iovec pointing to the `!` at 8
+              (data (i32.const 24) "\08\00\00\00\01\00\00\00")
+              ;; opens `file` in the directory `dir`, seeks 2 bytes in it and
+              ;; returns its fd
+              (func (export "open") (param $dir i32) (result i32)
+                (drop (call $path_open (local.get $dir) (i32.const 0) (i32.const 0) (i32.const 4)
+                  (i32.const 0) (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 48)))
+                (drop (call $fd_seek (i32.load (i32.const 48)) (i64.const 2) (i32.const 0) (i32.const 56)))
+                (i32.load (i32.const 48)))
+              ;; reads the rest of the file into the buffer and returns the
+              ;; number of bytes read
+              (func (export "read") (result i32)
+                (drop (call $fd_read (i32.load (i32.const 48)) (i32.const 16) (i32.const 1) (i32.const 64)))
+                (i32.load (i32.const 64)))
+              ;; writes `!` to the file and returns the number of bytes written
+              (func (export "write") (result i32)
+                (drop (call $fd_write (i32.load (i32.const 48)) (i32.const 24) (i32.const 1) (i32.const 72)))
+                (i32.load (i32.const 72))))
+            "#,
+        )
+        .unwrap();
+
+        let fs = MemFileSystem::new();
+        fs.create_dir(Path::new("/data")).unwrap();
+        fs.open(
+            Path::new("/data/file"),
+            OpenOptions::new().write(true).create(true),
+        )
+        .unwrap()
+        .write_all(b"hello")
+        .unwrap();
+        let mut wasi_env = WasiState::new("snapshot")
+            .set_fs(Box::new(fs))
+            .preopen_dir("/data")
+            .unwrap()
+            .finalize()
+            .unwrap();
+        let dir = *wasi_env.state().fs.preopen_fds.last().unwrap();
+        let import_object = wasi_env.import_object(&module).unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let fd = instance
+            .exports
+            .get_function("open")
+            .unwrap()
+            .call(&[Value::I32(dir as i32)])
+            .unwrap()[0]
+            .unwrap_i32() as u32;
+
+        let snapshot = wasi_env.snapshot(&instance).unwrap();
+        let (restored, restored_env) = snapshot.restore(&module).unwrap();
+
+        let fd_table = |fs: &WasiFs| {
+            let mut fds: Vec<_> = fs
+                .fd_map
+                .iter()
+                .map(|(fd, entry)| (*fd, entry.inode, entry.offset, entry.rights, entry.flags))
+                .collect();
+            fds.sort_by_key(|(fd, ..)| *fd);
+            fds
+        };
+        assert_eq!(
+            fd_table(&restored_env.state().fs),
+            fd_table(&wasi_env.state().fs)
+        );
+        assert_eq!(restored_env.state().fs.get_fd(fd).unwrap().offset, 2);
+
+        // the restored fd reads from its offset and moves on its own
+        let nread = restored
+            .exports
+            .get_function("read")
+            .unwrap()
+            .call(&[])
+            .unwrap()[0]
+            .unwrap_i32();
+        assert_eq!(nread, 3);
+        let memory = restored.exports.get_memory("memory").unwrap();
+        let buffer: Vec<u8> = memory.view::<u8>()[32..35]
+            .iter()
+            .map(|cell| cell.get())
+            .collect();
+        assert_eq!(buffer, b"llo");
+        assert_eq!(restored_env.state().fs.get_fd(fd).unwrap().offset, 5);
+        assert_eq!(wasi_env.state().fs.get_fd(fd).unwrap().offset, 2);
+
+        // what is written through the restored fd is in the restored
+        // filesystem when the file is opened again
+        let nwritten = restored
+            .exports
+            .get_function("write")
+            .unwrap()
+            .call(&[])
+            .unwrap()[0]
+            .unwrap_i32();
+        assert_eq!(nwritten, 1);
+        let reopened = restored
+            .exports
+            .get_function("open")
+            .unwrap()
+            .call(&[Value::I32(dir as i32)])
+            .unwrap()[0]
+            .unwrap_i32() as u32;
+        assert_ne!(reopened, fd);
+        let nread = restored
+            .exports
+            .get_function("read")
+            .unwrap()
+            .call(&[])
+            .unwrap()[0]
+            .unwrap_i32();
+        assert_eq!(nread, 4);
+        let buffer: Vec<u8> = memory.view::<u8>()[32..36]
+            .iter()
+            .map(|cell| cell.get())
+            .collect();
+        assert_eq!(buffer, b"llo!");
+    }
+}
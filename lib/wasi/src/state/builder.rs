@@ -1,6 +1,9 @@
 //! Builder system for configuring a [`WasiState`] and creating it.
 
-use crate::state::{WasiFile, WasiFs, WasiFsError, WasiState};
+use crate::state::{
+    FileSystem, HostFileSystem, OverlayFileSystem, SeededRng, VirtualClock, WasiFile, WasiFs,
+    WasiFsError, WasiQuotas, WasiState,
+};
 use crate::syscalls::types::{__WASI_STDERR_FILENO, __WASI_STDIN_FILENO, __WASI_STDOUT_FILENO};
 use crate::WasiEnv;
 use std::path::{Path, PathBuf};
@@ -42,6 +45,11 @@ pub struct WasiStateBuilder {
     stdout_override: Option<Box<dyn WasiFile>>,
     stderr_override: Option<Box<dyn WasiFile>>,
     stdin_override: Option<Box<dyn WasiFile>>,
+    fs_override: Option<Box<dyn FileSystem>>,
+    max_symlinks: Option<u32>,
+    quotas: WasiQuotas,
+    clock: Option<VirtualClock>,
+    rng: Option<SeededRng>,
 }
 
 impl std::fmt::Debug for WasiStateBuilder {
@@ -54,6 +62,11 @@ impl std::fmt::Debug for WasiStateBuilder {
             .field("stdout_override exists", &self.stdout_override.is_some())
             .field("stderr_override exists", &self.stderr_override.is_some())
             .field("stdin_override exists", &self.stdin_override.is_some())
+            .field("fs_override", &self.fs_override)
+            .field("max_symlinks", &self.max_symlinks)
+            .field("quotas", &self.quotas)
+            .field("clock", &self.clock)
+            .field("rng exists", &self.rng.is_some())
             .finish()
     }
 }
@@ -282,6 +295,99 @@ impl WasiStateBuilder {
         self
     }
 
+    /// Use `fs` instead of the host's filesystem to back the preopened
+    /// directories.
+    ///
+    /// The paths of the preopened directories are then looked up in `fs`.
+    /// For example, to make sure the WASI program never touches the host's
+    /// disk:
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// # use wasmer_wasi::{FileSystem, MemFileSystem, WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// let fs = MemFileSystem::new();
+    /// fs.create_dir(Path::new("/data")).unwrap();
+    ///
+    /// WasiState::new("program_name")
+    ///    .set_fs(Box::new(fs))
+    ///    .preopen_dir("/data")?
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_fs(&mut self, fs: Box<dyn FileSystem>) -> &mut Self {
+        self.fs_override = Some(fs);
+
+        self
+    }
+
+    /// Set the maximum number of symlinks that are followed when resolving
+    /// a path, after which resolution fails with `__WASI_ELOOP`.  Defaults to
+    /// [`MAX_SYMLINKS`](super::MAX_SYMLINKS).
+    pub fn max_symlinks(&mut self, max_symlinks: u32) -> &mut Self {
+        self.max_symlinks = Some(max_symlinks);
+
+        self
+    }
+
+    /// Limit what the program can consume through its syscalls, see
+    /// [`WasiQuotas`].
+    ///
+    /// ```
+    /// # use wasmer_wasi::{WasiQuotas, WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// WasiState::new("program_name")
+    ///    .quotas(WasiQuotas {
+    ///        max_open_fds: Some(64),
+    ///        max_stdout_bytes: Some(1024 * 1024),
+    ///        ..WasiQuotas::default()
+    ///    })
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn quotas(&mut self, quotas: WasiQuotas) -> &mut Self {
+        self.quotas = quotas;
+
+        self
+    }
+
+    /// Replace the host's clocks with a [`VirtualClock`] starting at `start`
+    /// nanoseconds since the Unix epoch and advancing by `step` nanoseconds
+    /// every time it is read.
+    pub fn virtual_clock(&mut self, start: u64, step: u64) -> &mut Self {
+        self.clock = Some(VirtualClock::new(start, step));
+
+        self
+    }
+
+    /// Replace the host's randomness with a [`SeededRng`] seeded with `seed`.
+    pub fn random_seed(&mut self, seed: [u8; 32]) -> &mut Self {
+        self.rng = Some(SeededRng::new(seed));
+
+        self
+    }
+
+    /// Make the program deterministic: the clocks are virtual, starting at
+    /// the Unix epoch and only advanced by the host or by sleeping, and the
+    /// randomness is seeded with `seed`.
+    ///
+    /// Together with deterministic inputs (arguments, environment, files
+    /// and stdin), every run of the program produces the same outputs.
+    ///
+    /// ```
+    /// # use wasmer_wasi::{WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// let state = WasiState::new("program_name").deterministic([42; 32]).build()?;
+    /// assert_eq!(state.clock.unwrap().now(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn deterministic(&mut self, seed: [u8; 32]) -> &mut Self {
+        self.virtual_clock(0, 0).random_seed(seed)
+    }
+
     /// Setup the WASI filesystem before running
     // TODO: improve ergonomics on this function
     pub fn setup_fs(
@@ -359,12 +465,40 @@ impl WasiStateBuilder {
             }
         }
 
-        // self.preopens are checked in [`PreopenDirBuilder::build`]
+        // self.preopens are checked in [`PreopenDirBuilder::build`], except for
+        // their existence which depends on the filesystem
+        let mut fs_backing = self
+            .fs_override
+            .take()
+            .unwrap_or_else(|| Box::new(HostFileSystem));
+        for preopen in self.preopens.iter_mut() {
+            if fs_backing.metadata(&preopen.path).is_err() {
+                return Err(WasiStateCreationError::PreopenedDirectoryNotFound(
+                    preopen.path.clone(),
+                ));
+            }
+            if let Some(upper) = preopen.overlay.take() {
+                fs_backing = Box::new(OverlayFileSystem::new(
+                    fs_backing,
+                    upper,
+                    preopen.path.clone(),
+                ));
+            }
+        }
+
+        // each state gets its own clock, shared with its filesystem
+        let clock = self.clock.as_ref().map(VirtualClock::fork);
+        if let Some(clock) = &clock {
+            fs_backing.set_clock(clock.clone());
+        }
 
         // this deprecation warning only applies to external callers
         #[allow(deprecated)]
-        let mut wasi_fs = WasiFs::new_with_preopen(&self.preopens)
+        let mut wasi_fs = WasiFs::new_with_preopen(&self.preopens, fs_backing)
             .map_err(WasiStateCreationError::WasiFsCreationError)?;
+        if let Some(max_symlinks) = self.max_symlinks {
+            wasi_fs.max_symlinks = max_symlinks;
+        }
         // set up the file system, overriding base files and calling the setup function
         if let Some(stdin_override) = self.stdin_override.take() {
             wasi_fs
@@ -384,6 +518,8 @@ impl WasiStateBuilder {
         if let Some(f) = &self.setup_fs_fn {
             f(&mut wasi_fs).map_err(WasiStateCreationError::WasiFsSetupError)?;
         }
+        // the quotas only apply to the program, not to the setup above
+        wasi_fs.quotas = self.quotas.clone();
         Ok(WasiState {
             fs: wasi_fs,
             args: self.args.clone(),
@@ -399,6 +535,8 @@ impl WasiStateBuilder {
                     env
                 })
                 .collect(),
+            clock,
+            rng: self.rng.clone(),
         })
     }
 
@@ -419,6 +557,7 @@ pub struct PreopenDirBuilder {
     read: bool,
     write: bool,
     create: bool,
+    overlay: Option<Box<dyn FileSystem>>,
 }
 
 /// The built version of `PreopenDirBuilder`
@@ -429,6 +568,7 @@ pub(crate) struct PreopenedDir {
     pub(crate) read: bool,
     pub(crate) write: bool,
     pub(crate) create: bool,
+    pub(crate) overlay: Option<Box<dyn FileSystem>>,
 }
 
 impl PreopenDirBuilder {
@@ -484,7 +624,37 @@ impl PreopenDirBuilder {
         self
     }
 
-    pub(crate) fn build(&self) -> Result<PreopenedDir, WasiStateCreationError> {
+    /// Layer the writable filesystem `upper` on top of this directory.
+    ///
+    /// The directory itself is only ever read from: files are copied up into
+    /// `upper` when they're written to and deletions only hide them, see
+    /// [`OverlayFileSystem`].  `upper` is addressed with the same paths as the
+    /// directory.
+    ///
+    /// Usage:
+    ///
+    /// ```no_run
+    /// # use wasmer_wasi::{MemFileSystem, WasiState, WasiStateCreationError};
+    /// # fn main() -> Result<(), WasiStateCreationError> {
+    /// WasiState::new("program_name")
+    ///    .preopen(|p| {
+    ///        p.directory("/usr/lib/python3.8")
+    ///            .alias("/lib")
+    ///            .read(true)
+    ///            .write(true)
+    ///            .overlay(Box::new(MemFileSystem::new()))
+    ///    })?
+    ///    .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn overlay(&mut self, upper: Box<dyn FileSystem>) -> &mut Self {
+        self.overlay = Some(upper);
+
+        self
+    }
+
+    pub(crate) fn build(&mut self) -> Result<PreopenedDir, WasiStateCreationError> {
         // ensure at least one is set
         if !(self.read || self.write || self.create) {
             return Err(WasiStateCreationError::PreopenedDirectoryError("Preopened directories must have at least one of read, write, create permissions set".to_string()));
@@ -497,9 +667,6 @@ impl PreopenDirBuilder {
         }
         let path = self.path.clone().unwrap();
 
-        if !path.exists() {
-            return Err(WasiStateCreationError::PreopenedDirectoryNotFound(path));
-        }
         if let Some(alias) = &self.alias {
             validate_mapped_dir_alias(alias)?;
         }
@@ -510,6 +677,7 @@ impl PreopenDirBuilder {
             read: self.read,
             write: self.write,
             create: self.create,
+            overlay: self.overlay.take(),
         })
     }
 }
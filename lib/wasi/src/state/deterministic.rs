@@ -0,0 +1,213 @@
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// A clock under the control of the host, replacing the host's clocks when
+/// set with [`WasiStateBuilder::virtual_clock`](super::WasiStateBuilder::virtual_clock).
+///
+/// The realtime clock reads the virtual time since the Unix epoch, the
+/// monotonic and CPU time clocks read the virtual time elapsed since the
+/// start.  Every read advances the time by a fixed step, which may be zero
+/// to only advance it with [`VirtualClock::advance`].  Sleeping in
+/// `poll_oneoff` advances the time instead of blocking.
+///
+/// Clones of a `VirtualClock` share the same time, which is how the
+/// [`FileSystem`](super::FileSystem) gets the times of its entries from it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtualClock {
+    start: __wasi_timestamp_t,
+    now: Arc<Mutex<__wasi_timestamp_t>>,
+    step: __wasi_timestamp_t,
+}
+
+impl VirtualClock {
+    /// Create a clock starting at `start` nanoseconds since the Unix epoch,
+    /// advancing by `step` nanoseconds on every read.
+    pub fn new(start: __wasi_timestamp_t, step: __wasi_timestamp_t) -> Self {
+        Self {
+            start,
+            now: Arc::new(Mutex::new(start)),
+            step,
+        }
+    }
+
+    /// Create a clock at the same time as this one, which doesn't share its
+    /// time with it.
+    pub(crate) fn fork(&self) -> Self {
+        Self {
+            start: self.start,
+            now: Arc::new(Mutex::new(self.now())),
+            step: self.step,
+        }
+    }
+
+    /// The current time, in nanoseconds since the Unix epoch.
+    pub fn now(&self) -> __wasi_timestamp_t {
+        *self.now.lock().unwrap()
+    }
+
+    /// The time elapsed since the start, in nanoseconds.
+    pub fn elapsed(&self) -> __wasi_timestamp_t {
+        self.now() - self.start
+    }
+
+    /// Move the time forward by `nanos` nanoseconds.
+    pub fn advance(&mut self, nanos: __wasi_timestamp_t) {
+        let mut now = self.now.lock().unwrap();
+        *now = now.saturating_add(nanos);
+    }
+
+    /// The time of the clock `clock_id`, without advancing it.
+    pub(crate) fn time(
+        &self,
+        clock_id: __wasi_clockid_t,
+    ) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        match clock_id {
+            __WASI_CLOCK_REALTIME => Ok(self.now()),
+            __WASI_CLOCK_MONOTONIC
+            | __WASI_CLOCK_PROCESS_CPUTIME_ID
+            | __WASI_CLOCK_THREAD_CPUTIME_ID => Ok(self.elapsed()),
+            _ => Err(__WASI_EINVAL),
+        }
+    }
+
+    /// Read the clock `clock_id`, then advance the time by the step.
+    pub(crate) fn read(
+        &mut self,
+        clock_id: __wasi_clockid_t,
+    ) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        let time = self.time(clock_id)?;
+        self.advance(self.step);
+        Ok(time)
+    }
+}
+
+impl PartialEq for VirtualClock {
+    fn eq(&self, other: &Self) -> bool {
+        self.start == other.start && self.now() == other.now() && self.step == other.step
+    }
+}
+
+impl Eq for VirtualClock {}
+
+/// A ChaCha20 random number generator replacing the host's randomness in
+/// `random_get` when set with
+/// [`WasiStateBuilder::random_seed`](super::WasiStateBuilder::random_seed).
+///
+/// The same seed always produces the same bytes, on every platform.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeededRng {
+    key: [u32; 8],
+    /// The number of bytes generated so far.
+    position: u64,
+}
+
+impl SeededRng {
+    /// Create a generator from a 256-bit seed, used as the ChaCha20 key.
+    pub fn new(seed: [u8; 32]) -> Self {
+        let mut key = [0; 8];
+        for (word, bytes) in key.iter_mut().zip(seed.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        Self { key, position: 0 }
+    }
+
+    /// Fill `buf` with the next random bytes.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let block = chacha20_block(&self.key, self.position / 64);
+            let start = (self.position % 64) as usize;
+            let len = (64 - start).min(buf.len() - filled);
+            buf[filled..filled + len].copy_from_slice(&block[start..start + len]);
+            filled += len;
+            self.position += len as u64;
+        }
+    }
+}
+
+/// The ChaCha20 block function with a 64-bit block counter and a zero nonce.
+fn chacha20_block(key: &[u32; 8], counter: u64) -> [u8; 64] {
+    fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        state[a] = state[a].wrapping_add(state[b]);
+        state[d] = (state[d] ^ state[a]).rotate_left(16);
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_left(12);
+        state[a] = state[a].wrapping_add(state[b]);
+        state[d] = (state[d] ^ state[a]).rotate_left(8);
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_left(7);
+    }
+
+    let mut initial = [0; 16];
+    // "expand 32-byte k"
+    initial[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    initial[4..12].copy_from_slice(key);
+    initial[12] = counter as u32;
+    initial[13] = (counter >> 32) as u32;
+
+    let mut state = initial;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut block = [0; 64];
+    for (i, bytes) in block.chunks_exact_mut(4).enumerate() {
+        bytes.copy_from_slice(&state[i].wrapping_add(initial[i]).to_le_bytes());
+    }
+    block
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn virtual_clock() {
+        let mut clock = VirtualClock::new(1_000, 10);
+        assert_eq!(clock.read(__WASI_CLOCK_REALTIME), Ok(1_000));
+        assert_eq!(clock.read(__WASI_CLOCK_MONOTONIC), Ok(10));
+        clock.advance(500);
+        assert_eq!(clock.read(__WASI_CLOCK_REALTIME), Ok(1_520));
+        assert_eq!(clock.elapsed(), 530);
+        assert_eq!(clock.read(42), Err(__WASI_EINVAL));
+
+        // clones share the time, forks don't
+        let mut fork = clock.fork();
+        let clone = clock.clone();
+        clock.advance(10);
+        fork.advance(20);
+        assert_eq!(clone.now(), 1_540);
+        assert_eq!(fork.now(), 1_550);
+    }
+
+    #[test]
+    fn chacha20_test_vectors() {
+        // RFC 8439, appendix A.1, test vectors #1 and #2
+        let mut rng = SeededRng::new([0; 32]);
+        let mut bytes = [0; 80];
+        rng.fill(&mut bytes[..10]);
+        rng.fill(&mut bytes[10..]);
+        assert_eq!(
+            bytes[..16],
+            [
+                0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86,
+                0xbd, 0x28
+            ]
+        );
+        assert_eq!(
+            bytes[64..],
+            [
+                0x9f, 0x07, 0xe7, 0xbe, 0x55, 0x51, 0x38, 0x7a, 0x98, 0xba, 0x97, 0x7c, 0x73, 0x2d,
+                0x08, 0x0d
+            ]
+        );
+    }
+}
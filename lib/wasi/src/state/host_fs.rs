@@ -0,0 +1,128 @@
+//! A [`FileSystem`] backed by the host's filesystem.
+
+use crate::state::{
+    DirEntry, FileSystem, FileType, HostFile, Metadata, OpenOptions, WasiFile, WasiFsError,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The default [`FileSystem`]: every operation goes straight to `std::fs`
+/// and opened files are [`HostFile`]s.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct HostFileSystem;
+
+impl HostFileSystem {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[typetag::serde]
+impl FileSystem for HostFileSystem {
+    fn metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        path.metadata()
+            .map(|md| host_metadata_to_metadata(&md))
+            .map_err(Into::into)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        path.symlink_metadata()
+            .map(|md| host_metadata_to_metadata(&md))
+            .map_err(Into::into)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf, WasiFsError> {
+        path.read_link().map_err(Into::into)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, WasiFsError> {
+        fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                Ok(DirEntry {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    file_type: entry.file_type()?.into(),
+                })
+            })
+            .collect()
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        fs::create_dir(path).map_err(Into::into)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        fs::remove_dir(path).map_err(Into::into)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), WasiFsError> {
+        fs::remove_file(path).map_err(Into::into)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), WasiFsError> {
+        fs::rename(from, to).map_err(Into::into)
+    }
+
+    fn open(&self, path: &Path, options: &OpenOptions) -> Result<Box<dyn WasiFile>, WasiFsError> {
+        let file = fs::OpenOptions::new()
+            .read(options.get_read())
+            .write(options.get_write())
+            .append(options.get_append())
+            .truncate(options.get_truncate())
+            .create(options.get_create())
+            .create_new(options.get_create_new())
+            .open(path)?;
+        Ok(Box::new(HostFile::new(
+            file,
+            path.to_path_buf(),
+            options.get_read(),
+            options.get_write() || options.get_append(),
+            options.get_append(),
+        )))
+    }
+}
+
+impl From<fs::FileType> for FileType {
+    fn from(file_type: fs::FileType) -> Self {
+        if file_type.is_dir() {
+            return FileType::Dir;
+        } else if file_type.is_file() {
+            return FileType::File;
+        } else if file_type.is_symlink() {
+            return FileType::Symlink;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if file_type.is_char_device() {
+                return FileType::CharDevice;
+            } else if file_type.is_block_device() {
+                return FileType::BlockDevice;
+            } else if file_type.is_fifo() {
+                return FileType::Fifo;
+            } else if file_type.is_socket() {
+                return FileType::Socket;
+            }
+        }
+        FileType::Unknown
+    }
+}
+
+fn host_metadata_to_metadata(md: &fs::Metadata) -> Metadata {
+    fn to_nanos(time: std::io::Result<SystemTime>) -> u64 {
+        time.ok()
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|t| t.as_nanos() as u64)
+            .unwrap_or(0)
+    }
+
+    Metadata {
+        file_type: md.file_type().into(),
+        len: md.len(),
+        accessed: to_nanos(md.accessed()),
+        modified: to_nanos(md.modified()),
+        created: to_nanos(md.created()),
+    }
+}
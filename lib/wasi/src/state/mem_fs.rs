@@ -0,0 +1,946 @@
+//! A [`FileSystem`] that lives entirely in memory.
+
+use crate::state::{
+    DirEntry, FileSystem, FileType, Metadata, OpenOptions, VirtualClock, WasiFile, WasiFsError,
+    MAX_SYMLINKS,
+};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/// An in-memory [`FileSystem`] supporting directories, files and symlinks.
+///
+/// Nothing done through a `MemFileSystem` ever reaches the host's disk.
+/// Cloning a `MemFileSystem` gives another handle to the same files, which
+/// is useful to populate the filesystem before passing it to
+/// [`WasiStateBuilder::set_fs`](super::WasiStateBuilder::set_fs) and to
+/// inspect it once the WASI program is done.
+///
+/// All paths are resolved from the root of the `MemFileSystem`: `/foo` and
+/// `foo` designate the same entry, and `..` never goes above the root.
+///
+/// When a [`WasiState`](super::WasiState) is frozen and unfrozen, the files
+/// that were open at the time keep sharing their contents with the unfrozen
+/// filesystem.
+///
+/// The times of the entries come from the host's clock, or from the
+/// [`VirtualClock`] of the [`WasiState`](super::WasiState) if it has one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemFileSystem {
+    root: Arc<Mutex<Node>>,
+    #[serde(default = "default_max_file_size")]
+    max_file_size: u64,
+    #[serde(default)]
+    clock: Option<VirtualClock>,
+}
+
+/// The default maximum size of the files of a [`MemFileSystem`]: the range
+/// of the 32-bit sizes.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = u32::MAX as u64;
+
+fn default_max_file_size() -> u64 {
+    DEFAULT_MAX_FILE_SIZE
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Times {
+    accessed: __wasi_timestamp_t,
+    modified: __wasi_timestamp_t,
+    created: __wasi_timestamp_t,
+}
+
+impl Times {
+    fn now(clock: Option<&VirtualClock>) -> Self {
+        let now = now(clock);
+        Self {
+            accessed: now,
+            modified: now,
+            created: now,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MemFileData {
+    bytes: Vec<u8>,
+    times: Times,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Node {
+    File(#[serde(with = "shared_data")] Arc<Mutex<MemFileData>>),
+    Dir {
+        entries: BTreeMap<String, Node>,
+        times: Times,
+    },
+    Symlink {
+        target: PathBuf,
+        times: Times,
+    },
+}
+
+impl Node {
+    fn new_dir(clock: Option<&VirtualClock>) -> Self {
+        Node::Dir {
+            entries: BTreeMap::new(),
+            times: Times::now(clock),
+        }
+    }
+
+    fn file_type(&self) -> FileType {
+        match self {
+            Node::File(_) => FileType::File,
+            Node::Dir { .. } => FileType::Dir,
+            Node::Symlink { .. } => FileType::Symlink,
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        let (len, times) = match self {
+            Node::File(data) => {
+                let data = data.lock().unwrap();
+                (data.bytes.len() as u64, data.times)
+            }
+            Node::Dir { entries, times } => (entries.len() as u64, *times),
+            Node::Symlink { target, times } => (target.as_os_str().len() as u64, *times),
+        };
+        Metadata {
+            file_type: self.file_type(),
+            len,
+            accessed: times.accessed,
+            modified: times.modified,
+            created: times.created,
+        }
+    }
+
+    fn get(&self, components: &[String]) -> Option<&Node> {
+        components.iter().try_fold(self, |node, name| match node {
+            Node::Dir { entries, .. } => entries.get(name),
+            _ => None,
+        })
+    }
+
+    /// Get the entries of the directory at `components`.
+    fn dir_entries_mut(
+        &mut self,
+        components: &[String],
+    ) -> Result<&mut BTreeMap<String, Node>, WasiFsError> {
+        let node = components.iter().try_fold(self, |node, name| match node {
+            Node::Dir { entries, .. } => entries.get_mut(name).ok_or(WasiFsError::EntityNotFound),
+            _ => Err(WasiFsError::BaseNotDirectory),
+        })?;
+        match node {
+            Node::Dir { entries, .. } => Ok(entries),
+            _ => Err(WasiFsError::BaseNotDirectory),
+        }
+    }
+}
+
+impl Default for MemFileSystem {
+    fn default() -> Self {
+        Self {
+            root: Arc::new(Mutex::new(Node::new_dir(None))),
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            clock: None,
+        }
+    }
+}
+
+impl MemFileSystem {
+    /// Create an empty filesystem containing only the root directory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the size that the files opened through this handle can't grow
+    /// beyond.  Writing past it fails with `EFBIG`.
+    ///
+    /// The default is [`DEFAULT_MAX_FILE_SIZE`].
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Create a symlink at `link` with the value `original`.  Like on the
+    /// host, the value is not checked and may be dangling.
+    pub fn symlink(&self, original: &Path, link: &Path) -> Result<(), WasiFsError> {
+        let mut root = self.lock();
+        let (parent, name) = resolve_parent(&root, link)?;
+        let entries = root.dir_entries_mut(&parent)?;
+        if entries.contains_key(&name) {
+            return Err(WasiFsError::AlreadyExists);
+        }
+        entries.insert(
+            name,
+            Node::Symlink {
+                target: original.to_path_buf(),
+                times: Times::now(self.clock.as_ref()),
+            },
+        );
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<Node> {
+        self.root.lock().unwrap()
+    }
+}
+
+/// Resolves `path` into the components of the entry it designates, following
+/// symlinks in all components but the last one unless `follow_last` is set.
+///
+/// The entry itself doesn't need to exist, but its parent does.
+fn resolve(root: &Node, path: &Path, follow_last: bool) -> Result<Vec<String>, WasiFsError> {
+    let mut pending: Vec<Component> = path.components().rev().collect();
+    let mut resolved: Vec<String> = Vec::new();
+    let mut symlinks_followed = 0;
+
+    while let Some(component) = pending.pop() {
+        let name = match component {
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => continue,
+            Component::ParentDir => {
+                resolved.pop();
+                continue;
+            }
+            Component::Normal(name) => name.to_string_lossy().to_string(),
+        };
+        let is_last = pending.is_empty();
+        let parent = root.get(&resolved).ok_or(WasiFsError::EntityNotFound)?;
+        let entries = match parent {
+            Node::Dir { entries, .. } => entries,
+            _ => return Err(WasiFsError::BaseNotDirectory),
+        };
+        match entries.get(&name) {
+            Some(Node::Symlink { target, .. }) if !is_last || follow_last => {
+                symlinks_followed += 1;
+                if symlinks_followed > MAX_SYMLINKS {
+                    return Err(WasiFsError::UnknownError(__WASI_ELOOP));
+                }
+                if target.is_absolute() {
+                    resolved.clear();
+                }
+                pending.extend(target.components().rev());
+            }
+            Some(_) => resolved.push(name),
+            None if is_last => resolved.push(name),
+            None => return Err(WasiFsError::EntityNotFound),
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Like [`resolve`] without following the last component, but returns the
+/// parent directory and the name of the entry separately.
+fn resolve_parent(root: &Node, path: &Path) -> Result<(Vec<String>, String), WasiFsError> {
+    let mut components = resolve(root, path, false)?;
+    let name = components.pop().ok_or(WasiFsError::InvalidInput)?;
+    Ok((components, name))
+}
+
+#[typetag::serde]
+impl FileSystem for MemFileSystem {
+    fn metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        let root = self.lock();
+        let components = resolve(&root, path, true)?;
+        root.get(&components)
+            .map(Node::metadata)
+            .ok_or(WasiFsError::EntityNotFound)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        let root = self.lock();
+        let components = resolve(&root, path, false)?;
+        root.get(&components)
+            .map(Node::metadata)
+            .ok_or(WasiFsError::EntityNotFound)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf, WasiFsError> {
+        let root = self.lock();
+        let components = resolve(&root, path, false)?;
+        match root.get(&components) {
+            Some(Node::Symlink { target, .. }) => Ok(target.clone()),
+            Some(_) => Err(WasiFsError::InvalidInput),
+            None => Err(WasiFsError::EntityNotFound),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, WasiFsError> {
+        let root = self.lock();
+        let components = resolve(&root, path, true)?;
+        match root.get(&components) {
+            Some(Node::Dir { entries, .. }) => Ok(entries
+                .iter()
+                .map(|(name, node)| DirEntry {
+                    name: name.clone(),
+                    file_type: node.file_type(),
+                })
+                .collect()),
+            Some(_) => Err(WasiFsError::BaseNotDirectory),
+            None => Err(WasiFsError::EntityNotFound),
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        let mut root = self.lock();
+        let (parent, name) = resolve_parent(&root, path)?;
+        let entries = root.dir_entries_mut(&parent)?;
+        if entries.contains_key(&name) {
+            return Err(WasiFsError::AlreadyExists);
+        }
+        entries.insert(name, Node::new_dir(self.clock.as_ref()));
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        let mut root = self.lock();
+        let (parent, name) = resolve_parent(&root, path)?;
+        let entries = root.dir_entries_mut(&parent)?;
+        match entries.get(&name) {
+            Some(Node::Dir {
+                entries: children, ..
+            }) if children.is_empty() => {
+                entries.remove(&name);
+                Ok(())
+            }
+            Some(Node::Dir { .. }) => Err(WasiFsError::UnknownError(__WASI_ENOTEMPTY)),
+            Some(_) => Err(WasiFsError::BaseNotDirectory),
+            None => Err(WasiFsError::EntityNotFound),
+        }
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), WasiFsError> {
+        let mut root = self.lock();
+        let (parent, name) = resolve_parent(&root, path)?;
+        let entries = root.dir_entries_mut(&parent)?;
+        match entries.get(&name) {
+            Some(Node::Dir { .. }) => Err(WasiFsError::UnknownError(__WASI_EISDIR)),
+            Some(_) => {
+                entries.remove(&name);
+                Ok(())
+            }
+            None => Err(WasiFsError::EntityNotFound),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), WasiFsError> {
+        let mut root = self.lock();
+        let (from_parent, from_name) = resolve_parent(&root, from)?;
+        let (to_parent, to_name) = resolve_parent(&root, to)?;
+
+        let mut from_components = from_parent.clone();
+        from_components.push(from_name.clone());
+        let mut to_components = to_parent.clone();
+        to_components.push(to_name.clone());
+        if from_components == to_components {
+            return root
+                .get(&from_components)
+                .map(|_| ())
+                .ok_or(WasiFsError::EntityNotFound);
+        }
+        // a directory can not be moved inside of itself
+        if to_components.starts_with(&from_components) {
+            return Err(WasiFsError::InvalidInput);
+        }
+
+        let from_is_dir = match root.get(&from_components) {
+            Some(node) => node.file_type().is_dir(),
+            None => return Err(WasiFsError::EntityNotFound),
+        };
+        match root.get(&to_components) {
+            Some(Node::Dir { entries, .. }) => {
+                if !from_is_dir {
+                    return Err(WasiFsError::UnknownError(__WASI_EISDIR));
+                }
+                if !entries.is_empty() {
+                    return Err(WasiFsError::UnknownError(__WASI_ENOTEMPTY));
+                }
+            }
+            Some(_) if from_is_dir => return Err(WasiFsError::BaseNotDirectory),
+            Some(_) => (),
+            None => {
+                // make sure the target's parent exists before detaching the source
+                root.dir_entries_mut(&to_parent)?;
+            }
+        }
+
+        let node = root
+            .dir_entries_mut(&from_parent)?
+            .remove(&from_name)
+            .ok_or(WasiFsError::EntityNotFound)?;
+        root.dir_entries_mut(&to_parent)?.insert(to_name, node);
+        Ok(())
+    }
+
+    fn open(&self, path: &Path, options: &OpenOptions) -> Result<Box<dyn WasiFile>, WasiFsError> {
+        let mut root = self.lock();
+        let components = resolve(&root, path, true)?;
+        let data = match root.get(&components) {
+            Some(Node::File(data)) => {
+                if options.get_create_new() {
+                    return Err(WasiFsError::AlreadyExists);
+                }
+                data.clone()
+            }
+            Some(Node::Dir { .. }) => return Err(WasiFsError::UnknownError(__WASI_EISDIR)),
+            Some(Node::Symlink { .. }) => unreachable!("symlinks are resolved above"),
+            None => {
+                if !(options.get_create() || options.get_create_new()) {
+                    return Err(WasiFsError::EntityNotFound);
+                }
+                let (name, parent) = components.split_last().ok_or(WasiFsError::InvalidInput)?;
+                let data = Arc::new(Mutex::new(MemFileData {
+                    bytes: Vec::new(),
+                    times: Times::now(self.clock.as_ref()),
+                }));
+                root.dir_entries_mut(parent)?
+                    .insert(name.clone(), Node::File(data.clone()));
+                data
+            }
+        };
+
+        let writable = options.get_write() || options.get_append();
+        if writable && options.get_truncate() {
+            let mut data = data.lock().unwrap();
+            data.bytes.clear();
+            data.times.modified = now(self.clock.as_ref());
+        }
+
+        Ok(Box::new(MemFile {
+            data,
+            cursor: 0,
+            readable: options.get_read(),
+            writable,
+            append: options.get_append(),
+            max_size: self.max_file_size,
+            clock: self.clock.clone(),
+        }))
+    }
+
+    fn set_clock(&mut self, clock: VirtualClock) {
+        self.clock = Some(clock);
+    }
+}
+
+/// A file opened from a [`MemFileSystem`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemFile {
+    #[serde(with = "shared_data")]
+    data: Arc<Mutex<MemFileData>>,
+    cursor: u64,
+    readable: bool,
+    writable: bool,
+    append: bool,
+    #[serde(default = "default_max_file_size")]
+    max_size: u64,
+    #[serde(default)]
+    clock: Option<VirtualClock>,
+}
+
+impl MemFile {
+    fn data(&self) -> MutexGuard<MemFileData> {
+        self.data.lock().unwrap()
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: VirtualClock) {
+        self.clock = Some(clock);
+    }
+}
+
+impl Read for MemFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.readable {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file was not opened for reading",
+            ));
+        }
+        let mut data = self.data.lock().unwrap();
+        let start = std::cmp::min(self.cursor, data.bytes.len() as u64) as usize;
+        let amt = std::cmp::min(buf.len(), data.bytes.len() - start);
+        buf[..amt].copy_from_slice(&data.bytes[start..start + amt]);
+        data.times.accessed = now(self.clock.as_ref());
+        self.cursor += amt as u64;
+        Ok(amt)
+    }
+}
+
+impl Write for MemFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.writable {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file was not opened for writing",
+            ));
+        }
+        // like on the host, an empty write never extends the file
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut data = self.data.lock().unwrap();
+        if self.append {
+            self.cursor = data.bytes.len() as u64;
+        }
+        let end = self
+            .cursor
+            .checked_add(buf.len() as u64)
+            .ok_or_else(|| wasi_io_error(__WASI_EINVAL))?;
+        // like on the host, the write is cut short at the maximum size and
+        // only fails if nothing can be written
+        let end = std::cmp::min(end, self.max_size);
+        if self.cursor >= end {
+            return Err(wasi_io_error(__WASI_EFBIG));
+        }
+        let start = usize::try_from(self.cursor).map_err(|_| wasi_io_error(__WASI_EFBIG))?;
+        let end = usize::try_from(end).map_err(|_| wasi_io_error(__WASI_EFBIG))?;
+        let amt = end.saturating_sub(start);
+        if data.bytes.len() < end {
+            data.bytes.resize(end, 0);
+        }
+        data.bytes[start..start + amt].copy_from_slice(&buf[..amt]);
+        data.times.modified = now(self.clock.as_ref());
+        self.cursor += amt as u64;
+        Ok(amt)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let new_cursor = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::End(offset) => add_offset(self.data().bytes.len() as u64, offset),
+            io::SeekFrom::Current(offset) => add_offset(self.cursor, offset),
+        };
+        self.cursor = new_cursor.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.cursor)
+    }
+}
+
+/// An I/O error carrying a WASI error number, which `fd_write` returns as is.
+fn wasi_io_error(errno: __wasi_errno_t) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, WasiFsError::UnknownError(errno))
+}
+
+fn add_offset(base: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        base.checked_sub(offset.wrapping_neg() as u64)
+    }
+}
+
+#[typetag::serde]
+impl WasiFile for MemFile {
+    fn last_accessed(&self) -> __wasi_timestamp_t {
+        self.data().times.accessed
+    }
+
+    fn set_last_accessed(&self, last_accessed: __wasi_timestamp_t) {
+        self.data().times.accessed = last_accessed;
+    }
+
+    fn last_modified(&self) -> __wasi_timestamp_t {
+        self.data().times.modified
+    }
+
+    fn set_last_modified(&self, last_modified: __wasi_timestamp_t) {
+        self.data().times.modified = last_modified;
+    }
+
+    fn created_time(&self) -> __wasi_timestamp_t {
+        self.data().times.created
+    }
+
+    fn set_created_time(&self, created_time: __wasi_timestamp_t) {
+        self.data().times.created = created_time;
+    }
+
+    fn size(&self) -> u64 {
+        self.data().bytes.len() as u64
+    }
+
+    fn set_len(&mut self, new_size: __wasi_filesize_t) -> Result<(), WasiFsError> {
+        if new_size > self.max_size {
+            return Err(WasiFsError::UnknownError(__WASI_EFBIG));
+        }
+        let new_size = usize::try_from(new_size).map_err(|_| WasiFsError::InvalidInput)?;
+        let mut data = self.data();
+        data.bytes.resize(new_size, 0);
+        data.times.modified = now(self.clock.as_ref());
+        Ok(())
+    }
+
+    fn unlink(&mut self) -> Result<(), WasiFsError> {
+        // the entry is removed from the `MemFileSystem` by the caller; the
+        // contents stay alive for as long as this handle is open
+        Ok(())
+    }
+
+    fn rename_file(&self, _new_name: &Path) -> Result<(), WasiFsError> {
+        // the entry is moved in the `MemFileSystem` by the caller
+        Ok(())
+    }
+
+    fn bytes_available(&self) -> Result<usize, WasiFsError> {
+        let len = self.data().bytes.len() as u64;
+        Ok(len.saturating_sub(self.cursor) as usize)
+    }
+}
+
+/// The time for the entries of a filesystem with the virtual clock `clock`.
+fn now(clock: Option<&VirtualClock>) -> __wasi_timestamp_t {
+    if let Some(clock) = clock {
+        return clock.now();
+    }
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as __wasi_timestamp_t)
+        .unwrap_or(0)
+}
+
+/// Run `f`, typically a (de)serialization of a [`WasiState`](super::WasiState),
+/// so that the contents of a file referenced from several places (the tree
+/// of a [`MemFileSystem`] and the [`MemFile`]s opened from it) are serialized
+/// once and shared again once deserialized.
+pub(crate) fn with_shared_file_data<T>(f: impl FnOnce() -> T) -> T {
+    shared_data::with_registry(f)
+}
+
+/// (De)serialization of the contents of the files, keeping track of the ones
+/// already seen while [`with_shared_file_data`] is running.
+mod shared_data {
+    use super::MemFileData;
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Registry {
+        serialized: HashMap<*const Mutex<MemFileData>, u64>,
+        deserialized: HashMap<u64, Arc<Mutex<MemFileData>>>,
+    }
+
+    thread_local! {
+        static REGISTRY: RefCell<Option<Registry>> = RefCell::new(None);
+    }
+
+    /// Restores the registry that was installed before, even if `f` panics.
+    struct RestoreRegistry(Option<Registry>);
+
+    impl Drop for RestoreRegistry {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            REGISTRY.with(|registry| *registry.borrow_mut() = previous);
+        }
+    }
+
+    pub(super) fn with_registry<T>(f: impl FnOnce() -> T) -> T {
+        let previous = REGISTRY.with(|registry| registry.borrow_mut().replace(Registry::default()));
+        let _restore = RestoreRegistry(previous);
+        f()
+    }
+
+    /// The contents of a file the first time it is seen, and a reference to
+    /// them afterwards.
+    #[derive(Serialize, Deserialize)]
+    enum Shared<T> {
+        Data(u64, T),
+        Ref(u64),
+    }
+
+    pub(super) fn serialize<S: Serializer>(
+        data: &Arc<Mutex<MemFileData>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let seen = REGISTRY.with(|registry| {
+            let mut registry = registry.borrow_mut();
+            let registry = registry.as_mut()?;
+            let next_id = registry.serialized.len() as u64;
+            let mut seen = true;
+            let id = *registry
+                .serialized
+                .entry(Arc::as_ptr(data))
+                .or_insert_with(|| {
+                    seen = false;
+                    next_id
+                });
+            Some((id, seen))
+        });
+        match seen {
+            Some((id, true)) => Shared::<&MemFileData>::Ref(id).serialize(serializer),
+            Some((id, false)) => Shared::Data(id, &*data.lock().unwrap()).serialize(serializer),
+            // without a registry, every file gets its own copy of its contents
+            None => Shared::Data(0, &*data.lock().unwrap()).serialize(serializer),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arc<Mutex<MemFileData>>, D::Error> {
+        match Shared::<MemFileData>::deserialize(deserializer)? {
+            Shared::Data(id, data) => {
+                let data = Arc::new(Mutex::new(data));
+                REGISTRY.with(|registry| {
+                    if let Some(registry) = registry.borrow_mut().as_mut() {
+                        registry.deserialized.insert(id, data.clone());
+                    }
+                });
+                Ok(data)
+            }
+            Shared::Ref(id) => REGISTRY
+                .with(|registry| {
+                    let registry = registry.borrow();
+                    registry.as_ref()?.deserialized.get(&id).cloned()
+                })
+                .ok_or_else(|| D::Error::custom(format!("unknown shared file data {}", id))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_file(fs: &MemFileSystem, path: &str, contents: &[u8]) {
+        let mut file = fs
+            .open(
+                Path::new(path),
+                OpenOptions::new().write(true).create(true).truncate(true),
+            )
+            .unwrap();
+        file.write_all(contents).unwrap();
+    }
+
+    fn read_file(fs: &MemFileSystem, path: &str) -> Result<Vec<u8>, WasiFsError> {
+        let mut file = fs.open(Path::new(path), OpenOptions::new().read(true))?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        Ok(contents)
+    }
+
+    #[test]
+    fn files_and_directories() {
+        let fs = MemFileSystem::new();
+        fs.create_dir(Path::new("/dir")).unwrap();
+        assert_eq!(
+            fs.create_dir(Path::new("/dir")),
+            Err(WasiFsError::AlreadyExists)
+        );
+        assert_eq!(
+            fs.create_dir(Path::new("/missing/dir")),
+            Err(WasiFsError::EntityNotFound)
+        );
+
+        write_file(&fs, "/dir/file", b"hello");
+        assert_eq!(read_file(&fs, "dir/./file").unwrap(), b"hello");
+        assert_eq!(fs.metadata(Path::new("/dir/file")).unwrap().len, 5);
+        assert!(fs.metadata(Path::new("/dir")).unwrap().is_dir());
+        assert_eq!(
+            fs.read_dir(Path::new("/dir")).unwrap(),
+            vec![DirEntry {
+                name: "file".to_string(),
+                file_type: FileType::File,
+            }]
+        );
+
+        assert_eq!(
+            fs.remove_dir(Path::new("/dir")),
+            Err(WasiFsError::UnknownError(__WASI_ENOTEMPTY))
+        );
+        fs.remove_file(Path::new("/dir/file")).unwrap();
+        assert_eq!(
+            read_file(&fs, "/dir/file"),
+            Err(WasiFsError::EntityNotFound)
+        );
+        fs.remove_dir(Path::new("/dir")).unwrap();
+        assert!(fs.read_dir(Path::new("/")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn open_options() {
+        let fs = MemFileSystem::new();
+        assert!(fs
+            .open(Path::new("/file"), OpenOptions::new().read(true))
+            .is_err());
+        write_file(&fs, "/file", b"hello");
+        assert_eq!(
+            fs.open(
+                Path::new("/file"),
+                OpenOptions::new().write(true).create_new(true)
+            )
+            .unwrap_err(),
+            WasiFsError::AlreadyExists
+        );
+
+        let mut file = fs
+            .open(Path::new("/file"), OpenOptions::new().append(true))
+            .unwrap();
+        file.write_all(b" world").unwrap();
+        assert_eq!(read_file(&fs, "/file").unwrap(), b"hello world");
+
+        let mut file = fs
+            .open(
+                Path::new("/file"),
+                OpenOptions::new().read(true).write(true),
+            )
+            .unwrap();
+        file.seek(io::SeekFrom::Start(6)).unwrap();
+        file.write_all(b"there").unwrap();
+        assert_eq!(read_file(&fs, "/file").unwrap(), b"hello there");
+
+        write_file(&fs, "/file", b"bye");
+        assert_eq!(read_file(&fs, "/file").unwrap(), b"bye");
+    }
+
+    #[test]
+    fn max_file_size() {
+        let fs = MemFileSystem::new().with_max_file_size(8);
+        let mut file = fs
+            .open(
+                Path::new("/file"),
+                OpenOptions::new().write(true).create(true),
+            )
+            .unwrap();
+        assert_eq!(file.write(b"hello world").unwrap(), 8);
+        assert_eq!(
+            WasiFsError::from(file.write(b"!").unwrap_err()),
+            WasiFsError::UnknownError(__WASI_EFBIG)
+        );
+        assert_eq!(read_file(&fs, "/file").unwrap(), b"hello wo");
+
+        file.seek(io::SeekFrom::Start(u64::MAX)).unwrap();
+        assert_eq!(
+            WasiFsError::from(file.write(b"!").unwrap_err()),
+            WasiFsError::UnknownError(__WASI_EINVAL)
+        );
+        assert_eq!(
+            file.set_len(9),
+            Err(WasiFsError::UnknownError(__WASI_EFBIG))
+        );
+        file.set_len(2).unwrap();
+        assert_eq!(read_file(&fs, "/file").unwrap(), b"he");
+    }
+
+    #[test]
+    fn empty_writes() {
+        let fs = MemFileSystem::new().with_max_file_size(8);
+        let mut file = fs
+            .open(
+                Path::new("/file"),
+                OpenOptions::new().write(true).create(true),
+            )
+            .unwrap();
+        assert_eq!(file.write(b"hi").unwrap(), 2);
+
+        // past the end of the file
+        file.seek(io::SeekFrom::Start(4)).unwrap();
+        assert_eq!(file.write(b"").unwrap(), 0);
+        assert_eq!(read_file(&fs, "/file").unwrap(), b"hi");
+
+        // past the maximum size
+        file.seek(io::SeekFrom::Start(16)).unwrap();
+        assert_eq!(file.write(b"").unwrap(), 0);
+        assert_eq!(read_file(&fs, "/file").unwrap(), b"hi");
+    }
+
+    #[test]
+    fn virtual_clock() {
+        let mut fs = MemFileSystem::new();
+        let mut clock = VirtualClock::new(1_000, 10);
+        fs.set_clock(clock.clone());
+        write_file(&fs, "/file", b"hello");
+        fs.create_dir(Path::new("/dir")).unwrap();
+        assert_eq!(fs.metadata(Path::new("/file")).unwrap().created, 1_000);
+        assert_eq!(fs.metadata(Path::new("/dir")).unwrap().created, 1_000);
+
+        // the times follow the clock without advancing it
+        clock.advance(500);
+        write_file(&fs, "/file", b"bye");
+        let metadata = fs.metadata(Path::new("/file")).unwrap();
+        assert_eq!((metadata.created, metadata.modified), (1_000, 1_500));
+        assert_eq!(clock.now(), 1_500);
+    }
+
+    #[test]
+    fn symlinks() {
+        let fs = MemFileSystem::new();
+        fs.create_dir(Path::new("/dir")).unwrap();
+        write_file(&fs, "/dir/file", b"contents");
+        fs.symlink(Path::new("dir/file"), Path::new("/relative"))
+            .unwrap();
+        fs.symlink(Path::new("/dir"), Path::new("/dir/absolute"))
+            .unwrap();
+        fs.symlink(Path::new("loop"), Path::new("/loop")).unwrap();
+
+        assert_eq!(read_file(&fs, "/relative").unwrap(), b"contents");
+        assert_eq!(read_file(&fs, "/dir/absolute/file").unwrap(), b"contents");
+        assert_eq!(
+            fs.read_link(Path::new("/relative")).unwrap(),
+            PathBuf::from("dir/file")
+        );
+        assert!(fs
+            .symlink_metadata(Path::new("/relative"))
+            .unwrap()
+            .is_symlink());
+        assert!(fs.metadata(Path::new("/relative")).unwrap().is_file());
+        assert_eq!(
+            fs.metadata(Path::new("/loop")),
+            Err(WasiFsError::UnknownError(__WASI_ELOOP))
+        );
+
+        // removing a symlink doesn't remove its target
+        fs.remove_file(Path::new("/relative")).unwrap();
+        assert_eq!(read_file(&fs, "/dir/file").unwrap(), b"contents");
+    }
+
+    #[test]
+    fn rename() {
+        let fs = MemFileSystem::new();
+        fs.create_dir(Path::new("/a")).unwrap();
+        fs.create_dir(Path::new("/b")).unwrap();
+        write_file(&fs, "/a/file", b"contents");
+
+        // open handles follow the file around
+        let mut file = fs
+            .open(Path::new("/a/file"), OpenOptions::new().read(true))
+            .unwrap();
+        fs.rename(Path::new("/a/file"), Path::new("/b/moved"))
+            .unwrap();
+        assert_eq!(
+            fs.metadata(Path::new("/a/file")),
+            Err(WasiFsError::EntityNotFound)
+        );
+        assert_eq!(read_file(&fs, "/b/moved").unwrap(), b"contents");
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "contents");
+
+        // files are replaced, directories are moved with their contents
+        write_file(&fs, "/b/other", b"other");
+        fs.rename(Path::new("/b/other"), Path::new("/b/moved"))
+            .unwrap();
+        assert_eq!(read_file(&fs, "/b/moved").unwrap(), b"other");
+        fs.rename(Path::new("/b"), Path::new("/a/b")).unwrap();
+        assert_eq!(read_file(&fs, "/a/b/moved").unwrap(), b"other");
+        assert_eq!(
+            fs.rename(Path::new("/a"), Path::new("/a/b/a")),
+            Err(WasiFsError::InvalidInput)
+        );
+    }
+}
@@ -12,14 +12,31 @@
 //!
 //! You can implement `WasiFile` for your own types to get custom behavior and extend WASI, see the
 //! [WASI plugin example](https://github.com/wasmerio/wasmer/blob/master/examples/plugin.rs).
+//!
+//! The files and directories themselves are provided by a [`FileSystem`], which is the host's
+//! filesystem by default.  Use [`WasiStateBuilder::set_fs`] to provide another one, such as a
+//! [`MemFileSystem`].  A preopened directory can also be overlaid with a writable
+//! filesystem through [`PreopenDirBuilder::overlay`], leaving the directory itself untouched.
 
 #![allow(clippy::cognitive_complexity, clippy::too_many_arguments)]
 
 mod builder;
+mod deterministic;
+mod host_fs;
+mod mem_fs;
+mod overlay_fs;
+mod quota;
 mod types;
+mod vfs;
 
 pub use self::builder::*;
+pub use self::deterministic::*;
+pub use self::host_fs::*;
+pub use self::mem_fs::*;
+pub use self::overlay_fs::*;
+pub use self::quota::*;
 pub use self::types::*;
+pub use self::vfs::*;
 use crate::syscalls::types::*;
 use generational_arena::Arena;
 pub use generational_arena::Index as Inode;
@@ -28,10 +45,8 @@ use std::collections::HashMap;
 use std::{
     borrow::Borrow,
     cell::Cell,
-    fs,
     io::Write,
-    path::{Path, PathBuf},
-    time::SystemTime,
+    path::{Component, Path, PathBuf},
 };
 use tracing::debug;
 
@@ -53,8 +68,9 @@ const STDOUT_DEFAULT_RIGHTS: __wasi_rights_t = __WASI_RIGHT_FD_DATASYNC
     | __WASI_RIGHT_POLL_FD_READWRITE;
 const STDERR_DEFAULT_RIGHTS: __wasi_rights_t = STDOUT_DEFAULT_RIGHTS;
 
-/// A completely aribtrary "big enough" number used as the upper limit for
-/// the number of symlinks that can be traversed when resolving a path
+/// A completely aribtrary "big enough" number used as the default upper limit
+/// for the number of symlinks that can be traversed when resolving a path, see
+/// [`WasiStateBuilder::max_symlinks`]
 pub const MAX_SYMLINKS: u32 = 128;
 
 /// A file that Wasi knows about that may or may not be open
@@ -73,7 +89,7 @@ pub enum Kind {
     File {
         /// the open file, if it's open
         handle: Option<Box<dyn WasiFile>>,
-        /// The path on the host system where the file is located
+        /// The path in the [`FileSystem`] where the file is located
         /// This is deprecated and will be removed soon
         path: PathBuf,
         /// Marks the file as a special file that only one `fd` can exist for
@@ -85,8 +101,7 @@ pub enum Kind {
     Dir {
         /// Parent directory
         parent: Option<Inode>,
-        /// The path on the host system where the directory is located
-        // TODO: wrap it like WasiFile
+        /// The path in the [`FileSystem`] where the directory is located
         path: PathBuf,
         /// The entries of a directory are lazily filled.
         entries: HashMap<String, Inode>,
@@ -109,7 +124,7 @@ pub enum Kind {
         base_po_dir: __wasi_fd_t,
         /// The path to the symlink from the `base_po_dir`
         path_to_symlink: PathBuf,
-        /// the value of the symlink as a relative path
+        /// the value of the symlink; absolute values are relative to `base_po_dir`
         relative_path: PathBuf,
     },
     Buffer {
@@ -163,6 +178,14 @@ pub struct WasiFs {
     inode_counter: Cell<u64>,
     /// for fds still open after the file has been deleted
     pub orphan_fds: HashMap<Inode, InodeVal>,
+    /// The filesystem that the files and directories are loaded from
+    pub fs_backing: Box<dyn FileSystem>,
+    /// The maximum number of symlinks followed when resolving a single path
+    pub max_symlinks: u32,
+    /// The limits on what the program can consume
+    pub quotas: WasiQuotas,
+    /// What the program has consumed so far
+    pub usage: WasiUsage,
 }
 
 impl WasiFs {
@@ -176,7 +199,7 @@ impl WasiFs {
         preopened_dirs: &[PathBuf],
         mapped_dirs: &[(String, PathBuf)],
     ) -> Result<Self, String> {
-        let (mut wasi_fs, root_inode) = Self::new_init()?;
+        let (mut wasi_fs, root_inode) = Self::new_init(Box::new(HostFileSystem))?;
 
         debug!("wasi::fs::preopen_dirs");
         for dir in preopened_dirs {
@@ -290,8 +313,11 @@ impl WasiFs {
     }
 
     /// Created for the builder API. like `new` but with more information
-    pub(crate) fn new_with_preopen(preopens: &[PreopenedDir]) -> Result<Self, String> {
-        let (mut wasi_fs, root_inode) = Self::new_init()?;
+    pub(crate) fn new_with_preopen(
+        preopens: &[PreopenedDir],
+        fs_backing: Box<dyn FileSystem>,
+    ) -> Result<Self, String> {
+        let (mut wasi_fs, root_inode) = Self::new_init(fs_backing)?;
 
         for PreopenedDir {
             path,
@@ -299,6 +325,7 @@ impl WasiFs {
             read,
             write,
             create,
+            ..
         } in preopens
         {
             debug!(
@@ -306,7 +333,7 @@ impl WasiFs {
                 &path.to_string_lossy(),
                 &alias
             );
-            let cur_dir_metadata = path.metadata().map_err(|e| {
+            let cur_dir_metadata = wasi_fs.fs_backing.metadata(path).map_err(|e| {
                 format!(
                     "Could not get metadata for file {:?}: {}",
                     path,
@@ -417,7 +444,7 @@ impl WasiFs {
 
     /// Private helper function to init the filesystem, called in `new` and
     /// `new_with_preopen`
-    fn new_init() -> Result<(Self, Inode), String> {
+    fn new_init(fs_backing: Box<dyn FileSystem>) -> Result<(Self, Inode), String> {
         debug!("Initializing WASI filesystem");
         let inodes = Arena::new();
         let mut wasi_fs = Self {
@@ -428,6 +455,10 @@ impl WasiFs {
             next_fd: Cell::new(3),
             inode_counter: Cell::new(1024),
             orphan_fds: HashMap::new(),
+            fs_backing,
+            max_symlinks: MAX_SYMLINKS,
+            quotas: WasiQuotas::default(),
+            usage: WasiUsage::default(),
         };
         wasi_fs.create_stdin();
         wasi_fs.create_stdout();
@@ -732,24 +763,24 @@ impl WasiFs {
         mut symlink_count: u32,
         follow_symlinks: bool,
     ) -> Result<Inode, __wasi_errno_t> {
-        if symlink_count > MAX_SYMLINKS {
-            return Err(__WASI_EMLINK);
+        if symlink_count > self.max_symlinks {
+            return Err(__WASI_ELOOP);
         }
 
         let base_dir = self.get_fd(base)?;
         let path: &Path = Path::new(path);
 
         let mut cur_inode = base_dir.inode;
-        let n_components = path.components().count();
         // TODO: rights checks
-        'path_iter: for (i, component) in path.components().enumerate() {
-            // used to terminate symlink resolution properly
-            let last_component = i + 1 == n_components;
+        'path_iter: for component in path.components() {
+            // whether `cur_inode` is the symlink named by this component, rather
+            // than a symlink the previous components resolved to
+            let mut component_is_symlink = false;
             // for each component traverse file structure
             // loading inodes as necessary
-            'symlink_resolution: while symlink_count < MAX_SYMLINKS {
+            'symlink_resolution: loop {
                 match &mut self.inodes[cur_inode].kind {
-                    Kind::Buffer { .. } => unimplemented!("state::get_inode_at_path for buffers"),
+                    Kind::Buffer { .. } => return Err(__WASI_ENOTDIR),
                     Kind::Dir {
                         ref mut entries,
                         ref path,
@@ -780,8 +811,12 @@ impl WasiFs {
                                 cd.push(component);
                                 cd
                             };
-                            let metadata = file.symlink_metadata().ok().ok_or(__WASI_EINVAL)?;
-                            let file_type = metadata.file_type();
+                            let metadata = self
+                                .fs_backing
+                                .symlink_metadata(&file)
+                                .ok()
+                                .ok_or(__WASI_EINVAL)?;
+                            let file_type = metadata.file_type;
                             // we want to insert newly opened dirs and files, but not transient symlinks
                             // TODO: explain why (think about this deeply when well rested)
                             let should_insert;
@@ -804,73 +839,52 @@ impl WasiFs {
                                 }
                             } else if file_type.is_symlink() {
                                 should_insert = false;
-                                let link_value = file.read_link().ok().ok_or(__WASI_EIO)?;
+                                let link_value =
+                                    self.fs_backing.read_link(&file).ok().ok_or(__WASI_EIO)?;
                                 debug!("attempting to decompose path {:?}", link_value);
 
-                                let (pre_open_dir_fd, relative_path) = if link_value.is_relative() {
-                                    self.path_into_pre_open_and_relative_path(&file)?
-                                } else {
-                                    unimplemented!("Absolute symlinks are not yet supported");
-                                };
+                                let (pre_open_dir_fd, relative_path) =
+                                    self.path_into_pre_open_and_relative_path(&file)?;
                                 loop_for_symlink = true;
-                                symlink_count += 1;
                                 Kind::Symlink {
                                     base_po_dir: pre_open_dir_fd,
                                     path_to_symlink: relative_path.to_owned(),
                                     relative_path: link_value,
                                 }
-                            } else {
-                                #[cfg(unix)]
+                            } else if file_type != FileType::Unknown {
+                                // char device, block device, fifo, or socket
+                                let kind = Kind::File {
+                                    handle: None,
+                                    path: file.clone(),
+                                    fd: None,
+                                };
+                                let new_inode = self.create_inode_with_stat(
+                                    kind,
+                                    false,
+                                    file.to_string_lossy().to_string(),
+                                    __wasi_filestat_t {
+                                        st_filetype: file_type.to_wasi_file_type(),
+                                        ..__wasi_filestat_t::default()
+                                    },
+                                );
+                                if let Kind::Dir {
+                                    ref mut entries, ..
+                                } = &mut self.inodes[cur_inode].kind
                                 {
-                                    use std::os::unix::fs::FileTypeExt;
-                                    let file_type: __wasi_filetype_t = if file_type.is_char_device()
-                                    {
-                                        __WASI_FILETYPE_CHARACTER_DEVICE
-                                    } else if file_type.is_block_device() {
-                                        __WASI_FILETYPE_BLOCK_DEVICE
-                                    } else if file_type.is_fifo() {
-                                        // FIFO doesn't seem to fit any other type, so unknown
-                                        __WASI_FILETYPE_UNKNOWN
-                                    } else if file_type.is_socket() {
-                                        // TODO: how do we know if it's a `__WASI_FILETYPE_SOCKET_STREAM` or
-                                        // a `__WASI_FILETYPE_SOCKET_DGRAM`?
-                                        __WASI_FILETYPE_SOCKET_STREAM
-                                    } else {
-                                        unimplemented!("state::get_inode_at_path unknown file type: not file, directory, symlink, char device, block device, fifo, or socket");
-                                    };
-
-                                    let kind = Kind::File {
-                                        handle: None,
-                                        path: file.clone(),
-                                        fd: None,
-                                    };
-                                    let new_inode = self.create_inode_with_stat(
-                                        kind,
-                                        false,
-                                        file.to_string_lossy().to_string(),
-                                        __wasi_filestat_t {
-                                            st_filetype: file_type,
-                                            ..__wasi_filestat_t::default()
-                                        },
+                                    entries.insert(
+                                        component.as_os_str().to_string_lossy().to_string(),
+                                        new_inode,
+                                    );
+                                } else {
+                                    unreachable!(
+                                        "Attempted to insert special device into non-directory"
                                     );
-                                    if let Kind::Dir {
-                                        ref mut entries, ..
-                                    } = &mut self.inodes[cur_inode].kind
-                                    {
-                                        entries.insert(
-                                            component.as_os_str().to_string_lossy().to_string(),
-                                            new_inode,
-                                        );
-                                    } else {
-                                        unreachable!(
-                                            "Attempted to insert special device into non-directory"
-                                        );
-                                    }
-                                    // perhaps just continue with symlink resolution and return at the end
-                                    return Ok(new_inode);
                                 }
-                                #[cfg(not(unix))]
-                                unimplemented!("state::get_inode_at_path unknown file type: not file, directory, or symlink");
+                                // perhaps just continue with symlink resolution and return at the end
+                                return Ok(new_inode);
+                            } else {
+                                // not a file, directory, symlink, char device, block device, fifo, or socket
+                                return Err(__WASI_ENOTSUP);
                             };
 
                             let new_inode =
@@ -890,6 +904,7 @@ impl WasiFs {
 
                             if loop_for_symlink && follow_symlinks {
                                 debug!("Following symlink to {:?}", cur_inode);
+                                component_is_symlink = true;
                                 continue 'symlink_resolution;
                             }
                         }
@@ -921,33 +936,23 @@ impl WasiFs {
                     } => {
                         let new_base_dir = *base_po_dir;
                         // allocate to reborrow mutabily to recur
-                        let new_path = {
-                            /*if let Kind::Root { .. } = self.inodes[base_po_dir].kind {
-                                assert!(false, "symlinks should never be relative to the root");
-                            }*/
-                            let mut base = path_to_symlink.clone();
-                            // remove the symlink file itself from the path, leaving just the path from the base
-                            // to the dir containing the symlink
-                            base.pop();
-                            base.push(relative_path);
-                            base.to_string_lossy().to_string()
-                        };
+                        let new_path = symlink_target_in_pre_open(path_to_symlink, relative_path)
+                            .to_string_lossy()
+                            .to_string();
                         debug!("Following symlink recursively");
+                        symlink_count += 1;
                         let symlink_inode = self.get_inode_at_path_inner(
                             new_base_dir,
                             &new_path,
-                            symlink_count + 1,
-                            follow_symlinks,
+                            symlink_count,
+                            true,
                         )?;
                         cur_inode = symlink_inode;
-                        // if we're at the very end and we found a file, then we're done
-                        // TODO: figure out if this should also happen for directories?
-                        if let Kind::File { .. } = &self.inodes[cur_inode].kind {
-                            // check if on last step
-                            if last_component {
-                                break 'symlink_resolution;
-                            }
+                        if component_is_symlink {
+                            // the symlink was this component, so move on to the next one
+                            break 'symlink_resolution;
                         }
+                        // look this component up in the target of the symlink
                         continue 'symlink_resolution;
                     }
                 }
@@ -1084,6 +1089,84 @@ impl WasiFs {
             .map(|v| (v, new_entity_name))
     }
 
+    /// Updates the inodes of the directory `inode` and of everything below
+    /// it after the directory was moved from `old_path` to `new_path` in
+    /// the [`FileSystem`], into the directory `new_parent`.
+    pub(crate) fn move_dir_inodes(
+        &mut self,
+        inode: Inode,
+        new_parent: Inode,
+        old_path: &Path,
+        new_path: &Path,
+    ) {
+        let move_path = |path: &Path| -> Option<PathBuf> {
+            let rest = path.strip_prefix(old_path).ok()?;
+            if rest.as_os_str().is_empty() {
+                Some(new_path.to_path_buf())
+            } else {
+                Some(new_path.join(rest))
+            }
+        };
+        if let Kind::Dir { parent, .. } = &mut self.inodes[inode].kind {
+            *parent = Some(new_parent);
+        }
+
+        let mut pending = vec![inode];
+        while let Some(inode) = pending.pop() {
+            // symlinks are stored relative to their preopened directory,
+            // which may have changed
+            let moved_symlink = match &self.inodes[inode].kind {
+                Kind::Symlink {
+                    base_po_dir,
+                    path_to_symlink,
+                    ..
+                } => self
+                    .fd_map
+                    .get(base_po_dir)
+                    .and_then(|fd| match &self.inodes[fd.inode].kind {
+                        Kind::Dir { path, .. } => move_path(&path.join(path_to_symlink)),
+                        _ => None,
+                    })
+                    .and_then(|host_path| {
+                        let preopen = self.preopen_of(&host_path)?;
+                        match &self.inodes[self.fd_map[&preopen].inode].kind {
+                            Kind::Dir { path, .. } => {
+                                let relative = host_path.strip_prefix(path).ok()?.to_path_buf();
+                                Some((preopen, relative))
+                            }
+                            _ => None,
+                        }
+                    }),
+                _ => None,
+            };
+
+            match &mut self.inodes[inode].kind {
+                Kind::Dir { path, entries, .. } => {
+                    if let Some(moved) = move_path(path) {
+                        *path = moved;
+                    }
+                    pending.extend(entries.values());
+                }
+                Kind::File { path, .. } => {
+                    if let Some(moved) = move_path(path) {
+                        *path = moved;
+                    }
+                }
+                Kind::Symlink {
+                    base_po_dir,
+                    path_to_symlink,
+                    ..
+                } => {
+                    if let Some((preopen, relative)) = moved_symlink {
+                        *base_po_dir = preopen;
+                        *path_to_symlink = relative;
+                    }
+                }
+                Kind::Root { .. } | Kind::Buffer { .. } => (),
+            }
+        }
+    }
+
     pub fn get_fd(&self, fd: __wasi_fd_t) -> Result<&Fd, __wasi_errno_t> {
         self.fd_map.get(&fd).ok_or(__WASI_EBADF)
     }
@@ -1268,6 +1351,7 @@ impl WasiFs {
         open_flags: u16,
         inode: Inode,
     ) -> Result<__wasi_fd_t, __wasi_errno_t> {
+        self.check_open_fds()?;
         let idx = self.next_fd.get();
         self.next_fd.set(idx + 1);
         self.fd_map.insert(
@@ -1394,9 +1478,9 @@ impl WasiFs {
                         ..__wasi_filestat_t::default()
                     })
                 }
-                None => path.metadata().ok()?,
+                None => self.fs_backing.metadata(path).ok()?,
             },
-            Kind::Dir { path, .. } => path.metadata().ok()?,
+            Kind::Dir { path, .. } => self.fs_backing.metadata(path).ok()?,
             Kind::Symlink {
                 base_po_dir,
                 path_to_symlink,
@@ -1406,7 +1490,7 @@ impl WasiFs {
                 let base_po_inode_v = &self.inodes[*base_po_inode];
                 match &base_po_inode_v.kind {
                     Kind::Root { .. } => {
-                        path_to_symlink.clone().symlink_metadata().ok()?
+                        self.fs_backing.symlink_metadata(path_to_symlink).ok()?
                     }
                     Kind::Dir { path, .. } => {
                         let mut real_path = path.clone();
@@ -1417,7 +1501,7 @@ impl WasiFs {
                         // TODO: adjust size of symlink, too
                         //      for all paths adjusted think about this
                         real_path.push(path_to_symlink);
-                        real_path.symlink_metadata().ok()?
+                        self.fs_backing.symlink_metadata(&real_path).ok()?
                     }
                     // if this triggers, there's a bug in the symlink code
                     _ => unreachable!("Symlink pointing to something that's not a directory as its base preopened directory"),
@@ -1425,29 +1509,7 @@ impl WasiFs {
             }
             _ => return None,
         };
-        Some(__wasi_filestat_t {
-            st_filetype: host_file_type_to_wasi_file_type(md.file_type()),
-            st_size: md.len(),
-            st_atim: md
-                .accessed()
-                .ok()?
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .ok()?
-                .as_nanos() as u64,
-            st_mtim: md
-                .modified()
-                .ok()?
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .ok()?
-                .as_nanos() as u64,
-            st_ctim: md
-                .created()
-                .ok()
-                .and_then(|ct| ct.duration_since(SystemTime::UNIX_EPOCH).ok())
-                .map(|ct| ct.as_nanos() as u64)
-                .unwrap_or(0),
-            ..__wasi_filestat_t::default()
-        })
+        Some(md.to_filestat())
     }
 
     /// Closes an open FD, handling all details such as FD being preopen
@@ -1539,6 +1601,10 @@ pub struct WasiState {
     pub fs: WasiFs,
     pub args: Vec<Vec<u8>>,
     pub envs: Vec<Vec<u8>>,
+    /// The clock used instead of the host's clocks, if any
+    pub clock: Option<VirtualClock>,
+    /// The random number generator used instead of the host's, if any
+    pub rng: Option<SeededRng>,
 }
 
 impl WasiState {
@@ -1551,24 +1617,220 @@ impl WasiState {
 
     /// Turn the WasiState into bytes
     pub fn freeze(&self) -> Option<Vec<u8>> {
-        bincode::serialize(self).ok()
+        // `bincode::serialize` does a first pass to size the buffer, which
+        // would record every file as already serialized
+        let mut bytes = Vec::new();
+        mem_fs::with_shared_file_data(|| bincode::serialize_into(&mut bytes, self)).ok()?;
+        Some(bytes)
     }
 
     /// Get a WasiState from bytes
     pub fn unfreeze(bytes: &[u8]) -> Option<Self> {
-        bincode::deserialize(bytes).ok()
+        let mut state: Self = mem_fs::with_shared_file_data(|| bincode::deserialize(bytes).ok())?;
+        // the clock is serialized separately from the filesystem's copy
+        if let Some(clock) = &state.clock {
+            state.fs.fs_backing.set_clock(clock.clone());
+            for (_, inode) in state.fs.inodes.iter_mut() {
+                if let Kind::File {
+                    handle: Some(handle),
+                    ..
+                } = &mut inode.kind
+                {
+                    if let Some(file) = handle.downcast_mut::<MemFile>() {
+                        file.set_clock(clock.clone());
+                    }
+                }
+            }
+        }
+        Some(state)
+    }
+}
+
+/// Returns the path that a symlink points to, relative to the preopened directory
+/// that contains it.  `path_to_symlink` is the path of the symlink from the
+/// preopened directory and `link` is the value of the symlink.
+///
+/// The preopened directory acts as the root directory for symlinks: absolute
+/// values are resolved from it and `..` never goes above it, so following a
+/// symlink can't escape the preopened directory.  `..` is resolved lexically.
+fn symlink_target_in_pre_open(path_to_symlink: &Path, link: &Path) -> PathBuf {
+    let mut components = Vec::new();
+    let parent_components = match path_to_symlink.parent() {
+        Some(parent) if link.is_relative() => parent.components(),
+        _ => Path::new("").components(),
+    };
+    for component in parent_components.chain(link.components()) {
+        match component {
+            Component::Normal(name) => components.push(name),
+            Component::ParentDir => {
+                components.pop();
+            }
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => (),
+        }
     }
+    components.iter().collect()
 }
 
-pub fn host_file_type_to_wasi_file_type(file_type: fs::FileType) -> __wasi_filetype_t {
-    // TODO: handle other file types
-    if file_type.is_dir() {
-        __WASI_FILETYPE_DIRECTORY
-    } else if file_type.is_file() {
-        __WASI_FILETYPE_REGULAR_FILE
-    } else if file_type.is_symlink() {
-        __WASI_FILETYPE_SYMBOLIC_LINK
-    } else {
-        __WASI_FILETYPE_UNKNOWN
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    /// A WASI filesystem with `/data` preopened from an in-memory filesystem
+    /// containing `symlinks`, and the fd of `/data`.
+    fn wasi_fs_with_symlinks(symlinks: &[(&str, &str)], max_symlinks: u32) -> (WasiFs, u32) {
+        let fs = MemFileSystem::new();
+        fs.create_dir(Path::new("/data")).unwrap();
+        fs.create_dir(Path::new("/data/dir")).unwrap();
+        fs.open(
+            Path::new("/data/dir/file"),
+            OpenOptions::new().write(true).create(true),
+        )
+        .unwrap();
+        fs.open(
+            Path::new("/secret"),
+            OpenOptions::new().write(true).create(true),
+        )
+        .unwrap();
+        for (original, link) in symlinks {
+            fs.symlink(Path::new(original), Path::new(link)).unwrap();
+        }
+
+        let state = WasiState::new("test")
+            .set_fs(Box::new(fs))
+            .max_symlinks(max_symlinks)
+            .preopen_dir("/data")
+            .unwrap()
+            .build()
+            .unwrap();
+        let fd = state.fs.preopen_fds[1];
+        (state.fs, fd)
+    }
+
+    fn host_path(wasi_fs: &WasiFs, inode: Inode) -> PathBuf {
+        match &wasi_fs.inodes[inode].kind {
+            Kind::File { path, .. } | Kind::Dir { path, .. } => path.clone(),
+            kind => panic!("unexpected kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn absolute_symlinks_are_relative_to_the_preopen() {
+        let (mut wasi_fs, fd) = wasi_fs_with_symlinks(&[("/dir", "/data/abs")], MAX_SYMLINKS);
+        let inode = wasi_fs.get_inode_at_path(fd, "abs/file", true).unwrap();
+        assert_eq!(host_path(&wasi_fs, inode), Path::new("/data/dir/file"));
+    }
+
+    #[test]
+    fn symlinks_do_not_escape_the_preopen() {
+        let (mut wasi_fs, fd) = wasi_fs_with_symlinks(
+            &[
+                ("../../secret", "/data/dir/relative"),
+                ("/../secret", "/data/absolute"),
+                ("../../../dir/file", "/data/dir/clamped"),
+            ],
+            MAX_SYMLINKS,
+        );
+        assert!(wasi_fs.get_inode_at_path(fd, "dir/relative", true).is_err());
+        assert!(wasi_fs.get_inode_at_path(fd, "absolute", true).is_err());
+        let inode = wasi_fs.get_inode_at_path(fd, "dir/clamped", true).unwrap();
+        assert_eq!(host_path(&wasi_fs, inode), Path::new("/data/dir/file"));
+    }
+
+    #[test]
+    fn moved_directories_update_their_inodes() {
+        let (mut wasi_fs, fd) = wasi_fs_with_symlinks(&[], MAX_SYMLINKS);
+        let dir = wasi_fs.get_inode_at_path(fd, "dir", false).unwrap();
+        let file = wasi_fs.get_inode_at_path(fd, "dir/file", false).unwrap();
+        // like `path_symlink` does
+        let link = wasi_fs.create_inode_with_default_stat(
+            Kind::Symlink {
+                base_po_dir: fd,
+                path_to_symlink: PathBuf::from("dir/link"),
+                relative_path: PathBuf::from("file"),
+            },
+            false,
+            "link".to_string(),
+        );
+        if let Kind::Dir { entries, .. } = &mut wasi_fs.inodes[dir].kind {
+            entries.insert("link".to_string(), link);
+        }
+        wasi_fs
+            .fs_backing
+            .create_dir(Path::new("/data/new"))
+            .unwrap();
+        let new = wasi_fs.get_inode_at_path(fd, "new", false).unwrap();
+
+        wasi_fs
+            .fs_backing
+            .rename(Path::new("/data/dir"), Path::new("/data/new/moved"))
+            .unwrap();
+        wasi_fs.move_dir_inodes(
+            dir,
+            new,
+            Path::new("/data/dir"),
+            Path::new("/data/new/moved"),
+        );
+        assert_eq!(host_path(&wasi_fs, dir), Path::new("/data/new/moved"));
+        assert_eq!(host_path(&wasi_fs, file), Path::new("/data/new/moved/file"));
+        match &wasi_fs.inodes[dir].kind {
+            Kind::Dir { parent, .. } => assert_eq!(*parent, Some(new)),
+            kind => panic!("unexpected kind {:?}", kind),
+        }
+        match &wasi_fs.inodes[link].kind {
+            Kind::Symlink {
+                base_po_dir,
+                path_to_symlink,
+                ..
+            } => {
+                assert_eq!(*base_po_dir, fd);
+                assert_eq!(path_to_symlink, Path::new("new/moved/link"));
+            }
+            kind => panic!("unexpected kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn components_after_a_directory_symlink() {
+        let (mut wasi_fs, fd) = wasi_fs_with_symlinks(&[("dir", "/data/link")], MAX_SYMLINKS);
+        wasi_fs
+            .fs_backing
+            .open(Path::new("/data/dir/file"), OpenOptions::new().write(true))
+            .unwrap()
+            .write_all(b"contents")
+            .unwrap();
+
+        for &follow_symlinks in &[false, true] {
+            let inode = wasi_fs
+                .get_inode_at_path(fd, "link/file", follow_symlinks)
+                .unwrap();
+            assert_eq!(host_path(&wasi_fs, inode), Path::new("/data/dir/file"));
+            let mut contents = String::new();
+            wasi_fs
+                .fs_backing
+                .open(&host_path(&wasi_fs, inode), OpenOptions::new().read(true))
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            assert_eq!(contents, "contents");
+        }
+    }
+
+    #[test]
+    fn symlink_depth_limit() {
+        let symlinks = [("dir/file", "/data/one"), ("one", "/data/two")];
+        let (mut wasi_fs, fd) = wasi_fs_with_symlinks(&symlinks, 2);
+        assert!(wasi_fs.get_inode_at_path(fd, "two", true).is_ok());
+        let (mut wasi_fs, fd) = wasi_fs_with_symlinks(&symlinks, 1);
+        assert_eq!(
+            wasi_fs.get_inode_at_path(fd, "two", true),
+            Err(__WASI_ELOOP)
+        );
+
+        let (mut wasi_fs, fd) = wasi_fs_with_symlinks(&[("loop", "/data/loop")], MAX_SYMLINKS);
+        assert_eq!(
+            wasi_fs.get_inode_at_path(fd, "loop", true),
+            Err(__WASI_ELOOP)
+        );
     }
 }
@@ -0,0 +1,447 @@
+//! A [`FileSystem`] layering a writable filesystem on top of a read-only one.
+
+use crate::state::{
+    DirEntry, FileSystem, Metadata, OpenOptions, VirtualClock, WasiFile, WasiFsError,
+};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A union of two [`FileSystem`]s below `root`, in the style of overlayfs.
+///
+/// Below `root` the `lower` filesystem is never modified: it's read from
+/// until an entry gets written to, at which point the entry is copied up
+/// into the `upper` filesystem and all further operations go there.
+/// Deleting an entry that exists in `lower` records a whiteout which hides
+/// it (and everything below it) from then on.  Both filesystems are
+/// addressed with the same paths.
+///
+/// Paths outside of `root` are passed through to `lower` unchanged, so
+/// overlays for several directories can be stacked on top of each other.
+/// This is what [`PreopenDirBuilder::overlay`] does.
+///
+/// Symlinks and special files in `lower` can't be copied up, so renaming
+/// them (or a directory containing them) fails.
+///
+/// [`PreopenDirBuilder::overlay`]: super::PreopenDirBuilder::overlay
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OverlayFileSystem {
+    lower: Box<dyn FileSystem>,
+    upper: Box<dyn FileSystem>,
+    root: PathBuf,
+    whiteouts: Mutex<HashSet<PathBuf>>,
+}
+
+impl OverlayFileSystem {
+    /// Layer `upper` on top of `lower` for every path inside of `root`.
+    pub fn new<P: Into<PathBuf>>(
+        lower: Box<dyn FileSystem>,
+        upper: Box<dyn FileSystem>,
+        root: P,
+    ) -> Self {
+        Self {
+            lower,
+            upper,
+            root: root.into(),
+            whiteouts: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn in_overlay(&self, path: &Path) -> bool {
+        path.starts_with(&self.root)
+    }
+
+    /// Whether `path` in the lower layer has been hidden by a whiteout.
+    fn is_whited_out(&self, path: &Path) -> bool {
+        let whiteouts = self.whiteouts.lock().unwrap();
+        path.ancestors()
+            .any(|ancestor| whiteouts.contains(ancestor))
+    }
+
+    /// Hide `path` in the lower layer, if there's anything to hide.
+    fn whiteout(&self, path: &Path) {
+        if self.lower.symlink_metadata(path).is_ok() {
+            self.whiteouts.lock().unwrap().insert(path.to_path_buf());
+        }
+    }
+
+    fn in_lower(&self, path: &Path) -> bool {
+        !self.is_whited_out(path) && self.lower.symlink_metadata(path).is_ok()
+    }
+
+    /// Run `op` on the layer `path` is visible in, looking in the upper layer first.
+    fn lookup<T>(
+        &self,
+        path: &Path,
+        op: impl Fn(&dyn FileSystem) -> Result<T, WasiFsError>,
+    ) -> Result<T, WasiFsError> {
+        if !self.in_overlay(path) {
+            return op(&*self.lower);
+        }
+        match op(&*self.upper) {
+            Ok(value) => Ok(value),
+            Err(_) if self.is_whited_out(path) => Err(WasiFsError::EntityNotFound),
+            Err(_) => op(&*self.lower),
+        }
+    }
+
+    /// Make sure that `dir` and all of its ancestors exist in the upper layer.
+    fn create_upper_dirs(&self, dir: &Path) -> Result<(), WasiFsError> {
+        let mut current = PathBuf::new();
+        for component in dir.components() {
+            current.push(component);
+            match self.upper.metadata(&current) {
+                Ok(metadata) if metadata.is_dir() => (),
+                Ok(_) => return Err(WasiFsError::BaseNotDirectory),
+                Err(_) => match self.upper.create_dir(&current) {
+                    Ok(()) | Err(WasiFsError::AlreadyExists) => (),
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Make sure the parent of `path` is a directory and exists in the upper layer.
+    fn prepare_upper_parent(&self, path: &Path) -> Result<(), WasiFsError> {
+        let parent = path.parent().ok_or(WasiFsError::InvalidInput)?;
+        if !self.metadata(parent)?.is_dir() {
+            return Err(WasiFsError::BaseNotDirectory);
+        }
+        self.create_upper_dirs(parent)
+    }
+
+    /// Copy the contents of the file at `from` to `to` in the upper layer.
+    fn copy_up_file(&self, from: &Path, to: &Path) -> Result<(), WasiFsError> {
+        let mut source = self.lookup(from, |fs| fs.open(from, OpenOptions::new().read(true)))?;
+        let mut destination = self.upper.open(
+            to,
+            OpenOptions::new().write(true).create(true).truncate(true),
+        )?;
+        io::copy(&mut source, &mut destination)?;
+        Ok(())
+    }
+
+    /// Recursively copy the entry at `from`, as currently visible, to `to`
+    /// in the upper layer.
+    fn copy_up(&self, from: &Path, to: &Path) -> Result<(), WasiFsError> {
+        let metadata = self.symlink_metadata(from)?;
+        if metadata.is_dir() {
+            self.upper.create_dir(to)?;
+            for entry in self.read_dir(from)? {
+                self.copy_up(&from.join(&entry.name), &to.join(&entry.name))?;
+            }
+            Ok(())
+        } else if metadata.is_file() {
+            self.copy_up_file(from, to)
+        } else {
+            Err(WasiFsError::UnknownError(__WASI_ENOTSUP))
+        }
+    }
+
+    /// Recursively remove `path` from the upper layer.
+    fn remove_upper_all(&self, path: &Path) -> Result<(), WasiFsError> {
+        if self.upper.symlink_metadata(path)?.is_dir() {
+            for entry in self.upper.read_dir(path)? {
+                self.remove_upper_all(&path.join(&entry.name))?;
+            }
+            self.upper.remove_dir(path)
+        } else {
+            self.upper.remove_file(path)
+        }
+    }
+}
+
+#[typetag::serde]
+impl FileSystem for OverlayFileSystem {
+    fn metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        self.lookup(path, |fs| fs.metadata(path))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        self.lookup(path, |fs| fs.symlink_metadata(path))
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf, WasiFsError> {
+        self.lookup(path, |fs| fs.read_link(path))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, WasiFsError> {
+        if !self.in_overlay(path) {
+            return self.lower.read_dir(path);
+        }
+        let upper_entries = self.upper.read_dir(path);
+        if self.is_whited_out(path)
+            || (self.upper.symlink_metadata(path).is_ok() && upper_entries.is_err())
+        {
+            return upper_entries;
+        }
+        let lower_entries = self.lower.read_dir(path);
+        let (mut entries, lower_entries) = match (upper_entries, lower_entries) {
+            (Ok(upper_entries), Ok(lower_entries)) => (upper_entries, lower_entries),
+            (Ok(upper_entries), Err(_)) => return Ok(upper_entries),
+            (Err(_), Ok(lower_entries)) => (Vec::new(), lower_entries),
+            (Err(_), Err(e)) => return Err(e),
+        };
+        let shadowed: HashSet<String> = entries.iter().map(|entry| entry.name.clone()).collect();
+        entries.extend(lower_entries.into_iter().filter(|entry| {
+            !shadowed.contains(&entry.name) && !self.is_whited_out(&path.join(&entry.name))
+        }));
+        Ok(entries)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        if !self.in_overlay(path) {
+            return self.lower.create_dir(path);
+        }
+        if self.symlink_metadata(path).is_ok() {
+            return Err(WasiFsError::AlreadyExists);
+        }
+        self.prepare_upper_parent(path)?;
+        self.upper.create_dir(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        if !self.in_overlay(path) {
+            return self.lower.remove_dir(path);
+        }
+        if !self.symlink_metadata(path)?.is_dir() {
+            return Err(WasiFsError::BaseNotDirectory);
+        }
+        if !self.read_dir(path)?.is_empty() {
+            return Err(WasiFsError::UnknownError(__WASI_ENOTEMPTY));
+        }
+        if self.upper.symlink_metadata(path).is_ok() {
+            self.upper.remove_dir(path)?;
+        }
+        self.whiteout(path);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), WasiFsError> {
+        if !self.in_overlay(path) {
+            return self.lower.remove_file(path);
+        }
+        if self.symlink_metadata(path)?.is_dir() {
+            return Err(WasiFsError::UnknownError(__WASI_EISDIR));
+        }
+        if self.upper.symlink_metadata(path).is_ok() {
+            self.upper.remove_file(path)?;
+        }
+        self.whiteout(path);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), WasiFsError> {
+        match (self.in_overlay(from), self.in_overlay(to)) {
+            (false, false) => return self.lower.rename(from, to),
+            (true, true) => (),
+            _ => return Err(WasiFsError::UnknownError(__WASI_EXDEV)),
+        }
+        let metadata = self.symlink_metadata(from)?;
+        if from == to {
+            return Ok(());
+        }
+        if metadata.is_dir() && to.starts_with(from) {
+            return Err(WasiFsError::InvalidInput);
+        }
+        if let Ok(target) = self.symlink_metadata(to) {
+            match (metadata.is_dir(), target.is_dir()) {
+                (true, true) => self.remove_dir(to)?,
+                (false, false) => self.remove_file(to)?,
+                (true, false) => return Err(WasiFsError::BaseNotDirectory),
+                (false, true) => return Err(WasiFsError::UnknownError(__WASI_EISDIR)),
+            }
+        }
+        self.prepare_upper_parent(to)?;
+
+        if !self.in_lower(from) {
+            return self.upper.rename(from, to);
+        }
+        self.copy_up(from, to)?;
+        if self.upper.symlink_metadata(from).is_ok() {
+            self.remove_upper_all(from)?;
+        }
+        self.whiteout(from);
+        Ok(())
+    }
+
+    fn open(&self, path: &Path, options: &OpenOptions) -> Result<Box<dyn WasiFile>, WasiFsError> {
+        if !self.in_overlay(path) || self.upper.symlink_metadata(path).is_ok() {
+            return self.lookup(path, |fs| fs.open(path, options));
+        }
+        let writing = options.get_write()
+            || options.get_append()
+            || options.get_truncate()
+            || options.get_create()
+            || options.get_create_new();
+        let lower_metadata = if self.is_whited_out(path) {
+            None
+        } else {
+            self.lower.metadata(path).ok()
+        };
+
+        match lower_metadata {
+            Some(_) if !writing => self.lower.open(path, options),
+            Some(_) if options.get_create_new() => Err(WasiFsError::AlreadyExists),
+            Some(metadata) if metadata.is_dir() => Err(WasiFsError::NotAFile),
+            Some(_) => {
+                self.prepare_upper_parent(path)?;
+                if !options.get_truncate() {
+                    self.copy_up_file(path, path)?;
+                }
+                self.upper.open(path, options.clone().create(true))
+            }
+            None if options.get_create() || options.get_create_new() => {
+                self.prepare_upper_parent(path)?;
+                self.upper.open(path, options)
+            }
+            None => Err(WasiFsError::EntityNotFound),
+        }
+    }
+
+    fn set_clock(&mut self, clock: VirtualClock) {
+        self.lower.set_clock(clock.clone());
+        self.upper.set_clock(clock);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::MemFileSystem;
+    use std::io::{Read, Write};
+
+    fn write_file(fs: &dyn FileSystem, path: &str, contents: &[u8]) {
+        let mut file = fs
+            .open(
+                Path::new(path),
+                OpenOptions::new().write(true).create(true).truncate(true),
+            )
+            .unwrap();
+        file.write_all(contents).unwrap();
+    }
+
+    fn read_file(fs: &dyn FileSystem, path: &str) -> Result<Vec<u8>, WasiFsError> {
+        let mut file = fs.open(Path::new(path), OpenOptions::new().read(true))?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        Ok(contents)
+    }
+
+    fn names(mut entries: Vec<DirEntry>) -> Vec<String> {
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries.into_iter().map(|entry| entry.name).collect()
+    }
+
+    /// An overlay on `/lib` of a lower layer with a few files, and a handle to
+    /// the lower layer to check it's never modified.
+    fn overlay() -> (OverlayFileSystem, MemFileSystem) {
+        let lower = MemFileSystem::new();
+        lower.create_dir(Path::new("/lib")).unwrap();
+        lower.create_dir(Path::new("/lib/dir")).unwrap();
+        write_file(&lower, "/lib/a", b"lower a");
+        write_file(&lower, "/lib/dir/b", b"lower b");
+        write_file(&lower, "/outside", b"outside");
+        let fs = OverlayFileSystem::new(
+            Box::new(lower.clone()),
+            Box::new(MemFileSystem::new()),
+            "/lib",
+        );
+        (fs, lower)
+    }
+
+    #[test]
+    fn copy_up_on_write() {
+        let (fs, lower) = overlay();
+        assert_eq!(read_file(&fs, "/lib/a").unwrap(), b"lower a");
+
+        let mut file = fs
+            .open(Path::new("/lib/a"), OpenOptions::new().append(true))
+            .unwrap();
+        file.write_all(b" and upper").unwrap();
+        assert_eq!(read_file(&fs, "/lib/a").unwrap(), b"lower a and upper");
+        assert_eq!(read_file(&lower, "/lib/a").unwrap(), b"lower a");
+
+        write_file(&fs, "/lib/dir/new", b"new");
+        assert_eq!(read_file(&fs, "/lib/dir/new").unwrap(), b"new");
+        assert!(lower.metadata(Path::new("/lib/dir/new")).is_err());
+        assert_eq!(
+            names(fs.read_dir(Path::new("/lib/dir")).unwrap()),
+            vec!["b", "new"]
+        );
+        assert_eq!(
+            fs.open(
+                Path::new("/lib/dir/b"),
+                OpenOptions::new().write(true).create_new(true)
+            )
+            .map(|_| ()),
+            Err(WasiFsError::AlreadyExists)
+        );
+
+        // paths outside of the overlay go straight to the lower layer
+        write_file(&fs, "/outside", b"changed");
+        assert_eq!(read_file(&lower, "/outside").unwrap(), b"changed");
+    }
+
+    #[test]
+    fn whiteouts() {
+        let (fs, lower) = overlay();
+        fs.remove_file(Path::new("/lib/a")).unwrap();
+        assert_eq!(read_file(&fs, "/lib/a"), Err(WasiFsError::EntityNotFound));
+        assert_eq!(names(fs.read_dir(Path::new("/lib")).unwrap()), vec!["dir"]);
+        assert!(lower.metadata(Path::new("/lib/a")).is_ok());
+
+        // a file recreated over a whiteout doesn't see the old contents
+        fs.open(
+            Path::new("/lib/a"),
+            OpenOptions::new().write(true).create_new(true),
+        )
+        .unwrap();
+        assert_eq!(read_file(&fs, "/lib/a").unwrap(), b"");
+
+        assert_eq!(
+            fs.remove_dir(Path::new("/lib/dir")),
+            Err(WasiFsError::UnknownError(__WASI_ENOTEMPTY))
+        );
+        fs.remove_file(Path::new("/lib/dir/b")).unwrap();
+        fs.remove_dir(Path::new("/lib/dir")).unwrap();
+        assert!(fs.metadata(Path::new("/lib/dir")).is_err());
+
+        // a directory recreated over a whiteout is empty
+        fs.create_dir(Path::new("/lib/dir")).unwrap();
+        assert!(fs.read_dir(Path::new("/lib/dir")).unwrap().is_empty());
+        assert_eq!(
+            read_file(&fs, "/lib/dir/b"),
+            Err(WasiFsError::EntityNotFound)
+        );
+        assert!(lower.metadata(Path::new("/lib/dir/b")).is_ok());
+    }
+
+    #[test]
+    fn rename() {
+        let (fs, lower) = overlay();
+        fs.rename(Path::new("/lib/dir"), Path::new("/lib/moved"))
+            .unwrap();
+        assert_eq!(read_file(&fs, "/lib/moved/b").unwrap(), b"lower b");
+        assert!(fs.metadata(Path::new("/lib/dir")).is_err());
+        assert_eq!(
+            names(fs.read_dir(Path::new("/lib")).unwrap()),
+            vec!["a", "moved"]
+        );
+        assert!(lower.metadata(Path::new("/lib/dir/b")).is_ok());
+
+        fs.rename(Path::new("/lib/moved/b"), Path::new("/lib/a"))
+            .unwrap();
+        assert_eq!(read_file(&fs, "/lib/a").unwrap(), b"lower b");
+        assert!(fs.read_dir(Path::new("/lib/moved")).unwrap().is_empty());
+
+        assert_eq!(
+            fs.rename(Path::new("/lib/a"), Path::new("/outside")),
+            Err(WasiFsError::UnknownError(__WASI_EXDEV))
+        );
+    }
+}
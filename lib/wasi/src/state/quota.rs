@@ -0,0 +1,270 @@
+use crate::state::{Inode, Kind, WasiFs};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Limits on the resources a WASI program can consume through its syscalls,
+/// set with [`WasiStateBuilder::quotas`](super::WasiStateBuilder::quotas).
+///
+/// Every limit is `None`, meaning unlimited, by default.  Syscalls that
+/// would go over a limit fail with `__WASI_EDQUOT`, or `__WASI_EMFILE` for
+/// the number of open file descriptors, without doing anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasiQuotas {
+    /// The maximum number of open file descriptors, including stdio and the
+    /// preopened directories.
+    pub max_open_fds: Option<u32>,
+    /// The maximum number of bytes written to the files in a preopened
+    /// directory, for each preopened directory.  Overwriting data counts
+    /// too: this is the total of everything written since the start.
+    pub max_bytes_written_per_preopen: Option<u64>,
+    /// The maximum size a file can be grown to.
+    pub max_file_size: Option<u64>,
+    /// The maximum number of files, directories and links created.
+    pub max_entries_created: Option<u64>,
+    /// The maximum number of bytes written to stdout.
+    pub max_stdout_bytes: Option<u64>,
+    /// The maximum number of bytes written to stderr.
+    pub max_stderr_bytes: Option<u64>,
+}
+
+/// The resources consumed so far by a WASI program, counted against its
+/// [`WasiQuotas`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasiUsage {
+    /// The number of bytes written to files, by preopened directory fd.
+    pub bytes_written: HashMap<__wasi_fd_t, u64>,
+    /// The number of files, directories and links created.
+    pub entries_created: u64,
+    /// The number of bytes written to stdout.
+    pub stdout_bytes: u64,
+    /// The number of bytes written to stderr.
+    pub stderr_bytes: u64,
+}
+
+/// Takes back `amount` from `used`.
+fn refund(used: &mut u64, amount: u64) {
+    *used = used.saturating_sub(amount);
+}
+
+/// Adds `amount` to `used` if it stays within `limit`.
+fn charge(used: &mut u64, amount: u64, limit: Option<u64>) -> Result<(), __wasi_errno_t> {
+    let total = used.saturating_add(amount);
+    match limit {
+        Some(limit) if total > limit => Err(__WASI_EDQUOT),
+        _ => {
+            *used = total;
+            Ok(())
+        }
+    }
+}
+
+impl WasiFs {
+    /// Fails with `__WASI_EMFILE` if no more file descriptors may be opened.
+    pub(crate) fn check_open_fds(&self) -> Result<(), __wasi_errno_t> {
+        match self.quotas.max_open_fds {
+            Some(max) if self.fd_map.len() >= max as usize => Err(__WASI_EMFILE),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts `len` bytes written to stdout or stderr.
+    pub(crate) fn charge_output(
+        &mut self,
+        fd: __wasi_fd_t,
+        len: u64,
+    ) -> Result<(), __wasi_errno_t> {
+        match fd {
+            __WASI_STDOUT_FILENO => charge(
+                &mut self.usage.stdout_bytes,
+                len,
+                self.quotas.max_stdout_bytes,
+            ),
+            __WASI_STDERR_FILENO => charge(
+                &mut self.usage.stderr_bytes,
+                len,
+                self.quotas.max_stderr_bytes,
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Takes back `len` bytes counted by [`charge_output`](Self::charge_output)
+    /// that couldn't be written.
+    pub(crate) fn refund_output(&mut self, fd: __wasi_fd_t, len: u64) {
+        match fd {
+            __WASI_STDOUT_FILENO => refund(&mut self.usage.stdout_bytes, len),
+            __WASI_STDERR_FILENO => refund(&mut self.usage.stderr_bytes, len),
+            _ => (),
+        }
+    }
+
+    /// Counts `len` bytes written at `offset` in the file of `inode`.  Only
+    /// files of the [`FileSystem`](super::FileSystem) are counted, not
+    /// special files or buffers.
+    pub(crate) fn charge_write(
+        &mut self,
+        inode: Inode,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), __wasi_errno_t> {
+        let inode_val = &self.inodes[inode];
+        let path = match &inode_val.kind {
+            Kind::File { path, fd: None, .. } => path,
+            _ => return Ok(()),
+        };
+        if let Some(max_file_size) = self.quotas.max_file_size {
+            let end = offset.saturating_add(len);
+            if end > max_file_size && end > inode_val.stat.st_size {
+                return Err(__WASI_EDQUOT);
+            }
+        }
+        if let Some(preopen) = self.preopen_of(path) {
+            let used = self.usage.bytes_written.entry(preopen).or_insert(0);
+            charge(used, len, self.quotas.max_bytes_written_per_preopen)?;
+        }
+        Ok(())
+    }
+
+    /// Takes back `len` bytes counted by [`charge_write`](Self::charge_write)
+    /// that couldn't be written.
+    pub(crate) fn refund_write(&mut self, inode: Inode, len: u64) {
+        let path = match &self.inodes[inode].kind {
+            Kind::File { path, fd: None, .. } => path,
+            _ => return,
+        };
+        if let Some(preopen) = self.preopen_of(path) {
+            if let Some(used) = self.usage.bytes_written.get_mut(&preopen) {
+                refund(used, len);
+            }
+        }
+    }
+
+    /// Counts the creation of a file, directory or link.
+    pub(crate) fn charge_entry_created(&mut self) -> Result<(), __wasi_errno_t> {
+        charge(
+            &mut self.usage.entries_created,
+            1,
+            self.quotas.max_entries_created,
+        )
+    }
+
+    /// Takes back the creation of an entry that failed after being counted.
+    pub(crate) fn refund_entry_created(&mut self) {
+        refund(&mut self.usage.entries_created, 1);
+    }
+
+    /// Returns the fd of the innermost preopened directory containing the
+    /// host path `path`.
+    pub(crate) fn preopen_of(&self, path: &Path) -> Option<__wasi_fd_t> {
+        self.preopen_fds
+            .iter()
+            .filter_map(|fd| {
+                let inode = self.fd_map.get(fd)?.inode;
+                match &self.inodes[inode].kind {
+                    Kind::Dir {
+                        path: preopen_path, ..
+                    } if path.starts_with(preopen_path) => {
+                        Some((*fd, preopen_path.components().count()))
+                    }
+                    _ => None,
+                }
+            })
+            .max_by_key(|(_, depth)| *depth)
+            .map(|(fd, _)| fd)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{FileSystem, MemFileSystem, OpenOptions, WasiState, ALL_RIGHTS};
+
+    /// A WASI filesystem with `/a` and `/b` preopened from an in-memory
+    /// filesystem, each containing a file named `file`.
+    fn wasi_fs_with_quotas(quotas: WasiQuotas) -> WasiFs {
+        let fs = MemFileSystem::new();
+        for dir in &["/a", "/b"] {
+            fs.create_dir(Path::new(dir)).unwrap();
+            fs.open(
+                &Path::new(dir).join("file"),
+                OpenOptions::new().write(true).create(true),
+            )
+            .unwrap();
+        }
+
+        WasiState::new("test")
+            .set_fs(Box::new(fs))
+            .preopen_dir("/a")
+            .unwrap()
+            .preopen_dir("/b")
+            .unwrap()
+            .quotas(quotas)
+            .build()
+            .unwrap()
+            .fs
+    }
+
+    #[test]
+    fn output_quotas() {
+        let mut wasi_fs = wasi_fs_with_quotas(WasiQuotas {
+            max_stdout_bytes: Some(10),
+            ..WasiQuotas::default()
+        });
+        assert_eq!(wasi_fs.charge_output(__WASI_STDOUT_FILENO, 6), Ok(()));
+        assert_eq!(
+            wasi_fs.charge_output(__WASI_STDOUT_FILENO, 5),
+            Err(__WASI_EDQUOT)
+        );
+        assert_eq!(wasi_fs.charge_output(__WASI_STDOUT_FILENO, 4), Ok(()));
+        assert_eq!(wasi_fs.charge_output(__WASI_STDERR_FILENO, 100), Ok(()));
+        assert_eq!(wasi_fs.usage.stdout_bytes, 10);
+        assert_eq!(wasi_fs.usage.stderr_bytes, 100);
+
+        wasi_fs.refund_output(__WASI_STDOUT_FILENO, 4);
+        assert_eq!(wasi_fs.usage.stdout_bytes, 6);
+    }
+
+    #[test]
+    fn write_quotas() {
+        let mut wasi_fs = wasi_fs_with_quotas(WasiQuotas {
+            max_bytes_written_per_preopen: Some(10),
+            max_file_size: Some(8),
+            ..WasiQuotas::default()
+        });
+        let (a, b) = (wasi_fs.preopen_fds[1], wasi_fs.preopen_fds[2]);
+        let file_a = wasi_fs.get_inode_at_path(a, "file", false).unwrap();
+        let file_b = wasi_fs.get_inode_at_path(b, "file", false).unwrap();
+
+        assert_eq!(wasi_fs.charge_write(file_a, 4, 5), Err(__WASI_EDQUOT));
+        assert_eq!(wasi_fs.charge_write(file_a, 0, 8), Ok(()));
+        assert_eq!(wasi_fs.charge_write(file_a, 0, 3), Err(__WASI_EDQUOT));
+        assert_eq!(wasi_fs.charge_write(file_b, 0, 8), Ok(()));
+        assert_eq!(wasi_fs.usage.bytes_written.get(&a), Some(&8));
+        assert_eq!(wasi_fs.usage.bytes_written.get(&b), Some(&8));
+
+        wasi_fs.refund_write(file_b, 8);
+        assert_eq!(wasi_fs.usage.bytes_written.get(&b), Some(&0));
+        assert_eq!(wasi_fs.charge_write(file_b, 0, 8), Ok(()));
+    }
+
+    #[test]
+    fn entry_and_fd_quotas() {
+        let mut wasi_fs = wasi_fs_with_quotas(WasiQuotas {
+            max_entries_created: Some(1),
+            max_open_fds: Some(6),
+            ..WasiQuotas::default()
+        });
+        assert_eq!(wasi_fs.charge_entry_created(), Ok(()));
+        assert_eq!(wasi_fs.charge_entry_created(), Err(__WASI_EDQUOT));
+
+        // stdio, the virtual root and the two preopened directories
+        assert_eq!(wasi_fs.fd_map.len(), 6);
+        let inode = wasi_fs.fd_map[&wasi_fs.preopen_fds[1]].inode;
+        assert_eq!(
+            wasi_fs.create_fd(ALL_RIGHTS, ALL_RIGHTS, 0, 0, inode),
+            Err(__WASI_EMFILE)
+        );
+    }
+}
@@ -646,6 +646,12 @@ impl WasiFile for HostFile {
 
 impl From<io::Error> for WasiFsError {
     fn from(io_error: io::Error) -> Self {
+        if let Some(error) = io_error
+            .get_ref()
+            .and_then(|error| error.downcast_ref::<WasiFsError>())
+        {
+            return *error;
+        }
         match io_error.kind() {
             io::ErrorKind::AddrInUse => WasiFsError::AddressInUse,
             io::ErrorKind::AddrNotAvailable => WasiFsError::AddressNotAvailable,
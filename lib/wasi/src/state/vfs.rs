@@ -0,0 +1,227 @@
+//! The filesystem abstraction that [`WasiFs`] is built on top of.
+//!
+//! [`WasiFs`] only keeps track of the inodes and file descriptors that the
+//! WASI program knows about; every operation that needs to touch the actual
+//! files and directories is delegated to a [`FileSystem`].  By default this is
+//! a [`HostFileSystem`], which forwards everything to `std::fs`, but it can be
+//! replaced with [`WasiStateBuilder::set_fs`], for example with a
+//! [`MemFileSystem`] to make sure the WASI program never touches the host's
+//! disk.
+//!
+//! [`WasiFs`]: super::WasiFs
+//! [`HostFileSystem`]: super::HostFileSystem
+//! [`MemFileSystem`]: super::MemFileSystem
+//! [`WasiStateBuilder::set_fs`]: super::WasiStateBuilder::set_fs
+
+use crate::state::{VirtualClock, WasiFile, WasiFsError};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A backing store for the files and directories exposed through WASI.
+///
+/// Paths given to the methods of this trait are the paths stored in the
+/// inodes of [`WasiFs`](super::WasiFs), that is the preopened directory's path
+/// joined with the path traversed from it.  Sandboxing is done by `WasiFs`
+/// before calling into the `FileSystem`.
+#[typetag::serde(tag = "type")]
+pub trait FileSystem: fmt::Debug + Send + 'static {
+    /// Get the metadata of the entry at `path`, following symlinks.
+    fn metadata(&self, path: &Path) -> Result<Metadata, WasiFsError>;
+
+    /// Get the metadata of the entry at `path` without following a symlink
+    /// in the last component.
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata, WasiFsError>;
+
+    /// Read the value of the symlink at `path`.
+    fn read_link(&self, path: &Path) -> Result<PathBuf, WasiFsError>;
+
+    /// List the entries of the directory at `path`.  The order of the entries
+    /// is unspecified.
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, WasiFsError>;
+
+    /// Create a new, empty directory at `path`.
+    fn create_dir(&self, path: &Path) -> Result<(), WasiFsError>;
+
+    /// Remove the empty directory at `path`.
+    fn remove_dir(&self, path: &Path) -> Result<(), WasiFsError>;
+
+    /// Remove the file or symlink at `path`.
+    fn remove_file(&self, path: &Path) -> Result<(), WasiFsError>;
+
+    /// Move the entry at `from` to `to`, replacing `to` if it's a file.
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), WasiFsError>;
+
+    /// Open the file at `path` as described by `options`.
+    fn open(&self, path: &Path, options: &OpenOptions) -> Result<Box<dyn WasiFile>, WasiFsError>;
+
+    /// Use `clock` instead of the host's clock for the times of the entries
+    /// from now on.  Filesystems whose times aren't under their control,
+    /// like the host's, ignore it.
+    fn set_clock(&mut self, _clock: VirtualClock) {}
+}
+
+/// The type of an entry in a [`FileSystem`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileType {
+    Dir,
+    File,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+    /// Anything that doesn't fit in the other variants.
+    Unknown,
+}
+
+impl FileType {
+    pub fn is_dir(self) -> bool {
+        self == FileType::Dir
+    }
+
+    pub fn is_file(self) -> bool {
+        self == FileType::File
+    }
+
+    pub fn is_symlink(self) -> bool {
+        self == FileType::Symlink
+    }
+
+    /// The WASI file type that this file type is exposed as.
+    pub fn to_wasi_file_type(self) -> __wasi_filetype_t {
+        match self {
+            FileType::Dir => __WASI_FILETYPE_DIRECTORY,
+            FileType::File => __WASI_FILETYPE_REGULAR_FILE,
+            FileType::Symlink => __WASI_FILETYPE_SYMBOLIC_LINK,
+            FileType::CharDevice => __WASI_FILETYPE_CHARACTER_DEVICE,
+            FileType::BlockDevice => __WASI_FILETYPE_BLOCK_DEVICE,
+            // FIFO doesn't seem to fit any other type, so unknown
+            FileType::Fifo => __WASI_FILETYPE_UNKNOWN,
+            // TODO: how do we know if it's a `__WASI_FILETYPE_SOCKET_STREAM` or
+            // a `__WASI_FILETYPE_SOCKET_DGRAM`?
+            FileType::Socket => __WASI_FILETYPE_SOCKET_STREAM,
+            FileType::Unknown => __WASI_FILETYPE_UNKNOWN,
+        }
+    }
+}
+
+/// Metadata about an entry in a [`FileSystem`].
+///
+/// Timestamps are in nanoseconds since the UNIX epoch; `0` means unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub file_type: FileType,
+    pub len: u64,
+    pub accessed: __wasi_timestamp_t,
+    pub modified: __wasi_timestamp_t,
+    pub created: __wasi_timestamp_t,
+}
+
+impl Metadata {
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_dir()
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type.is_file()
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type.is_symlink()
+    }
+
+    /// Convert the metadata into a WASI filestat.  The inode number is left
+    /// unset as it's managed by [`WasiFs`](super::WasiFs).
+    pub fn to_filestat(&self) -> __wasi_filestat_t {
+        __wasi_filestat_t {
+            st_filetype: self.file_type.to_wasi_file_type(),
+            st_size: self.len,
+            st_atim: self.accessed,
+            st_mtim: self.modified,
+            st_ctim: self.created,
+            ..__wasi_filestat_t::default()
+        }
+    }
+}
+
+/// An entry returned by [`FileSystem::read_dir`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// The name of the entry, without the path of the directory.
+    pub name: String,
+    pub file_type: FileType,
+}
+
+/// Options for [`FileSystem::open`], mirroring `std::fs::OpenOptions`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+}
+
+impl OpenOptions {
+    /// Create a blank set of options, all set to `false`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    pub fn get_read(&self) -> bool {
+        self.read
+    }
+
+    pub fn get_write(&self) -> bool {
+        self.write
+    }
+
+    pub fn get_append(&self) -> bool {
+        self.append
+    }
+
+    pub fn get_truncate(&self) -> bool {
+        self.truncate
+    }
+
+    pub fn get_create(&self) -> bool {
+        self.create
+    }
+
+    pub fn get_create_new(&self) -> bool {
+        self.create_new
+    }
+}
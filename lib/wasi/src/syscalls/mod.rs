@@ -17,9 +17,8 @@ use self::types::*;
 use crate::{
     ptr::{Array, WasmPtr},
     state::{
-        self, host_file_type_to_wasi_file_type, iterate_poll_events, poll, Fd, HostFile, Inode,
-        InodeVal, Kind, PollEvent, PollEventBuilder, WasiFile, WasiFsError, WasiState,
-        MAX_SYMLINKS,
+        self, iterate_poll_events, poll, Fd, Inode, InodeVal, Kind, OpenOptions, PollEvent,
+        PollEventBuilder, VirtualClock, WasiFile, WasiFsError, WasiState, MAX_SYMLINKS,
     },
     WasiEnv, WasiError,
 };
@@ -52,7 +51,10 @@ fn write_bytes_inner<T: Write>(
         let bytes = iov_inner.buf.deref(memory, 0, iov_inner.buf_len)?;
         write_loc
             .write_all(&bytes.iter().map(|b_cell| b_cell.get()).collect::<Vec<u8>>())
-            .map_err(|_| __WASI_EIO)?;
+            .map_err(|err| match err.get_ref() {
+                Some(error) if error.is::<WasiFsError>() => WasiFsError::from(err).into_wasi_err(),
+                _ => __WASI_EIO,
+            })?;
 
         // TODO: handle failure more accurately
         bytes_written += iov_inner.buf_len;
@@ -70,6 +72,14 @@ fn write_bytes<T: Write>(
     result
 }
 
+/// The total length of the buffers of `iovs_arr_cell`.
+fn total_iovs_len(iovs_arr_cell: &[Cell<__wasi_ciovec_t>]) -> u64 {
+    iovs_arr_cell
+        .iter()
+        .map(|iov| iov.get().buf_len as u64)
+        .sum()
+}
+
 fn read_bytes<T: Read>(
     mut reader: T,
     memory: &Memory,
@@ -117,7 +127,38 @@ fn write_buffer_array(
     __WASI_ESUCCESS
 }
 
-fn get_current_time_in_nanos() -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+/// The number of nanoseconds to sleep for the clock subscription
+/// `clock_info` of `poll_oneoff`, with the time read from the virtual clock
+/// `clock` if there's one.
+fn clock_subscription_timeout(
+    clock: Option<&VirtualClock>,
+    clock_info: &__wasi_subscription_clock_t,
+) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+    let now = match clock {
+        Some(clock) => clock.time(clock_info.clock_id)?,
+        None => {
+            let now = Cell::new(0);
+            let errno = platform_clock_time_get(clock_info.clock_id, clock_info.precision, &now);
+            if errno != __WASI_ESUCCESS {
+                return Err(errno);
+            }
+            now.get()
+        }
+    };
+    if clock_info.flags & __WASI_SUBSCRIPTION_CLOCK_ABSTIME != 0 {
+        Ok(clock_info.timeout.saturating_sub(now))
+    } else {
+        Ok(clock_info.timeout)
+    }
+}
+
+/// The current time, from the virtual clock `clock` if there's one.
+fn get_current_time_in_nanos(
+    clock: Option<&mut VirtualClock>,
+) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+    if let Some(clock) = clock {
+        return clock.read(__WASI_CLOCK_REALTIME);
+    }
     let now = std::time::SystemTime::now();
     let duration = now
         .duration_since(std::time::SystemTime::UNIX_EPOCH)
@@ -204,9 +245,14 @@ pub fn clock_res_get(
     resolution: WasmPtr<__wasi_timestamp_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::clock_res_get");
-    let memory = env.memory();
+    let (memory, state) = env.get_memory_and_wasi_state(0);
 
     let out_addr = wasi_try!(resolution.deref(memory));
+    if state.clock.is_some() {
+        // the virtual clock counts in nanoseconds
+        out_addr.set(1);
+        return __WASI_ESUCCESS;
+    }
     platform_clock_res_get(clock_id, out_addr)
 }
 
@@ -230,10 +276,15 @@ pub fn clock_time_get(
         "wasi::clock_time_get clock_id: {}, precision: {}",
         clock_id, precision
     );
-    let memory = env.memory();
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let out_addr = wasi_try!(time.deref(memory));
-    let result = platform_clock_time_get(clock_id, precision, out_addr);
+    let result = if let Some(clock) = state.clock.as_mut() {
+        out_addr.set(wasi_try!(clock.read(clock_id)));
+        __WASI_ESUCCESS
+    } else {
+        platform_clock_time_get(clock_id, precision, out_addr)
+    };
     debug!(
         "time: {} => {}",
         wasi_try!(time.deref(memory)).get(),
@@ -341,6 +392,12 @@ pub fn fd_allocate(
         return __WASI_EACCES;
     }
     let new_size = wasi_try!(offset.checked_add(len), __WASI_EINVAL);
+    let current_size = state.fs.inodes[inode].stat.st_size;
+    if new_size > current_size {
+        wasi_try!(state
+            .fs
+            .charge_write(inode, current_size, new_size - current_size));
+    }
 
     match &mut state.fs.inodes[inode].kind {
         Kind::File { handle, .. } => {
@@ -536,6 +593,12 @@ pub fn fd_filestat_set_size(
     if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_FILESTAT_SET_SIZE) {
         return __WASI_EACCES;
     }
+    let current_size = state.fs.inodes[inode].stat.st_size;
+    if st_size > current_size {
+        wasi_try!(state
+            .fs
+            .charge_write(inode, current_size, st_size - current_size));
+    }
 
     match &mut state.fs.inodes[inode].kind {
         Kind::File { handle, .. } => {
@@ -588,13 +651,19 @@ pub fn fd_filestat_set_times(
     }
 
     let inode_idx = fd_entry.inode;
+    // both times are set from the same reading of the clock
+    let now = if fst_flags & (__WASI_FILESTAT_SET_ATIM_NOW | __WASI_FILESTAT_SET_MTIM_NOW) != 0 {
+        wasi_try!(get_current_time_in_nanos(state.clock.as_mut()))
+    } else {
+        0
+    };
     let inode = &mut state.fs.inodes[inode_idx];
 
     if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 || fst_flags & __WASI_FILESTAT_SET_ATIM_NOW != 0 {
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 {
             st_atim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            now
         };
         inode.stat.st_atim = time_to_set;
         // TODO: set it for more than just files
@@ -609,7 +678,7 @@ pub fn fd_filestat_set_times(
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_MTIM != 0 {
             st_mtim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            now
         };
         inode.stat.st_mtim = time_to_set;
         // TODO: set it for more than just files
@@ -803,21 +872,35 @@ pub fn fd_pwrite(
     let bytes_written = match fd {
         __WASI_STDIN_FILENO => return __WASI_EINVAL,
         __WASI_STDOUT_FILENO => {
-            if let Some(ref mut stdout) =
-                wasi_try!(state.fs.stdout_mut().map_err(WasiFsError::into_wasi_err))
-            {
-                wasi_try!(write_bytes(stdout, memory, iovs_arr_cell))
-            } else {
-                return __WASI_EBADF;
+            let len = total_iovs_len(iovs_arr_cell);
+            wasi_try!(state.fs.charge_output(fd, len));
+            let written = match state.fs.stdout_mut().map_err(WasiFsError::into_wasi_err) {
+                Ok(Some(stdout)) => write_bytes(stdout, memory, iovs_arr_cell),
+                Ok(None) => Err(__WASI_EBADF),
+                Err(err) => Err(err),
+            };
+            match written {
+                Ok(bytes_written) => bytes_written,
+                Err(err) => {
+                    state.fs.refund_output(fd, len);
+                    return err;
+                }
             }
         }
         __WASI_STDERR_FILENO => {
-            if let Some(ref mut stderr) =
-                wasi_try!(state.fs.stderr_mut().map_err(WasiFsError::into_wasi_err))
-            {
-                wasi_try!(write_bytes(stderr, memory, iovs_arr_cell))
-            } else {
-                return __WASI_EBADF;
+            let len = total_iovs_len(iovs_arr_cell);
+            wasi_try!(state.fs.charge_output(fd, len));
+            let written = match state.fs.stderr_mut().map_err(WasiFsError::into_wasi_err) {
+                Ok(Some(stderr)) => write_bytes(stderr, memory, iovs_arr_cell),
+                Ok(None) => Err(__WASI_EBADF),
+                Err(err) => Err(err),
+            };
+            match written {
+                Ok(bytes_written) => bytes_written,
+                Err(err) => {
+                    state.fs.refund_output(fd, len);
+                    return err;
+                }
             }
         }
         _ => {
@@ -830,15 +913,17 @@ pub fn fd_pwrite(
             }
 
             let inode_idx = fd_entry.inode;
+            let len = total_iovs_len(iovs_arr_cell);
+            wasi_try!(state.fs.charge_write(inode_idx, offset, len));
             let inode = &mut state.fs.inodes[inode_idx];
 
-            match &mut inode.kind {
+            let written = match &mut inode.kind {
                 Kind::File { handle, .. } => {
                     if let Some(handle) = handle {
                         handle.seek(std::io::SeekFrom::Start(offset as u64));
-                        wasi_try!(write_bytes(handle, memory, iovs_arr_cell))
+                        write_bytes(handle, memory, iovs_arr_cell)
                     } else {
-                        return __WASI_EINVAL;
+                        Err(__WASI_EINVAL)
                     }
                 }
                 Kind::Dir { .. } | Kind::Root { .. } => {
@@ -847,11 +932,14 @@ pub fn fd_pwrite(
                 }
                 Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_pwrite"),
                 Kind::Buffer { buffer } => {
-                    wasi_try!(write_bytes(
-                        &mut buffer[(offset as usize)..],
-                        memory,
-                        iovs_arr_cell
-                    ))
+                    write_bytes(&mut buffer[(offset as usize)..], memory, iovs_arr_cell)
+                }
+            };
+            match written {
+                Ok(bytes_written) => bytes_written,
+                Err(err) => {
+                    state.fs.refund_write(inode_idx, len);
+                    return err;
                 }
             }
         }
@@ -982,17 +1070,17 @@ pub fn fd_readdir(
             // we need to support multiple calls,
             // simple and obviously correct implementation for now:
             // maintain consistent order via lexacographic sorting
-            let fs_info = wasi_try!(wasi_try!(std::fs::read_dir(path).map_err(|_| __WASI_EIO))
-                .collect::<Result<Vec<_>, _>>()
-                .map_err(|_| __WASI_EIO));
-            let mut entry_vec = wasi_try!(fs_info
+            let fs_info = wasi_try!(state.fs.fs_backing.read_dir(path).map_err(|_| __WASI_EIO));
+            let mut entry_vec = fs_info
                 .into_iter()
-                .map(|entry| Ok((
-                    entry.file_name().to_string_lossy().to_string(),
-                    host_file_type_to_wasi_file_type(entry.file_type().map_err(|_| __WASI_EIO)?),
-                    0, // TODO: inode
-                )))
-                .collect::<Result<Vec<(String, u8, u64)>, _>>());
+                .map(|entry| {
+                    (
+                        entry.name,
+                        entry.file_type.to_wasi_file_type(),
+                        0, // TODO: inode
+                    )
+                })
+                .collect::<Vec<(String, u8, u64)>>();
             entry_vec.extend(
                 entries
                     .iter()
@@ -1006,7 +1094,9 @@ pub fn fd_readdir(
                         )
                     }),
             );
-            entry_vec.sort_by(|a, b| a.0.cmp(&b.0));
+            // sort on every field, not only the name, so that the order
+            // doesn't depend on the order of the host's entries
+            entry_vec.sort();
             entry_vec
         }
         Kind::Root { entries } => {
@@ -1260,21 +1350,35 @@ pub fn fd_write(
     let bytes_written = match fd {
         __WASI_STDIN_FILENO => return __WASI_EINVAL,
         __WASI_STDOUT_FILENO => {
-            if let Some(ref mut stdout) =
-                wasi_try!(state.fs.stdout_mut().map_err(WasiFsError::into_wasi_err))
-            {
-                wasi_try!(write_bytes(stdout, memory, iovs_arr_cell))
-            } else {
-                return __WASI_EBADF;
+            let len = total_iovs_len(iovs_arr_cell);
+            wasi_try!(state.fs.charge_output(fd, len));
+            let written = match state.fs.stdout_mut().map_err(WasiFsError::into_wasi_err) {
+                Ok(Some(stdout)) => write_bytes(stdout, memory, iovs_arr_cell),
+                Ok(None) => Err(__WASI_EBADF),
+                Err(err) => Err(err),
+            };
+            match written {
+                Ok(bytes_written) => bytes_written,
+                Err(err) => {
+                    state.fs.refund_output(fd, len);
+                    return err;
+                }
             }
         }
         __WASI_STDERR_FILENO => {
-            if let Some(ref mut stderr) =
-                wasi_try!(state.fs.stderr_mut().map_err(WasiFsError::into_wasi_err))
-            {
-                wasi_try!(write_bytes(stderr, memory, iovs_arr_cell))
-            } else {
-                return __WASI_EBADF;
+            let len = total_iovs_len(iovs_arr_cell);
+            wasi_try!(state.fs.charge_output(fd, len));
+            let written = match state.fs.stderr_mut().map_err(WasiFsError::into_wasi_err) {
+                Ok(Some(stderr)) => write_bytes(stderr, memory, iovs_arr_cell),
+                Ok(None) => Err(__WASI_EBADF),
+                Err(err) => Err(err),
+            };
+            match written {
+                Ok(bytes_written) => bytes_written,
+                Err(err) => {
+                    state.fs.refund_output(fd, len);
+                    return err;
+                }
             }
         }
         _ => {
@@ -1284,17 +1388,24 @@ pub fn fd_write(
                 return __WASI_EACCES;
             }
 
-            let offset = fd_entry.offset as usize;
             let inode_idx = fd_entry.inode;
+            // appending writes go to the end of the file whatever the offset
+            let offset = if fd_entry.flags & __WASI_FDFLAG_APPEND != 0 {
+                state.fs.inodes[inode_idx].stat.st_size
+            } else {
+                fd_entry.offset
+            } as usize;
+            let len = total_iovs_len(iovs_arr_cell);
+            wasi_try!(state.fs.charge_write(inode_idx, offset as u64, len));
             let inode = &mut state.fs.inodes[inode_idx];
 
-            let bytes_written = match &mut inode.kind {
+            let written = match &mut inode.kind {
                 Kind::File { handle, .. } => {
                     if let Some(handle) = handle {
                         handle.seek(std::io::SeekFrom::Start(offset as u64));
-                        wasi_try!(write_bytes(handle, memory, iovs_arr_cell))
+                        write_bytes(handle, memory, iovs_arr_cell)
                     } else {
-                        return __WASI_EINVAL;
+                        Err(__WASI_EINVAL)
                     }
                 }
                 Kind::Dir { .. } | Kind::Root { .. } => {
@@ -1303,7 +1414,14 @@ pub fn fd_write(
                 }
                 Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_write"),
                 Kind::Buffer { buffer } => {
-                    wasi_try!(write_bytes(&mut buffer[offset..], memory, iovs_arr_cell))
+                    write_bytes(&mut buffer[offset..], memory, iovs_arr_cell)
+                }
+            };
+            let bytes_written = match written {
+                Ok(bytes_written) => bytes_written,
+                Err(err) => {
+                    state.fs.refund_write(inode_idx, len);
+                    return err;
                 }
             };
 
@@ -1392,19 +1510,35 @@ pub fn path_create_directory(
                     cur_dir_inode = *child;
                 } else {
                     let mut adjusted_path = path.clone();
+                    let mut created = false;
                     // TODO: double check this doesn't risk breaking the sandbox
                     adjusted_path.push(comp);
-                    if adjusted_path.exists() && !adjusted_path.is_dir() {
-                        return __WASI_ENOTDIR;
-                    } else if !adjusted_path.exists() {
-                        wasi_try!(std::fs::create_dir(&adjusted_path).ok(), __WASI_EIO);
+                    match state.fs.fs_backing.metadata(&adjusted_path) {
+                        Ok(metadata) if !metadata.is_dir() => return __WASI_ENOTDIR,
+                        Ok(_) => (),
+                        Err(_) => {
+                            wasi_try!(state.fs.charge_entry_created());
+                            if state.fs.fs_backing.create_dir(&adjusted_path).is_err() {
+                                state.fs.refund_entry_created();
+                                return __WASI_EIO;
+                            }
+                            created = true;
+                        }
                     }
                     let kind = Kind::Dir {
                         parent: Some(cur_dir_inode),
                         path: adjusted_path,
                         entries: Default::default(),
                     };
-                    let new_inode = wasi_try!(state.fs.create_inode(kind, false, comp.to_string()));
+                    let new_inode = match state.fs.create_inode(kind, false, comp.to_string()) {
+                        Ok(new_inode) => new_inode,
+                        Err(err) => {
+                            if created {
+                                state.fs.refund_entry_created();
+                            }
+                            return err;
+                        }
+                    };
                     // reborrow to insert
                     if let Kind::Dir {
                         ref mut entries, ..
@@ -1531,13 +1665,19 @@ pub fn path_filestat_set_times(
         .get_stat_for_kind(&state.fs.inodes[file_inode].kind)
         .ok_or(__WASI_EIO));
 
+    // both times are set from the same reading of the clock
+    let now = if fst_flags & (__WASI_FILESTAT_SET_ATIM_NOW | __WASI_FILESTAT_SET_MTIM_NOW) != 0 {
+        wasi_try!(get_current_time_in_nanos(state.clock.as_mut()))
+    } else {
+        0
+    };
     let inode = &mut state.fs.inodes[fd_inode];
 
     if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 || fst_flags & __WASI_FILESTAT_SET_ATIM_NOW != 0 {
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 {
             st_atim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            now
         };
         inode.stat.st_atim = time_to_set;
         // TODO: set it for more than just files
@@ -1551,7 +1691,7 @@ pub fn path_filestat_set_times(
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_MTIM != 0 {
             st_mtim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            now
         };
         inode.stat.st_mtim = time_to_set;
         // TODO: set it for more than just files
@@ -1626,16 +1766,19 @@ pub fn path_link(
     if state.fs.inodes[source_inode].stat.st_nlink == __wasi_linkcount_t::max_value() {
         return __WASI_EMLINK;
     }
-    match &mut state.fs.inodes[target_parent_inode].kind {
+    match &state.fs.inodes[target_parent_inode].kind {
         Kind::Dir { entries, .. } => {
             if entries.contains_key(&new_entry_name) {
                 return __WASI_EEXIST;
             }
-            entries.insert(new_entry_name, source_inode);
         }
         Kind::Root { .. } => return __WASI_EINVAL,
         Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } => return __WASI_ENOTDIR,
     }
+    wasi_try!(state.fs.charge_entry_created());
+    if let Kind::Dir { entries, .. } = &mut state.fs.inodes[target_parent_inode].kind {
+        entries.insert(new_entry_name, source_inode);
+    }
     state.fs.inodes[source_inode].stat.st_nlink += 1;
 
     __WASI_ESUCCESS
@@ -1720,12 +1863,8 @@ pub fn path_open(
     let adjusted_rights = /*fs_rights_base &*/ working_dir_rights_inheriting;
     let inode = if let Ok(inode) = maybe_inode {
         // Happy path, we found the file we're trying to open
-        match &mut state.fs.inodes[inode].kind {
-            Kind::File {
-                ref mut handle,
-                path,
-                fd,
-            } => {
+        match &state.fs.inodes[inode].kind {
+            Kind::File { handle, path, fd } => {
                 if let Some(special_fd) = fd {
                     // short circuit if we're dealing with a special file
                     assert!(handle.is_some());
@@ -1735,10 +1874,11 @@ pub fn path_open(
                 if o_flags & __WASI_O_DIRECTORY != 0 {
                     return __WASI_ENOTDIR;
                 }
-                if o_flags & __WASI_O_EXCL != 0 && path.exists() {
+                let path = path.clone();
+                if o_flags & __WASI_O_EXCL != 0 && state.fs.fs_backing.metadata(&path).is_ok() {
                     return __WASI_EEXIST;
                 }
-                let mut open_options = std::fs::OpenOptions::new();
+                let mut open_options = OpenOptions::new();
                 let write_permission = adjusted_rights & __WASI_RIGHT_FD_WRITE != 0;
                 // append, truncate, and create all require the permission to write
                 let (append_permission, truncate_permission, create_permission) =
@@ -1768,29 +1908,26 @@ pub fn path_open(
                 if o_flags & __WASI_O_TRUNC != 0 {
                     open_flags |= Fd::TRUNCATE;
                 }
-                *handle = Some(Box::new(HostFile::new(
-                    wasi_try!(open_options.open(&path).map_err(|_| __WASI_EIO)),
-                    path.to_path_buf(),
-                    true,
-                    adjusted_rights & __WASI_RIGHT_FD_WRITE != 0,
-                    false,
-                )));
+                let file = wasi_try!(state
+                    .fs
+                    .fs_backing
+                    .open(&path, open_options)
+                    .map_err(|_| __WASI_EIO));
+                if let Kind::File { handle, .. } = &mut state.fs.inodes[inode].kind {
+                    *handle = Some(file);
+                }
             }
             Kind::Buffer { .. } => unimplemented!("wasi::path_open for Buffer type files"),
             Kind::Dir { .. } | Kind::Root { .. } => {
                 // TODO: adjust these to be correct
-                if o_flags & __WASI_O_EXCL != 0 && path_arg.exists() {
+                if o_flags & __WASI_O_EXCL != 0 {
                     return __WASI_EEXIST;
                 }
             }
-            Kind::Symlink {
-                base_po_dir,
-                path_to_symlink,
-                relative_path,
-            } => {
-                // I think this should return an error (because symlinks should be resolved away by the path traversal)
-                // TODO: investigate this
-                unimplemented!("SYMLINKS IN PATH_OPEN");
+            Kind::Symlink { .. } => {
+                // symlinks are resolved away by the path traversal unless
+                // `__WASI_LOOKUP_SYMLINK_FOLLOW` isn't set
+                return __WASI_ELOOP;
             }
         }
         inode
@@ -1818,10 +1955,12 @@ pub fn path_open(
                 Kind::Root { .. } => return __WASI_EACCES,
                 _ => return __WASI_EINVAL,
             };
+            wasi_try!(state.fs.check_open_fds());
+            wasi_try!(state.fs.charge_entry_created());
             // once we got the data we need from the parent, we lookup the host file
             // todo: extra check that opening with write access is okay
             let handle = {
-                let mut open_options = std::fs::OpenOptions::new();
+                let mut open_options = OpenOptions::new();
                 let open_options = open_options
                     .read(true)
                     .append(fs_flags & __WASI_FDFLAG_APPEND != 0)
@@ -1831,16 +1970,14 @@ pub fn path_open(
                     .create_new(true);
                 open_flags |= Fd::READ | Fd::WRITE | Fd::CREATE | Fd::TRUNCATE;
 
-                Some(Box::new(HostFile::new(
-                    wasi_try!(open_options.open(&new_file_host_path).map_err(|e| {
+                match state.fs.fs_backing.open(&new_file_host_path, open_options) {
+                    Ok(file) => Some(file),
+                    Err(e) => {
                         debug!("Error opening file {}", e);
-                        __WASI_EIO
-                    })),
-                    new_file_host_path.clone(),
-                    true,
-                    true,
-                    true,
-                )) as Box<dyn WasiFile>)
+                        state.fs.refund_entry_created();
+                        return __WASI_EIO;
+                    }
+                }
             };
 
             let new_inode = {
@@ -1849,7 +1986,13 @@ pub fn path_open(
                     path: new_file_host_path,
                     fd: None,
                 };
-                wasi_try!(state.fs.create_inode(kind, false, new_entity_name.clone()))
+                match state.fs.create_inode(kind, false, new_entity_name.clone()) {
+                    Ok(new_inode) => new_inode,
+                    Err(err) => {
+                        state.fs.refund_entry_created();
+                        return err;
+                    }
+                }
             };
 
             if let Kind::Dir {
@@ -1969,7 +2112,7 @@ pub fn path_remove_directory(
     let host_path_to_remove = match &state.fs.inodes[inode].kind {
         Kind::Dir { entries, path, .. } => {
             if !entries.is_empty()
-                || wasi_try!(std::fs::read_dir(path).ok(), __WASI_EIO).count() != 0
+                || !wasi_try!(state.fs.fs_backing.read_dir(path).ok(), __WASI_EIO).is_empty()
             {
                 return __WASI_ENOTEMPTY;
             }
@@ -1993,7 +2136,12 @@ pub fn path_remove_directory(
         ),
     }
 
-    if std::fs::remove_dir(path_str).is_err() {
+    if state
+        .fs
+        .fs_backing
+        .remove_dir(&host_path_to_remove)
+        .is_err()
+    {
         // reinsert to prevent FS from being in bad state
         if let Kind::Dir {
             ref mut entries, ..
@@ -2065,7 +2213,7 @@ pub fn path_rename(
                 return __WASI_EEXIST;
             }
             let mut out_path = path.clone();
-            out_path.push(target_path);
+            out_path.push(&target_entry_name);
             out_path
         }
         Kind::Root { .. } => return __WASI_ENOTCAPABLE,
@@ -2081,20 +2229,22 @@ pub fn path_rename(
         }
     };
 
-    match &mut state.fs.inodes[source_entry].kind {
-        Kind::File {
-            handle,
-            ref mut path,
-            ..
-        } => {
-            let result = if let Some(h) = handle {
-                h.rename_file(&host_adjusted_target_path)
-                    .map_err(|e| e.into_wasi_err())
+    match &state.fs.inodes[source_entry].kind {
+        Kind::File { handle, path, .. } => {
+            let result = if path.as_os_str().is_empty() {
+                // a virtual file which isn't backed by the filesystem
+                match handle {
+                    Some(h) => h
+                        .rename_file(&host_adjusted_target_path)
+                        .map_err(|e| e.into_wasi_err()),
+                    None => Ok(()),
+                }
             } else {
-                let out =
-                    std::fs::rename(&path, &host_adjusted_target_path).map_err(|_| __WASI_EIO);
-                *path = host_adjusted_target_path;
-                out
+                state
+                    .fs
+                    .fs_backing
+                    .rename(path, &host_adjusted_target_path)
+                    .map_err(|_| __WASI_EIO)
             };
             // if the above operation failed we have to revert the previous change and then fail
             if let Err(e) = result {
@@ -2103,8 +2253,32 @@ pub fn path_rename(
                     return e;
                 }
             }
+            if let Kind::File { path, .. } = &mut state.fs.inodes[source_entry].kind {
+                if !path.as_os_str().is_empty() {
+                    *path = host_adjusted_target_path;
+                }
+            }
+        }
+        Kind::Dir { path, .. } => {
+            let path = path.clone();
+            let result = state
+                .fs
+                .fs_backing
+                .rename(&path, &host_adjusted_target_path);
+            // if the above operation failed we have to revert the previous change and then fail
+            if let Err(e) = result {
+                if let Kind::Dir { entries, .. } = &mut state.fs.inodes[source_parent_inode].kind {
+                    entries.insert(source_entry_name, source_entry);
+                    return e.into_wasi_err();
+                }
+            }
+            state.fs.move_dir_inodes(
+                source_entry,
+                target_parent_inode,
+                &path,
+                &host_adjusted_target_path,
+            );
         }
-        Kind::Dir { path, .. } => unimplemented!("wasi::path_rename on Directories"),
         Kind::Buffer { .. } => {}
         Kind::Symlink { .. } => {}
         Kind::Root { .. } => unreachable!("The root can not be moved"),
@@ -2173,6 +2347,8 @@ pub fn path_symlink(
         }
     }
 
+    wasi_try!(state.fs.charge_entry_created());
+
     let mut source_path = std::path::Path::new(old_path_str);
     let mut relative_path = std::path::PathBuf::new();
     for _ in 0..depth {
@@ -2255,13 +2431,18 @@ pub fn path_unlink_file(
     if state.fs.inodes[removed_inode].stat.st_nlink == 0 {
         match &mut state.fs.inodes[removed_inode].kind {
             Kind::File { handle, path, .. } => {
-                if let Some(h) = handle {
-                    wasi_try!(h.unlink().map_err(WasiFsError::into_wasi_err));
+                if path.as_os_str().is_empty() {
+                    // a virtual file which isn't backed by the filesystem
+                    if let Some(h) = handle {
+                        wasi_try!(h.unlink().map_err(WasiFsError::into_wasi_err));
+                    }
                 } else {
-                    // File is closed
-                    // problem with the abstraction, we can't call unlink because there's no handle
-                    // TODO: replace this code
-                    wasi_try!(std::fs::remove_file(path).map_err(|_| __WASI_EIO));
+                    let path = path.clone();
+                    wasi_try!(state
+                        .fs
+                        .fs_backing
+                        .remove_file(&path)
+                        .map_err(WasiFsError::into_wasi_err));
                 }
             }
             Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
@@ -2362,15 +2543,12 @@ pub fn poll_oneoff(
                 Some(fd)
             }
             EventType::Clock(clock_info) => {
-                if clock_info.clock_id == __WASI_CLOCK_REALTIME {
-                    // this is a hack
-                    // TODO: do this properly
-                    ns_to_sleep = clock_info.timeout;
-                    clock_subs.push(clock_info);
-                    None
-                } else {
-                    unimplemented!("Polling not implemented for clocks yet");
-                }
+                ns_to_sleep = wasi_try!(clock_subscription_timeout(
+                    state.clock.as_ref(),
+                    &clock_info
+                ));
+                clock_subs.push(s.user_data);
+                None
             }
         };
 
@@ -2420,8 +2598,11 @@ pub fn poll_oneoff(
             let remaining_ns = ns_to_sleep as i64 - total_ns_slept as i64;
             if remaining_ns > 0 {
                 debug!("Sleeping for {} nanoseconds", remaining_ns);
-                let duration = std::time::Duration::from_nanos(remaining_ns as u64);
-                std::thread::sleep(duration);
+                // the virtual clock is advanced below instead
+                if state.clock.is_none() {
+                    let duration = std::time::Duration::from_nanos(remaining_ns as u64);
+                    std::thread::sleep(duration);
+                }
                 total_ns_slept += remaining_ns;
             }
         }
@@ -2472,10 +2653,12 @@ pub fn poll_oneoff(
         event_array[events_seen].set(event);
         events_seen += 1;
     }
-    for clock_info in clock_subs {
+    if let Some(clock) = state.clock.as_mut() {
+        clock.advance(total_ns_slept as u64);
+    }
+    for userdata in clock_subs {
         let event = __wasi_event_t {
-            // TOOD: review userdata value
-            userdata: 0,
+            userdata,
             error: __WASI_ESUCCESS,
             type_: __WASI_EVENTTYPE_CLOCK,
             u: unsafe {
@@ -2514,12 +2697,15 @@ pub fn proc_raise(env: &WasiEnv, sig: __wasi_signal_t) -> __wasi_errno_t {
 ///     The number of bytes that will be written
 pub fn random_get(env: &WasiEnv, buf: WasmPtr<u8, Array>, buf_len: u32) -> __wasi_errno_t {
     debug!("wasi::random_get buf_len: {}", buf_len);
-    let memory = env.memory();
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let buf = wasi_try!(buf.deref(memory, 0, buf_len));
 
-    let res = unsafe {
-        let u8_buffer = &mut *(buf as *const [_] as *mut [_] as *mut [u8]);
+    let u8_buffer = unsafe { &mut *(buf as *const [_] as *mut [_] as *mut [u8]) };
+    let res = if let Some(rng) = state.rng.as_mut() {
+        rng.fill(u8_buffer);
+        Ok(())
+    } else {
         getrandom::getrandom(u8_buffer)
     };
     match res {
@@ -2563,3 +2749,159 @@ pub fn sock_shutdown(env: &WasiEnv, sock: __wasi_fd_t, how: __wasi_sdflags_t) ->
     debug!("wasi::sock_shutdown");
     unimplemented!("wasi::sock_shutdown")
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{FileSystem, MemFileSystem, OpenOptions, WasiQuotas};
+    use std::path::Path;
+    use wasmer::{Cranelift, Instance, Module, Store, JIT};
+
+    /// Polls the clock subscription `clock_info`, with the user data 42 and
+    /// a virtual clock starting at `start`, and returns the event and the
+    /// time of the virtual clock afterwards.
+    fn poll_clock(
+        start: __wasi_timestamp_t,
+        clock_info: __wasi_subscription_clock_t,
+    ) -> (__wasi_event_t, __wasi_timestamp_t) {
+        let store = Store::new(&JIT::new(Cranelift::default()).engine());
+        let module = Module::new(
+            &store,
+            r#"
+            (module
+              (import "wasi_snapshot_preview1" "poll_oneoff"
+                (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (func (export "poll") (param i32 i32 i32 i32) (result i32)
+                (call $poll_oneoff (local.get 0) (local.get 1) (local.get 2) (local.get 3))))
+            "#,
+        )
+        .unwrap();
+        let mut wasi_env = WasiState::new("poll")
+            .virtual_clock(start, 0)
+            .finalize()
+            .unwrap();
+        let import_object = wasi_env.import_object(&module).unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let memory = instance.exports.get_memory("memory").unwrap();
+
+        let subscription = WasmPtr::<__wasi_subscription_t>::new(0);
+        let event = WasmPtr::<__wasi_event_t>::new(64);
+        let nevents = WasmPtr::<u32>::new(128);
+        subscription
+            .deref(memory)
+            .unwrap()
+            .set(__wasi_subscription_t {
+                userdata: 42,
+                type_: __WASI_EVENTTYPE_CLOCK,
+                u: __wasi_subscription_u { clock: clock_info },
+            });
+        let errno = instance
+            .exports
+            .get_function("poll")
+            .unwrap()
+            .call(&[
+                Value::I32(0),
+                Value::I32(64),
+                Value::I32(1),
+                Value::I32(128),
+            ])
+            .unwrap()[0]
+            .unwrap_i32();
+        assert_eq!(errno, __WASI_ESUCCESS as i32);
+        assert_eq!(nevents.deref(memory).unwrap().get(), 1);
+
+        let event = event.deref(memory).unwrap().get();
+        let now = wasi_env.state().clock.as_ref().unwrap().now();
+        (event, now)
+    }
+
+    #[test]
+    fn monotonic_relative_sleep() {
+        let (event, now) = poll_clock(
+            1_000,
+            __wasi_subscription_clock_t {
+                clock_id: __WASI_CLOCK_MONOTONIC,
+                timeout: 500,
+                precision: 0,
+                flags: 0,
+            },
+        );
+        assert_eq!(event.userdata, 42);
+        assert_eq!(event.error, __WASI_ESUCCESS);
+        assert_eq!(event.type_, __WASI_EVENTTYPE_CLOCK);
+        assert_eq!(now, 1_500);
+    }
+
+    #[test]
+    fn realtime_absolute_sleep() {
+        let (event, now) = poll_clock(
+            1_000,
+            __wasi_subscription_clock_t {
+                clock_id: __WASI_CLOCK_REALTIME,
+                timeout: 1_800,
+                precision: 0,
+                flags: __WASI_SUBSCRIPTION_CLOCK_ABSTIME,
+            },
+        );
+        assert_eq!(event.userdata, 42);
+        assert_eq!(event.error, __WASI_ESUCCESS);
+        assert_eq!(now, 1_800);
+    }
+
+    #[test]
+    fn failed_exclusive_create_is_not_counted() {
+        let store = Store::new(&JIT::new(Cranelift::default()).engine());
+        let module = Module::new(
+            &store,
+            r#"
+            (module
+              (import "wasi_snapshot_preview1" "path_open"
+                (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 0) "link")
+              ;; creates `link` in the directory `dir`, failing if it exists
+              (func (export "create") (param $dir i32) (result i32)
+                (call $path_open (local.get $dir) (i32.const 1) (i32.const 0) (i32.const 4)
+                  (i32.const 5) (i64.const -1) (i64.const -1) (i32.const 0) (i32.const 16))))
+            "#,
+        )
+        .unwrap();
+
+        // `link` points to an existing file, but can't be followed without
+        // any symlink allowed, so only the backing filesystem finds out that
+        // it exists when creating it
+        let fs = MemFileSystem::new();
+        fs.create_dir(Path::new("/data")).unwrap();
+        fs.open(
+            Path::new("/data/file"),
+            OpenOptions::new().write(true).create(true),
+        )
+        .unwrap();
+        fs.symlink(Path::new("file"), Path::new("/data/link"))
+            .unwrap();
+        let mut wasi_env = WasiState::new("create")
+            .set_fs(Box::new(fs))
+            .max_symlinks(0)
+            .quotas(WasiQuotas {
+                max_entries_created: Some(1),
+                ..WasiQuotas::default()
+            })
+            .preopen_dir("/data")
+            .unwrap()
+            .finalize()
+            .unwrap();
+        let dir = *wasi_env.state().fs.preopen_fds.last().unwrap();
+        let import_object = wasi_env.import_object(&module).unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let errno = instance
+            .exports
+            .get_function("create")
+            .unwrap()
+            .call(&[Value::I32(dir as i32)])
+            .unwrap()[0]
+            .unwrap_i32();
+        assert_eq!(errno, __WASI_EIO as i32);
+        assert_eq!(wasi_env.state().fs.usage.entries_created, 0);
+    }
+}
@@ -0,0 +1,390 @@
+//! Tracing of the syscalls made by a WASI program, enabled with
+//! [`WasiEnv::set_tracer`].
+
+use crate::ptr::{Array, WasmPtr};
+use crate::syscalls::types::*;
+use crate::WasiEnv;
+use std::fmt::{self, Write as _};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A syscall made by a WASI program, with its decoded arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyscallEvent {
+    /// The name of the syscall, like `fd_write`.
+    pub name: &'static str,
+    /// The arguments of the syscall, by name.  Strings are decoded from the
+    /// memory of the program and replace their pointer and length, flags
+    /// are decoded by name, and the values written to out pointers like
+    /// `nwritten` replace the pointers when the syscall succeeds.
+    pub args: Vec<(&'static str, TraceValue)>,
+    /// The errno returned, or `None` for `proc_exit` which never returns.
+    pub errno: Option<__wasi_errno_t>,
+    /// The time spent in the syscall.
+    pub duration: Duration,
+    /// The number of bytes read or written, for the syscalls transferring
+    /// data.
+    pub bytes: Option<u64>,
+}
+
+/// The value of an argument of a [`SyscallEvent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceValue {
+    Unsigned(u64),
+    Signed(i64),
+    /// A pointer into the memory of the program.
+    Pointer(u32),
+    String(String),
+    /// The names of the flags set.
+    Flags(Vec<&'static str>),
+}
+
+impl SyscallEvent {
+    /// Formats the event as a single line JSON object, like
+    /// `{"syscall":"fd_close","args":{"fd":3},"errno":0,"duration_ns":1200,"bytes":null}`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        json.push_str("{\"syscall\":");
+        write_json_string(&mut json, self.name);
+        json.push_str(",\"args\":{");
+        for (i, (name, value)) in self.args.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write_json_string(&mut json, name);
+            json.push(':');
+            match value {
+                TraceValue::Unsigned(value) => write!(json, "{}", value).unwrap(),
+                TraceValue::Signed(value) => write!(json, "{}", value).unwrap(),
+                TraceValue::Pointer(value) => write!(json, "{}", value).unwrap(),
+                TraceValue::String(value) => write_json_string(&mut json, value),
+                TraceValue::Flags(flags) => {
+                    json.push('[');
+                    for (i, flag) in flags.iter().enumerate() {
+                        if i > 0 {
+                            json.push(',');
+                        }
+                        write_json_string(&mut json, flag);
+                    }
+                    json.push(']');
+                }
+            }
+        }
+        json.push_str("},\"errno\":");
+        match self.errno {
+            Some(errno) => write!(json, "{}", errno).unwrap(),
+            None => json.push_str("null"),
+        }
+        write!(json, ",\"duration_ns\":{}", self.duration.as_nanos()).unwrap();
+        json.push_str(",\"bytes\":");
+        match self.bytes {
+            Some(bytes) => write!(json, "{}", bytes).unwrap(),
+            None => json.push_str("null"),
+        }
+        json.push('}');
+        json
+    }
+}
+
+fn write_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// The callback receiving the [`SyscallEvent`]s, shared by the clones of a
+/// [`WasiEnv`].
+#[derive(Clone)]
+pub(crate) struct Tracer(pub(crate) Arc<dyn Fn(&SyscallEvent) + Send + Sync>);
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Tracer")
+    }
+}
+
+/// An argument of a syscall, before decoding.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum RawArg {
+    Unsigned(u64),
+    Signed(i64),
+    Pointer(u32),
+}
+
+/// The types of the arguments of the syscalls.
+pub(crate) trait TraceArg {
+    fn raw(self) -> RawArg;
+}
+
+macro_rules! impl_trace_arg {
+    ($variant:ident, $as:ty, $($ty:ty),*) => {
+        $(impl TraceArg for $ty {
+            fn raw(self) -> RawArg {
+                RawArg::$variant(self as $as)
+            }
+        })*
+    };
+}
+
+impl_trace_arg!(Unsigned, u64, u8, u16, u32, u64);
+impl_trace_arg!(Signed, i64, i64);
+
+impl<T: Copy, Ty> TraceArg for WasmPtr<T, Ty> {
+    fn raw(self) -> RawArg {
+        RawArg::Pointer(self.offset())
+    }
+}
+
+const O_FLAGS: &[(u64, &str)] = &[
+    (__WASI_O_CREAT as u64, "CREAT"),
+    (__WASI_O_DIRECTORY as u64, "DIRECTORY"),
+    (__WASI_O_EXCL as u64, "EXCL"),
+    (__WASI_O_TRUNC as u64, "TRUNC"),
+];
+
+const FD_FLAGS: &[(u64, &str)] = &[
+    (__WASI_FDFLAG_APPEND as u64, "APPEND"),
+    (__WASI_FDFLAG_DSYNC as u64, "DSYNC"),
+    (__WASI_FDFLAG_NONBLOCK as u64, "NONBLOCK"),
+    (__WASI_FDFLAG_RSYNC as u64, "RSYNC"),
+    (__WASI_FDFLAG_SYNC as u64, "SYNC"),
+];
+
+const LOOKUP_FLAGS: &[(u64, &str)] = &[(__WASI_LOOKUP_SYMLINK_FOLLOW as u64, "SYMLINK_FOLLOW")];
+
+/// The flags an argument of a syscall is made of, if any.
+fn flags_of(syscall: &str, arg: &str) -> Option<&'static [(u64, &'static str)]> {
+    match (syscall, arg) {
+        (_, "o_flags") => Some(O_FLAGS),
+        (_, "fs_flags") | ("fd_fdstat_set_flags", "flags") => Some(FD_FLAGS),
+        (_, "dirflags") | (_, "old_flags") => Some(LOOKUP_FLAGS),
+        (syscall, "flags") if syscall.starts_with("path_") => Some(LOOKUP_FLAGS),
+        _ => None,
+    }
+}
+
+fn decode_flags(value: u64, flags: &[(u64, &'static str)]) -> Vec<&'static str> {
+    flags
+        .iter()
+        .filter(|(flag, _)| value & flag != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// Whether the argument is an out pointer to a `u32` worth reporting.
+fn is_u32_out(syscall: &str, arg: &str) -> bool {
+    matches!(
+        arg,
+        "nwritten" | "nread" | "bufused" | "buf_used" | "nevents"
+    ) || (syscall, arg) == ("path_open", "fd")
+}
+
+/// Decodes the arguments of a syscall made with the memory of `env` and
+/// reports it to `tracer`.
+pub(crate) fn report(
+    env: &WasiEnv,
+    tracer: &Tracer,
+    name: &'static str,
+    raw_args: &[(&'static str, RawArg)],
+    errno: Option<__wasi_errno_t>,
+    duration: Duration,
+) {
+    let memory = env.memory();
+    let succeeded = errno == Some(__WASI_ESUCCESS);
+    let mut args = Vec::with_capacity(raw_args.len());
+    let mut bytes = None;
+
+    let mut i = 0;
+    while i < raw_args.len() {
+        let (arg, raw) = raw_args[i];
+        i += 1;
+        let value = match raw {
+            RawArg::Pointer(ptr) if matches!(arg, "path" | "old_path" | "new_path") => {
+                // strings are passed as a pointer followed by their length
+                let len = match raw_args.get(i) {
+                    Some((_, RawArg::Unsigned(len))) => {
+                        i += 1;
+                        *len as u32
+                    }
+                    _ => 0,
+                };
+                let string = WasmPtr::<u8, Array>::new(ptr)
+                    .get_utf8_string(memory, len)
+                    .unwrap_or_default();
+                TraceValue::String(string)
+            }
+            RawArg::Pointer(ptr) if succeeded && is_u32_out(name, arg) => {
+                match WasmPtr::<u32>::new(ptr).deref(memory) {
+                    Ok(cell) => {
+                        let value = cell.get() as u64;
+                        if arg != "nevents" && arg != "fd" {
+                            bytes = Some(value);
+                        }
+                        TraceValue::Unsigned(value)
+                    }
+                    Err(_) => TraceValue::Pointer(ptr),
+                }
+            }
+            RawArg::Pointer(ptr) => TraceValue::Pointer(ptr),
+            RawArg::Unsigned(value) => match flags_of(name, arg) {
+                Some(flags) => TraceValue::Flags(decode_flags(value, flags)),
+                None => TraceValue::Unsigned(value),
+            },
+            RawArg::Signed(value) => TraceValue::Signed(value),
+        };
+        args.push((arg, value));
+    }
+
+    if name == "random_get" && succeeded {
+        if let Some((_, TraceValue::Unsigned(len))) = args.iter().find(|(arg, _)| *arg == "buf_len")
+        {
+            bytes = Some(*len);
+        }
+    }
+
+    (tracer.0)(&SyscallEvent {
+        name,
+        args,
+        errno,
+        duration,
+        bytes,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Pipe, WasiState};
+    use std::sync::Mutex;
+    use wasmer::{Cranelift, Instance, Module, Store, JIT};
+
+    #[test]
+    fn json_lines() {
+        let event = SyscallEvent {
+            name: "path_open",
+            args: vec![
+                ("dirfd", TraceValue::Unsigned(3)),
+                ("path", TraceValue::String("a \"b\"\n".to_string())),
+                ("o_flags", TraceValue::Flags(decode_flags(9, O_FLAGS))),
+                ("offset", TraceValue::Signed(-2)),
+            ],
+            errno: Some(__WASI_ENOENT),
+            duration: Duration::from_micros(3),
+            bytes: None,
+        };
+        assert_eq!(
+            event.to_json(),
+            format!(
+                r#"{{"syscall":"path_open","args":{{"dirfd":3,"path":"a \"b\"\n","o_flags":["CREAT","TRUNC"],"offset":-2}},"errno":{},"duration_ns":3000,"bytes":null}}"#,
+                __WASI_ENOENT
+            )
+        );
+    }
+
+    #[test]
+    fn flags_by_argument() {
+        assert_eq!(flags_of("path_open", "o_flags"), Some(O_FLAGS));
+        assert_eq!(flags_of("path_open", "fs_flags"), Some(FD_FLAGS));
+        assert_eq!(flags_of("fd_fdstat_set_flags", "flags"), Some(FD_FLAGS));
+        assert_eq!(flags_of("path_filestat_get", "flags"), Some(LOOKUP_FLAGS));
+        assert_eq!(flags_of("sock_recv", "ri_flags"), None);
+        assert_eq!(
+            decode_flags(__WASI_FDFLAG_APPEND as u64 | 1 << 10, FD_FLAGS),
+            vec!["APPEND"]
+        );
+    }
+
+    #[test]
+    fn traced_imports() {
+        let store = Store::new(&JIT::new(Cranelift::default()).engine());
+        let module = Module::new(
+            &store,
+            r#"
+            (module
+              (import "wasi_snapshot_preview1" "fd_write"
+                (func $fd_write (param i32 i32 i32 i32) (result i32)))
+              (import "wasi_snapshot_preview1" "clock_time_get"
+                (func $clock_time_get (param i32 i64 i32) (result i32)))
+              (memory (export "memory") 1)
+              ;; an iovec pointing to "hello\n"
+              (data (i32.const 0) "\10\00\00\00\06\00\00\00")
+              (data (i32.const 16) "hello\n")
+              (func (export "_start")
+                (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 24)))
+                (drop (call $clock_time_get (i32.const 0) (i64.const 1000) (i32.const 32)))))
+            "#,
+        )
+        .unwrap();
+
+        let mut env = WasiState::new("trace")
+            .stdout(Box::new(Pipe::new()))
+            .virtual_clock(5, 10)
+            .finalize()
+            .unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        {
+            let events = events.clone();
+            env.set_tracer(move |event| events.lock().unwrap().push(event.clone()));
+        }
+        let import_object = env.import_object(&module).unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        instance
+            .exports
+            .get_function("_start")
+            .unwrap()
+            .call(&[])
+            .unwrap();
+
+        let events: Vec<_> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| SyscallEvent {
+                duration: Duration::default(),
+                ..event.clone()
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                SyscallEvent {
+                    name: "fd_write",
+                    args: vec![
+                        ("fd", TraceValue::Unsigned(1)),
+                        ("iovs", TraceValue::Pointer(0)),
+                        ("iovs_len", TraceValue::Unsigned(1)),
+                        ("nwritten", TraceValue::Unsigned(6)),
+                    ],
+                    errno: Some(__WASI_ESUCCESS),
+                    duration: Duration::default(),
+                    bytes: Some(6),
+                },
+                SyscallEvent {
+                    name: "clock_time_get",
+                    args: vec![
+                        ("clock_id", TraceValue::Unsigned(0)),
+                        ("precision", TraceValue::Unsigned(1000)),
+                        ("time", TraceValue::Pointer(32)),
+                    ],
+                    errno: Some(__WASI_ESUCCESS),
+                    duration: Duration::default(),
+                    bytes: None,
+                },
+            ]
+        );
+        assert_eq!(
+            events[0].to_json(),
+            r#"{"syscall":"fd_write","args":{"fd":1,"iovs":0,"iovs_len":1,"nwritten":6},"errno":0,"duration_ns":0,"bytes":6}"#
+        );
+    }
+}
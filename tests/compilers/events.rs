@@ -0,0 +1,108 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use wasmer::*;
+
+#[derive(Default)]
+struct Recorder {
+    events: Mutex<Vec<String>>,
+}
+
+impl Recorder {
+    fn record(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+}
+
+impl StoreEvents for Recorder {
+    fn instance_created(&self, instance: &Instance) {
+        self.record(format!("instance created: {:?}", instance.module().name()));
+    }
+
+    fn instance_dropped(&self) {
+        self.record("instance dropped".to_string());
+    }
+
+    fn memory_grown(&self, old: Pages, new: Pages) {
+        self.record(format!("memory grown: {} -> {}", old.0, new.0));
+    }
+
+    fn table_grown(&self, old: u32, new: u32) {
+        self.record(format!("table grown: {} -> {}", old, new));
+    }
+
+    fn trap_raised(&self, error: &RuntimeError) {
+        self.record(format!("trap raised: {}", error.message()));
+    }
+
+    fn host_function_entered(&self) {
+        self.record("host function entered".to_string());
+    }
+
+    fn host_function_exited(&self) {
+        self.record("host function exited".to_string());
+    }
+}
+
+#[compiler_test(events)]
+fn test_store_events(config: crate::Config) -> Result<()> {
+    let store = config.store();
+    let recorder = Arc::new(Recorder::default());
+    store.set_events(Some(recorder.clone()));
+
+    let module = Module::new(
+        &store,
+        r#"
+        (module $events
+            (import "host" "call" (func $call))
+            (memory 1)
+            (table (export "table") 1 funcref)
+            (func (export "call_host") call $call)
+            (func (export "grow") (param i32) (result i32)
+                local.get 0
+                memory.grow)
+            (func (export "trap") unreachable))
+        "#,
+    )?;
+    let imports = imports! {
+        "host" => {
+            "call" => Function::new_native(&store, || {}),
+        },
+    };
+    let instance = Instance::new(&module, &imports)?;
+    assert_eq!(recorder.take(), vec!["instance created: Some(\"events\")"]);
+
+    let call_host: NativeFunc<(), ()> = instance.exports.get_native_function("call_host")?;
+    call_host.call()?;
+    assert_eq!(
+        recorder.take(),
+        vec!["host function entered", "host function exited"]
+    );
+
+    let grow: NativeFunc<u32, i32> = instance.exports.get_native_function("grow")?;
+    assert_eq!(grow.call(2)?, 1);
+    // Failing to grow doesn't notify anything
+    assert_eq!(grow.call(0x1_0000)?, -1);
+    let table = instance.exports.get_table("table")?;
+    table.grow(1, Val::FuncRef(None))?;
+    assert_eq!(
+        recorder.take(),
+        vec!["memory grown: 1 -> 3", "table grown: 1 -> 2"]
+    );
+
+    let trap = instance.exports.get_function("trap")?;
+    assert!(trap.call(&[]).is_err());
+    assert_eq!(recorder.take(), vec!["trap raised: unreachable"]);
+
+    drop((call_host, grow, instance));
+    assert_eq!(recorder.take(), vec!["instance dropped"]);
+
+    // Instances failing to be created aren't notified
+    let module = Module::new(&store, "(module (func $start unreachable) (start $start))")?;
+    assert!(Instance::new(&module, &imports! {}).is_err());
+    assert_eq!(recorder.take(), vec!["trap raised: unreachable"]);
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use anyhow::Result;
+use std::sync::Arc;
+use wasmer::*;
+
+const WAT: &str = r#"
+    (module
+        (memory 2)
+        (func (export "sum") (param i32) (result i32)
+            (local i32 i64 i64)
+            local.get 0)
+        (func (export "nop")))
+"#;
+
+fn store_with_limits(config: &crate::Config, limits: ModuleLimits) -> Store {
+    let engine = config.engine(config.compiler_config(config.canonicalize_nans));
+    let mut tunables = BaseTunables::for_target(engine.target());
+    tunables.module_limits = Some(limits);
+    Store::new_with_tunables(&*engine, tunables)
+}
+
+fn store_with_limiter(config: &crate::Config, limiter: Arc<dyn ResourceLimiter>) -> Store {
+    let engine = config.engine(config.compiler_config(config.canonicalize_nans));
+    let mut tunables = BaseTunables::for_target(engine.target());
+    tunables.resource_limiter = Some(limiter);
+    Store::new_with_tunables(&*engine, tunables)
+}
+
+fn exceeded(result: Result<Module, CompileError>) -> Option<(ModuleLimit, u64, u64)> {
+    match result {
+        Err(CompileError::LimitExceeded {
+            limit,
+            actual,
+            maximum,
+        }) => Some((limit, actual, maximum)),
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => None,
+    }
+}
+
+#[compiler_test(limits)]
+fn test_module_within_limits(config: crate::Config) -> Result<()> {
+    let store = store_with_limits(
+        &config,
+        ModuleLimits {
+            max_functions: Some(2),
+            max_exports: Some(2),
+            max_memory_pages: Some(Pages(2)),
+            max_locals_per_function: Some(3),
+            ..Default::default()
+        },
+    );
+    Module::new(&store, WAT)?;
+    Ok(())
+}
+
+#[compiler_test(limits)]
+fn test_module_exceeding_limits(config: crate::Config) -> Result<()> {
+    let store = store_with_limits(
+        &config,
+        ModuleLimits {
+            max_locals_per_function: Some(2),
+            ..Default::default()
+        },
+    );
+    let expected = Some((ModuleLimit::LocalsPerFunction, 3, 2));
+    let bytes = wat::parse_str(WAT)?;
+    assert!(matches!(
+        Module::validate(&store, &bytes),
+        Err(CompileError::LimitExceeded { .. })
+    ));
+    assert_eq!(exceeded(Module::new(&store, &bytes)), expected);
+    // The limits are enforced when compiling too
+    assert_eq!(
+        exceeded(unsafe { Module::from_binary_unchecked(&store, &bytes) }),
+        expected
+    );
+    assert!(matches!(
+        Module::from_reader(&store, &bytes[..]),
+        Err(IoCompileError::Compile(CompileError::LimitExceeded { .. }))
+    ));
+
+    let store = store_with_limits(
+        &config,
+        ModuleLimits {
+            max_memory_pages: Some(Pages(1)),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        exceeded(Module::new(&store, &bytes)),
+        Some((ModuleLimit::MemoryPages, 2, 1))
+    );
+    Ok(())
+}
+
+#[compiler_test(limits)]
+fn test_resource_limiter(config: crate::Config) -> Result<()> {
+    const PAGE: u64 = WASM_PAGE_SIZE as u64;
+    let budget = Arc::new(
+        ResourceBudget::new()
+            .with_max_memory_bytes(3 * PAGE)
+            .with_max_table_elements(2)
+            .with_max_instances(1),
+    );
+    let store = store_with_limiter(&config, budget.clone());
+    let module = Module::new(
+        &store,
+        r#"
+        (module
+            (memory 1)
+            (table (export "table") 1 funcref)
+            (func (export "grow") (param i32) (result i32)
+                local.get 0
+                memory.grow))
+        "#,
+    )?;
+
+    let instance = Instance::new(&module, &imports! {})?;
+    assert_eq!(budget.instances(), 1);
+    assert_eq!(budget.memory_bytes(), PAGE);
+    assert_eq!(budget.table_elements(), 1);
+    assert!(matches!(
+        Instance::new(&module, &imports! {}),
+        Err(InstantiationError::Link(LinkError::Resource(_)))
+    ));
+
+    let grow: NativeFunc<u32, i32> = instance.exports.get_native_function("grow")?;
+    assert_eq!(grow.call(2)?, 1);
+    assert_eq!(budget.memory_bytes(), 3 * PAGE);
+    // Growing the memory beyond its maximum still fails with -1
+    assert_eq!(grow.call(0x1_0000)?, -1);
+    // But the limiter denying the growth traps
+    let error = grow.call(1).unwrap_err();
+    assert!(error.downcast::<ResourceLimitError>().is_ok());
+    assert_eq!(budget.memory_bytes(), 3 * PAGE);
+
+    let table = instance.exports.get_table("table")?;
+    assert_eq!(table.grow(1, Val::FuncRef(None))?, 1);
+    let error = table.grow(1, Val::FuncRef(None)).unwrap_err();
+    assert!(error.downcast::<ResourceLimitError>().is_ok());
+
+    // Host memories count too
+    assert!(matches!(
+        Memory::new(&store, MemoryType::new(1, None, false)),
+        Err(MemoryError::ResourceLimit(_))
+    ));
+
+    drop((grow, instance));
+    assert_eq!(budget.instances(), 0);
+    assert_eq!(budget.memory_bytes(), 0);
+    assert_eq!(budget.table_elements(), 0);
+    Instance::new(&module, &imports! {})?;
+    Ok(())
+}
@@ -6,12 +6,15 @@
 extern crate compiler_test_derive;
 
 mod config;
+mod events;
 mod imports;
+mod limits;
 mod metering;
 mod middlewares;
 // mod multi_value_imports;
 mod native_functions;
 mod serialize;
+mod streaming;
 mod traps;
 mod wasi;
 mod wast;
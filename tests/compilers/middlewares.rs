@@ -1,7 +1,9 @@
 use anyhow::Result;
 
 use loupe::MemoryUsage;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use wasmer::vm::ModuleInfo;
 use wasmer::wasmparser::Operator;
 use wasmer::*;
 
@@ -205,3 +207,136 @@ fn middleware_chain_order_2(mut config: crate::Config) -> Result<()> {
     assert_eq!(result, 48);
     Ok(())
 }
+
+/// Calls an imported `trace` hook at the start of each function, and
+/// doubles the results of `i32.add` with an added `double` function, which
+/// is exported.
+#[derive(Debug, MemoryUsage)]
+struct TraceGen {
+    #[loupe(skip)]
+    functions: Mutex<Option<(FunctionAdditions, u32, u32, LocalFunctionIndex)>>,
+}
+
+#[derive(Debug)]
+struct Trace {
+    additions: FunctionAdditions,
+    trace: u32,
+    double: u32,
+    started: bool,
+}
+
+impl ModuleMiddleware for TraceGen {
+    fn generate_function_middleware(&self, _: LocalFunctionIndex) -> Box<dyn FunctionMiddleware> {
+        let (additions, trace, double, _) = self.functions.lock().unwrap().clone().unwrap();
+        Box::new(Trace {
+            additions,
+            trace,
+            double,
+            started: false,
+        })
+    }
+
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut additions = FunctionAdditions::new(module_info);
+        let trace = additions.add_import(
+            module_info,
+            "tracing",
+            "trace",
+            FunctionType::new(vec![Type::I32], vec![]),
+        );
+        let double = additions.add_function(
+            module_info,
+            FunctionType::new(vec![Type::I32], vec![Type::I32]),
+        );
+        let double_index = module_info.func_index(double);
+        module_info
+            .exports
+            .insert("double".to_string(), ExportIndex::Function(double_index));
+        *self.functions.lock().unwrap() =
+            Some((additions, trace.as_u32(), double_index.as_u32(), double));
+    }
+
+    fn function_bodies(&self, _: &ModuleInfo) -> Vec<(LocalFunctionIndex, Vec<u8>)> {
+        let (_, _, _, double) = self.functions.lock().unwrap().clone().unwrap();
+        // No locals, `local.get 0`, `i32.const 2`, `i32.mul` and `end`.
+        vec![(double, vec![0x00, 0x20, 0x00, 0x41, 0x02, 0x6c, 0x0b])]
+    }
+}
+
+impl FunctionMiddleware for Trace {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if !self.started {
+            self.started = true;
+            state.extend(&[
+                Operator::I32Const { value: 1 },
+                Operator::Call {
+                    function_index: self.trace,
+                },
+            ]);
+        }
+        match operator {
+            Operator::I32Add => state.extend(&[
+                Operator::I32Add,
+                Operator::Call {
+                    function_index: self.double,
+                },
+            ]),
+            operator => state.push_operator(self.additions.remap(operator)),
+        }
+        Ok(())
+    }
+}
+
+#[compiler_test(middlewares)]
+fn middleware_function_additions(mut config: crate::Config) -> Result<()> {
+    config.set_middlewares(vec![Arc::new(TraceGen {
+        functions: Mutex::new(None),
+    }) as Arc<dyn ModuleMiddleware>]);
+    let store = config.store();
+    let wat = r#"(module
+        (import "env" "log" (func $log (param i32)))
+        (func $add (export "add") (param i32 i32) (result i32)
+           (call $log (local.get 0))
+           (i32.add (local.get 0)
+                    (local.get 1)))
+        (func (export "add_twice") (param i32 i32) (result i32)
+           (call $add (local.get 0)
+                      (local.get 1)))
+)"#;
+    let module = Module::new(&store, wat).unwrap();
+
+    let logged = Arc::new(AtomicI32::new(0));
+    let traced = Arc::new(AtomicI32::new(0));
+    let (log, trace) = (logged.clone(), traced.clone());
+    let import_object = imports! {
+        "env" => {
+            "log" => Function::new(&store, FunctionType::new(vec![Type::I32], vec![]), move |values| {
+                log.store(values[0].unwrap_i32(), Ordering::SeqCst);
+                Ok(vec![])
+            }),
+        },
+        "tracing" => {
+            "trace" => Function::new(&store, FunctionType::new(vec![Type::I32], vec![]), move |values| {
+                trace.fetch_add(values[0].unwrap_i32(), Ordering::SeqCst);
+                Ok(vec![])
+            }),
+        },
+    };
+
+    let instance = Instance::new(&module, &import_object)?;
+
+    let add: NativeFunc<(i32, i32), i32> = instance.exports.get_native_function("add_twice")?;
+    assert_eq!(add.call(4, 6)?, 20);
+    assert_eq!(logged.load(Ordering::SeqCst), 4);
+    assert_eq!(traced.load(Ordering::SeqCst), 2);
+
+    // The added function isn't traced.
+    let double: NativeFunc<i32, i32> = instance.exports.get_native_function("double")?;
+    assert_eq!(double.call(5)?, 10);
+    assert_eq!(traced.load(Ordering::SeqCst), 2);
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use anyhow::Result;
+use futures::executor::block_on;
+use wasmer_middlewares::metering::{get_remaining_points, MeteringPoints};
+use wasmer_middlewares::Metering;
+
+use std::io::{self, Read};
+use std::sync::Arc;
+use wasmer::wasmparser::Operator;
+use wasmer::*;
+
+const WAT: &str = r#"
+    (module $streamed
+        (import "host" "double" (func $double (param i32) (result i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 16) "\2a")
+        (func $load (result i32)
+            i32.const 16
+            i32.load8_u)
+        (func (export "run") (result i32)
+            call $load
+            call $double))
+"#;
+
+/// A reader returning the bytes a few at a time, and then an error if any.
+struct ChunkedReader {
+    bytes: Vec<u8>,
+    position: usize,
+    error: Option<io::Error>,
+}
+
+impl ChunkedReader {
+    fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            position: 0,
+            error: None,
+        }
+    }
+}
+
+impl Read for ChunkedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = &self.bytes[self.position..];
+        if remaining.is_empty() {
+            if let Some(error) = self.error.take() {
+                return Err(error);
+            }
+        }
+        let len = remaining.len().min(buf.len()).min(3);
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+fn run(store: &Store, module: &Module) -> Result<i32> {
+    let import_object = imports! {
+        "host" => {
+            "double" => Function::new_native(store, |x: i32| x * 2),
+        },
+    };
+    let instance = Instance::new(module, &import_object)?;
+    let run: NativeFunc<(), i32> = instance.exports.get_native_function("run")?;
+    Ok(run.call()?)
+}
+
+#[compiler_test(streaming)]
+fn test_from_reader(config: crate::Config) -> Result<()> {
+    let store = config.store();
+    let bytes = wat::parse_str(WAT)?;
+    let module = Module::from_reader(&store, ChunkedReader::new(bytes))?;
+    assert_eq!(module.name(), Some("streamed"));
+    assert_eq!(run(&store, &module)?, 84);
+    Ok(())
+}
+
+#[compiler_test(streaming)]
+fn test_from_reader_with_middlewares(mut config: crate::Config) -> Result<()> {
+    config
+        .middlewares
+        .push(Arc::new(Metering::new(100, |_: &Operator| 1)));
+    let store = config.store();
+    let bytes = wat::parse_str(WAT)?;
+    let module = Module::from_reader(&store, ChunkedReader::new(bytes))?;
+    let import_object = imports! {
+        "host" => {
+            "double" => Function::new_native(&store, |x: i32| x * 2),
+        },
+    };
+    let instance = Instance::new(&module, &import_object)?;
+    let run: NativeFunc<(), i32> = instance.exports.get_native_function("run")?;
+    assert_eq!(run.call()?, 84);
+    assert!(matches!(
+        get_remaining_points(&instance),
+        MeteringPoints::Remaining(points) if points < 100
+    ));
+    Ok(())
+}
+
+#[compiler_test(streaming)]
+fn test_from_reader_errors(config: crate::Config) -> Result<()> {
+    let store = config.store();
+    let bytes = wat::parse_str(WAT)?;
+
+    let truncated = bytes[..bytes.len() - 4].to_vec();
+    assert!(matches!(
+        Module::from_reader(&store, ChunkedReader::new(truncated.clone())),
+        Err(IoCompileError::Compile(_))
+    ));
+
+    let mut reader = ChunkedReader::new(truncated);
+    reader.error = Some(io::Error::new(io::ErrorKind::ConnectionReset, "reset"));
+    match Module::from_reader(&store, reader) {
+        Err(IoCompileError::Io(error)) => assert_eq!(error.kind(), io::ErrorKind::ConnectionReset),
+        _ => panic!("expected an IO error"),
+    }
+
+    let invalid = wat::parse_str(r#"(module (func (result i32) i64.const 0))"#)?;
+    assert!(matches!(
+        Module::from_reader(&store, ChunkedReader::new(invalid)),
+        Err(IoCompileError::Compile(CompileError::Validate(_)))
+    ));
+    Ok(())
+}
+
+/// The bytes as a stream of `Ok` chunks of 3 bytes.
+fn chunks(bytes: &[u8]) -> Vec<io::Result<Vec<u8>>> {
+    bytes.chunks(3).map(|chunk| Ok(chunk.to_vec())).collect()
+}
+
+#[compiler_test(streaming)]
+fn test_from_stream(config: crate::Config) -> Result<()> {
+    let store = config.store();
+    let bytes = wat::parse_str(WAT)?;
+    let stream = futures::stream::iter(chunks(&bytes));
+    let module = block_on(Module::from_stream(&store, stream))?;
+    assert_eq!(module.name(), Some("streamed"));
+    assert_eq!(run(&store, &module)?, 84);
+    Ok(())
+}
+
+#[compiler_test(streaming)]
+fn test_from_stream_errors(config: crate::Config) -> Result<()> {
+    let store = config.store();
+    let bytes = wat::parse_str(WAT)?;
+
+    let truncated = &bytes[..bytes.len() - 4];
+    let stream = futures::stream::iter(chunks(truncated));
+    assert!(matches!(
+        block_on(Module::from_stream(&store, stream)),
+        Err(IoCompileError::Compile(_))
+    ));
+
+    let mut failing = chunks(truncated);
+    failing.push(Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset")));
+    let stream = futures::stream::iter(failing);
+    match block_on(Module::from_stream(&store, stream)) {
+        Err(IoCompileError::Io(error)) => assert_eq!(error.kind(), io::ErrorKind::ConnectionReset),
+        _ => panic!("expected an IO error"),
+    }
+    Ok(())
+}
+
+/// A reader using the engine of a store while the module is received.
+struct EngineUsingReader {
+    reader: ChunkedReader,
+    store: Store,
+}
+
+impl Read for EngineUsingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Module::validate(&self.store, b"\0asm\x01\0\0\0").unwrap();
+        Function::new_native(&self.store, |x: i32| x);
+        self.reader.read(buf)
+    }
+}
+
+#[compiler_test(streaming)]
+fn test_from_reader_doesnt_lock_engine(config: crate::Config) -> Result<()> {
+    let store = config.store();
+    let reader = EngineUsingReader {
+        reader: ChunkedReader::new(wat::parse_str(WAT)?),
+        store: store.clone(),
+    };
+    let module = Module::from_reader(&store, reader)?;
+    assert_eq!(run(&store, &module)?, 84);
+    Ok(())
+}